@@ -0,0 +1,496 @@
+use std::{
+    io::{self, Read},
+    path::Path,
+    process::{self, Stdio},
+};
+
+use crate::{audio, digest::Md5, tools::Tool, Error, Result};
+
+/// Decoded audio, as interleaved 32-bit float samples streamed from ffmpeg.
+pub(crate) struct Decoder {
+    child: process::Child,
+    pub(crate) channels: usize,
+    pub(crate) sample_rate: u32,
+}
+
+impl Decoder {
+    /// Starts decoding a file at its own channel count and sample rate, so
+    /// that multichannel sources aren't folded down to stereo.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        Decoder::start(path, &[])
+    }
+
+    /// Starts decoding a file, stopping at the first error rather than
+    /// concealing damaged frames, so that `finish` fails.
+    pub(crate) fn open_strict(path: &Path) -> Result<Self> {
+        Decoder::start(path, &["-xerror"])
+    }
+
+    fn start(path: &Path, options: &[&str]) -> Result<Self> {
+        let format = audio::read(path)?;
+        let (channels, sample_rate) = (usize::from(format.channels), format.sample_rate);
+
+        let child = Tool::Ffmpeg
+            .command()
+            .args(options)
+            .args(["-loglevel", "error", "-i"])
+            .arg(path)
+            .args(["-f", "f32le", "-acodec", "pcm_f32le", "-ac"])
+            .arg(channels.to_string())
+            .arg("-ar")
+            .arg(sample_rate.to_string())
+            .arg("-")
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|_| Tool::Ffmpeg.missing())?;
+
+        Ok(Decoder {
+            child,
+            channels,
+            sample_rate,
+        })
+    }
+
+    /// Fills `buf` with as many samples as are available, returning how many
+    /// were read. Zero means the stream has ended.
+    pub(crate) fn read(&mut self, buf: &mut [f32]) -> io::Result<usize> {
+        let stdout = self.child.stdout.as_mut().expect("stdout is piped");
+        let mut bytes = vec![0; buf.len() * 4];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match stdout.read(&mut bytes[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+
+        let samples = filled / 4;
+        for (sample, chunk) in buf.iter_mut().zip(bytes[..samples * 4].chunks_exact(4)) {
+            *sample = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        Ok(samples)
+    }
+
+    /// Waits for ffmpeg to exit, failing if it couldn't decode the file.
+    pub(crate) fn finish(mut self, path: &Path) -> Result<()> {
+        if self.child.wait()?.success() {
+            Ok(())
+        } else {
+            Err(Error::FfmpegFailed(path.display().to_string()))
+        }
+    }
+}
+
+/// A track's dynamic range, measured the way the TT DR Offline Meter does.
+pub(crate) struct DynamicRange {
+    pub(crate) dr: f64,
+    /// Highest sample peak, in dBFS
+    pub(crate) peak: f64,
+    /// Overall RMS, in dBFS, scaled so a full-scale sine reads 0
+    pub(crate) rms: f64,
+}
+
+/// Measures the dynamic range of a file.
+///
+/// The audio is cut into three second blocks, and the RMS and peak of each
+/// block are taken per channel. A channel's DR is the ratio, in decibels, of
+/// its second highest block peak to the RMS of its loudest 20% of blocks; the
+/// track's DR is the mean across channels.
+pub(crate) fn dynamic_range(path: &Path) -> Result<DynamicRange> {
+    let mut decoder = Decoder::open(path)?;
+    let channels = decoder.channels;
+    let block_len = decoder.sample_rate as usize * 3;
+
+    // per channel: (block RMS values, block peaks)
+    let mut blocks: Vec<(Vec<f64>, Vec<f64>)> = vec![Default::default(); channels];
+    let mut total_square = 0.0;
+    let mut total_samples = 0;
+    let mut buf = vec![0.0; block_len * channels];
+
+    loop {
+        let read = decoder.read(&mut buf)?;
+        let frames = read / channels;
+        if frames == 0 {
+            break;
+        }
+
+        for (channel, (rms, peaks)) in blocks.iter_mut().enumerate() {
+            let samples = buf[..frames * channels]
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .map(|&s| f64::from(s));
+            let (square, peak) = samples.fold((0.0, 0.0_f64), |(square, peak), s| {
+                (square + s * s, peak.max(s.abs()))
+            });
+            total_square += square;
+
+            // A trailing partial block only counts when it's all there is.
+            if frames == block_len || rms.is_empty() {
+                rms.push((2.0 * square / frames as f64).sqrt());
+                peaks.push(peak);
+            }
+        }
+        total_samples += frames * channels;
+
+        if frames < block_len {
+            break;
+        }
+    }
+    decoder.finish(path)?;
+
+    let mut dr_sum = 0.0;
+    let mut peak_max = 0.0_f64;
+    for (mut rms, mut peaks) in blocks {
+        rms.sort_by(|a, b| b.total_cmp(a));
+        peaks.sort_by(|a, b| b.total_cmp(a));
+
+        let loudest = ((rms.len() as f64 * 0.2).round() as usize).max(1);
+        let rms_20 = (rms.iter().take(loudest).map(|r| r * r).sum::<f64>() / loudest as f64).sqrt();
+        let peak = peaks.get(1).or(peaks.first()).copied().unwrap_or_default();
+        peak_max = peak_max.max(peaks.first().copied().unwrap_or_default());
+
+        if rms_20 > 0.0 {
+            dr_sum += 20.0 * (peak / rms_20).log10();
+        }
+    }
+
+    Ok(DynamicRange {
+        dr: dr_sum / channels as f64,
+        peak: 20.0 * peak_max.log10(),
+        rms: 10.0 * (2.0 * total_square / total_samples.max(1) as f64).log10(),
+    })
+}
+
+/// Silence at either end of a track, in seconds.
+pub(crate) struct Silence {
+    pub(crate) leading: f64,
+    pub(crate) trailing: f64,
+}
+
+/// Measures the silence before the first and after the last sample louder
+/// than `threshold` dBFS on any channel. A track which is silent throughout
+/// is all leading silence.
+pub(crate) fn silence(path: &Path, threshold: f64) -> Result<Silence> {
+    const CHUNK: usize = 4096;
+
+    let mut decoder = Decoder::open(path)?;
+    let channels = decoder.channels;
+    let threshold = 10.0_f64.powf(threshold / 20.0) as f32;
+
+    let mut buf = vec![0.0; CHUNK * channels];
+    let (mut frames, mut first, mut last) = (0, None, 0);
+    loop {
+        let read = decoder.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        for (i, frame) in buf[..read].chunks_exact(channels).enumerate() {
+            if frame.iter().any(|s| s.abs() > threshold) {
+                first.get_or_insert(frames + i);
+                last = frames + i + 1;
+            }
+        }
+        frames += read / channels;
+    }
+    let rate = f64::from(decoder.sample_rate);
+    decoder.finish(path)?;
+
+    Ok(match first {
+        Some(first) => Silence {
+            leading: first as f64 / rate,
+            trailing: (frames - last) as f64 / rate,
+        },
+        None => Silence {
+            leading: frames as f64 / rate,
+            trailing: 0.0,
+        },
+    })
+}
+
+/// The MD5 of a file's decoded samples, as a FLAC encoder computes the one
+/// it stores in STREAMINFO: each sample as a signed little-endian integer of
+/// as many whole bytes as `bits` needs, channels interleaved. Floats hold
+/// samples of up to 24 bits exactly. Fails if the file doesn't decode
+/// cleanly.
+pub(crate) fn audio_md5(path: &Path, bits: u8) -> Result<[u8; 16]> {
+    const CHUNK: usize = 65536;
+
+    let mut decoder = Decoder::open_strict(path)?;
+    let width = usize::from(bits).div_ceil(8);
+    let scale = f64::from(bits).exp2() / 2.0;
+    let (min, max) = (-scale, scale - 1.0);
+
+    let mut md5 = Md5::new();
+    let mut buf = vec![0.0; CHUNK];
+    let mut bytes = Vec::with_capacity(CHUNK * width);
+    loop {
+        let read = decoder.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        bytes.clear();
+        for &sample in &buf[..read] {
+            let sample = (f64::from(sample) * scale).round().clamp(min, max) as i32;
+            bytes.extend_from_slice(&sample.to_le_bytes()[..width]);
+        }
+        md5.update(&bytes);
+    }
+    decoder.finish(path)?;
+
+    Ok(md5.finish())
+}
+
+/// What a high-resolution file's samples actually contain.
+pub(crate) struct Resolution {
+    /// Every sample fits in 16 bits, so any extra bits are padding.
+    pub(crate) padded_to_16: bool,
+    /// Energy above 22.05 kHz relative to the whole spectrum, in dB, when the
+    /// sample rate allows for any.
+    pub(crate) ultrasonic: Option<f64>,
+}
+
+/// Decodes a file to test whether its bit depth and sample rate carry real
+/// information. Floats hold 24-bit samples exactly, so padding shows up as
+/// samples which are whole multiples of 2^-15.
+pub(crate) fn resolution(path: &Path) -> Result<Resolution> {
+    const WINDOW: usize = 4096;
+    // Analyze one window in this many to keep long files quick.
+    const STRIDE: usize = 8;
+
+    let mut decoder = Decoder::open(path)?;
+    let channels = decoder.channels;
+    let cutoff = (22050.0 / decoder.sample_rate as f64 * WINDOW as f64) as usize;
+    let has_ultrasonic = cutoff < WINDOW / 2;
+
+    let mut padded_to_16 = true;
+    let (mut high, mut total) = (0.0, 0.0);
+    let mut buf = vec![0.0; WINDOW * channels];
+    let (mut re, mut im) = (vec![0.0; WINDOW], vec![0.0; WINDOW]);
+
+    for index in 0.. {
+        let read = decoder.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        padded_to_16 &= buf[..read].iter().all(|&s| (s * 32768.0).fract() == 0.0);
+
+        if has_ultrasonic && read == buf.len() && index % STRIDE == 0 {
+            // Fold the channels together and apply a Hann window.
+            for (i, frame) in buf.chunks_exact(channels).enumerate() {
+                let mono = frame.iter().map(|&s| f64::from(s)).sum::<f64>() / channels as f64;
+                let hann =
+                    0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / WINDOW as f64).cos();
+                re[i] = mono * hann;
+                im[i] = 0.0;
+            }
+            fft(&mut re, &mut im);
+
+            for bin in 1..WINDOW / 2 {
+                let energy = re[bin] * re[bin] + im[bin] * im[bin];
+                total += energy;
+                if bin >= cutoff {
+                    high += energy;
+                }
+            }
+        }
+    }
+    decoder.finish(path)?;
+
+    let ultrasonic = has_ultrasonic.then(|| match (high, total) {
+        (_, 0.0) | (0.0, _) => f64::NEG_INFINITY,
+        (high, total) => 10.0 * (high / total).log10(),
+    });
+
+    Ok(Resolution {
+        padded_to_16,
+        ultrasonic,
+    })
+}
+
+/// A track's loudness, measured as EBU R128 / ITU-R BS.1770 specify.
+pub(crate) struct Loudness {
+    /// The mean weighted square of each 400 ms gating block, overlapping by
+    /// 75%. Kept so an album's loudness can be measured over all its
+    /// tracks' blocks together.
+    pub(crate) blocks: Vec<f64>,
+    /// Highest sample peak, as a linear amplitude
+    pub(crate) peak: f64,
+}
+
+/// Measures a file's loudness. Each channel is K-weighted (a high shelf and
+/// a high-pass filter), and the weighted mean square is taken over 400 ms
+/// blocks every 100 ms.
+pub(crate) fn loudness(path: &Path) -> Result<Loudness> {
+    let mut decoder = Decoder::open(path)?;
+    let channels = decoder.channels;
+    let rate = f64::from(decoder.sample_rate);
+    let step = (rate / 10.0).round() as usize;
+
+    let mut filters = vec![KWeighting::new(rate); channels];
+    // Surround channels count for more; the LFE channel of 5.1 not at all.
+    let weights: Vec<f64> = (0..channels)
+        .map(|channel| match (channels, channel) {
+            (6, 3) => 0.0,
+            (5 | 6, 3..) => 1.41,
+            _ => 1.0,
+        })
+        .collect();
+
+    // The weighted sum of squares of each 100 ms step.
+    let mut steps = Vec::new();
+    let mut peak = 0.0_f64;
+    let mut buf = vec![0.0; step * channels];
+    loop {
+        let read = decoder.read(&mut buf)?;
+        let frames = read / channels;
+        if frames == 0 {
+            break;
+        }
+        let mut sum = 0.0;
+        for frame in buf[..frames * channels].chunks_exact(channels) {
+            for ((filter, &weight), &sample) in filters.iter_mut().zip(&weights).zip(frame) {
+                let sample = f64::from(sample);
+                peak = peak.max(sample.abs());
+                let weighted = filter.process(sample);
+                sum += weight * weighted * weighted;
+            }
+        }
+        if frames == step {
+            steps.push(sum);
+        }
+        if frames < step {
+            break;
+        }
+    }
+    decoder.finish(path)?;
+
+    let blocks = steps
+        .windows(4)
+        .map(|window| window.iter().sum::<f64>() / (4 * step) as f64)
+        .collect();
+    Ok(Loudness { blocks, peak })
+}
+
+/// Integrated loudness in LUFS over a set of gating blocks: the loudness of
+/// the blocks above -70 LUFS, then of those within 10 LU of that. Nothing if
+/// every block is silent.
+pub(crate) fn integrated(blocks: &[f64]) -> Option<f64> {
+    let lufs = |energy: f64| -0.691 + 10.0 * energy.log10();
+    let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+
+    let audible: Vec<f64> = blocks
+        .iter()
+        .copied()
+        .filter(|&energy| energy > 0.0 && lufs(energy) > -70.0)
+        .collect();
+    if audible.is_empty() {
+        return None;
+    }
+    let threshold = lufs(mean(&audible)) - 10.0;
+    let gated: Vec<f64> = audible
+        .into_iter()
+        .filter(|&energy| lufs(energy) > threshold)
+        .collect();
+    Some(lufs(mean(&gated)))
+}
+
+/// The two biquads of the K-weighting filter, designed for any sample rate
+/// as libebur128 does.
+#[derive(Clone)]
+struct KWeighting {
+    b: [f64; 5],
+    a: [f64; 5],
+    state: [f64; 5],
+}
+
+impl KWeighting {
+    fn new(rate: f64) -> Self {
+        use std::f64::consts::PI;
+
+        // The high shelf, modelling the acoustic effect of the head.
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10.0_f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let pb = [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ];
+        let pa = [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0];
+
+        // The high-pass, the RLB weighting curve.
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let rb = [1.0, -2.0, 1.0];
+        let ra = [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0];
+
+        // The two stages combined into one fourth-order filter.
+        KWeighting {
+            b: [
+                pb[0] * rb[0],
+                pb[0] * rb[1] + pb[1] * rb[0],
+                pb[0] * rb[2] + pb[1] * rb[1] + pb[2] * rb[0],
+                pb[1] * rb[2] + pb[2] * rb[1],
+                pb[2] * rb[2],
+            ],
+            a: [
+                1.0,
+                pa[1] + ra[1],
+                pa[2] + pa[1] * ra[1] + ra[2],
+                pa[1] * ra[2] + pa[2] * ra[1],
+                pa[2] * ra[2],
+            ],
+            state: [0.0; 5],
+        }
+    }
+
+    /// Filters one sample (direct form II).
+    fn process(&mut self, sample: f64) -> f64 {
+        let (b, a, v) = (&self.b, &self.a, &mut self.state);
+        v[0] = sample - a[1] * v[1] - a[2] * v[2] - a[3] * v[3] - a[4] * v[4];
+        let output = b[0] * v[0] + b[1] * v[1] + b[2] * v[2] + b[3] * v[3] + b[4] * v[4];
+        v.copy_within(0..4, 1);
+        output
+    }
+}
+
+/// An in-place iterative radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
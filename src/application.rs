@@ -0,0 +1,128 @@
+use std::str::FromStr;
+
+use metaflac::{
+    block::{Application, BlockType},
+    Block,
+};
+
+use crate::{encoding, Error, Result};
+
+/// A registered four-byte APPLICATION block id, given either as four ASCII
+/// characters (`CUET`) or eight hex digits (`43554554`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ApplicationId(pub(crate) [u8; 4]);
+
+impl FromStr for ApplicationId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(bytes) = <[u8; 4]>::try_from(s.as_bytes()) {
+            return Ok(ApplicationId(bytes));
+        }
+
+        hex::decode(s)
+            .ok()
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+            .map(ApplicationId)
+            .ok_or_else(|| Error::InvalidApplicationId(s.into()))
+    }
+}
+
+impl ApplicationId {
+    /// The id as text when it is printable ASCII, otherwise as hex.
+    pub(crate) fn display(id: &[u8]) -> String {
+        if id.iter().all(|b| b.is_ascii_graphic()) {
+            String::from_utf8_lossy(id).into_owned()
+        } else {
+            hex::encode(id)
+        }
+    }
+}
+
+/// How application data is represented outside of the file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum DataFormat {
+    #[default]
+    Hex,
+    Base64,
+    /// raw bytes; only valid when reading from or writing to a file
+    Raw,
+}
+
+impl DataFormat {
+    pub(crate) fn encode(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            DataFormat::Hex => hex::encode(data).into_bytes(),
+            DataFormat::Base64 => encoding::base64_encode(data).into_bytes(),
+            DataFormat::Raw => data.to_vec(),
+        }
+    }
+
+    pub(crate) fn decode(self, data: &[u8]) -> Result<Vec<u8>> {
+        let text = || String::from_utf8_lossy(data).trim().to_string();
+        match self {
+            DataFormat::Hex => hex::decode(text()).map_err(|_| Error::InvalidData("hex")),
+            DataFormat::Base64 => {
+                encoding::base64_decode(&text()).ok_or(Error::InvalidData("base64"))
+            }
+            DataFormat::Raw => Ok(data.to_vec()),
+        }
+    }
+}
+
+pub(crate) fn blocks(tag: &metaflac::Tag) -> impl Iterator<Item = &Application> {
+    tag.get_blocks(BlockType::Application)
+        .filter_map(|block| match block {
+            Block::Application(application) => Some(application),
+            _ => None,
+        })
+}
+
+/// Replaces every APPLICATION block with the given id by a single block
+/// holding `data`, or removes them when `data` is `None`.
+pub(crate) fn replace(tag: &mut metaflac::Tag, id: &ApplicationId, data: Option<Vec<u8>>) {
+    let retained: Vec<Application> = blocks(tag)
+        .filter(|application| application.id != id.0)
+        .cloned()
+        .collect();
+
+    tag.remove_blocks(BlockType::Application);
+    for application in retained {
+        tag.push_block(Block::Application(application));
+    }
+
+    if let Some(data) = data {
+        tag.push_block(Block::Application(Application {
+            id: id.0.to_vec(),
+            data,
+        }));
+    }
+}
+
+/// The data of every APPLICATION block with the given id, hex encoded, in
+/// order, as the audit log records them.
+pub(crate) fn fields(tag: &metaflac::Tag, id: &[u8]) -> Vec<String> {
+    blocks(tag)
+        .filter(|application| application.id == id)
+        .map(|application| hex::encode(&application.data))
+        .collect()
+}
+
+/// Replaces the APPLICATION blocks with the given id by those of
+/// [`fields`].
+pub(crate) fn set_fields(tag: &mut metaflac::Tag, id: &[u8], fields: &[String]) -> Result<()> {
+    let data = fields
+        .iter()
+        .map(|field| hex::decode(field).map_err(|_| Error::InvalidData("hex")))
+        .collect::<Result<Vec<_>>>()?;
+
+    let id = ApplicationId(<[u8; 4]>::try_from(id).map_err(|_| Error::InvalidData("hex"))?);
+    replace(tag, &id, None);
+    for data in data {
+        tag.push_block(Block::Application(Application {
+            id: id.0.to_vec(),
+            data,
+        }));
+    }
+    Ok(())
+}
@@ -0,0 +1,389 @@
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    ffi::OsStr,
+    fs::{self, DirBuilder, File},
+    hash::{BuildHasher, Hasher},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use crate::{Error, Result};
+
+/// Whether a path names an archive flacdat can read: `.zip`, `.tar`,
+/// `.tar.gz`, or `.tgz`.
+pub(crate) fn is_archive(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    [".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+/// An archive's file name without its archive extension: `Album` for
+/// `Album.tar.gz`.
+pub(crate) fn stem(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let lower = name.to_ascii_lowercase();
+    [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find(|suffix| lower.ends_with(*suffix))
+        .map_or(name.to_string(), |suffix| {
+            name[..name.len() - suffix.len()].to_string()
+        })
+}
+
+/// The files of an archive, extracted to a temporary directory which is
+/// removed when this is dropped.
+pub(crate) struct Extracted {
+    dir: PathBuf,
+
+    /// Each entry's name within the archive, with `/` separators, and where
+    /// it was extracted to.
+    pub(crate) entries: Vec<(String, PathBuf)>,
+}
+
+impl Drop for Extracted {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            eprintln!("unable to remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+/// Extracts the entries of an archive with one of the given extensions,
+/// skipping everything else. Entries come out in archive order.
+pub(crate) fn extract(path: &Path, extensions: &[&str]) -> Result<Extracted> {
+    let mut extracted = Extracted {
+        dir: temporary_dir()?,
+        entries: Vec::new(),
+    };
+    let wanted = |name: &str| {
+        Path::new(name)
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| {
+                extensions
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(extension))
+            })
+    };
+    let mut save = |name: String, reader: &mut dyn Read| -> Result<()> {
+        let target = extracted.dir.join(safe_path(path, &name)?);
+        // Such as `a.flac` and `./a.flac`, which the second would overwrite.
+        if let Some((other, _)) = extracted.entries.iter().find(|(_, to)| *to == target) {
+            return Err(Error::Archive(format!(
+                "{}: entries {other} and {name} are the same file",
+                path.display()
+            )));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(reader, &mut File::create(&target)?)?;
+        extracted.entries.push((name, target));
+        Ok(())
+    };
+
+    let lower = path.to_string_lossy().to_ascii_lowercase();
+    let file = BufReader::new(File::open(path)?);
+    if lower.ends_with(".zip") {
+        read_zip(path, file, &wanted, &mut save)?;
+    } else if lower.ends_with(".tar") {
+        read_tar(path, file, &wanted, &mut save)?;
+    } else {
+        read_tar(path, GzDecoder::new(file), &wanted, &mut save)?;
+    }
+
+    Ok(extracted)
+}
+
+/// Makes a new directory to extract to in the system's temporary directory,
+/// readable by the user alone on Unix. Its name is random, so that no one
+/// can make it first, or guess it, and taken names are passed over.
+fn temporary_dir() -> io::Result<PathBuf> {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    loop {
+        // Each RandomState is keyed afresh from a random seed.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(process::id());
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        let dir = env::temp_dir().join(format!("flacdat-{:016x}", hasher.finish()));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// An entry name as a relative path without `.` components, refusing names
+/// which would escape the extraction directory.
+fn safe_path(archive: &Path, name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect())
+    } else {
+        Err(Error::Archive(format!(
+            "{}: entry {name} points outside the archive",
+            archive.display()
+        )))
+    }
+}
+
+fn read_zip(
+    path: &Path,
+    mut reader: impl Read + Seek,
+    wanted: &dyn Fn(&str) -> bool,
+    save: &mut dyn FnMut(String, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let invalid = |what: &str| Error::Archive(format!("{}: {what}", path.display()));
+    let u16_le = |b: &[u8], at: usize| u16::from_le_bytes([b[at], b[at + 1]]);
+    let u32_le = |b: &[u8], at: usize| u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]);
+
+    // The end of central directory record sits at the end of the file,
+    // followed only by a comment of up to 64 KiB.
+    let length = reader.seek(SeekFrom::End(0))?;
+    let tail_len = length.min(22 + 0xffff);
+    reader.seek(SeekFrom::Start(length - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    reader.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&at| tail[at..at + 4] == [0x50, 0x4b, 0x05, 0x06])
+        .ok_or_else(|| invalid("not a zip file"))?;
+    let count = u16_le(&tail, end + 10);
+    let directory_offset = u32_le(&tail, end + 16);
+    if count == 0xffff || directory_offset == 0xffff_ffff {
+        return Err(invalid("zip64 archives are not supported"));
+    }
+
+    reader.seek(SeekFrom::Start(directory_offset.into()))?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let mut header = [0; 46];
+        reader.read_exact(&mut header)?;
+        if header[..4] != [0x50, 0x4b, 0x01, 0x02] {
+            return Err(invalid("corrupt central directory"));
+        }
+        let flags = u16_le(&header, 8);
+        let method = u16_le(&header, 10);
+        let compressed = u32_le(&header, 20);
+        let name_len = u16_le(&header, 28) as usize;
+        let skip = u16_le(&header, 30) as i64 + u16_le(&header, 32) as i64;
+        let offset = u32_le(&header, 42);
+
+        let mut name = vec![0; name_len];
+        reader.read_exact(&mut name)?;
+        reader.seek(SeekFrom::Current(skip))?;
+
+        // Without the UTF-8 flag, names are nominally code page 437, but
+        // archivers in practice write whatever the system used.
+        let name = String::from_utf8_lossy(&name).into_owned();
+        if flags & 0x1 != 0 && wanted(&name) {
+            return Err(invalid(&format!("{name} is encrypted")));
+        }
+        if wanted(&name) {
+            entries.push((name, method, compressed, offset));
+        }
+    }
+
+    for (name, method, compressed, offset) in entries {
+        let mut header = [0; 30];
+        reader.seek(SeekFrom::Start(offset.into()))?;
+        reader.read_exact(&mut header)?;
+        let skip = u16_le(&header, 26) as i64 + u16_le(&header, 28) as i64;
+        reader.seek(SeekFrom::Current(skip))?;
+
+        let mut data = (&mut reader).take(compressed.into());
+        match method {
+            0 => save(name, &mut data)?,
+            8 => save(name, &mut DeflateDecoder::new(data))?,
+            method => {
+                return Err(invalid(&format!(
+                    "{name} uses unsupported compression method {method}"
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_tar(
+    path: &Path,
+    mut reader: impl Read,
+    wanted: &dyn Fn(&str) -> bool,
+    save: &mut dyn FnMut(String, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let invalid = |what: &str| Error::Archive(format!("{}: {what}", path.display()));
+    let text = |field: &[u8]| {
+        let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..end]).into_owned()
+    };
+
+    // A GNU long name or pax path applies to the entry which follows it.
+    let mut long_name: Option<String> = None;
+    loop {
+        let mut header = [0; 512];
+        reader.read_exact(&mut header)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+
+        let size = u64::from_str_radix(text(&header[124..136]).trim(), 8)
+            .map_err(|_| invalid("corrupt tar header"))?;
+        let padded = size.div_ceil(512) * 512;
+        let mut name = text(&header[..100]);
+        if &header[257..262] == b"ustar" {
+            let prefix = text(&header[345..500]);
+            if !prefix.is_empty() {
+                name = format!("{prefix}/{name}");
+            }
+        }
+
+        let mut body = (&mut reader).take(padded);
+        match header[156] {
+            b'L' | b'x' => {
+                let mut data = Vec::new();
+                body.read_to_end(&mut data)?;
+                data.truncate(size as usize);
+                long_name = match header[156] {
+                    b'L' => Some(text(&data)),
+                    _ => pax_path(&data).or(long_name),
+                };
+                continue;
+            }
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or(name);
+                if wanted(&name) {
+                    save(name, &mut (&mut body).take(size))?;
+                }
+            }
+            _ => long_name = None,
+        }
+        io::copy(&mut body, &mut io::sink())?;
+    }
+}
+
+/// The `path` record of a pax extended header.
+fn pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines().find_map(|line| {
+        let (_, record) = line.split_once(' ')?;
+        record.strip_prefix("path=").map(String::from)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tar entry's header and padded body.
+    fn entry(name: &str, prefix: &str, kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut header = [0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        let mut entry = header.to_vec();
+        entry.extend_from_slice(data);
+        entry.resize(512 + data.len().div_ceil(512) * 512, 0);
+        entry
+    }
+
+    /// Extracts the FLACs of a tar archive of the given entries.
+    fn extract_tar(test: &str, entries: &[Vec<u8>]) -> Result<Extracted> {
+        let path = env::temp_dir().join(format!("flacdat-{}-{test}.tar", process::id()));
+        let mut tar = entries.concat();
+        tar.resize(tar.len() + 1024, 0);
+        fs::write(&path, tar)?;
+        let extracted = extract(&path, &["flac"]);
+        fs::remove_file(&path)?;
+        extracted
+    }
+
+    #[test]
+    fn entries_outside_the_archive_are_refused() {
+        for name in ["../a.flac", "music/../../a.flac", "/tmp/a.flac"] {
+            let Err(e) = extract_tar("outside", &[entry(name, "", b'0', b"flac")]) else {
+                panic!("{name} was extracted");
+            };
+            assert!(e.to_string().contains("points outside the archive"), "{e}");
+        }
+    }
+
+    #[test]
+    fn entries_naming_the_same_file_are_refused() {
+        let Err(e) = extract_tar(
+            "same",
+            &[
+                entry("a.flac", "", b'0', b"one"),
+                entry("./a.flac", "", b'0', b"two"),
+            ],
+        ) else {
+            panic!("both were extracted");
+        };
+        assert!(e.to_string().contains("entries a.flac and ./a.flac"), "{e}");
+    }
+
+    #[test]
+    fn ustar_prefixes_lead_entry_names() {
+        let extracted = extract_tar(
+            "prefix",
+            &[
+                entry("01.flac", "Prince/Purple Rain", b'0', b"flac"),
+                entry("cover.jpg", "Prince/Purple Rain", b'0', b"jpeg"),
+            ],
+        )
+        .unwrap();
+        let [(name, path)] = &extracted.entries[..] else {
+            panic!("{:?}", extracted.entries);
+        };
+        assert_eq!(name, "Prince/Purple Rain/01.flac");
+        assert!(path.ends_with("Prince/Purple Rain/01.flac"));
+        assert_eq!(fs::read(path).unwrap(), b"flac");
+    }
+
+    #[test]
+    fn pax_paths_name_the_next_entry() {
+        let long = format!("{}/01.flac", "a".repeat(150));
+        let record = format!(" path={long}\n");
+        let record = format!("{}{record}", record.len() + 4);
+        let extracted = extract_tar(
+            "pax",
+            &[
+                entry("PaxHeader", "", b'x', record.as_bytes()),
+                entry("truncated.flac", "", b'0', b"flac"),
+                entry("next.flac", "", b'0', b"next"),
+            ],
+        )
+        .unwrap();
+        let names: Vec<_> = extracted.entries.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, [&long, "next.flac"]);
+        assert_eq!(fs::read(&extracted.entries[0].1).unwrap(), b"flac");
+    }
+}
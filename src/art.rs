@@ -0,0 +1,542 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use id3::{frame::PictureType, TagLike};
+
+use crate::{
+    audit::AuditLog, dj, lock::FileLock, preflight, tools::Tool, verify, Attributes, Error, Result,
+};
+
+/// What `art dedupe --fix` does with a cover shared by every track of an
+/// album.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum DedupePolicy {
+    /// Keep the picture on the first track only.
+    #[default]
+    FirstTrack,
+    /// Move the picture to a folder.jpg (or .png) beside the tracks.
+    Folder,
+}
+
+impl FromStr for DedupePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "first-track" | "first" => Ok(DedupePolicy::FirstTrack),
+            "folder" => Ok(DedupePolicy::Folder),
+            _ => Err(format!("expected first-track or folder; found {s}")),
+        }
+    }
+}
+
+/// An embedded picture, read from either a FLAC PICTURE block or an ID3 APIC
+/// frame. Picture types use the ID3 numbering, which FLAC shares.
+#[derive(Clone, Debug)]
+pub(crate) struct Picture {
+    pub(crate) kind: PictureType,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Picture {
+    fn new(kind: PictureType, data: Vec<u8>) -> Self {
+        let (width, height) = dimensions(&data).unwrap_or_default();
+        Picture {
+            kind,
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Reads the pictures embedded in a FLAC or MP3 file, in file order.
+pub(crate) fn read(path: &Path) -> Result<Vec<Picture>> {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("flac") => {
+            let flac = metaflac::Tag::read_from_path(path)?;
+            Ok(flac
+                .pictures()
+                .map(|picture| {
+                    let mut converted =
+                        Picture::new(from_flac_type(picture.picture_type), picture.data.clone());
+                    if converted.width == 0 {
+                        (converted.width, converted.height) = (picture.width, picture.height);
+                    }
+                    converted
+                })
+                .collect())
+        }
+        Some("mp3") => {
+            let tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            Ok(tag
+                .pictures()
+                .map(|picture| Picture::new(picture.picture_type, picture.data.clone()))
+                .collect())
+        }
+        _ => Err(Error::UnsupportedFileTye(path.display().to_string())),
+    }
+}
+
+/// The picture best representing the album: the front cover if there is one,
+/// otherwise the first picture.
+pub(crate) fn primary(pictures: &[Picture]) -> Option<&Picture> {
+    pictures
+        .iter()
+        .find(|picture| picture.kind == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+}
+
+pub(crate) fn from_flac_type(kind: metaflac::block::PictureType) -> PictureType {
+    use metaflac::block::PictureType as Flac;
+    match kind {
+        Flac::Other => PictureType::Other,
+        Flac::Icon => PictureType::Icon,
+        Flac::OtherIcon => PictureType::OtherIcon,
+        Flac::CoverFront => PictureType::CoverFront,
+        Flac::CoverBack => PictureType::CoverBack,
+        Flac::Leaflet => PictureType::Leaflet,
+        Flac::Media => PictureType::Media,
+        Flac::LeadArtist => PictureType::LeadArtist,
+        Flac::Artist => PictureType::Artist,
+        Flac::Conductor => PictureType::Conductor,
+        Flac::Band => PictureType::Band,
+        Flac::Composer => PictureType::Composer,
+        Flac::Lyricist => PictureType::Lyricist,
+        Flac::RecordingLocation => PictureType::RecordingLocation,
+        Flac::DuringRecording => PictureType::DuringRecording,
+        Flac::DuringPerformance => PictureType::DuringPerformance,
+        Flac::ScreenCapture => PictureType::ScreenCapture,
+        Flac::BrightFish => PictureType::BrightFish,
+        Flac::Illustration => PictureType::Illustration,
+        Flac::BandLogo => PictureType::BandLogo,
+        Flac::PublisherLogo => PictureType::PublisherLogo,
+    }
+}
+
+pub(crate) fn to_flac_type(kind: PictureType) -> metaflac::block::PictureType {
+    use metaflac::block::PictureType as Flac;
+    match kind {
+        PictureType::Other | PictureType::Undefined(_) => Flac::Other,
+        PictureType::Icon => Flac::Icon,
+        PictureType::OtherIcon => Flac::OtherIcon,
+        PictureType::CoverFront => Flac::CoverFront,
+        PictureType::CoverBack => Flac::CoverBack,
+        PictureType::Leaflet => Flac::Leaflet,
+        PictureType::Media => Flac::Media,
+        PictureType::LeadArtist => Flac::LeadArtist,
+        PictureType::Artist => Flac::Artist,
+        PictureType::Conductor => Flac::Conductor,
+        PictureType::Band => Flac::Band,
+        PictureType::Composer => Flac::Composer,
+        PictureType::Lyricist => Flac::Lyricist,
+        PictureType::RecordingLocation => Flac::RecordingLocation,
+        PictureType::DuringRecording => Flac::DuringRecording,
+        PictureType::DuringPerformance => Flac::DuringPerformance,
+        PictureType::ScreenCapture => Flac::ScreenCapture,
+        PictureType::BrightFish => Flac::BrightFish,
+        PictureType::Illustration => Flac::Illustration,
+        PictureType::BandLogo => Flac::BandLogo,
+        PictureType::PublisherLogo => Flac::PublisherLogo,
+    }
+}
+
+/// Reads the pixel dimensions from a PNG, JPEG, or GIF header.
+pub(crate) fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| {
+        data.get(at..at + 2)
+            .map(|b| u32::from(u16::from_be_bytes([b[0], b[1]])))
+    };
+    let be32 = |at: usize| {
+        data.get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be32(16)?, be32(20)?));
+    }
+
+    if data.starts_with(b"GIF8") {
+        let le16 = |at: usize| {
+            data.get(at..at + 2)
+                .map(|b| u32::from(u16::from_le_bytes([b[0], b[1]])))
+        };
+        return Some((le16(6)?, le16(8)?));
+    }
+
+    if data.starts_with(&[0xff, 0xd8]) {
+        // Walk the marker segments until a start-of-frame marker, which holds
+        // the dimensions.
+        let mut at = 2;
+        while at + 4 <= data.len() {
+            if data[at] != 0xff {
+                return None;
+            }
+            let marker = data[at + 1];
+            let length = be16(at + 2)? as usize;
+            let is_sof = matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+            if is_sof {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            at += 2 + length;
+        }
+    }
+
+    None
+}
+
+/// A picture embedded, byte for byte, in every track of an album.
+pub(crate) struct Duplicate {
+    pub(crate) dir: PathBuf,
+    pub(crate) album: String,
+    /// The album's tracks, in track order.
+    pub(crate) paths: Vec<String>,
+    pub(crate) picture: Picture,
+}
+
+impl Duplicate {
+    /// Bytes saved by keeping a single copy.
+    pub(crate) fn reclaimable(&self) -> usize {
+        self.picture.data.len() * (self.paths.len() - 1)
+    }
+}
+
+/// Groups files into albums by directory and album tag, and finds the
+/// pictures every track of each album shares.
+pub(crate) fn duplicates(paths: &[String]) -> Result<Vec<Duplicate>> {
+    // (directory, album) -> [(track, path, pictures)]
+    type Track = (Option<u32>, String, Vec<Picture>);
+    let mut albums: HashMap<(PathBuf, String), Vec<Track>> = HashMap::new();
+    for path in paths {
+        let attributes = Attributes::from_path(path)?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("")).to_owned();
+        albums
+            .entry((dir, attributes.album.unwrap_or_default()))
+            .or_default()
+            .push((attributes.track, path.clone(), read(Path::new(path))?));
+    }
+
+    let mut duplicates = Vec::new();
+    for ((dir, album), mut tracks) in albums {
+        if tracks.len() < 2 {
+            continue;
+        }
+        tracks.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+        let (first, rest) = tracks.split_first().expect("at least two tracks");
+        for picture in &first.2 {
+            let shared = rest
+                .iter()
+                .all(|track| track.2.iter().any(|other| other.data == picture.data));
+            if shared {
+                duplicates.push(Duplicate {
+                    dir: dir.clone(),
+                    album: album.clone(),
+                    paths: tracks.iter().map(|track| track.1.clone()).collect(),
+                    picture: picture.clone(),
+                });
+            }
+        }
+    }
+
+    duplicates.sort_by(|a, b| (&a.dir, &a.album).cmp(&(&b.dir, &b.album)));
+    Ok(duplicates)
+}
+
+/// Removes every embedded picture whose data matches `data`, recording the
+/// change in `log`. Returns whether the file changed.
+pub(crate) fn strip(
+    path: &str,
+    data: &[u8],
+    preserve_dj_data: bool,
+    log: &mut AuditLog,
+) -> Result<bool> {
+    let _lock = FileLock::acquire(path)?;
+
+    match Path::new(path).extension().and_then(OsStr::to_str) {
+        Some("flac") => {
+            let mut flac = metaflac::Tag::read_from_path(path)?;
+            let pictures: Vec<_> = flac.pictures().cloned().collect();
+            if !pictures.iter().any(|picture| picture.data == data) {
+                return Ok(false);
+            }
+
+            let before = flac_picture_fields(&flac);
+
+            flac.remove_blocks(metaflac::BlockType::Picture);
+            for picture in pictures.into_iter().filter(|picture| picture.data != data) {
+                flac.push_block(metaflac::Block::Picture(picture));
+            }
+
+            let _writable = preflight::Writable::new(path)?;
+            verify::write_flac(&mut flac, Path::new(path))?;
+            log.pictures(path, &before, &flac_picture_fields(&flac))?;
+        }
+        Some("mp3") => {
+            let mut tag = id3::Tag::read_from_path(path)?;
+            let pictures: Vec<_> = tag.pictures().cloned().collect();
+            if !pictures.iter().any(|picture| picture.data == data) {
+                return Ok(false);
+            }
+            let before = tag.clone();
+
+            tag.remove_all_pictures();
+            for picture in pictures.into_iter().filter(|picture| picture.data != data) {
+                tag.add_frame(picture);
+            }
+            if preserve_dj_data {
+                dj::verify(Path::new(path), &before, &tag)?;
+            }
+
+            let _writable = preflight::Writable::new(path)?;
+            verify::write_id3(&tag, Path::new(path))?;
+            log.id3(path, &before, &tag)?;
+        }
+        _ => return Err(Error::UnsupportedFileTye(path.into())),
+    }
+
+    Ok(true)
+}
+
+/// Embeds `data` as the front cover, replacing any front cover already
+/// there, recording the change in `log`. Returns whether the file changed.
+pub(crate) fn embed(
+    path: &str,
+    data: &[u8],
+    preserve_dj_data: bool,
+    log: &mut AuditLog,
+) -> Result<bool> {
+    let _lock = FileLock::acquire(path)?;
+    let front = |picture_type| picture_type == PictureType::CoverFront;
+
+    match Path::new(path).extension().and_then(OsStr::to_str) {
+        Some("flac") => {
+            let mut flac = metaflac::Tag::read_from_path(path)?;
+            let covers: Vec<_> = flac
+                .pictures()
+                .filter(|picture| front(from_flac_type(picture.picture_type)))
+                .collect();
+            if let [cover] = covers[..] {
+                if cover.data == data {
+                    return Ok(false);
+                }
+            }
+
+            let before = flac_picture_fields(&flac);
+
+            flac.remove_picture_type(metaflac::block::PictureType::CoverFront);
+            let mut picture = metaflac::block::Picture::new();
+            picture.picture_type = metaflac::block::PictureType::CoverFront;
+            picture.mime_type = mime_type(data).into();
+            (picture.width, picture.height) = dimensions(data).unwrap_or_default();
+            picture.data = data.to_vec();
+            flac.push_block(metaflac::Block::Picture(picture));
+
+            let _writable = preflight::Writable::new(path)?;
+            verify::write_flac(&mut flac, Path::new(path))?;
+            log.pictures(path, &before, &flac_picture_fields(&flac))?;
+        }
+        Some("mp3") => {
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(e.into()),
+            };
+            let covers: Vec<_> = tag
+                .pictures()
+                .filter(|picture| front(picture.picture_type))
+                .collect();
+            if let [cover] = covers[..] {
+                if cover.data == data {
+                    return Ok(false);
+                }
+            }
+            let before = tag.clone();
+
+            tag.remove_picture_by_type(PictureType::CoverFront);
+            tag.add_frame(id3::frame::Picture {
+                mime_type: mime_type(data).into(),
+                picture_type: PictureType::CoverFront,
+                description: String::new(),
+                data: data.to_vec(),
+            });
+            if preserve_dj_data {
+                dj::verify(Path::new(path), &before, &tag)?;
+            }
+
+            let _writable = preflight::Writable::new(path)?;
+            verify::write_id3(&tag, Path::new(path))?;
+            log.id3(path, &before, &tag)?;
+        }
+        _ => return Err(Error::UnsupportedFileTye(path.into())),
+    }
+
+    Ok(true)
+}
+
+/// A FLAC's PICTURE blocks, each hex encoded, in order, as the audit log
+/// records them.
+pub(crate) fn flac_picture_fields(flac: &metaflac::Tag) -> Vec<String> {
+    flac.pictures()
+        .map(|picture| hex::encode(picture.to_bytes()))
+        .collect()
+}
+
+/// Replaces a FLAC's PICTURE blocks with those of [`flac_picture_fields`].
+pub(crate) fn set_flac_pictures(flac: &mut metaflac::Tag, fields: &[String]) -> Result<()> {
+    let malformed = || {
+        metaflac::Error::new(
+            metaflac::ErrorKind::InvalidInput,
+            "malformed picture in audit log",
+        )
+    };
+    let pictures = fields
+        .iter()
+        .map(|field| {
+            let bytes = hex::decode(field).map_err(|_| malformed())?;
+            metaflac::block::Picture::from_bytes(&bytes)
+        })
+        .collect::<metaflac::Result<Vec<_>>>()?;
+
+    flac.remove_blocks(metaflac::BlockType::Picture);
+    for picture in pictures {
+        flac.push_block(metaflac::Block::Picture(picture));
+    }
+    Ok(())
+}
+
+/// Names of cover images kept beside the tracks, in order of preference.
+/// Matched without regard to case.
+const COVER_NAMES: &[&str] = &[
+    "folder.jpg",
+    "folder.jpeg",
+    "folder.png",
+    "cover.jpg",
+    "cover.jpeg",
+    "cover.png",
+];
+
+/// The cover image in a directory, if it has one.
+pub(crate) fn find_cover(dir: &Path) -> Result<Option<PathBuf>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if let Some(rank) = COVER_NAMES.iter().position(|cover| *cover == name) {
+            found.push((rank, path));
+        }
+    }
+    Ok(found.into_iter().min().map(|(_, path)| path))
+}
+
+/// Scales an image down so its longest edge is at most `max_size` pixels,
+/// keeping its format. Images already small enough come back unchanged.
+pub(crate) fn shrink(data: Vec<u8>, max_size: u32) -> Result<Vec<u8>> {
+    match dimensions(&data) {
+        Some((width, height)) if width.max(height) > max_size => {}
+        _ => return Ok(data),
+    }
+    let extension = extension(&data);
+    reencode(
+        &data,
+        extension,
+        Some(format!(
+            "scale={max_size}:{max_size}:force_original_aspect_ratio=decrease"
+        )),
+    )
+}
+
+/// An image as a JPEG, converting it if it's in another format.
+pub(crate) fn jpeg(data: Vec<u8>) -> Result<Vec<u8>> {
+    match extension(&data) {
+        "jpg" => Ok(data),
+        _ => reencode(&data, "jpg", None),
+    }
+}
+
+/// Runs an image through ffmpeg, with a video filter if given, writing it out
+/// in the format of `extension`.
+fn reencode(data: &[u8], extension: &str, filter: Option<String>) -> Result<Vec<u8>> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let stem = env::temp_dir().join(format!(
+        "flacdat-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let source = stem.with_extension(format!("source.{}", self::extension(data)));
+    let output = stem.with_extension(extension);
+    fs::write(&source, data)?;
+
+    let mut command = Tool::Ffmpeg.command();
+    command
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(&source);
+    if let Some(filter) = filter {
+        command.arg("-vf").arg(filter);
+    }
+    let status = command.arg(&output).status();
+    let _ = fs::remove_file(&source);
+
+    if !status?.success() {
+        let _ = fs::remove_file(&output);
+        return Err(Error::FfmpegFailed(source.display().to_string()));
+    }
+    let data = fs::read(&output)?;
+    fs::remove_file(&output)?;
+    Ok(data)
+}
+
+/// The MIME type matching an image's format.
+pub(crate) fn mime_type(data: &[u8]) -> &'static str {
+    match extension(data) {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// The file extension matching an image's format.
+pub(crate) fn extension(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "png"
+    } else if data.starts_with(b"GIF8") {
+        "gif"
+    } else {
+        "jpg"
+    }
+}
+
+/// A filesystem-safe name for an album, used to key cached images:
+/// lowercase alphanumerics separated by single dashes.
+pub(crate) fn album_key(artist: &str, album: &str) -> String {
+    let mut key = String::new();
+    for c in format!("{artist} {album}")
+        .chars()
+        .flat_map(char::to_lowercase)
+    {
+        if c.is_alphanumeric() {
+            key.push(c);
+        } else if !key.is_empty() && !key.ends_with('-') {
+            key.push('-');
+        }
+    }
+    key.trim_end_matches('-').to_string()
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    warning::{self, Code},
+    Attributes,
+};
+
+/// Looks over the albums about to be filed by album artist, so that none is
+/// scattered by surprise. Tracks with no album artist borrow the one the rest
+/// of their album agrees on rather than falling back to their own artist;
+/// their tags are left as they are. Albums which will be split anyway, and
+/// tracks crediting someone other than their album artist, are reported.
+///
+/// An album is the tracks in one directory with the same album tag.
+pub(crate) fn settle(files: &mut [(PathBuf, Attributes)]) {
+    let mut albums: BTreeMap<(PathBuf, String), Vec<usize>> = BTreeMap::new();
+    for (idx, (path, attributes)) in files.iter().enumerate() {
+        if let Some(album) = &attributes.album {
+            let dir = path.parent().unwrap_or(Path::new("")).to_owned();
+            albums.entry((dir, album.clone())).or_default().push(idx);
+        }
+    }
+
+    for ((dir, album), tracks) in albums {
+        let name = format!("{album:?} in {}", dir.display());
+        let distinct = |value: fn(&Attributes) -> Option<String>| {
+            let mut values: Vec<String> = tracks
+                .iter()
+                .filter_map(|&idx| value(&files[idx].1))
+                .collect();
+            values.sort();
+            values.dedup();
+            values
+        };
+        let album_artists = distinct(|attributes| attributes.album_artist.clone());
+
+        match &album_artists[..] {
+            [] => {
+                let artists = distinct(credit);
+                if artists.len() > 1 {
+                    warning::emit(
+                        Code::MixedArtists,
+                        format_args!(
+                            "{name}: no album artist, and its tracks credit {}; it will be split \
+                             between their directories unless an album artist is set",
+                            artists.join("; ")
+                        ),
+                    );
+                }
+            }
+            [album_artist] => {
+                let mut borrowed = 0;
+                let mut others = Vec::new();
+                for &idx in &tracks {
+                    let (path, attributes) = &mut files[idx];
+                    if attributes.album_artist.is_none() {
+                        attributes.album_artist = Some(album_artist.clone());
+                        borrowed += 1;
+                    }
+                    if let Some(artist) = credit(attributes).filter(|artist| artist != album_artist)
+                    {
+                        let file = path.file_name().unwrap_or_default().to_string_lossy();
+                        others.push(format!("{file} ({artist})"));
+                    }
+                }
+                if borrowed > 0 {
+                    warning::emit(
+                        Code::MixedArtists,
+                        format_args!(
+                            "{name}: filing {borrowed} track(s) with no album artist under \
+                             {album_artist:?}, with the rest of the album"
+                        ),
+                    );
+                }
+                if !others.is_empty() {
+                    warning::emit(
+                        Code::MixedArtists,
+                        format_args!(
+                            "{name}: {} track(s) credit artists other than the album artist \
+                             {album_artist:?}: {}",
+                            others.len(),
+                            others.join(", ")
+                        ),
+                    );
+                }
+            }
+            album_artists => warning::emit(
+                Code::MixedArtists,
+                format_args!(
+                    "{name}: its tracks name {} album artists ({}); it will be split between them",
+                    album_artists.len(),
+                    album_artists.join("; ")
+                ),
+            ),
+        }
+    }
+}
+
+/// A track's artists, as a pattern would render them.
+fn credit(attributes: &Attributes) -> Option<String> {
+    (!attributes.artist.is_empty()).then(|| attributes.artist.join(", "))
+}
@@ -0,0 +1,428 @@
+use std::{ffi::OsStr, path::Path, str::FromStr};
+
+use id3::TagLike;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    digest, mp4, ogg, sheet,
+    tags::{first_vorbis, id3_extended_text, id3_text, parse_flag, year_of_date},
+    Error, Result,
+};
+
+/// The tags flacdat reads and writes, under the same names in FLAC and MP3
+/// files. These are the columns of an attribute sheet.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Attributes {
+    pub album: Option<String>,
+    pub artist: Vec<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+    pub year: Option<i32>,
+    pub language: Vec<String>,
+    pub work: Option<String>,
+    pub movement_name: Option<String>,
+    pub movement: Option<u32>,
+    pub movement_total: Option<u32>,
+    pub show_movement: Option<bool>,
+    pub grouping: Option<String>,
+    pub media: Option<String>,
+    pub release_country: Option<String>,
+    pub genre: Vec<String>,
+    pub album_artist: Option<String>,
+    pub disc: Option<u32>,
+    pub composer: Vec<String>,
+    pub comment: Option<String>,
+}
+
+impl Attributes {
+    /// Loads the attributes of a FLAC or MP3 file, or reads those of an Ogg
+    /// Vorbis, Opus, or M4A file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        static FLAC: &str = "flac";
+        static MP3: &str = "mp3";
+
+        let path = path.as_ref();
+
+        if path.extension() == Some(OsStr::new(FLAC)) {
+            return Self::from_flac_path(path);
+        }
+
+        if path.extension() == Some(OsStr::new(MP3)) {
+            return Self::from_mp3_path(path);
+        }
+
+        // Read only: list can inventory these, but nothing writes them.
+        match path.extension().and_then(OsStr::to_str) {
+            Some("ogg" | "oga" | "opus") => Ok(Self::from_vorbis(&ogg::read_comments(path)?)),
+            Some("m4a") => Ok(Self::from_vorbis(&mp4::read_comments(path)?)),
+            _ => Err(Error::UnsupportedFileTye(path.display().to_string())),
+        }
+    }
+
+    pub fn with_path(self, path: impl Into<String>) -> FileAttributes {
+        FileAttributes {
+            path: path.into(),
+            attributes: self,
+            fingerprint: None,
+            artist_missing: false,
+            fields: Vec::new(),
+        }
+    }
+
+    /// A short hash of every attribute, which `list` writes beside each row so
+    /// that `apply` can tell when a file's tags have changed since. Tags
+    /// rather than size and modification time, so that copying a library
+    /// doesn't make its sheets stale.
+    pub fn fingerprint(&self) -> String {
+        let mut text = String::new();
+        for &attribute in Attribute::ALL {
+            text.push_str(attribute.name());
+            for value in self.values(attribute) {
+                text.push('\x1f');
+                text.push_str(&value);
+            }
+            text.push('\x1e');
+        }
+        hex::encode(&digest::sha256(text.as_bytes())[..6])
+    }
+
+    fn from_flac_path(path: &Path) -> Result<Self> {
+        let mut flac = metaflac::Tag::read_from_path(path)?;
+        Ok(Self::from_vorbis(flac.vorbis_comments_mut()))
+    }
+
+    pub fn from_vorbis(comment: &metaflac::block::VorbisComment) -> Self {
+        Attributes {
+            album: comment
+                .album()
+                .into_iter()
+                .flatten()
+                .next()
+                .map(|s| s.into()),
+            artist: comment.artist().cloned().unwrap_or_default(),
+            title: comment
+                .title()
+                .into_iter()
+                .flatten()
+                .next()
+                .map(|s| s.into()),
+            track: comment.track().into_iter().next(),
+
+            // DATE is the standard field, but YEAR is still common:
+            // https://www.reddit.com/r/musichoarder/comments/p20pzi/how_do_you_store_date_tags_in_flacvorbis_comment/
+            year: ["YEAR", "DATE", "ORIGINALDATE"]
+                .into_iter()
+                .find_map(|key| first_vorbis(comment, key))
+                .and_then(|s| year_of_date(s).trim().parse().ok()),
+            language: comment.get("LANGUAGE").cloned().unwrap_or_default(),
+            work: first_vorbis(comment, "WORK"),
+            movement_name: first_vorbis(comment, "MOVEMENTNAME"),
+            movement: first_vorbis(comment, "MOVEMENT").and_then(|s| s.parse().ok()),
+            movement_total: first_vorbis(comment, "MOVEMENTTOTAL").and_then(|s| s.parse().ok()),
+            show_movement: first_vorbis(comment, "SHOWMOVEMENT").and_then(|s| parse_flag(&s)),
+            grouping: first_vorbis(comment, "GROUPING"),
+            media: first_vorbis(comment, "MEDIA"),
+            release_country: first_vorbis(comment, "RELEASECOUNTRY"),
+            genre: comment.get("GENRE").cloned().unwrap_or_default(),
+            album_artist: first_vorbis(comment, "ALBUMARTIST")
+                .or_else(|| first_vorbis(comment, "ALBUM ARTIST")),
+            // Sometimes written with the total: "1/2"
+            disc: first_vorbis(comment, "DISCNUMBER")
+                .and_then(|s| s.split('/').next()?.trim().parse().ok()),
+            composer: comment.get("COMPOSER").cloned().unwrap_or_default(),
+            comment: first_vorbis(comment, "COMMENT")
+                .or_else(|| first_vorbis(comment, "DESCRIPTION")),
+        }
+    }
+
+    fn from_mp3_path(path: &Path) -> Result<Self> {
+        let tag = id3::Tag::read_from_path(path)?;
+        Ok(Self::from_id3(&tag))
+    }
+
+    pub fn from_id3(tag: &id3::Tag) -> Self {
+        // MVIN holds the movement number and, optionally, the total: "2/4"
+        let movement = id3_text(tag, "MVIN").pop().unwrap_or_default();
+        let (movement_number, movement_total) = match movement.split_once('/') {
+            Some((number, total)) => (Some(number), Some(total)),
+            None => (Some(movement.as_str()), None),
+        };
+
+        Attributes {
+            album: tag.album().map(|s| s.to_string()),
+            // ID3v2.4 separates multiple artists with NUL
+            artist: id3_text(tag, "TPE1"),
+            title: tag.title().map(|s| s.to_string()),
+            track: tag.track(),
+            year: tag.year(),
+
+            language: id3_text(tag, "TLAN"),
+            work: id3_extended_text(tag, "WORK"),
+            movement_name: id3_text(tag, "MVNM").pop(),
+            movement: movement_number.and_then(|n| n.parse().ok()),
+            movement_total: movement_total.and_then(|n| n.parse().ok()),
+            show_movement: id3_extended_text(tag, "SHOWMOVEMENT").and_then(|s| parse_flag(&s)),
+            // iTunes writes grouping to GRP1; everything else, and iTunes before 12.5, to TIT1
+            grouping: id3_text(tag, "GRP1")
+                .pop()
+                .or_else(|| id3_text(tag, "TIT1").pop()),
+            media: id3_text(tag, "TMED").pop(),
+            release_country: id3_extended_text(tag, "RELEASECOUNTRY")
+                .or_else(|| id3_extended_text(tag, "MusicBrainz Album Release Country")),
+            genre: id3_text(tag, "TCON"),
+            album_artist: tag.album_artist().map(|s| s.to_string()),
+            disc: tag.disc(),
+            composer: id3_text(tag, "TCOM"),
+            // The comment proper has no description; others belong to other software
+            comment: tag
+                .comments()
+                .find(|comment| comment.description.is_empty())
+                .map(|comment| comment.text.clone()),
+        }
+    }
+
+    /// The values held for a given attribute, formatted as text.
+    pub fn values(&self, attribute: Attribute) -> Vec<String> {
+        match attribute {
+            Attribute::Album => self.album.iter().cloned().collect(),
+            Attribute::Artist => self.artist.clone(),
+            Attribute::Title => self.title.iter().cloned().collect(),
+            Attribute::Track => self.track.iter().map(|n| n.to_string()).collect(),
+            Attribute::Year => self.year.iter().map(|n| n.to_string()).collect(),
+            Attribute::Language => self.language.clone(),
+            Attribute::Work => self.work.iter().cloned().collect(),
+            Attribute::MovementName => self.movement_name.iter().cloned().collect(),
+            Attribute::Movement => self.movement.iter().map(|n| n.to_string()).collect(),
+            Attribute::MovementTotal => self.movement_total.iter().map(|n| n.to_string()).collect(),
+            Attribute::ShowMovement => self
+                .show_movement
+                .iter()
+                .map(|&flag| if flag { "1" } else { "0" }.to_string())
+                .collect(),
+            Attribute::Grouping => self.grouping.iter().cloned().collect(),
+            Attribute::Media => self.media.iter().cloned().collect(),
+            Attribute::ReleaseCountry => self.release_country.iter().cloned().collect(),
+            Attribute::Genre => self.genre.clone(),
+            Attribute::AlbumArtist => self.album_artist.iter().cloned().collect(),
+            Attribute::Disc => self.disc.iter().map(|n| n.to_string()).collect(),
+            Attribute::Composer => self.composer.clone(),
+            Attribute::Comment => self.comment.iter().cloned().collect(),
+        }
+    }
+
+    /// Replaces the values held for an attribute. Empty values are dropped, so
+    /// setting an attribute to `[""]` clears it.
+    pub fn set_values(&mut self, attribute: Attribute, values: Vec<String>) -> Result<()> {
+        let mut values: Vec<String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+
+        fn parse<T: FromStr>(attribute: Attribute, value: Option<String>) -> Result<Option<T>> {
+            value
+                .map(|value| {
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| Error::InvalidValue { attribute, value })
+                })
+                .transpose()
+        }
+
+        match attribute {
+            Attribute::Album => self.album = values.pop(),
+            Attribute::Artist => self.artist = values,
+            Attribute::Title => self.title = values.pop(),
+            Attribute::Track => self.track = parse(attribute, values.pop())?,
+            Attribute::Year => self.year = parse(attribute, values.pop().map(year_of_date))?,
+            Attribute::Language => self.language = values,
+            Attribute::Work => self.work = values.pop(),
+            Attribute::MovementName => self.movement_name = values.pop(),
+            Attribute::Movement => self.movement = parse(attribute, values.pop())?,
+            Attribute::MovementTotal => self.movement_total = parse(attribute, values.pop())?,
+            Attribute::ShowMovement => {
+                self.show_movement = values
+                    .pop()
+                    .map(|value| parse_flag(&value).ok_or(Error::InvalidValue { attribute, value }))
+                    .transpose()?
+            }
+            Attribute::Grouping => self.grouping = values.pop(),
+            Attribute::Media => self.media = values.pop(),
+            Attribute::ReleaseCountry => self.release_country = values.pop(),
+            Attribute::Genre => self.genre = values,
+            Attribute::AlbumArtist => self.album_artist = values.pop(),
+            Attribute::Disc => self.disc = parse(attribute, values.pop())?,
+            Attribute::Composer => self.composer = values,
+            Attribute::Comment => self.comment = values.pop(),
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the values held for an attribute with those in text as a
+    /// sheet's cell holds them: separated by [`sheet::separator`], a comma
+    /// unless configured otherwise, for the attributes which take more than
+    /// one.
+    pub(crate) fn set_text(&mut self, attribute: Attribute, text: &str) -> Result<()> {
+        match attribute {
+            Attribute::Artist | Attribute::Language | Attribute::Genre | Attribute::Composer => {
+                self.set_values(attribute, sheet::split(text))
+            }
+            _ => self.set_values(attribute, vec![text.to_string()]),
+        }
+    }
+}
+
+/// One of the fields of [`Attributes`], by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    Album,
+    Artist,
+    Title,
+    Track,
+    Year,
+    Language,
+    Work,
+    MovementName,
+    Movement,
+    MovementTotal,
+    ShowMovement,
+    Grouping,
+    Media,
+    ReleaseCountry,
+    Genre,
+    AlbumArtist,
+    Disc,
+    Composer,
+    Comment,
+}
+
+impl Attribute {
+    pub const ALL: &'static [Attribute] = &[
+        Attribute::Album,
+        Attribute::Artist,
+        Attribute::Title,
+        Attribute::Track,
+        Attribute::Year,
+        Attribute::Language,
+        Attribute::Work,
+        Attribute::MovementName,
+        Attribute::Movement,
+        Attribute::MovementTotal,
+        Attribute::ShowMovement,
+        Attribute::Grouping,
+        Attribute::Media,
+        Attribute::ReleaseCountry,
+        Attribute::Genre,
+        Attribute::AlbumArtist,
+        Attribute::Disc,
+        Attribute::Composer,
+        Attribute::Comment,
+    ];
+
+    /// The attribute's name in sheets, conditions, and pipelines.
+    pub fn name(self) -> &'static str {
+        match self {
+            Attribute::Album => "album",
+            Attribute::Artist => "artist",
+            Attribute::Title => "title",
+            Attribute::Track => "track",
+            Attribute::Year => "year",
+            Attribute::Language => "language",
+            Attribute::Work => "work",
+            Attribute::MovementName => "movementname",
+            Attribute::Movement => "movement",
+            Attribute::MovementTotal => "movementtotal",
+            Attribute::ShowMovement => "showmovement",
+            Attribute::Grouping => "grouping",
+            Attribute::Media => "media",
+            Attribute::ReleaseCountry => "releasecountry",
+            Attribute::Genre => "genre",
+            Attribute::AlbumArtist => "albumartist",
+            Attribute::Disc => "disc",
+            Attribute::Composer => "composer",
+            Attribute::Comment => "comment",
+        }
+    }
+
+    /// A description of the values this attribute accepts, for error messages.
+    pub(crate) fn expected(self) -> &'static str {
+        match self {
+            Attribute::Album
+            | Attribute::Artist
+            | Attribute::Title
+            | Attribute::Work
+            | Attribute::MovementName
+            | Attribute::Grouping
+            | Attribute::Media
+            | Attribute::ReleaseCountry
+            | Attribute::Genre
+            | Attribute::AlbumArtist
+            | Attribute::Composer
+            | Attribute::Comment => "text",
+            Attribute::Track | Attribute::Movement | Attribute::MovementTotal | Attribute::Disc => {
+                "a positive whole number"
+            }
+            Attribute::ShowMovement => "1 or 0",
+            Attribute::Year => "a year such as 1984",
+            Attribute::Language => "a language code such as eng",
+        }
+    }
+
+    pub fn vorbis_key(self) -> &'static str {
+        match self {
+            Attribute::Album => "ALBUM",
+            Attribute::Artist => "ARTIST",
+            Attribute::Title => "TITLE",
+            Attribute::Track => "TRACKNUMBER",
+            Attribute::Year => "DATE",
+            Attribute::Language => "LANGUAGE",
+            Attribute::Work => "WORK",
+            Attribute::MovementName => "MOVEMENTNAME",
+            Attribute::Movement => "MOVEMENT",
+            Attribute::MovementTotal => "MOVEMENTTOTAL",
+            Attribute::ShowMovement => "SHOWMOVEMENT",
+            Attribute::Grouping => "GROUPING",
+            Attribute::Media => "MEDIA",
+            Attribute::ReleaseCountry => "RELEASECOUNTRY",
+            Attribute::Genre => "GENRE",
+            Attribute::AlbumArtist => "ALBUMARTIST",
+            Attribute::Disc => "DISCNUMBER",
+            Attribute::Composer => "COMPOSER",
+            Attribute::Comment => "COMMENT",
+        }
+    }
+}
+
+impl FromStr for Attribute {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let lower = s.to_ascii_lowercase();
+        if lower == "lang" {
+            return Ok(Attribute::Language);
+        }
+
+        Attribute::ALL
+            .iter()
+            .copied()
+            .find(|attribute| attribute.name() == lower)
+            .ok_or_else(|| Error::UnknownAttribute(s.into()))
+    }
+}
+
+/// A row of an attribute sheet: a file and the attributes it should have.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FileAttributes {
+    pub path: String,
+    #[serde(flatten)]
+    pub attributes: Attributes,
+    /// The fingerprint of the file's tags when the sheet was listed
+    #[serde(skip)]
+    pub(crate) fingerprint: Option<String>,
+    /// Whether the sheet has no artist column, so that the file's artist is
+    /// kept rather than cleared
+    #[serde(skip)]
+    pub(crate) artist_missing: bool,
+    /// Raw fields, by vorbis key, and their cells, from the columns of a
+    /// sheet read with `--all-fields` which name no attribute
+    #[serde(skip)]
+    pub(crate) fields: Vec<(String, String)>,
+}
@@ -0,0 +1,335 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    process::{self, Stdio},
+};
+
+use crate::{encoding, tools::Tool, Error, Result};
+
+/// How many ffprobe processes to run at once when probing in bulk.
+const PROBE_BATCH: usize = 8;
+
+/// The shape of a file's audio stream, read from its headers.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AudioFormat {
+    pub(crate) channels: u8,
+    pub(crate) sample_rate: u32,
+    /// Bits per sample, for formats which have such a thing
+    pub(crate) bits: Option<u8>,
+}
+
+impl AudioFormat {
+    /// A name for the channel layout, following the FLAC default channel
+    /// assignments.
+    pub(crate) fn layout(&self) -> String {
+        match self.channels {
+            1 => "mono".into(),
+            2 => "stereo".into(),
+            3 => "3.0".into(),
+            4 => "quad".into(),
+            5 => "5.0".into(),
+            6 => "5.1".into(),
+            7 => "6.1".into(),
+            8 => "7.1".into(),
+            n => format!("{n} channels"),
+        }
+    }
+}
+
+/// Reads the audio format of a file, natively for FLAC, WAV, and MP3 and with
+/// ffprobe for anything else.
+pub(crate) fn read(path: &Path) -> Result<AudioFormat> {
+    match read_native(path) {
+        Err(Error::UnsupportedFileTye(_)) => probe(path)?.finish(path),
+        result => result,
+    }
+}
+
+/// The length of a file's audio in seconds: from STREAMINFO for FLAC, and
+/// from ffprobe for anything else. `None` when neither can tell.
+pub(crate) fn duration(path: &Path) -> Result<Option<f64>> {
+    if path.extension() == Some(OsStr::new("flac")) {
+        let flac = metaflac::Tag::read_from_path(path)?;
+        return Ok(flac
+            .get_streaminfo()
+            .filter(|info| info.sample_rate > 0 && info.total_samples > 0)
+            .map(|info| info.total_samples as f64 / f64::from(info.sample_rate)));
+    }
+
+    let Ok(output) = Tool::Ffprobe
+        .command()
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+    else {
+        return Ok(None);
+    };
+    Ok(String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+}
+
+/// Reads the audio formats of many files, running ffprobe for those which
+/// can't be read natively several at a time rather than one after another.
+pub(crate) fn read_many<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<AudioFormat>> {
+    let mut formats = Vec::with_capacity(paths.len());
+    let mut pending = Vec::new();
+
+    for (idx, path) in paths.iter().enumerate() {
+        match read_native(path.as_ref()) {
+            Ok(format) => formats.push(Some(format)),
+            Err(Error::UnsupportedFileTye(_)) => {
+                formats.push(None);
+                pending.push(idx);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    for batch in pending.chunks(PROBE_BATCH) {
+        let probes: Vec<_> = batch
+            .iter()
+            .map(|&idx| probe(paths[idx].as_ref()))
+            .collect::<Result<_>>()?;
+        for (&idx, probe) in batch.iter().zip(probes) {
+            formats[idx] = Some(probe.finish(paths[idx].as_ref())?);
+        }
+    }
+
+    Ok(formats.into_iter().flatten().collect())
+}
+
+/// A running ffprobe process describing a file's first audio stream.
+struct Probe(process::Child);
+
+fn probe(path: &Path) -> Result<Probe> {
+    Tool::Ffprobe
+        .command()
+        .args(["-v", "error", "-select_streams", "a:0", "-show_entries"])
+        .arg("stream=channels,sample_rate,bits_per_raw_sample,bits_per_sample")
+        .args(["-of", "default=noprint_wrappers=1"])
+        .arg(path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map(Probe)
+        .map_err(|_| Tool::Ffprobe.missing())
+}
+
+impl Probe {
+    /// Parses ffprobe's `key=value` output. Fields ffprobe doesn't know come
+    /// back as `N/A` or `0`.
+    fn finish(self, path: &Path) -> Result<AudioFormat> {
+        let output = self.0.wait_with_output()?;
+        let unsupported = || Error::UnsupportedFileTye(path.display().to_string());
+        if !output.status.success() {
+            return Err(unsupported());
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout);
+        let field = |key: &str| -> Option<u32> {
+            text.lines()
+                .filter_map(|line| line.split_once('='))
+                .find(|&(k, _)| k == key)
+                .and_then(|(_, value)| value.trim().parse().ok())
+                .filter(|&value| value != 0)
+        };
+
+        Ok(AudioFormat {
+            channels: field("channels").ok_or_else(unsupported)? as u8,
+            sample_rate: field("sample_rate").ok_or_else(unsupported)?,
+            bits: field("bits_per_raw_sample")
+                .or_else(|| field("bits_per_sample"))
+                .map(|bits| bits as u8),
+        })
+    }
+}
+
+fn read_native(path: &Path) -> Result<AudioFormat> {
+    let format = match path.extension().and_then(OsStr::to_str) {
+        Some("flac") => metaflac::Tag::read_from_path(path)?
+            .get_streaminfo()
+            .map(|info| AudioFormat {
+                channels: info.num_channels,
+                sample_rate: info.sample_rate,
+                bits: Some(info.bits_per_sample),
+            }),
+        Some("wav") => read_wav(&mut BufReader::new(File::open(path)?))?,
+        Some("mp3") => read_mp3(&mut BufReader::new(File::open(path)?))?,
+        _ => None,
+    };
+
+    format.ok_or_else(|| Error::UnsupportedFileTye(path.display().to_string()))
+}
+
+fn read_wav(reader: &mut (impl Read + Seek)) -> Result<Option<AudioFormat>> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Ok(None);
+    }
+
+    loop {
+        let mut chunk = [0; 8];
+        if reader.read_exact(&mut chunk).is_err() {
+            return Ok(None);
+        }
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+        if &chunk[..4] == b"fmt " {
+            let mut fmt = [0; 16];
+            reader.read_exact(&mut fmt)?;
+            return Ok(Some(AudioFormat {
+                channels: fmt[2],
+                sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                bits: Some(fmt[14]),
+            }));
+        }
+
+        // Chunks are padded to an even length.
+        reader.seek(SeekFrom::Current(i64::from(size + size % 2)))?;
+    }
+}
+
+/// The tags a WAV file carries in its RIFF INFO and Broadcast Wave (bext)
+/// chunks, as vorbis comments. Where both give a field, INFO wins.
+pub(crate) fn wav_tags(path: &Path) -> Result<Vec<(&'static str, String)>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Ok(Vec::new());
+    }
+
+    let mut info = Vec::new();
+    let mut bext = Vec::new();
+    loop {
+        let mut chunk = [0; 8];
+        if reader.read_exact(&mut chunk).is_err() {
+            break;
+        }
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        match &chunk[..4] {
+            b"LIST" | b"bext" => {
+                let mut body = vec![0; size as usize];
+                reader.read_exact(&mut body)?;
+                if size % 2 == 1 {
+                    reader.seek(SeekFrom::Current(1))?;
+                }
+                match &chunk[..4] {
+                    b"LIST" if body.starts_with(b"INFO") => info.extend(read_info(&body[4..])),
+                    b"LIST" => {}
+                    _ => bext.extend(read_bext(&body)),
+                }
+            }
+            // The samples are usually most of the file, and tags can follow
+            // them.
+            _ => {
+                reader.seek(SeekFrom::Current(i64::from(size + size % 2)))?;
+            }
+        }
+    }
+
+    for (key, value) in bext {
+        if !info.iter().any(|(existing, _)| *existing == key) {
+            info.push((key, value));
+        }
+    }
+    Ok(info)
+}
+
+/// The fields of an INFO list which have a vorbis comment to go to.
+fn read_info(mut body: &[u8]) -> Vec<(&'static str, String)> {
+    let mut tags = Vec::new();
+    while body.len() >= 8 {
+        let id = &body[..4];
+        let size = u32::from_le_bytes([body[4], body[5], body[6], body[7]]) as usize;
+        let Some(value) = body.get(8..8 + size) else {
+            break;
+        };
+        let key = match id {
+            b"INAM" => Some("TITLE"),
+            b"IART" => Some("ARTIST"),
+            b"IPRD" => Some("ALBUM"),
+            b"ICMT" => Some("COMMENT"),
+            b"ICRD" => Some("DATE"),
+            b"IGNR" => Some("GENRE"),
+            b"ITRK" | b"IPRT" => Some("TRACKNUMBER"),
+            b"ICOP" => Some("COPYRIGHT"),
+            _ => None,
+        };
+        if let (Some(key), Some(value)) = (key, text(value)) {
+            if !tags.iter().any(|(existing, _)| *existing == key) {
+                tags.push((key, value));
+            }
+        }
+        body = body.get(8 + size + size % 2..).unwrap_or_default();
+    }
+    tags
+}
+
+/// The description and origination date of a bext chunk, which start it as
+/// fixed-width fields: a 256-byte description, then a 32-byte originator and
+/// reference, then the date as yyyy-mm-dd.
+fn read_bext(body: &[u8]) -> Vec<(&'static str, String)> {
+    [("COMMENT", 0..256), ("DATE", 320..330)]
+        .into_iter()
+        .filter_map(|(key, range)| Some((key, text(body.get(range)?)?)))
+        .collect()
+}
+
+/// A text field, which may be padded with NULs.
+fn text(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let (value, _) = encoding::decode(&bytes[..end]);
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn read_mp3(reader: &mut (impl Read + Seek)) -> Result<Option<AudioFormat>> {
+    let mut id3 = [0; 10];
+    reader.read_exact(&mut id3)?;
+    let start = if &id3[..3] == b"ID3" {
+        10 + id3[6..]
+            .iter()
+            .fold(0, |acc, &b| (acc << 7) | u64::from(b & 0x7f))
+    } else {
+        0
+    };
+    reader.seek(SeekFrom::Start(start))?;
+
+    // Look for the first frame sync within a reasonable distance of the tag.
+    let mut buf = vec![0; 64 * 1024];
+    let len = reader.read(&mut buf)?;
+    let buf = &buf[..len];
+
+    for header in buf.windows(4) {
+        if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+            continue;
+        }
+
+        let version = (header[1] >> 3) & 0x3;
+        let layer = (header[1] >> 1) & 0x3;
+        let rate_index = (header[2] >> 2) & 0x3;
+        if version == 1 || layer == 0 || rate_index == 3 {
+            continue;
+        }
+
+        let base = [44100, 48000, 32000][rate_index as usize];
+        let sample_rate = match version {
+            3 => base,
+            2 => base / 2,
+            _ => base / 4,
+        };
+        let channels = if header[3] >> 6 == 3 { 1 } else { 2 };
+
+        return Ok(Some(AudioFormat {
+            channels,
+            sample_rate,
+            bits: None,
+        }));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,663 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use metaflac::block::VorbisComment;
+
+use crate::{
+    application, art, config,
+    lock::FileLock,
+    manifest::{self, Action},
+    preflight, tags, verify, Error, Result,
+};
+
+/// An append-only record of mutating operations, written as tab-separated
+/// records with a variable number of fields:
+///
+/// ```text
+/// op      <id> <unix time> <description>
+/// vorbis  <id> <path> <key> <old count> <old values...> <new values...>
+/// id3     <id> <path> <frame> <old count> <old values...> <new values...>
+/// picture <id> <path> <old count> <old pictures...> <new pictures...>
+/// app     <id> <path> <app id> <old count> <old data...> <new data...>
+/// create  <id> <path> <size> <modified>
+/// rename  <id> <old path> <new path>
+/// delete  <id> <path>
+/// ```
+///
+/// A FLAC's pictures are recorded whole, each PICTURE block hex encoded, and
+/// so are its APPLICATION blocks of an id, by their data.
+///
+/// Each operation has an id which can later be passed to `flacdat revert`.
+pub(crate) struct AuditLog {
+    id: String,
+    description: String,
+    writer: Option<csv::Writer<File>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Operation {
+    pub(crate) id: String,
+    pub(crate) timestamp: u64,
+    pub(crate) description: String,
+    pub(crate) changes: Vec<Change>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Change {
+    Vorbis {
+        path: String,
+        key: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    Id3 {
+        path: String,
+        /// The frame's key, as [`tags::id3_frame_fields`] gives it
+        key: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    Pictures {
+        path: String,
+        /// The PICTURE blocks, as [`art::flac_picture_fields`] gives them
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    Application {
+        path: String,
+        /// The block's id, hex encoded
+        id: String,
+        /// The blocks' data, as [`application::fields`] gives it
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    Create {
+        path: String,
+        /// The file's size and modification time once written, in
+        /// nanoseconds since the epoch; `None` in logs from before they were
+        /// recorded
+        stamp: Option<(u64, u128)>,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    /// A file removed for good, which makes the operation irreversible
+    Delete {
+        path: String,
+    },
+}
+
+impl AuditLog {
+    /// Starts a new operation. Nothing is written until the first change is
+    /// recorded, so operations which change nothing leave no trace.
+    ///
+    /// Ids are the time in milliseconds, the process id, and a count of the
+    /// operations the process has begun, so that no two runs share one.
+    pub(crate) fn begin(description: impl Into<String>) -> Self {
+        static BEGUN: AtomicUsize = AtomicUsize::new(0);
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let count = BEGUN.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{millis:x}-{:x}-{count}", process::id());
+        manifest::begin(&id);
+
+        AuditLog {
+            id,
+            description: description.into(),
+            writer: None,
+        }
+    }
+
+    /// Records every vorbis comment key whose values differ between `before`
+    /// and `after`.
+    pub(crate) fn vorbis(
+        &mut self,
+        path: impl AsRef<Path>,
+        before: &VorbisComment,
+        after: &VorbisComment,
+    ) -> Result<()> {
+        manifest::record(Action::Modified, path.as_ref());
+        let path = path.as_ref().to_string_lossy();
+        let mut keys: Vec<&String> = before
+            .comments
+            .keys()
+            .chain(after.comments.keys())
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        let id = self.id.clone();
+        let empty = Vec::new();
+        for key in keys {
+            let old = before.comments.get(key).unwrap_or(&empty);
+            let new = after.comments.get(key).unwrap_or(&empty);
+            if old != new {
+                let count = old.len().to_string();
+                let mut record = vec!["vorbis", &id, &path, key, &count];
+                record.extend(old.iter().map(String::as_str));
+                record.extend(new.iter().map(String::as_str));
+                self.write(&record)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records every frame of an MP3's ID3 tag whose values differ between
+    /// `before` and `after`.
+    pub(crate) fn id3(
+        &mut self,
+        path: impl AsRef<Path>,
+        before: &id3::Tag,
+        after: &id3::Tag,
+    ) -> Result<()> {
+        manifest::record(Action::Modified, path.as_ref());
+        let path = path.as_ref().to_string_lossy();
+        let (before, after) = (
+            tags::id3_frame_fields(before),
+            tags::id3_frame_fields(after),
+        );
+        let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let id = self.id.clone();
+        let empty = Vec::new();
+        for key in keys {
+            let old = before.get(key).unwrap_or(&empty);
+            let new = after.get(key).unwrap_or(&empty);
+            if old != new {
+                let count = old.len().to_string();
+                let mut record = vec!["id3", &id, &path, key, &count];
+                record.extend(old.iter().map(String::as_str));
+                record.extend(new.iter().map(String::as_str));
+                self.write(&record)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a FLAC's pictures, as [`art::flac_picture_fields`] gives them,
+    /// if they differ between `before` and `after`.
+    pub(crate) fn pictures(
+        &mut self,
+        path: impl AsRef<Path>,
+        before: &[String],
+        after: &[String],
+    ) -> Result<()> {
+        if before == after {
+            return Ok(());
+        }
+        manifest::record(Action::Modified, path.as_ref());
+        let path = path.as_ref().to_string_lossy();
+        let id = self.id.clone();
+        let count = before.len().to_string();
+        let mut record = vec!["picture", &id, &path, &count];
+        record.extend(before.iter().map(String::as_str));
+        record.extend(after.iter().map(String::as_str));
+        self.write(&record)
+    }
+
+    /// Records a FLAC's APPLICATION blocks of an id, as
+    /// [`application::fields`] gives them, if they differ between `before`
+    /// and `after`.
+    pub(crate) fn application(
+        &mut self,
+        path: impl AsRef<Path>,
+        application: &[u8],
+        before: &[String],
+        after: &[String],
+    ) -> Result<()> {
+        if before == after {
+            return Ok(());
+        }
+        manifest::record(Action::Modified, path.as_ref());
+        let path = path.as_ref().to_string_lossy();
+        let (id, application) = (self.id.clone(), hex::encode(application));
+        let count = before.len().to_string();
+        let mut record = vec!["app", &id, &path, &application, &count];
+        record.extend(before.iter().map(String::as_str));
+        record.extend(after.iter().map(String::as_str));
+        self.write(&record)
+    }
+
+    /// Records the creation of a new file, once it has been written in full;
+    /// reverting removes it, unless it has changed since.
+    pub(crate) fn create(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        manifest::record(Action::Created, path.as_ref());
+        let (size, modified) = stamp(path.as_ref())?;
+        let path = path.as_ref().to_string_lossy();
+        let id = self.id.clone();
+        self.write(&[
+            "create",
+            &id,
+            &path,
+            &size.to_string(),
+            &modified.to_string(),
+        ])
+    }
+
+    /// Records a file being moved; reverting moves it back.
+    pub(crate) fn rename(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+        manifest::record(
+            Action::Renamed {
+                from: from.as_ref().into(),
+            },
+            to.as_ref(),
+        );
+        let from = from.as_ref().to_string_lossy();
+        let to = to.as_ref().to_string_lossy();
+        let id = self.id.clone();
+        self.write(&["rename", &id, &from, &to])
+    }
+
+    /// Records a file being deleted. Nothing is kept to restore it from, so
+    /// an operation which deletes a file can't be reverted.
+    pub(crate) fn delete(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        manifest::record(Action::Deleted, path.as_ref());
+        let path = path.as_ref().to_string_lossy();
+        let id = self.id.clone();
+        self.write(&["delete", &id, &path])
+    }
+
+    fn write(&mut self, record: &[&str]) -> Result<()> {
+        if self.writer.is_none() {
+            let path = default_path().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "unable to locate audit log")
+            })?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .flexible(true)
+                .from_writer(file);
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string();
+            writer.write_record(["op", &self.id, &timestamp, &self.description])?;
+            self.writer = Some(writer);
+        }
+
+        // Hold the lock for the whole record so that concurrent processes
+        // can't interleave partial lines.
+        let writer = self.writer.as_mut().expect("writer initialized above");
+        writer.get_ref().lock()?;
+        let result = writer
+            .write_record(record)
+            .and_then(|_| Ok(writer.flush()?));
+        writer.get_ref().unlock()?;
+        Ok(result?)
+    }
+}
+
+/// Reads every operation in the log, oldest first.
+pub(crate) fn read_operations() -> Result<Vec<Operation>> {
+    let Some(path) = default_path() else {
+        return Ok(Vec::new());
+    };
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(file);
+
+    let mut operations: Vec<Operation> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx: usize| record.get(idx).unwrap_or_default().to_string();
+        let id = field(1);
+
+        if &record[0] == "op" {
+            index.insert(id.clone(), operations.len());
+            operations.push(Operation {
+                id,
+                timestamp: field(2).parse().unwrap_or_default(),
+                description: field(3),
+                changes: Vec::new(),
+            });
+            continue;
+        }
+
+        let change = match &record[0] {
+            kind @ ("vorbis" | "id3" | "app") => {
+                let count: usize = field(4).parse().unwrap_or_default();
+                let values: Vec<String> = record.iter().skip(5).map(String::from).collect();
+                let (old, new) = values.split_at(count.min(values.len()));
+                let (path, key, old, new) = (field(2), field(3), old.to_vec(), new.to_vec());
+                match kind {
+                    "vorbis" => Change::Vorbis {
+                        path,
+                        key,
+                        old,
+                        new,
+                    },
+                    "app" => Change::Application {
+                        path,
+                        id: key,
+                        old,
+                        new,
+                    },
+                    _ => Change::Id3 {
+                        path,
+                        key,
+                        old,
+                        new,
+                    },
+                }
+            }
+            "picture" => {
+                let count: usize = field(3).parse().unwrap_or_default();
+                let values: Vec<String> = record.iter().skip(4).map(String::from).collect();
+                let (old, new) = values.split_at(count.min(values.len()));
+                Change::Pictures {
+                    path: field(2),
+                    old: old.to_vec(),
+                    new: new.to_vec(),
+                }
+            }
+            "create" => Change::Create {
+                path: field(2),
+                stamp: field(3).parse().ok().zip(field(4).parse().ok()),
+            },
+            "rename" => Change::Rename {
+                from: field(2),
+                to: field(3),
+            },
+            "delete" => Change::Delete { path: field(2) },
+            _ => continue,
+        };
+
+        if let Some(&idx) = index.get(&id) {
+            operations[idx].changes.push(change);
+        }
+    }
+
+    Ok(operations)
+}
+
+/// Undoes an operation, most recent change first, recording the reversal as a
+/// new operation of its own.
+pub(crate) fn revert(operation: &Operation, force: bool, chmod_if_needed: bool) -> Result<()> {
+    for change in &operation.changes {
+        if let Change::Delete { path } = change {
+            return Err(Error::RevertDeleted(path.clone()));
+        }
+    }
+
+    let mut log = AuditLog::begin(format!("revert {}", operation.id));
+    let created: Vec<&str> = operation
+        .changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Create { path, .. } => Some(path.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    // A created file which has been edited, re-tagged, or replaced since is
+    // the user's now. Files with no stamp, logged before stamps were, can't
+    // be told apart, and are kept too.
+    if !force {
+        for change in &operation.changes {
+            let Change::Create { path, stamp } = change else {
+                continue;
+            };
+            let current = match self::stamp(Path::new(path)) {
+                Ok(current) => current,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if *stamp != Some(current) {
+                return Err(Error::RevertCreated(path.clone()));
+            }
+        }
+    }
+
+    // what's edited, old values, new values
+    type Edit<'a> = (Field<'a>, &'a [String], &'a [String]);
+    let mut edits: HashMap<&str, Vec<Edit>> = HashMap::new();
+    let mut frame_edits: HashMap<&str, Vec<Edit>> = HashMap::new();
+    for change in operation.changes.iter().rev() {
+        let (edits, path, field, old, new) = match change {
+            Change::Vorbis {
+                path,
+                key,
+                old,
+                new,
+            } => (&mut edits, path, Field::Comment(key), old, new),
+            Change::Pictures { path, old, new } => (&mut edits, path, Field::Pictures, old, new),
+            Change::Application { path, id, old, new } => {
+                (&mut edits, path, Field::Application(id), old, new)
+            }
+            Change::Id3 {
+                path,
+                key,
+                old,
+                new,
+            } => (&mut frame_edits, path, Field::Frame(key), old, new),
+            _ => continue,
+        };
+        if !created.contains(&path.as_str()) {
+            // Files edited and then moved, as pipelines which rename do,
+            // are restored where they are now, before being moved back.
+            let path = renamed(&operation.changes, path);
+            edits.entry(path).or_default().push((field, old, new));
+        }
+    }
+
+    let mut paths: Vec<_> = edits.keys().copied().collect();
+    paths.sort();
+    let mut frame_paths: Vec<_> = frame_edits.keys().copied().collect();
+    frame_paths.sort();
+    preflight::check_writable(paths.iter().chain(&frame_paths), chmod_if_needed)?;
+
+    // Check every file before touching any of them, so a conflict doesn't
+    // leave the operation half reverted.
+    let conflict = |path: &str, field: Field| Error::RevertConflict {
+        path: path.into(),
+        key: field.to_string(),
+    };
+    let mut flacs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let lock = FileLock::acquire(path)?;
+        let flac = metaflac::Tag::read_from_path(path)?;
+        for &(field, _, new) in &edits[path] {
+            let current = match field {
+                Field::Comment(key) => flac
+                    .vorbis_comments()
+                    .and_then(|comment| comment.get(key))
+                    .cloned()
+                    .unwrap_or_default(),
+                Field::Pictures => art::flac_picture_fields(&flac),
+                Field::Application(id) => {
+                    application::fields(&flac, &hex::decode(id).unwrap_or_default())
+                }
+                Field::Frame(_) => unreachable!("frames are edited in MP3s"),
+            };
+            if current != new && !force {
+                return Err(conflict(path, field));
+            }
+        }
+        flacs.push((path, flac, lock));
+    }
+    let mut mp3s = Vec::with_capacity(frame_paths.len());
+    for path in frame_paths {
+        let lock = FileLock::acquire(path)?;
+        let tag = match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let fields = tags::id3_frame_fields(&tag);
+        for &(field, _, new) in &frame_edits[path] {
+            let current = fields
+                .get(&field.to_string())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            if current != new && !force {
+                return Err(conflict(path, field));
+            }
+        }
+        mp3s.push((path, tag, lock));
+    }
+
+    for (path, mut flac, _lock) in flacs {
+        let comment = |flac: &metaflac::Tag| flac.vorbis_comments().cloned().unwrap_or_default();
+        let before = (comment(&flac), art::flac_picture_fields(&flac));
+        let mut applications = Vec::new();
+
+        for &(field, old, _) in &edits[path] {
+            match field {
+                Field::Comment(key) if old.is_empty() => flac.vorbis_comments_mut().remove(key),
+                Field::Comment(key) => flac.vorbis_comments_mut().set(key, old.to_vec()),
+                Field::Pictures => art::set_flac_pictures(&mut flac, old)?,
+                Field::Application(id) => {
+                    let id = hex::decode(id).map_err(|_| Error::InvalidData("hex"))?;
+                    applications.push((application::fields(&flac, &id), id.clone()));
+                    application::set_fields(&mut flac, &id, old)?;
+                }
+                Field::Frame(_) => unreachable!("frames are edited in MP3s"),
+            }
+        }
+
+        let _writable = preflight::Writable::new(path)?;
+        verify::write_flac(&mut flac, Path::new(path))?;
+        log.vorbis(path, &before.0, &comment(&flac))?;
+        log.pictures(path, &before.1, &art::flac_picture_fields(&flac))?;
+        for (before, id) in applications {
+            log.application(path, &id, &before, &application::fields(&flac, &id))?;
+        }
+    }
+
+    for (path, mut tag, _lock) in mp3s {
+        let before = tag.clone();
+        for &(field, old, _) in &frame_edits[path] {
+            tags::set_id3_frame_field(&mut tag, &field.to_string(), old)?;
+        }
+
+        let _writable = preflight::Writable::new(path)?;
+        verify::write_id3(&tag, Path::new(path))?;
+        log.id3(path, &before, &tag)?;
+    }
+
+    for path in created.into_iter().rev() {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    for change in operation.changes.iter().rev() {
+        if let Change::Rename { from, to } = change {
+            if Path::new(from).exists() {
+                return Err(Error::RenameConflict(from.into()));
+            }
+            fs::rename(to, from)?;
+            log.rename(to, from)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// What of a file a change edits, as reverting it restores.
+#[derive(Clone, Copy)]
+enum Field<'a> {
+    /// A vorbis comment, by key
+    Comment(&'a str),
+    /// An ID3 frame, by its key from [`tags::id3_frame_fields`]
+    Frame(&'a str),
+    /// A FLAC's PICTURE blocks
+    Pictures,
+    /// A FLAC's APPLICATION blocks of an id, hex encoded
+    Application(&'a str),
+}
+
+impl fmt::Display for Field<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::Comment(key) | Field::Frame(key) => f.write_str(key),
+            Field::Pictures => f.write_str("PICTURE"),
+            Field::Application(id) => write!(f, "APPLICATION {id}"),
+        }
+    }
+}
+
+/// Where a file an operation changed went, following its renames.
+fn renamed<'a>(changes: &'a [Change], path: &'a str) -> &'a str {
+    changes.iter().fold(path, |path, change| match change {
+        Change::Rename { from, to } if from == path => to,
+        _ => path,
+    })
+}
+
+/// A file's size and modification time, in nanoseconds since the epoch.
+fn stamp(path: &Path) -> io::Result<(u64, u128)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    Ok((metadata.len(), modified))
+}
+
+/// `$FLACDAT_LOG`, falling back to the platform data directory.
+fn default_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("FLACDAT_LOG") {
+        return Some(path.into());
+    }
+
+    config::data_dir().map(|dir| dir.join("audit.log"))
+}
+
+/// Formats a unix timestamp as an ISO 8601 UTC date and time.
+pub(crate) fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Civil-from-days; see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,163 @@
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{Error, Result};
+
+/// A web service flacdat can hold an API token for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Service {
+    Discogs,
+    Acoustid,
+    Lastfm,
+}
+
+impl Service {
+    pub(crate) const ALL: &'static [Service] =
+        &[Service::Discogs, Service::Acoustid, Service::Lastfm];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Service::Discogs => "discogs",
+            Service::Acoustid => "acoustid",
+            Service::Lastfm => "lastfm",
+        }
+    }
+
+    /// `FLACDAT_<SERVICE>_TOKEN`, which takes precedence over the keyring so
+    /// scripts and CI can supply a token without storing it.
+    fn var(self) -> String {
+        format!("FLACDAT_{}_TOKEN", self.name().to_ascii_uppercase())
+    }
+}
+
+/// Where a token was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Source {
+    Environment,
+    Keyring,
+}
+
+/// The token for a service, from the environment or the OS keyring.
+pub(crate) fn token(service: Service) -> Result<Option<(String, Source)>> {
+    if let Some(token) = env::var(service.var())
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        return Ok(Some((token, Source::Environment)));
+    }
+
+    let output = match env::consts::OS {
+        "macos" => run(
+            Command::new("security")
+                .args(["find-generic-password", "-s", "flacdat", "-a"])
+                .arg(service.name())
+                .arg("-w"),
+            None,
+        )?,
+        _ => run(
+            Command::new("secret-tool")
+                .args(["lookup", "service", "flacdat", "account"])
+                .arg(service.name()),
+            None,
+        )?,
+    };
+    Ok(output
+        .map(|token| token.trim_end_matches(['\r', '\n']).to_string())
+        .filter(|token| !token.is_empty())
+        .map(|token| (token, Source::Keyring)))
+}
+
+/// Stores a token in the OS keyring: the Secret Service (GNOME Keyring,
+/// KWallet) through `secret-tool`, or the macOS keychain through `security`.
+pub(crate) fn store(service: Service, token: &str) -> Result<()> {
+    let label = format!("flacdat {} token", service.name());
+    let stored = match env::consts::OS {
+        // security takes the password as an argument; it is briefly visible
+        // to other processes of the same user, as with any keychain script.
+        "macos" => run(
+            Command::new("security")
+                .args(["add-generic-password", "-U", "-s", "flacdat", "-l"])
+                .arg(&label)
+                .arg("-a")
+                .arg(service.name())
+                .arg("-w")
+                .arg(token),
+            None,
+        )?,
+        _ => run(
+            Command::new("secret-tool")
+                .args(["store", "--label"])
+                .arg(&label)
+                .args(["service", "flacdat", "account"])
+                .arg(service.name()),
+            Some(token),
+        )?,
+    };
+    stored
+        .map(|_| ())
+        .ok_or_else(|| keyring_failed("store", service))
+}
+
+/// Removes a token from the OS keyring. Removing a token which isn't there
+/// is not an error.
+pub(crate) fn remove(service: Service) -> Result<()> {
+    match env::consts::OS {
+        "macos" => {
+            run(
+                Command::new("security")
+                    .args(["delete-generic-password", "-s", "flacdat", "-a"])
+                    .arg(service.name()),
+                None,
+            )?;
+        }
+        _ => {
+            run(
+                Command::new("secret-tool")
+                    .args(["clear", "service", "flacdat", "account"])
+                    .arg(service.name()),
+                None,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn keyring_failed(action: &str, service: Service) -> Error {
+    Error::Keyring(format!("unable to {action} the {} token", service.name()))
+}
+
+/// Runs a keyring program, returning its output if it succeeded. A missing
+/// program is an error naming the keyring flacdat needs.
+fn run(command: &mut Command, input: Option<&str>) -> Result<Option<String>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|_| {
+            Error::Keyring(match env::consts::OS {
+                "macos" => format!("{program} is needed to use the keychain"),
+                "windows" => "no supported keyring on this platform; set the FLACDAT_<SERVICE>_TOKEN environment variable instead".into(),
+                _ => format!("{program} is needed to use the keyring (install libsecret-tools or libsecret)"),
+            })
+        })?;
+
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned()))
+}
@@ -0,0 +1,261 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{Error, Result};
+
+/// A structural element of a file's metadata: a FLAC metadata block or an
+/// ID3 tag header or frame.
+pub(crate) struct Block {
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    pub(crate) kind: String,
+    pub(crate) detail: String,
+}
+
+/// Inventories the metadata blocks of a FLAC file or the ID3 frames of an
+/// MP3, reading the raw structures rather than going through the tag
+/// libraries, so that offsets and sizes are exactly as stored.
+pub(crate) fn read(path: &Path) -> Result<Vec<Block>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut blocks = read_id3(&mut reader)?;
+
+    let mut magic = [0; 4];
+    let start = reader.stream_position()?;
+    match reader.read_exact(&mut magic) {
+        Ok(()) if &magic == b"fLaC" => blocks.extend(read_flac(&mut reader, start + 4)?),
+        Ok(()) | Err(_) if !blocks.is_empty() => {}
+        _ => return Err(Error::UnsupportedFileTye(path.display().to_string())),
+    }
+
+    let length = reader.seek(SeekFrom::End(0))?;
+    if length >= 128 {
+        let mut tail = [0; 3];
+        reader.seek(SeekFrom::End(-128))?;
+        reader.read_exact(&mut tail)?;
+        if &tail == b"TAG" {
+            blocks.push(Block {
+                offset: length - 128,
+                size: 128,
+                kind: "ID3v1".into(),
+                detail: String::new(),
+            });
+        }
+    }
+
+    Ok(blocks)
+}
+
+fn read_flac(reader: &mut (impl Read + Seek), mut offset: u64) -> Result<Vec<Block>> {
+    let mut blocks = Vec::new();
+
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let size = u64::from(u32::from_be_bytes([0, header[1], header[2], header[3]]));
+
+        let mut payload = vec![0; size as usize];
+        reader.read_exact(&mut payload)?;
+
+        let (name, detail) = describe_flac_block(kind, &payload);
+        blocks.push(Block {
+            offset,
+            size: size + 4,
+            kind: name,
+            detail,
+        });
+
+        offset += size + 4;
+        if is_last {
+            break;
+        }
+    }
+
+    blocks.push(Block {
+        offset,
+        size: 0,
+        kind: "AUDIO".into(),
+        detail: "first frame".into(),
+    });
+
+    Ok(blocks)
+}
+
+fn describe_flac_block(kind: u8, payload: &[u8]) -> (String, String) {
+    let u32_be = |at: usize| -> Option<u32> {
+        payload
+            .get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let u32_le = |at: usize| -> Option<u32> {
+        payload
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    match kind {
+        0 => {
+            let detail = payload
+                .get(10..18)
+                .map(|b| {
+                    let packed = u64::from_be_bytes(b.try_into().unwrap());
+                    let sample_rate = packed >> 44;
+                    let channels = (packed >> 41 & 0x7) + 1;
+                    let bits = (packed >> 36 & 0x1f) + 1;
+                    let samples = packed & 0xf_ffff_ffff;
+                    format!("{sample_rate} Hz, {channels} ch, {bits} bit, {samples} samples")
+                })
+                .unwrap_or_default();
+            ("STREAMINFO".into(), detail)
+        }
+        1 => ("PADDING".into(), String::new()),
+        2 => {
+            let id = payload
+                .get(..4)
+                .map(|id| String::from_utf8_lossy(id).into_owned())
+                .unwrap_or_default();
+            ("APPLICATION".into(), format!("id {id}"))
+        }
+        3 => ("SEEKTABLE".into(), format!("{} points", payload.len() / 18)),
+        4 => {
+            let detail = u32_le(0)
+                .and_then(|len| {
+                    let len = len as usize;
+                    let vendor = payload.get(4..4 + len)?;
+                    let count = u32_le(4 + len)?;
+                    Some(format!(
+                        "vendor {:?}, {count} comments",
+                        String::from_utf8_lossy(vendor)
+                    ))
+                })
+                .unwrap_or_default();
+            ("VORBIS_COMMENT".into(), detail)
+        }
+        5 => ("CUESHEET".into(), String::new()),
+        6 => {
+            let detail = (|| {
+                let picture_type = u32_be(0)?;
+                let mime_len = u32_be(4)? as usize;
+                let mime = String::from_utf8_lossy(payload.get(8..8 + mime_len)?);
+                let desc_len = u32_be(8 + mime_len)? as usize;
+                let dims = 12 + mime_len + desc_len;
+                let (width, height) = (u32_be(dims)?, u32_be(dims + 4)?);
+                let data_len = u32_be(dims + 16)?;
+                Some(format!(
+                    "type {picture_type}, {mime}, {width}x{height}, {data_len} bytes"
+                ))
+            })()
+            .unwrap_or_default();
+            ("PICTURE".into(), detail)
+        }
+        kind => (format!("UNKNOWN({kind})"), String::new()),
+    }
+}
+
+/// Reads an ID3v2 tag at the current position, if there is one, leaving the
+/// reader positioned just past it.
+fn read_id3(reader: &mut (impl Read + Seek)) -> Result<Vec<Block>> {
+    let mut header = [0; 10];
+    match reader.read_exact(&mut header) {
+        Ok(()) if &header[..3] == b"ID3" => {}
+        Ok(()) | Err(_) => {
+            reader.seek(SeekFrom::Start(0))?;
+            return Ok(Vec::new());
+        }
+    }
+
+    let major = header[3];
+    let flags = header[5];
+    let size = u64::from(syncsafe(&header[6..10]));
+
+    let mut blocks = vec![Block {
+        offset: 0,
+        size: size + 10,
+        kind: format!("ID3v2.{major}"),
+        detail: format!("flags {flags:#04x}"),
+    }];
+
+    let mut body = vec![0; size as usize];
+    reader.read_exact(&mut body)?;
+
+    let (id_len, header_len) = if major == 2 { (3, 6) } else { (4, 10) };
+    let mut at = 0;
+    while at + header_len <= body.len() {
+        let frame = &body[at..at + header_len];
+        if frame[0] == 0 {
+            blocks.push(Block {
+                offset: 10 + at as u64,
+                size: (body.len() - at) as u64,
+                kind: "PADDING".into(),
+                detail: String::new(),
+            });
+            break;
+        }
+
+        let id = String::from_utf8_lossy(&frame[..id_len]).into_owned();
+        let frame_size = match major {
+            2 => u32::from_be_bytes([0, frame[3], frame[4], frame[5]]),
+            3 => u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]),
+            _ => syncsafe(&frame[4..8]),
+        } as usize;
+
+        let content = body
+            .get(at + header_len..at + header_len + frame_size)
+            .unwrap_or_default();
+        blocks.push(Block {
+            offset: 10 + at as u64,
+            size: (header_len + frame_size) as u64,
+            kind: id.clone(),
+            detail: describe_id3_frame(&id, content),
+        });
+
+        at += header_len + frame_size;
+    }
+
+    Ok(blocks)
+}
+
+/// Summarizes a frame's content: the text of text frames, the description of
+/// user-defined and object frames.
+fn describe_id3_frame(id: &str, content: &[u8]) -> String {
+    let Some((&encoding, text)) = content.split_first() else {
+        return String::new();
+    };
+
+    if !(id.starts_with('T') || id == "GEOB" || id == "COMM") {
+        return String::new();
+    }
+
+    let text = match encoding {
+        1 | 2 => {
+            let big_endian = encoding == 2 || text.starts_with(&[0xfe, 0xff]);
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| match big_endian {
+                    true => u16::from_be_bytes([pair[0], pair[1]]),
+                    false => u16::from_le_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+
+    let text: String = text
+        .trim_start_matches('\u{feff}')
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(60)
+        .collect();
+    text.trim().to_string()
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |acc, &b| (acc << 7) | u32::from(b & 0x7f))
+}
@@ -0,0 +1,177 @@
+use std::path::Path;
+
+/// The fewest characters a shortened component is cut down to.
+const MIN_CHARS: usize = 8;
+
+/// The longest file name and path a filesystem allows, in bytes.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Budget {
+    name: usize,
+    path: usize,
+}
+
+impl Budget {
+    /// The limits of the filesystem holding `dir`, or the nearest directory
+    /// above it which exists. `max_path` lowers the path limit, as for a
+    /// library which will be copied to a device with a shorter one.
+    pub(crate) fn for_dir(dir: &Path, max_path: Option<usize>) -> Budget {
+        let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.into());
+        let existing = dir.ancestors().find(|dir| dir.exists()).unwrap_or(&dir);
+        let (name, path) = limits(existing);
+        Budget {
+            name,
+            path: max_path.map_or(path, |max| max.min(path)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn limits(dir: &Path) -> (usize, usize) {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let query = |name| {
+        let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        // SAFETY: dir is a valid nul-terminated string for the length of the call.
+        let limit = unsafe { libc::pathconf(dir.as_ptr(), name) };
+        usize::try_from(limit).ok().filter(|&limit| limit > 0)
+    };
+    // PATH_MAX counts the terminating nul.
+    (
+        query(libc::_PC_NAME_MAX).unwrap_or(255),
+        query(libc::_PC_PATH_MAX).map_or(4095, |limit| limit - 1),
+    )
+}
+
+#[cfg(not(unix))]
+fn limits(_: &Path) -> (usize, usize) {
+    // MAX_PATH, for Windows without long path support
+    (255, 259)
+}
+
+/// A templated path, shortened to fit a budget.
+#[derive(Debug)]
+pub(crate) struct Fitted {
+    /// The path relative to the directory it was fitted under.
+    pub(crate) relative: String,
+    /// Each component which was shortened, before and after.
+    pub(crate) shortened: Vec<(String, String)>,
+}
+
+/// One component of a path, split into the part which may be shortened and
+/// the parts which may not: a file name's leading track number and its
+/// extension.
+struct Component {
+    prefix: String,
+    body: String,
+    suffix: String,
+}
+
+impl Component {
+    fn len(&self) -> usize {
+        self.prefix.len() + self.body.len() + self.suffix.len()
+    }
+
+    fn joined(&self) -> String {
+        format!("{}{}{}", self.prefix, self.body, self.suffix)
+    }
+}
+
+/// Fits `relative`, a rendered pattern with / between components, under
+/// `base`. A component longer than the filesystem allows is shortened to the
+/// limit; then, while the whole path is too long, the longest component is
+/// cut back a character at a time. Nothing if the path can't be made short
+/// enough.
+pub(crate) fn fit(base: &Path, relative: &str, budget: Budget) -> Option<Fitted> {
+    let base = match base.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => base,
+    };
+    let base_len = std::path::absolute(base)
+        .unwrap_or_else(|_| base.into())
+        .as_os_str()
+        .len();
+
+    let parts: Vec<&str> = relative.split('/').collect();
+    let mut components: Vec<Component> = parts
+        .iter()
+        .enumerate()
+        .map(|(idx, part)| match idx + 1 == parts.len() {
+            true => file_name(part),
+            false => Component {
+                prefix: String::new(),
+                body: part.to_string(),
+                suffix: String::new(),
+            },
+        })
+        .collect();
+
+    for component in &mut components {
+        let room = budget
+            .name
+            .checked_sub(component.prefix.len() + component.suffix.len())?;
+        truncate(&mut component.body, room);
+    }
+
+    let total =
+        |components: &[Component]| base_len + components.iter().map(|c| c.len() + 1).sum::<usize>();
+    while total(&components) > budget.path {
+        let longest = components
+            .iter_mut()
+            .filter(|c| c.body.chars().count() > MIN_CHARS)
+            .max_by_key(|c| c.body.len())?;
+        longest.body.pop();
+    }
+
+    let mut shortened = Vec::new();
+    for (component, original) in components.iter_mut().zip(&parts) {
+        if component.joined() == *original {
+            continue;
+        }
+        // Don't leave a cut name ending in a dangling separator.
+        let trimmed = component.body.trim_end_matches([' ', '.', ',', '-', '_']);
+        if !trimmed.is_empty() {
+            component.body.truncate(trimmed.len());
+        }
+        shortened.push((original.to_string(), component.joined()));
+    }
+
+    Some(Fitted {
+        relative: components
+            .iter()
+            .map(Component::joined)
+            .collect::<Vec<_>>()
+            .join("/"),
+        shortened,
+    })
+}
+
+/// Splits a file name into its track number (with any disc number and the
+/// separator after it), the rest of its stem, and its extension.
+fn file_name(name: &str) -> Component {
+    let (stem, suffix) = match Path::new(name).extension() {
+        Some(extension) => name.split_at(name.len() - extension.len() - 1),
+        None => (name, ""),
+    };
+    let number = match stem.starts_with(|c: char| c.is_ascii_digit()) {
+        true => stem
+            .find(|c: char| !c.is_ascii_digit() && c != '-')
+            .unwrap_or(stem.len()),
+        false => 0,
+    };
+    let prefix = stem[number..]
+        .find(|c: char| !matches!(c, ' ' | '.' | '-' | '_'))
+        .map_or(stem.len(), |separator| number + separator);
+    Component {
+        prefix: stem[..prefix].into(),
+        body: stem[prefix..].into(),
+        suffix: suffix.into(),
+    }
+}
+
+/// Shortens a string to at most `len` bytes, on a character boundary.
+fn truncate(s: &mut String, len: usize) {
+    if s.len() > len {
+        let boundary = (0..=len).rev().find(|&idx| s.is_char_boundary(idx));
+        s.truncate(boundary.unwrap_or_default());
+    }
+}
@@ -0,0 +1,119 @@
+use std::{ffi::OsStr, path::Path};
+
+use crate::{warning, Attribute, Attributes, Error, Result};
+
+/// Where a format keeps an attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Support {
+    /// a field of the format's own, which players read
+    Native,
+    /// only a user-defined field, which few players read
+    Extended,
+}
+
+/// A format `apply` writes tags to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Flac,
+    Mp3,
+}
+
+impl Format {
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("flac") => Some(Format::Flac),
+            Some("mp3") => Some(Format::Mp3),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Flac => "FLAC",
+            Format::Mp3 => "MP3",
+        }
+    }
+
+    /// Where the format keeps an attribute. Vorbis comments take any name,
+    /// so FLAC holds every attribute as its own; ID3 has frames for all but
+    /// three.
+    pub(crate) fn support(self, attribute: Attribute) -> Support {
+        match (self, attribute) {
+            (
+                Format::Mp3,
+                Attribute::Work | Attribute::ShowMovement | Attribute::ReleaseCountry,
+            ) => Support::Extended,
+            _ => Support::Native,
+        }
+    }
+}
+
+/// What `apply` does with an attribute a format has no field of its own for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Policy {
+    /// write it to a user-defined TXXX frame, named as its vorbis comment is
+    #[default]
+    Txxx,
+    /// leave it out
+    Drop,
+    /// refuse to apply the sheet
+    Abort,
+}
+
+/// Reports, before anything is written, each format among the files a sheet
+/// is applied to which has no field of its own for some attribute the sheet
+/// sets, and what becomes of those attributes: with `Policy::Abort`, the
+/// sheet is refused.
+pub(crate) fn report<'a>(
+    rows: impl IntoIterator<Item = (&'a String, &'a Attributes)>,
+    policy: Policy,
+) -> Result<()> {
+    let mut unsupported: Vec<(Format, Vec<Attribute>)> = Vec::new();
+    for (path, attr) in rows {
+        let Some(format) = Format::of(Path::new(path)) else {
+            continue;
+        };
+        let idx = match unsupported.iter().position(|(f, _)| *f == format) {
+            Some(idx) => idx,
+            None => {
+                unsupported.push((format, Vec::new()));
+                unsupported.len() - 1
+            }
+        };
+        for &attribute in Attribute::ALL {
+            let attributes = &mut unsupported[idx].1;
+            if format.support(attribute) != Support::Native
+                && !attr.values(attribute).is_empty()
+                && !attributes.contains(&attribute)
+            {
+                attributes.push(attribute);
+            }
+        }
+    }
+
+    for (format, attributes) in unsupported {
+        if attributes.is_empty() {
+            continue;
+        }
+        let names: Vec<_> = attributes.iter().map(|a| a.name()).collect();
+        let names = names.join(", ");
+        let format = format.name();
+        match policy {
+            Policy::Txxx => warning::emit(
+                warning::Code::UnrepresentableField,
+                format_args!("{format} has no field for {names}; writing TXXX frames instead"),
+            ),
+            Policy::Drop => warning::emit(
+                warning::Code::UnrepresentableField,
+                format_args!("{format} has no field for {names}; leaving them out"),
+            ),
+            Policy::Abort => {
+                return Err(Error::Unrepresentable {
+                    format: format.into(),
+                    attributes: names,
+                })
+            }
+        }
+    }
+    Ok(())
+}
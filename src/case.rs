@@ -0,0 +1,151 @@
+use std::sync::OnceLock;
+
+/// Words title case leaves as they're spelled here rather than capitalizing,
+/// such as `iPhone`, `AC/DC`, or `EP`. Matched without regard to case, so
+/// `ac/dc` and `Ac/Dc` both become `AC/DC`.
+#[derive(Clone, Debug)]
+pub(crate) struct Dictionary {
+    words: Vec<String>,
+}
+
+/// Spellings kept without being configured.
+const BUILT_IN: &[&str] = &[
+    "EP", "LP", "DJ", "MC", "TV", "UK", "USA", "OK", "II", "III", "IV", "VI", "VII", "VIII", "IX",
+    "XI", "XII",
+];
+
+/// Words which stay in lower case except at the start or end of a title.
+const SMALL: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "nor", "of", "on", "or", "the",
+    "to", "vs", "with",
+];
+
+impl Default for Dictionary {
+    fn default() -> Self {
+        Dictionary {
+            words: BUILT_IN.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+}
+
+impl Dictionary {
+    /// Adds a spelling, replacing any with the same letters.
+    pub(crate) fn add(&mut self, word: &str) {
+        self.words.retain(|known| !known.eq_ignore_ascii_case(word));
+        self.words.push(word.into());
+    }
+
+    /// Adds the words of a shared list: one per line, with `#` starting a
+    /// comment.
+    pub(crate) fn add_list(&mut self, text: &str) {
+        for line in text.lines() {
+            let word = line.split('#').next().unwrap_or_default().trim();
+            if !word.is_empty() {
+                self.add(word);
+            }
+        }
+    }
+
+    fn get(&self, word: &str) -> Option<&str> {
+        self.words
+            .iter()
+            .find(|known| known.to_lowercase() == word.to_lowercase())
+            .map(String::as_str)
+    }
+}
+
+static DICTIONARY: OnceLock<Dictionary> = OnceLock::new();
+
+/// Records the dictionary from config. Only the first call has any effect.
+pub(crate) fn configure(dictionary: Dictionary) {
+    let _ = DICTIONARY.set(dictionary);
+}
+
+/// Capitalizes each word of a title, after the dictionary. Small words such
+/// as "of" and "the" are lower-cased unless they start or end the title,
+/// follow a colon, or open a parenthesis; every other word gets a capital
+/// and the rest of its letters in lower case, and so does each part of a
+/// hyphenated word. Punctuation around a word doesn't stop it matching the
+/// dictionary.
+pub(crate) fn title(value: &str) -> String {
+    let dictionary = DICTIONARY.get_or_init(Dictionary::default);
+    let words: Vec<&str> = value.split(' ').collect();
+    let last = words.iter().rposition(|word| !word.is_empty());
+
+    let mut titled = Vec::with_capacity(words.len());
+    let mut starts = true;
+    for (idx, word) in words.iter().enumerate() {
+        // Entries such as "feat." include their punctuation.
+        let core = match dictionary.get(word) {
+            Some(_) => word,
+            None => word.trim_matches(|c: char| !c.is_alphanumeric()),
+        };
+        let start = word.find(core).unwrap_or(0);
+        let (before, after) = (&word[..start], &word[start + core.len()..]);
+
+        let cased = if let Some(known) = dictionary.get(core) {
+            known.to_string()
+        } else if !starts
+            && !before.contains(['(', '['])
+            && Some(idx) != last
+            && SMALL.contains(&&*core.to_lowercase())
+        {
+            core.to_lowercase()
+        } else {
+            core.split('-')
+                .map(capitalize)
+                .collect::<Vec<_>>()
+                .join("-")
+        };
+        titled.push(format!("{before}{cased}{after}"));
+
+        if !word.is_empty() {
+            starts = word.ends_with(':');
+        }
+    }
+    titled.join(" ")
+}
+
+/// Capitalizes the first word of a title and any word following a colon,
+/// and lower-cases the rest, after the dictionary. "I" keeps its capital, as
+/// do its contractions.
+pub(crate) fn sentence(value: &str) -> String {
+    let dictionary = DICTIONARY.get_or_init(Dictionary::default);
+    let mut cased = Vec::new();
+    let mut starts = true;
+    for word in value.split(' ') {
+        let core = match dictionary.get(word) {
+            Some(_) => word,
+            None => word.trim_matches(|c: char| !c.is_alphanumeric()),
+        };
+        let start = word.find(core).unwrap_or(0);
+        let (before, after) = (&word[..start], &word[start + core.len()..]);
+
+        let lower = core.to_lowercase();
+        let pronoun = lower == "i" || lower.starts_with("i'");
+        let core = if let Some(known) = dictionary.get(core) {
+            known.to_string()
+        } else if starts || pronoun {
+            capitalize(core)
+        } else {
+            lower
+        };
+        cased.push(format!("{before}{core}{after}"));
+
+        if !word.is_empty() {
+            starts = word.ends_with(':');
+        }
+    }
+    cased.join(" ")
+}
+
+fn capitalize(part: &str) -> String {
+    let mut chars = part.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
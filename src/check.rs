@@ -0,0 +1,246 @@
+use std::{path::Path, str::FromStr};
+
+use metaflac::block::VorbisComment;
+
+use crate::{analyze, art, audio, audit::AuditLog, snapshot, writeback, Error, Result};
+
+/// Which spellings of the track and disc total keys to write. Players
+/// disagree on whether they read `TRACKTOTAL` or `TOTALTRACKS` (and likewise
+/// `DISCTOTAL` or `TOTALDISCS`), so by default both are kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum TotalsSpelling {
+    #[default]
+    Both,
+    /// `TRACKTOTAL` and `DISCTOTAL`
+    Short,
+    /// `TOTALTRACKS` and `TOTALDISCS`
+    Long,
+}
+
+impl FromStr for TotalsSpelling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "both" => Ok(TotalsSpelling::Both),
+            "short" | "tracktotal" => Ok(TotalsSpelling::Short),
+            "long" | "totaltracks" => Ok(TotalsSpelling::Long),
+            _ => Err(format!("expected both, short, or long; found {s}")),
+        }
+    }
+}
+
+/// The sample rate and bit depth combinations a library accepts, from
+/// `[hires] allow = 96000/24` entries in config. With no entries, every
+/// combination is allowed.
+#[derive(Debug, Default)]
+pub(crate) struct HiresPolicy {
+    pub(crate) allowed: Vec<(u32, u8)>,
+}
+
+impl HiresPolicy {
+    pub(crate) fn allows(&self, sample_rate: u32, bits: u8) -> bool {
+        self.allowed.is_empty() || self.allowed.contains(&(sample_rate, bits))
+    }
+}
+
+/// A `rate/bits` pair, such as `44100/16`.
+pub(crate) struct RateAndBits(pub(crate) u32, pub(crate) u8);
+
+impl FromStr for RateAndBits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let expected = || format!("expected a rate and bit depth such as 96000/24; found {s}");
+        let (rate, bits) = s.split_once('/').ok_or_else(expected)?;
+        Ok(RateAndBits(
+            rate.trim().parse().map_err(|_| expected())?,
+            bits.trim().parse().map_err(|_| expected())?,
+        ))
+    }
+}
+
+/// Content below this level, relative to the whole spectrum, is taken to be
+/// resampling noise rather than real high-frequency information.
+const ULTRASONIC_FLOOR: f64 = -90.0;
+
+/// Describes each way a high-resolution file falls short of its nominal format
+/// or the configured policy.
+pub(crate) fn hires_issues(path: &Path, policy: &HiresPolicy) -> Result<Vec<String>> {
+    let format = audio::read(path)?;
+    let bits = format.bits.unwrap_or(16);
+    let mut issues = Vec::new();
+
+    if !policy.allows(format.sample_rate, bits) {
+        issues.push(format!(
+            "{} Hz / {bits} bit is not allowed by policy",
+            format.sample_rate
+        ));
+    }
+
+    if bits <= 16 && format.sample_rate <= 48000 {
+        return Ok(issues);
+    }
+
+    let resolution = analyze::resolution(path)?;
+    if bits > 16 && resolution.padded_to_16 {
+        issues.push(format!("{bits} bit, but the samples are 16 bit padded"));
+    }
+    if let Some(level) = resolution.ultrasonic {
+        if level < ULTRASONIC_FLOOR {
+            issues.push(format!(
+                "{} Hz, but there is no content above 22 kHz",
+                format.sample_rate
+            ));
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Describes what's wrong with a FLAC file's audio: frames which don't
+/// decode, or decoded samples which don't match the MD5 in STREAMINFO. A file
+/// whose encoder stored no MD5 is checked against the one write-back kept
+/// when it was first checked, or has it kept now.
+pub(crate) fn integrity_issues(path: &Path, log: &mut AuditLog) -> Result<Vec<String>> {
+    let flac = match metaflac::Tag::read_from_path(path) {
+        Ok(flac) => flac,
+        Err(e) => return Ok(vec![format!("unreadable metadata: {e}")]),
+    };
+    let Some(info) = flac.get_streaminfo() else {
+        return Ok(vec!["no STREAMINFO block".into()]);
+    };
+
+    let md5 = match analyze::audio_md5(path, info.bits_per_sample) {
+        Ok(md5) => md5,
+        Err(Error::FfmpegFailed(_)) => return Ok(vec!["doesn't decode cleanly".into()]),
+        Err(e) => return Err(e),
+    };
+    if info.md5.iter().any(|&b| b != 0) {
+        return match info.md5 == md5 {
+            true => Ok(Vec::new()),
+            false => Ok(vec![format!(
+                "decoded audio has MD5 {}, but STREAMINFO records {}",
+                hex::encode(md5),
+                hex::encode(&info.md5)
+            )]),
+        };
+    }
+
+    let md5 = hex::encode(md5);
+    match writeback::read(path, &[writeback::AUDIO_MD5])? {
+        Some(kept) if kept[0] != md5 => Ok(vec![format!(
+            "decoded audio has MD5 {md5}, but {} records {}",
+            writeback::AUDIO_MD5,
+            kept[0]
+        )]),
+        Some(_) => Ok(Vec::new()),
+        None => {
+            writeback::store(path, &[(writeback::AUDIO_MD5, md5)], log)?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// (short, long) spellings of each total.
+const TOTALS: [(&str, &str); 2] = [("TRACKTOTAL", "TOTALTRACKS"), ("DISCTOTAL", "TOTALDISCS")];
+
+/// Describes each way a comment's totals deviate from the configured spelling.
+pub(crate) fn totals_issues(comment: &VorbisComment, spelling: TotalsSpelling) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    for (short, long) in TOTALS {
+        let (short_values, long_values) = (comment.get(short), comment.get(long));
+        match (short_values, long_values, spelling) {
+            (None, None, _) => {}
+            (Some(a), Some(b), TotalsSpelling::Both) if a != b => {
+                issues.push(format!("{short} and {long} disagree"))
+            }
+            (Some(_), None, TotalsSpelling::Both) => issues.push(format!("{long} is missing")),
+            (None, Some(_), TotalsSpelling::Both) => issues.push(format!("{short} is missing")),
+            (_, Some(_), TotalsSpelling::Short) => issues.push(format!("uses {long}")),
+            (Some(_), _, TotalsSpelling::Long) => issues.push(format!("uses {short}")),
+            _ => {}
+        }
+    }
+
+    issues
+}
+
+/// Rewrites totals to the configured spelling, copying values between
+/// spellings as needed. Where both spellings exist, the short one wins.
+pub(crate) fn fix_totals(comment: &mut VorbisComment, spelling: TotalsSpelling) {
+    for (short, long) in TOTALS {
+        let Some(values) = comment.get(short).or_else(|| comment.get(long)).cloned() else {
+            continue;
+        };
+
+        match spelling {
+            TotalsSpelling::Both => {
+                comment.set(short, values.clone());
+                comment.set(long, values);
+            }
+            TotalsSpelling::Short => {
+                comment.set(short, values);
+                comment.remove(long);
+            }
+            TotalsSpelling::Long => {
+                comment.set(long, values);
+                comment.remove(short);
+            }
+        }
+    }
+}
+
+/// Describes each tag problem known to trip up DLNA media servers such as
+/// MiniDLNA and Plex: a missing album artist (which splits compilations into
+/// one album per artist), empty values, embedded art in formats other than
+/// JPEG, and characters that break their scanners or XML responses.
+pub(crate) fn dlna_issues(path: &Path) -> Result<Vec<String>> {
+    let tags = snapshot::read_tags(path)?;
+    let mut issues = Vec::new();
+
+    let has_album_artist = tags.iter().any(|(key, values)| {
+        (key.eq_ignore_ascii_case("ALBUMARTIST") || key == "TPE2")
+            && values.iter().any(|value| !value.trim().is_empty())
+    });
+    if !has_album_artist {
+        issues.push("album artist is missing".to_string());
+    }
+
+    for (key, values) in &tags {
+        if key == "PICTURE" {
+            continue;
+        }
+        if values.iter().any(|value| value.trim().is_empty()) {
+            issues.push(format!("{key} is empty"));
+        }
+        if let Some(c) = values
+            .iter()
+            .flat_map(|value| value.chars())
+            .find(|&c| bad_char(c))
+        {
+            issues.push(format!("{key} contains U+{:04X}", c as u32));
+        }
+    }
+
+    for picture in art::read(path)? {
+        if !picture.data.starts_with(&[0xff, 0xd8, 0xff]) {
+            let format = match art::extension(&picture.data) {
+                "jpg" => "unrecognized",
+                format => format,
+            };
+            issues.push(format!("{:?} art is {format}, not JPEG", picture.kind));
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Control characters other than whitespace aren't allowed in the XML servers
+/// send to clients, a byte order mark within a value is left over from a bad
+/// conversion, and the replacement character means the text was mangled on
+/// the way in.
+fn bad_char(c: char) -> bool {
+    (c.is_control() && !matches!(c, '\t' | '\n' | '\r')) || c == '\u{feff}' || c == '\u{fffd}'
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{config, Error, Result};
+
+/// Progress through a long-running operation, saved after each file so that
+/// an interrupted run can pick up where it stopped. Checkpoints are written as
+/// tab-separated records:
+///
+/// ```text
+/// checkpoint <operation>
+/// <path>     <result fields...>
+/// ```
+///
+/// The file is removed once the operation finishes.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    done: HashMap<String, Vec<String>>,
+    writer: csv::Writer<File>,
+}
+
+impl Checkpoint {
+    /// Resumes from an existing checkpoint, or starts a new one in the data
+    /// directory.
+    pub(crate) fn open(operation: &str, resume: Option<&Path>) -> Result<Self> {
+        let (path, done) = match resume {
+            Some(path) => (path.to_owned(), read(path, operation)?),
+            None => {
+                let dir = config::data_dir()
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "unable to locate data directory")
+                    })?
+                    .join("checkpoints");
+                fs::create_dir_all(&dir)?;
+
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                let name = format!("{}-{millis:x}.tsv", operation.replace(' ', "-"));
+                (dir.join(name), HashMap::new())
+            }
+        };
+
+        let is_new = done.is_empty() && !path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .from_writer(file);
+        if is_new {
+            writer.write_record(["checkpoint", operation])?;
+            writer.flush()?;
+            eprintln!(
+                "saving progress to {0}; if interrupted, resume with --resume {0}",
+                path.display()
+            );
+        }
+
+        Ok(Checkpoint { path, done, writer })
+    }
+
+    /// The saved result for a file, if it was finished before.
+    pub(crate) fn get(&self, item: &str) -> Option<&[String]> {
+        self.done.get(item).map(Vec::as_slice)
+    }
+
+    pub(crate) fn record(&mut self, item: &str, fields: &[String]) -> Result<()> {
+        let mut record = vec![item];
+        record.extend(fields.iter().map(String::as_str));
+        self.writer.write_record(&record)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Removes the checkpoint after a successful run.
+    pub(crate) fn finish(self) -> Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+fn read(path: &Path, operation: &str) -> Result<HashMap<String, Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .has_headers(false)
+        .from_path(path)?;
+
+    let mut records = reader.records();
+    match records.next().transpose()? {
+        Some(header) if &header[0] == "checkpoint" && header.get(1) == Some(operation) => {}
+        _ => {
+            return Err(Error::Checkpoint(format!(
+                "{} is not a checkpoint for {operation}",
+                path.display()
+            )))
+        }
+    }
+
+    let mut done = HashMap::new();
+    for record in records {
+        let record = record?;
+        let mut fields = record.iter().map(String::from);
+        if let Some(item) = fields.next() {
+            done.insert(item, fields.collect());
+        }
+    }
+    Ok(done)
+}
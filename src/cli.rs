@@ -0,0 +1,6272 @@
+use std::{
+    borrow::Cow,
+    cell::OnceCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env,
+    ffi::OsStr,
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    net::SocketAddr,
+    num::NonZeroUsize,
+    path::{self, Path, PathBuf},
+    process, slice,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{builder::NonEmptyStringValueParser, Parser};
+use id3::TagLike;
+use metaflac::block::VorbisComment;
+
+use crate::{
+    analyze,
+    application::{self, ApplicationId, DataFormat},
+    archive, art, artists, audio,
+    audit::{self, AuditLog},
+    auth, blocks, budget, capability, case, check,
+    checkpoint::Checkpoint,
+    collate,
+    condition::Condition,
+    config::Config,
+    convert::{self, Backend, Conversion},
+    copy, describe, device, digest, dj, dupes, encoding,
+    failures::{self, Failures},
+    feed, fetch, grouping, ingest,
+    lock::{FileLock, LibraryLock},
+    lyrics, manifest,
+    matching::{self, Matching},
+    metrics, musicbrainz, nfo, nml, normalize, ogg, output, pathmap, pipeline, plan, playlist,
+    preflight,
+    progress::Progress,
+    recipe, riplog,
+    roots::{FileArgument, Roots},
+    safety, session, sheet, snapshot, stats, strip,
+    tags::{self, format_track, write_id3, write_vorbis},
+    template,
+    throttle::{self, Throttle},
+    tools::{self, Tool},
+    transcode, tui, verify, versions, warning,
+    watch::Watcher,
+    writeback, Attribute, Attributes, Error, FileAttributes, Result,
+};
+
+#[derive(Debug, Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// path to a config file
+    ///
+    /// Defaults to $FLACDAT_CONFIG or flacdat/config in the platform config directory.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// allow changes to fields marked as protected in config
+    #[arg(long, global = true)]
+    unprotect: bool,
+
+    /// rewrite a path prefix in attribute sheets and output paths, as HOST=LOCAL
+    ///
+    /// For running against the same files mounted elsewhere, as in a container: with
+    /// --path-prefix-map /home/me/music=/data, sheet paths under /home/me/music are read from /data,
+    /// and paths listed under /data are written as /home/me/music. May be given more than once.
+    #[arg(long, global = true, value_name = "HOST=LOCAL")]
+    path_prefix_map: Vec<pathmap::PrefixPair>,
+
+    /// limit how fast files are read, as bytes (20MB/s) or files (2files/s) per second
+    #[arg(long, global = true)]
+    throttle: Option<throttle::Rate>,
+
+    /// run at low priority, along with any tools flacdat starts
+    #[arg(long, global = true)]
+    nice: bool,
+
+    /// work through files in the order given rather than sorted by path
+    #[arg(long, global = true)]
+    keep_order: bool,
+
+    /// read every file back after writing its tags, failing unless the audio is byte-identical
+    /// and the tags read back as written
+    #[arg(long, global = true)]
+    verify_writes: bool,
+
+    /// keep values computed from the audio in the files, and use ones kept before
+    ///
+    /// The dynamic range and loudness measured by analyze dr and gain, and the decoded MD5 of
+    /// files verify finds without one in STREAMINFO, are kept in FLACDAT_* tags along with the
+    /// duration, and read back rather than measured again while the audio is unchanged. Off
+    /// unless given, or set with [scan] writeback = true in config.
+    #[arg(long, global = true)]
+    writeback: bool,
+
+    /// go ahead without asking, even with more files than [safety] max-files allows
+    ///
+    /// Commands which change files ask before changing more than 1,000 of them at once, or fail
+    /// when there's no terminal to ask at.
+    #[arg(long, short, global = true)]
+    yes: bool,
+
+    /// answer web lookups from the cache only, making no requests
+    ///
+    /// Lookups which aren't cached fail at once. Failures for want of the network, offline or not,
+    /// exit with status 75 rather than 1, so scripts can tell them apart and retry later.
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// silence a warning, given by code (W001) or name (nonstandard-year); may be given more than
+    /// once
+    #[arg(long, global = true, value_name = "CODE")]
+    allow: Vec<warning::Code>,
+
+    /// make a warning an error, failing the command once it finishes; may be given more than once
+    #[arg(long, global = true, value_name = "CODE")]
+    deny: Vec<warning::Code>,
+
+    /// make every warning not allowed an error
+    #[arg(long, global = true)]
+    warn_as_error: bool,
+
+    /// write a JSON manifest of every file the command created, changed, or moved to FILE
+    ///
+    /// Each entry gives the file's path (and former path, if moved), what happened to it, the id
+    /// of the audit log operation which did it, and its size and SHA-256 once the command has
+    /// finished.
+    #[arg(long, global = true, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// separate the values of artist, language, genre, and composer in sheets with SEP rather
+    /// than a comma
+    ///
+    /// For names with commas in them. Overrides [format] multi-separator. Values are split on the
+    /// separator when sheets are read, and joined with it when they're listed.
+    #[arg(long, global = true, value_name = "SEP", value_parser = NonEmptyStringValueParser::new())]
+    multi_sep: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+enum Command {
+    Apply(ApplyAttributes),
+    List(List),
+    Edit(EditTags),
+    Tui(EditInTerminal),
+    Diff(DiffSheet),
+    Find(FindFiles),
+    Stats(ShowStats),
+    Playlist(MakePlaylist),
+    Convert(ConvertToFlac),
+    Run(RunPipeline),
+    Log(ShowLog),
+    Revert(RevertOperation),
+    #[command(subcommand)]
+    Check(Check),
+    Blocks(ShowBlocks),
+    Describe(DescribeFiles),
+    #[command(subcommand)]
+    App(App),
+    #[command(subcommand)]
+    Art(Art),
+    Lyrics(LyricsSidecars),
+    #[command(subcommand)]
+    Analyze(Analyze),
+    #[command(subcommand)]
+    Snapshot(Snapshot),
+    Export(ExportTags),
+    Ingest(Ingest),
+    Watch(Watch),
+    #[command(subcommand)]
+    Recipe(Recipe),
+    Feed(MakeFeed),
+    Nml(ExportNml),
+    Nfo(ExportNfo),
+    #[command(subcommand)]
+    Riplog(Riplog),
+    Tracklist(MakeTracklist),
+    Versions(FindVersions),
+    Dupes(FindDupes),
+    Rename(RenameFiles),
+    Organize(Organize),
+    TagFromFilename(TagFromFilename),
+    Set(Box<SetAttributes>),
+    Strip(StripTags),
+    Normalize(NormalizeTags),
+    CopyTags(CopyTags),
+    Lookup(LookupRelease),
+    #[command(subcommand)]
+    Session(Session),
+    Plan(PlanDiscs),
+    Gain(ReplayGain),
+    Transcode(TranscodeFiles),
+    Verify(VerifyAudio),
+    #[command(subcommand)]
+    Auth(Auth),
+    Tools(ShowTools),
+}
+
+/// manage API tokens for web services, kept in the OS keyring
+///
+/// Tokens live in the Secret Service keyring (through secret-tool) or the macOS keychain, never in
+/// config. A FLACDAT_<SERVICE>_TOKEN environment variable takes precedence over a stored token.
+#[derive(Debug, clap::Subcommand)]
+enum Auth {
+    Set(AuthSet),
+    Remove(AuthRemove),
+    Status(AuthStatus),
+}
+
+/// store a token, read from stdin
+#[derive(Debug, Parser)]
+struct AuthSet {
+    #[arg(value_enum)]
+    service: auth::Service,
+}
+
+/// remove a stored token
+#[derive(Debug, Parser)]
+struct AuthRemove {
+    #[arg(value_enum)]
+    service: auth::Service,
+}
+
+/// show which services have a token, and where it comes from
+#[derive(Debug, Parser)]
+struct AuthStatus {}
+
+/// move files into a library laid out by their tags
+///
+/// Each file goes to the path the pattern gives under --into, with directories created as needed.
+/// Patterns are as for rename; the default files tracks as
+/// <album artist, or artist>/<album>/<track> <title>. A file already at the target is never
+/// overwritten. Prints each old and new path.
+///
+/// Names too long for the library's filesystem are shortened, keeping their track number and
+/// extension, and each is reported. Every target is worked out before anything is moved.
+///
+/// When the pattern names the album artist, a track with none is filed with the rest of its album
+/// if they agree on one. Albums which will still be split between directories, and tracks
+/// crediting artists other than the album artist, are reported (warning mixed-artists).
+#[derive(Debug, Parser)]
+struct Organize {
+    /// files or directories to organize
+    files: Vec<PathBuf>,
+
+    /// the library to file tracks into
+    #[arg(long)]
+    into: PathBuf,
+
+    /// the path of each file within the library
+    #[arg(
+        long,
+        default_value = "{albumartist|artist}/{album}/{track:02} {title}"
+    )]
+    pattern: String,
+
+    /// copy files rather than moving them
+    #[arg(long)]
+    copy: bool,
+
+    /// organize the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// show where files would go without moving anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// the longest path allowed, in bytes, if shorter than the filesystem's limit
+    #[arg(long)]
+    max_path: Option<usize>,
+}
+
+/// clean up the spelling of tags in place
+///
+/// Trims whitespace from the ends of values, collapses runs of spaces, and composes accented
+/// letters written as a letter and a combining mark (Unicode NFC), unless told not to. --case
+/// recases titles and albums, --feat spells credits of featured artists the same way everywhere, and
+/// --track-width pads FLAC track numbers. Each change is printed; with --dry-run, nothing is
+/// written.
+#[derive(Debug, Parser)]
+struct NormalizeTags {
+    /// FLAC or MP3 files, or directories of them
+    files: Vec<PathBuf>,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// leave whitespace at the ends of values
+    #[arg(long)]
+    no_trim: bool,
+
+    /// leave runs of spaces within values
+    #[arg(long)]
+    no_collapse: bool,
+
+    /// leave letters and combining marks as they are
+    #[arg(long)]
+    no_nfc: bool,
+
+    /// recase titles and albums
+    #[arg(long, value_enum)]
+    case: Option<normalize::Case>,
+
+    /// credit featured artists ("ft.", "featuring", "(feat. ...)") in the title or among the
+    /// artists
+    #[arg(long, value_enum)]
+    feat: Option<normalize::Feat>,
+
+    /// zero-pad FLAC track numbers to this many digits, or strip padding with 0
+    ///
+    /// Defaults to [format] track-width; track numbers are left as they are without either. A
+    /// number written with its total, as 3/12, is left alone.
+    #[arg(long)]
+    track_width: Option<usize>,
+
+    /// print the changes without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+impl NormalizeTags {
+    const EXTENSIONS: &'static [&'static str] = &["flac", "mp3"];
+}
+
+/// remove tags from files
+///
+/// Fields are attribute names, which remove every key or frame the attribute is read from, or raw
+/// vorbis keys, ID3 frame IDs (four capitals, as TXXX), or TXXX descriptions. --all removes every
+/// vorbis comment and picture from FLAC files, and every ID3 frame from MP3s.
+#[derive(Debug, Parser)]
+#[command(group(clap::ArgGroup::new("what").required(true).args(["all", "field", "padding"]).multiple(true)))]
+struct StripTags {
+    files: Vec<PathBuf>,
+
+    /// remove every tag
+    #[arg(long, conflicts_with = "field")]
+    all: bool,
+
+    /// a field to remove; may be given more than once
+    #[arg(long)]
+    field: Vec<strip::Field>,
+
+    /// rewrite FLAC files without padding, so no space is left where tags were
+    #[arg(long)]
+    padding: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// refuse to rewrite an MP3 unless its Serato and Rekordbox cue and beatgrid frames survive
+    #[arg(long)]
+    preserve_dj_data: bool,
+}
+
+/// copy tags and artwork from one set of files to another
+///
+/// Pairs the files under --to with those under --from by disc and track number, or by file name
+/// less its extension, then copies every attribute and embedded picture across. FLAC to FLAC
+/// copies the whole vorbis comment except ReplayGain values, which belong to the old audio. Files
+/// which can't be paired are reported and left alone.
+#[derive(Debug, Parser)]
+struct CopyTags {
+    /// the file or directory to copy tags from
+    #[arg(long)]
+    from: PathBuf,
+
+    /// the file or directory to copy tags to
+    #[arg(long)]
+    to: PathBuf,
+
+    /// how files are paired
+    #[arg(long, value_enum, default_value_t)]
+    by: copy::Pairing,
+
+    /// pair the files in subdirectories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// show the pairs and changes without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// refuse to rewrite an MP3 unless its Serato and Rekordbox cue and beatgrid frames survive
+    #[arg(long)]
+    preserve_dj_data: bool,
+}
+
+/// look up a release on MusicBrainz and write it as an attribute sheet
+///
+/// Give a release MBID with --release-id, or --artist and --album to search. The best match is
+/// used and the others are listed on stderr, so a different one can be chosen by id. Files given
+/// are paired with the release's tracks in order to fill in the path column, so the sheet can be
+/// piped straight into apply. With --session, the sheet is saved for review instead.
+#[derive(Debug, Parser)]
+struct LookupRelease {
+    /// files or directories whose tracks the release describes, in track order
+    files: Vec<PathBuf>,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// the MusicBrainz release id
+    #[arg(long, conflicts_with_all = ["artist", "album"], required_unless_present = "album")]
+    release_id: Option<String>,
+
+    /// search for a release by this artist
+    #[arg(long, requires = "album")]
+    artist: Option<String>,
+
+    /// search for a release with this title
+    #[arg(long, requires = "artist")]
+    album: Option<String>,
+
+    /// zero-pad track numbers to this many digits
+    #[arg(long)]
+    track_width: Option<usize>,
+
+    /// how to write the sheet; apply reads any but table
+    #[arg(long, value_enum, default_value_t)]
+    format: output::Format,
+
+    /// separate the fields of csv with this instead of commas
+    #[arg(long, value_enum)]
+    delimiter: Option<sheet::Delimiter>,
+
+    /// add the release to this review session rather than printing its sheet
+    ///
+    /// Albums in a session wait to be approved or rejected with the session commands, over as
+    /// many sittings as it takes, and only approved ones are written by apply --session.
+    #[arg(long, requires = "files", conflicts_with = "format")]
+    session: Option<String>,
+}
+
+/// review albums proposed by lookups before applying them
+///
+/// Sessions are stored in the data directory. Add to one with lookup --session, and apply the
+/// albums approved so far with apply --session; each is applied once.
+#[derive(Debug, clap::Subcommand)]
+enum Session {
+    List(SessionList),
+    Show(SessionShow),
+    Approve(SessionReview),
+    Reject(SessionReview),
+}
+
+/// list sessions, or the albums in one and where each stands
+#[derive(Debug, Parser)]
+struct SessionList {
+    name: Option<String>,
+}
+
+/// print the attribute sheet proposed for an album
+///
+/// The sheet is kept in the session's directory, whose path is printed on stderr, and can be
+/// edited there before the album is approved.
+#[derive(Debug, Parser)]
+struct SessionShow {
+    name: String,
+    album: usize,
+}
+
+/// mark albums in a session approved or rejected
+#[derive(Debug, Parser)]
+struct SessionReview {
+    name: String,
+
+    /// the albums' numbers, as session list shows them
+    #[arg(required_unless_present = "pending")]
+    albums: Vec<usize>,
+
+    /// every album still pending
+    #[arg(long, conflicts_with = "albums")]
+    pending: bool,
+}
+
+/// split files into discs or mixtapes no longer than a given length
+///
+/// Tracks keep their order, and are spread so the discs come out about the same length rather
+/// than leaving the last one short. One M3U playlist is written per disc, named disc-01.m3u and so
+/// on, and each disc's track count and length are printed.
+#[derive(Debug, Parser)]
+struct PlanDiscs {
+    /// files or directories to plan
+    files: Vec<PathBuf>,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// the most a disc may hold, as seconds, m:ss, or h:mm:ss; 79:57 fits an 80-minute CD-R
+    #[arg(long)]
+    max_duration: plan::Length,
+
+    /// what to keep together on one disc
+    #[arg(long, value_enum, default_value_t)]
+    split_by: plan::Split,
+
+    /// the directory to write playlists to
+    #[arg(long)]
+    out: PathBuf,
+
+    /// the name playlists start with
+    #[arg(long, default_value = "disc")]
+    name: String,
+}
+
+/// write M3U8 playlists of files, ordered by their tags
+///
+/// With --by album, a playlist is written for each album into the directory --out, named
+/// <album artist, or artist> - <album>.m3u8, with its tracks in disc and track order; files without
+/// an album are left out. Otherwise every file goes into the one playlist --out, ordered by --sort.
+/// Each playlist's path is printed.
+#[derive(Debug, Parser)]
+struct MakePlaylist {
+    /// files or directories to list
+    files: Vec<PathBuf>,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// how to divide the files between playlists
+    #[arg(long, value_enum, default_value_t)]
+    by: playlist::By,
+
+    /// what to order a playlist of every file by
+    #[arg(long, value_enum, default_value_t)]
+    sort: playlist::Sort,
+
+    /// the playlist to write, or with --by album, the directory to write them to
+    #[arg(long)]
+    out: PathBuf,
+
+    /// write each track's path relative to its playlist, separated with '/', as portable players
+    /// need; otherwise paths are absolute
+    #[arg(long)]
+    relative: bool,
+}
+
+/// measure loudness and write ReplayGain tags
+///
+/// Loudness is measured as EBU R128 specifies, and each gain brings a track to the ReplayGain 2.0
+/// reference of -18 LUFS. Writes REPLAYGAIN_TRACK_GAIN and REPLAYGAIN_TRACK_PEAK (TXXX frames in
+/// MP3s). With --album, files are grouped into albums by album tag, or as --group-by says, and each
+/// also gets REPLAYGAIN_ALBUM_GAIN and REPLAYGAIN_ALBUM_PEAK, measured over the whole album.
+#[derive(Debug, Parser)]
+struct ReplayGain {
+    files: Vec<String>,
+
+    /// also write album gain and peak
+    #[arg(long)]
+    album: bool,
+
+    /// group files into albums by these attributes, comma-separated, or by directory with dir
+    ///
+    /// Defaults to album. Grouping by tag, as with albumartist,album, keeps together albums whose
+    /// tracks are spread over several directories; dir keeps apart albums which share a name.
+    #[arg(long, value_name = "KEYS", requires = "album")]
+    group_by: Option<grouping::Grouping>,
+
+    /// print the gains without writing them
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// temporarily make read-only files writable when writing tags
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// encode lossy copies of FLAC files, carrying over their tags and pictures
+///
+/// Each copy is written beside its source or, with --out, into that directory, keeping its place
+/// under the directory it was found in. MP3s are tagged as by set, with any other vorbis comments
+/// in TXXX frames, and take every picture. Opus files keep every comment, and the front cover. M4A
+/// files take the tags MP4 has fields for, and the first picture. Existing copies are left alone
+/// unless --force is given.
+#[derive(Debug, Parser)]
+struct TranscodeFiles {
+    /// FLAC files or directories to transcode
+    files: Vec<PathBuf>,
+
+    /// the format to encode to
+    #[arg(long, value_enum)]
+    to: transcode::Codec,
+
+    /// the bitrate to encode at, as ffmpeg takes it
+    #[arg(long, default_value = "192k")]
+    bitrate: String,
+
+    /// encode at this rate, in Hz, rather than one picked for each file
+    ///
+    /// Without it, each file keeps its own rate if the format takes it and it's no higher than
+    /// --max-sample-rate, or else takes the highest such rate of its own family (multiples of
+    /// 44.1kHz or of 48kHz), so as not to resample between them when it can be helped. Resampling
+    /// between them when a rate of the source's family would do is warned about (resample).
+    #[arg(long, value_name = "HZ", conflicts_with = "max_sample_rate")]
+    sample_rate: Option<u32>,
+
+    /// the highest rate to pick, in Hz
+    #[arg(long, value_name = "HZ", default_value_t = 48000)]
+    max_sample_rate: u32,
+
+    /// the directory to write copies to
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// transcode the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// replace existing copies
+    #[arg(long)]
+    force: bool,
+}
+
+/// decode FLAC files and check them against the MD5 in STREAMINFO
+///
+/// Reports files which don't decode cleanly or whose decoded audio doesn't match the digest the
+/// encoder stored, and exits with a non-zero status if there are any. Files without a stored MD5
+/// are only checked for decoding, unless write-back is on (--writeback): then the MD5 of their
+/// audio is kept in FLACDAT_AUDIO_MD5 the first time, and later runs check against that. Run it
+/// over a library now and then to catch bit rot.
+#[derive(Debug, Parser)]
+struct VerifyAudio {
+    files: Vec<String>,
+
+    /// continue an interrupted run from its checkpoint file
+    #[arg(long)]
+    resume: Option<PathBuf>,
+}
+
+/// set tags to the same literal values in every file listed
+///
+/// Only the fields given are changed. Fields holding several values (artist, language, genre,
+/// composer) may be given more than once; --artist replaces the artists, while --append-artist
+/// adds to them. MP3 files take album, title, track, year, artist, genre, album artist, disc,
+/// composer, and comment.
+#[derive(Debug, Parser)]
+struct SetAttributes {
+    files: Vec<PathBuf>,
+
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    album: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    artist: Vec<String>,
+    /// add an artist, keeping those already there
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    append_artist: Vec<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    title: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    track: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    year: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    language: Vec<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    work: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    movement_name: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    movement: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    movement_total: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    show_movement: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    grouping: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    media: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    release_country: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    genre: Vec<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    album_artist: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    disc: Option<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    composer: Vec<String>,
+    #[arg(long, value_parser = NonEmptyStringValueParser::new())]
+    comment: Option<String>,
+
+    /// digits to zero-pad track numbers to
+    #[arg(long)]
+    track_width: Option<usize>,
+
+    /// show what would change without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// refuse to rewrite an MP3 unless its Serato and Rekordbox cue and beatgrid frames survive
+    #[arg(long)]
+    preserve_dj_data: bool,
+}
+
+/// tag files from their names
+///
+/// The pattern is as for rename, read the other way: "{track} - {artist} - {title}" takes the
+/// track, artist, and title from "03 - Artist - Title.flac". A / in the pattern matches directory
+/// names too, as in "{artist}/{album}/{track} {title}". Files whose names don't match are
+/// reported and skipped. Other tags are kept.
+#[derive(Debug, Parser)]
+struct TagFromFilename {
+    files: Vec<PathBuf>,
+
+    /// how file names are laid out
+    #[arg(long)]
+    pattern: String,
+
+    /// show what would be written without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// rename files after their tags
+///
+/// The pattern names attributes in braces, with an optional zero-padding width:
+/// "{track:02} - {artist} - {title}.flac". Characters which aren't safe in file names are
+/// replaced with _, and a pattern without an extension keeps each file's own. Files are renamed
+/// within their directory, and a / in the pattern makes subdirectories. Prints each old and new
+/// path. Names too long for the filesystem are shortened as for organize.
+#[derive(Debug, Parser)]
+struct RenameFiles {
+    files: Vec<PathBuf>,
+
+    /// the new name of each file
+    #[arg(long)]
+    pattern: String,
+
+    /// show the new names without renaming anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// the longest path allowed, in bytes, if shorter than the filesystem's limit
+    #[arg(long)]
+    max_path: Option<usize>,
+}
+
+/// find files holding the same audio, whatever their tags or names
+///
+/// FLAC files are compared by the MD5 of their decoded audio, as STREAMINFO records it, or as verify
+/// kept it in FLACDAT_AUDIO_MD5, or else as ffmpeg decodes it; MP3s by their frames, less their
+/// tags. The first file of each group, in path order, is the one kept: --move-to or --delete deals
+/// with the rest, printing what it does in place of the groups.
+#[derive(Debug, Parser)]
+struct FindDupes {
+    /// FLAC or MP3 files, or directories of them
+    files: Vec<PathBuf>,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: dupes::Format,
+
+    /// move every copy but the one kept into DIR, under its own path
+    #[arg(long, value_name = "DIR", conflicts_with = "delete")]
+    move_to: Option<PathBuf>,
+
+    /// delete every copy but the one kept; this can't be reverted, unlike
+    /// --move-to
+    #[arg(long)]
+    delete: bool,
+
+    /// print what would be moved or deleted without doing it
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// find alternate versions of the same song, to help pick one for playlists
+///
+/// Tracks are grouped by artist and title, ignoring markers such as "(Live)", "[Radio Edit]", or
+/// "- Instrumental". Each group is printed with every version's kind and length; the unmarked
+/// version sharing its length with the most others is marked with *, and same-length copies and
+/// unmarked tracks of a different length are called out.
+#[derive(Debug, Parser)]
+struct FindVersions {
+    /// files or directories to search
+    files: Vec<PathBuf>,
+
+    /// search the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+}
+
+/// number and title the files of a directory from a plain text tracklist
+///
+/// Reads one title per line, optionally numbered as "1. Title", and matches the lines to the
+/// directory's files in name order. Prints an attribute sheet to review and pass to apply.
+#[derive(Debug, Parser)]
+struct MakeTracklist {
+    dir: PathBuf,
+
+    /// a file containing the tracklist; by default it is read from stdin
+    #[arg(long)]
+    tracklist: Option<PathBuf>,
+}
+
+/// record how an album was ripped, from the ripper's log
+#[derive(Debug, clap::Subcommand)]
+enum Riplog {
+    Import(RiplogImport),
+}
+
+/// check an EAC or whipper log against an album's FLAC files and tag them with its provenance
+///
+/// The files, one per track in track order, must match the log's track count and, where the log
+/// has them, its copy CRCs. Each file gets RIPPER, RIPDRIVE, RIPDATE, and ACCURATERIP tags, and
+/// the log is copied into the album's directory.
+#[derive(Debug, Parser)]
+struct RiplogImport {
+    log: PathBuf,
+    files: Vec<String>,
+
+    /// tag the files even where their audio doesn't match the log's CRCs
+    #[arg(long)]
+    force: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// export tags, BPM, and key as a Traktor NML collection
+///
+/// Reads BPM from BPM (TBPM in MP3) and key from INITIALKEY or KEY (TKEY in MP3). Import the
+/// collection in Traktor to pick up the tags without re-analyzing the files.
+#[derive(Debug, Parser)]
+struct ExportNml {
+    /// files or directories to export
+    files: Vec<PathBuf>,
+
+    /// export the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// the collection file to write
+    #[arg(long)]
+    out: PathBuf,
+}
+
+/// write Kodi album.nfo and artist.nfo files from tags
+///
+/// Each directory of tracks gets an album.nfo, and the directory above it an artist.nfo when
+/// every album found there has the same album artist. Kodi and Jellyfin read the MusicBrainz IDs
+/// in these files rather than guessing at the release. Existing NFO files are left alone unless
+/// --force is given.
+#[derive(Debug, Parser)]
+struct ExportNfo {
+    /// files or directories to export
+    files: Vec<PathBuf>,
+
+    /// export the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// replace existing NFO files
+    #[arg(long)]
+    force: bool,
+
+    /// show the files which would be written without writing them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// publish audiobook or podcast folders as podcast feeds
+///
+/// Writes an RSS feed for each folder, named after it, plus feeds.opml listing them all. The
+/// folders must be served over HTTP at --base-url, which corresponds to --root; feeds go into
+/// --out, which must also be under --root. Each feed's cover is the first embedded picture found.
+#[derive(Debug, Parser)]
+struct MakeFeed {
+    dirs: Vec<PathBuf>,
+
+    /// the URL --root is served at; defaults to [feed] base-url in config
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// the directory served at the base URL
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+
+    /// the directory to write feeds to
+    #[arg(long)]
+    out: PathBuf,
+}
+
+/// share tagging as a recipe someone with the same rip can reproduce
+#[derive(Debug, clap::Subcommand)]
+enum Recipe {
+    Create(RecipeCreate),
+    Apply(RecipeApply),
+}
+
+/// write the current tags of a set of FLAC files, and their audio checksums, to a recipe
+///
+/// Paths are recorded as given, so run this from the album directory to make a recipe others can
+/// apply to their own copy.
+#[derive(Debug, Parser)]
+struct RecipeCreate {
+    files: Vec<String>,
+
+    /// the recipe file to write
+    #[arg(long)]
+    out: PathBuf,
+
+    /// include the steps of this pipeline from config, run after the recipe's tags are set
+    #[arg(long)]
+    pipeline: Option<String>,
+}
+
+/// tag files exactly as a recipe describes
+///
+/// Every file's audio is checked against the recipe before anything is written, so tags only go
+/// onto the same rip. The recipe's tags replace the files' own, then its steps run.
+#[derive(Debug, Parser)]
+struct RecipeApply {
+    recipe: PathBuf,
+
+    /// the directory the recipe's paths are relative to
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+
+    /// apply even where the audio doesn't match
+    #[arg(long)]
+    force: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// bring a downloads folder into the library in one step
+///
+/// Unpacks archives, converts WAVs to FLAC, and files tagged tracks as
+/// <artist>/<album>/<track> - <title>; anything else keeps its name under a directory named after
+/// the archive or folder it came in. The plan is shown for confirmation before anything is
+/// written, files already in the library are skipped, and downloads are left in place.
+#[derive(Debug, Parser)]
+struct Ingest {
+    downloads: PathBuf,
+
+    /// the library to file tracks into
+    #[arg(long)]
+    into: PathBuf,
+}
+
+/// bring files into the library as they arrive in an inbox
+///
+/// Runs until interrupted, taking files in the directory, or any below it, once they've stopped
+/// changing; those already there when it starts are taken first. With --convert, WAV, AIFF, ALAC,
+/// APE, and WavPack files are converted to FLAC beside them. With --apply, each file is tagged
+/// from the sheet's row for a file of the same name, whatever its extension, so a row for take.wav
+/// tags take.flac; the sheet is read afresh each time, so rows can be added as it runs. With
+/// --organize, files are then moved under --into, as organize moves them.
+///
+/// A file which can't be brought in is reported and left where it is; the rest carry on.
+///
+/// With --metrics, counts of files brought in and of those which couldn't be, by stage (convert,
+/// tag, sheet, organize), the number waiting, and a histogram of conversion times are served for
+/// Prometheus at http://<ADDR>/metrics.
+#[derive(Debug, Parser)]
+struct Watch {
+    /// the inbox to watch
+    dir: PathBuf,
+
+    /// convert sources to FLAC
+    #[arg(long)]
+    convert: bool,
+
+    /// remove each source once it has been converted
+    #[arg(long, requires = "convert")]
+    delete_source: bool,
+
+    /// convert with flacdat's own encoder rather than ffmpeg, as convert --no-ffmpeg
+    #[arg(long, requires = "convert")]
+    no_ffmpeg: bool,
+
+    /// an attribute sheet to tag files from
+    #[arg(long, value_name = "SHEET")]
+    apply: Option<PathBuf>,
+
+    /// the path of each file within the library, as for organize
+    #[arg(long, value_name = "PATTERN", requires = "into")]
+    organize: Option<String>,
+
+    /// the library to file tracks into
+    #[arg(long, requires = "organize")]
+    into: Option<PathBuf>,
+
+    /// how long a file must go unchanged before it's taken, in seconds
+    #[arg(long, default_value = "2")]
+    settle: u64,
+
+    /// take the files already there and exit, rather than waiting for more
+    #[arg(long)]
+    once: bool,
+
+    /// serve metrics at this address, such as 127.0.0.1:9477
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<SocketAddr>,
+}
+
+/// export the tags of a tree as text files suitable for committing to git
+///
+/// Writes one album.tags file per directory, or one <file>.tags per track with --per-track,
+/// mirroring the layout of the library. Output is in a fixed order, so re-exporting unchanged
+/// tags leaves the files untouched and git diffs show only real changes.
+#[derive(Debug, Parser)]
+struct ExportTags {
+    /// the directory to export; defaults to every root in config, or the current directory
+    dir: Option<PathBuf>,
+
+    /// the directory to write tag files to
+    #[arg(long)]
+    out: PathBuf,
+
+    /// write one file per track instead of one per album directory
+    #[arg(long)]
+    per_track: bool,
+}
+
+/// record the tags of a whole tree and compare them later
+#[derive(Debug, clap::Subcommand)]
+enum Snapshot {
+    Save(SnapshotSave),
+    Diff(SnapshotDiff),
+}
+
+/// save the tags of every FLAC and MP3 file under a directory
+///
+/// Snapshots hold tags and embedded picture checksums only, never audio, and are stored in the
+/// data directory under the given name. Saving over an existing name replaces it.
+#[derive(Debug, Parser)]
+struct SnapshotSave {
+    name: String,
+
+    /// the directory to snapshot; defaults to every root in config, or the current directory
+    dir: Option<PathBuf>,
+}
+
+/// report every field that changed between two snapshots
+///
+/// With one name, compares the snapshot against the current state of the directory it was taken
+/// of. Prints one line per change: the path, the field, and the old and new values; added and
+/// removed files are marked with + and -.
+#[derive(Debug, Parser)]
+struct SnapshotDiff {
+    name: String,
+    other: Option<String>,
+}
+
+/// show the external programs flacdat uses and whether they're installed
+///
+/// Each tool is looked up with $FLACDAT_<TOOL>, then [tools] <tool> = <path> in config, then PATH.
+#[derive(Debug, Parser)]
+struct ShowTools {}
+
+/// measure the audio itself
+#[derive(Debug, clap::Subcommand)]
+enum Analyze {
+    Dr(AnalyzeDr),
+    Spectrogram(AnalyzeSpectrogram),
+    Gaps(AnalyzeGaps),
+}
+
+/// measure the silence at the start and end of each track
+///
+/// Prints the leading and trailing silence of each track, in seconds. --write stores them as
+/// LEADING SILENCE and TRAILING SILENCE tags (TXXX frames in MP3s) for players to place crossfades
+/// by; --gapless also marks MP3s as part of a gapless album, as iTunes does.
+#[derive(Debug, Parser)]
+struct AnalyzeGaps {
+    files: Vec<String>,
+
+    /// the level, in dBFS, below which audio counts as silence
+    #[arg(long, default_value_t = -60.0, allow_negative_numbers = true)]
+    threshold: f64,
+
+    /// write the measurements to tags
+    #[arg(long)]
+    write: bool,
+
+    /// mark MP3s as part of a gapless album (an iTunPGAP comment)
+    #[arg(long, requires = "write")]
+    gapless: bool,
+
+    /// temporarily make read-only files writable when writing tags
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// continue an interrupted run from its checkpoint file
+    #[arg(long)]
+    resume: Option<PathBuf>,
+}
+
+/// render a spectrogram of each file as a PNG
+///
+/// Images are named after the file they show, and the path of each is printed.
+#[derive(Debug, Parser)]
+struct AnalyzeSpectrogram {
+    files: Vec<String>,
+
+    /// the directory to write images to
+    #[arg(long)]
+    out: PathBuf,
+
+    /// the image size, as WIDTHxHEIGHT
+    #[arg(long, default_value = "1024x512")]
+    size: String,
+}
+
+/// compute the dynamic range (DR) score of each track and album
+///
+/// Tracks are grouped into albums by album tag, or as --group-by says; an album's score is the mean
+/// of its tracks'.
+/// Prints the score, peak, and RMS of each track, then the score of each album.
+#[derive(Debug, Parser)]
+struct AnalyzeDr {
+    files: Vec<String>,
+
+    /// write the scores to DYNAMIC RANGE and ALBUM DYNAMIC RANGE tags
+    #[arg(long)]
+    write: bool,
+
+    /// group tracks into albums by these attributes, comma-separated, or by directory with dir
+    ///
+    /// Defaults to album.
+    #[arg(long, value_name = "KEYS")]
+    group_by: Option<grouping::Grouping>,
+
+    /// temporarily make read-only files writable when writing tags
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// continue an interrupted run from its checkpoint file
+    #[arg(long)]
+    resume: Option<PathBuf>,
+}
+
+/// inspect and manage embedded pictures
+#[derive(Debug, clap::Subcommand)]
+enum Art {
+    Dedupe(ArtDedupe),
+    Embed(ArtEmbed),
+    Extract(ArtExtract),
+    Sync(ArtSync),
+    Thumbs(ArtThumbs),
+}
+
+/// write one folder.jpg per album into a copy of the library's layout, for media centers
+///
+/// Each directory of tracks gets the directory at its place under --to, as transcode --out lays
+/// copies out, holding its cover as folder.jpg, the name Kodi and Jellyfin look for. The cover is
+/// the front cover embedded in the first of its tracks which has one, or else the folder.jpg or
+/// cover image beside them; covers in other formats are converted to JPEG. Prints the path of each
+/// cover written. Covers already there are kept unless --force is given.
+#[derive(Debug, Parser)]
+struct ArtSync {
+    /// FLAC or MP3 files, or directories of them
+    files: Vec<PathBuf>,
+
+    /// the directory to write covers to
+    #[arg(long)]
+    to: PathBuf,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// scale down covers whose longest edge exceeds this many pixels
+    #[arg(long)]
+    max_size: Option<u32>,
+
+    /// replace covers which already exist
+    #[arg(long)]
+    force: bool,
+}
+
+/// extract and resize album covers into a cache directory
+///
+/// Writes one <artist>-<album>.jpg per album, named with lowercase letters, digits, and dashes,
+/// and prints the path of each. Existing thumbnails are kept unless --force is given.
+#[derive(Debug, Parser)]
+struct ArtThumbs {
+    files: Vec<String>,
+
+    /// the longest edge of each thumbnail, in pixels
+    #[arg(long, default_value_t = 300)]
+    size: u32,
+
+    /// the cache directory
+    #[arg(long)]
+    out: PathBuf,
+
+    /// regenerate thumbnails which already exist
+    #[arg(long)]
+    force: bool,
+}
+
+/// embed a cover image as each file's front cover
+///
+/// The image is the --image given, or else the folder.jpg, folder.png, cover.jpg, or cover.png
+/// beside each file. An existing front cover is replaced; other pictures are kept.
+#[derive(Debug, Parser)]
+struct ArtEmbed {
+    files: Vec<String>,
+
+    /// the image to embed in every file
+    #[arg(long)]
+    image: Option<PathBuf>,
+
+    /// scale down images whose longest edge exceeds this many pixels
+    #[arg(long)]
+    max_size: Option<u32>,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// refuse to rewrite an MP3 unless its Serato and Rekordbox cue and beatgrid frames survive
+    #[arg(long)]
+    preserve_dj_data: bool,
+}
+
+/// write each file's embedded cover out to an image file
+///
+/// The front cover is chosen when a file has several pictures. Each image is named after its file,
+/// as <basename>.jpg (or .png or .gif), beside the file or in --dir. Prints the path of each image
+/// written. Existing images are kept unless --force is given.
+#[derive(Debug, Parser)]
+struct ArtExtract {
+    files: Vec<String>,
+
+    /// write images to this directory instead of beside each file
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// overwrite images which already exist
+    #[arg(long)]
+    force: bool,
+}
+
+/// embed lyrics from sidecar files, or write them out to sidecars
+///
+/// Each file's lyrics come from the .lrc or .txt file of the same name beside it, preferring the
+/// .lrc. FLACs keep them in the LYRICS comment as written, timestamps and all; MP3s get an
+/// unsynchronised lyrics (USLT) frame of the words alone and, from an .lrc, a synchronised (SYLT)
+/// frame of its timed lines too. Prints each file given lyrics, and the sidecar they came from.
+///
+/// With --extract, lyrics go the other way, to <basename>.lrc when they're timed and to
+/// <basename>.txt otherwise, for players which only read sidecars. Prints the path of each
+/// sidecar written. Existing sidecars are kept unless --force is given.
+#[derive(Debug, Parser)]
+struct LyricsSidecars {
+    /// FLAC or MP3 files, or directories of them
+    files: Vec<PathBuf>,
+
+    /// take the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// write each file's lyrics out to a sidecar rather than embedding them
+    #[arg(long)]
+    extract: bool,
+
+    /// overwrite sidecars which already exist
+    #[arg(long, requires = "extract")]
+    force: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long, conflicts_with = "extract")]
+    chmod_if_needed: bool,
+}
+
+/// find covers embedded identically in every track of an album
+///
+/// Tracks are grouped into albums by directory and album tag. Prints the directory, album,
+/// track count, picture type, picture size, and bytes reclaimable by keeping a single copy.
+#[derive(Debug, Parser)]
+struct ArtDedupe {
+    files: Vec<String>,
+
+    /// remove the duplicates according to the policy
+    #[arg(long)]
+    fix: bool,
+
+    /// keep the cover on the first track only, or move it to folder.jpg
+    ///
+    /// Defaults to [art] dedupe = first-track|folder in config, or first-track.
+    #[arg(long)]
+    policy: Option<art::DedupePolicy>,
+
+    /// temporarily make read-only files writable when fixing
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// refuse to rewrite an MP3 unless its Serato and Rekordbox cue and beatgrid frames survive
+    #[arg(long)]
+    preserve_dj_data: bool,
+}
+
+/// read and write FLAC APPLICATION blocks
+#[derive(Debug, clap::Subcommand)]
+enum App {
+    List(AppList),
+    Export(AppExport),
+    Import(AppImport),
+    Remove(AppRemove),
+}
+
+/// list the APPLICATION blocks in FLAC files
+#[derive(Debug, Parser)]
+struct AppList {
+    files: Vec<String>,
+}
+
+/// print the data of an APPLICATION block
+#[derive(Debug, Parser)]
+struct AppExport {
+    file: String,
+
+    /// the application id, as four characters or eight hex digits
+    #[arg(long)]
+    id: ApplicationId,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: DataFormat,
+
+    /// write to this file rather than stdout
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+/// store data in an APPLICATION block, replacing any block with the same id
+#[derive(Debug, Parser)]
+struct AppImport {
+    file: String,
+
+    /// the application id, as four characters or eight hex digits
+    #[arg(long)]
+    id: ApplicationId,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: DataFormat,
+
+    /// the encoded data
+    #[arg(long, conflicts_with = "from", required_unless_present = "from")]
+    data: Option<String>,
+
+    /// read the data from this file
+    #[arg(long)]
+    from: Option<PathBuf>,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// remove APPLICATION blocks with a given id
+#[derive(Debug, Parser)]
+struct AppRemove {
+    files: Vec<String>,
+
+    /// the application id, as four characters or eight hex digits
+    #[arg(long)]
+    id: ApplicationId,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// list the metadata blocks of FLAC files or ID3 frames of MP3s
+///
+/// Prints the offset, size, type, and a short description of each block.
+#[derive(Debug, Parser)]
+struct ShowBlocks {
+    files: Vec<String>,
+}
+
+/// describe everything about each file as a JSON document
+///
+/// Each document holds the file's mapped attributes, its raw tags (vorbis comments, or ID3 frames
+/// by ID), its audio format and length, its pictures, its metadata blocks, and SHA-256 hashes of
+/// the file and each picture, along with the audio MD5 stored in a FLAC's STREAMINFO.
+#[derive(Debug, Parser)]
+struct DescribeFiles {
+    files: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: describe::Format,
+}
+
+/// lint files for tagging problems
+#[derive(Debug, clap::Subcommand)]
+enum Check {
+    Totals(CheckTotals),
+    Hires(CheckHires),
+    Dlna(CheckDlna),
+    Tags(CheckTags),
+    Device(CheckDevice),
+}
+
+/// report tags a playback device would cut short or show wrongly
+///
+/// Profiles come from [device <name>] sections in config, giving the ID3 version the device reads
+/// (id3 = 2.3), the characters it can show (charset = unicode, latin-1, or ascii), and the longest
+/// field it shows (max-length = 30). Built in are old-car (ID3v2.3, latin-1, 30 characters),
+/// ascii, and id3v23.
+#[derive(Debug, Parser)]
+struct CheckDevice {
+    /// the device profile to check against
+    profile: String,
+
+    files: Vec<String>,
+
+    /// write copies of the files into this directory with their tags made to fit the device
+    ///
+    /// Characters the device can't show are folded to the nearest it can (é to e, curly quotes to
+    /// straight ones) or replaced with ?, fields are cut to its length, and MP3 tags are written in
+    /// the ID3 version it reads.
+    #[arg(long, value_name = "DIR")]
+    export: Option<PathBuf>,
+}
+
+/// warn about nonstandard and missing tags
+///
+/// Reports a YEAR tag without DATE or a DATE not in ISO 8601 form (W001), a missing album artist
+/// (W002), and a missing track number (W003), as list and apply do. These are warnings: the check
+/// fails only for those denied, or with --warn-as-error.
+#[derive(Debug, Parser)]
+struct CheckTags {
+    files: Vec<String>,
+}
+
+/// flag tags known to break MiniDLNA and Plex scanning
+///
+/// Reports a missing album artist, empty values, embedded art that isn't JPEG, and control or
+/// replacement characters in values.
+#[derive(Debug, Parser)]
+struct CheckDlna {
+    files: Vec<String>,
+}
+
+/// flag high-resolution files whose content doesn't live up to their format
+///
+/// Reports 24-bit files holding 16-bit samples, high sample rate files with nothing above 22 kHz,
+/// and rate/depth combinations not listed by [hires] allow = <rate>/<bits> entries in config.
+#[derive(Debug, Parser)]
+struct CheckHires {
+    files: Vec<String>,
+
+    /// continue an interrupted run from its checkpoint file
+    #[arg(long)]
+    resume: Option<PathBuf>,
+}
+
+/// flag files whose track/disc totals don't use the configured spelling
+///
+/// The spelling is set with [format] totals = both|short|long in config;
+/// both TRACKTOTAL and TOTALTRACKS (and DISCTOTAL and TOTALDISCS) are expected by default.
+#[derive(Debug, Parser)]
+struct CheckTotals {
+    files: Vec<String>,
+
+    /// rewrite the totals to the configured spelling
+    #[arg(long)]
+    fix: bool,
+
+    /// temporarily make read-only files writable when fixing
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+#[derive(Debug, Default, Parser)]
+struct ApplyAttributes {
+    /// a file or http(s) URL containing attributes to be applied
+    ///
+    /// csv, tsv, or json, as list writes; by default, attributes will be read from stdin
+    #[arg(long)]
+    attributes: Option<String>,
+
+    /// apply the albums approved in this review session, rather than a sheet
+    ///
+    /// Approved albums are marked applied afterwards, unless this is a dry run, so that running it
+    /// again after approving more applies only those.
+    #[arg(long, conflicts_with_all = ["attributes", "sha256"])]
+    session: Option<String>,
+
+    /// apply rows to files whose tags have changed since the sheet was listed
+    ///
+    /// Sheets written by list carry a fingerprint of each file's tags; rows whose file no longer
+    /// matches are skipped with a warning (stale-row) unless this is given.
+    #[arg(long)]
+    force: bool,
+
+    /// refuse the attribute sheet unless its SHA-256 matches this hex digest
+    ///
+    /// Sheets fetched from a URL without a pinned digest are applied with a warning giving the
+    /// digest to pin.
+    #[arg(long)]
+    sha256: Option<String>,
+
+    /// directory for output files to be written to
+    #[arg(long, conflicts_with = "in_place")]
+    output: Option<String>,
+
+    /// rewrite tags in the original files rather than writing tagged copies
+    ///
+    /// Either way, tags are written to a temporary copy beside the file's destination, which is
+    /// moved into place only once it's complete, so an interrupted run never leaves a file half
+    /// written.
+    #[arg(long)]
+    in_place: bool,
+
+    /// replace files already in the output directory rather than refusing to write
+    #[arg(long, conflicts_with = "in_place")]
+    overwrite: bool,
+
+    /// before rewriting a file in place, keep the original beside it with this suffix appended
+    ///
+    /// e.g. --backup .orig
+    #[arg(long, requires = "in_place")]
+    backup: Option<String>,
+
+    /// temporarily make read-only files writable when rewriting in place
+    #[arg(long, requires = "in_place")]
+    chmod_if_needed: bool,
+
+    /// only touch files whose existing tags match this condition
+    ///
+    /// e.g. "artist == 'Prince' && year < 1990"
+    #[arg(long)]
+    when: Option<Condition>,
+
+    /// skip rows of the attribute sheet which fail validation instead of stopping
+    #[arg(long)]
+    skip_invalid: bool,
+
+    /// write columns which name no attribute as raw fields, named by their headers
+    ///
+    /// FLAC files get vorbis comments and MP3s TXXX frames, as list --all-fields lists them. An
+    /// empty cell removes the field.
+    #[arg(long)]
+    all_fields: bool,
+
+    /// the character separating the sheet's fields; by default, whichever its header row uses
+    ///
+    /// Columns may come in any order, and any but path may be left out: attributes without a
+    /// column are left as they are.
+    #[arg(long, value_enum)]
+    delimiter: Option<sheet::Delimiter>,
+
+    /// zero-pad written track numbers to this many digits
+    #[arg(long)]
+    track_width: Option<usize>,
+
+    /// resolve relative paths in the attribute sheet against this directory
+    #[arg(long, required_if_eq("matching", "filename"))]
+    root: Option<String>,
+
+    /// find rows whose paths don't exist anywhere under --root, by as much of the end of the path
+    /// as matches a file there
+    #[arg(long, short, requires = "root")]
+    recursive: bool,
+
+    /// how rows are matched to files
+    ///
+    /// Rows which match no file, or more than one, are skipped with a warning (unmatched-row,
+    /// ambiguous-row).
+    #[arg(long = "match", value_enum, default_value_t)]
+    matching: Matching,
+
+    /// print the changes each file would get, as <path> <attribute> <old> -> <new>, without
+    /// writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// refuse to write an MP3 unless its Serato and Rekordbox cue and beatgrid frames survive
+    #[arg(long)]
+    preserve_dj_data: bool,
+
+    /// what to do with attributes a format has no field of its own for
+    ///
+    /// ID3 has no frame for work, showmovement, or releasecountry. Which formats in the sheet lack
+    /// which of its attributes is reported (unrepresentable-field) before anything is written.
+    #[arg(long, value_enum, default_value_t)]
+    unrepresentable: capability::Policy,
+
+    /// apply this many files at a time; defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<NonZeroUsize>,
+
+    /// don't show the progress line or the count of files that succeeded and failed
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// how to report the files which failed, once every other file has been tried
+    ///
+    /// A file which can't be read, or fails to apply, doesn't stop the others. Exits with status 3
+    /// if some files failed, or 4 if every one did.
+    #[arg(long, value_enum, default_value_t)]
+    errors: failures::Report,
+
+    /// stop at the first file which fails
+    #[arg(long)]
+    fail_fast: bool,
+}
+
+#[derive(Debug, Parser)]
+struct List {
+    /// files or directories to list; members of .zip and .tar archives are listed as
+    /// <archive>!/<entry>
+    ///
+    /// Ogg Vorbis, Opus, and M4A files are listed alongside FLAC and MP3, though no other command
+    /// reads or writes them yet.
+    files: Vec<PathBuf>,
+
+    /// list the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// zero-pad track numbers to this many digits
+    #[arg(long)]
+    track_width: Option<usize>,
+
+    /// include technical columns describing the audio stream, embedded pictures, and DJ software
+    /// data
+    #[arg(long)]
+    technical: bool,
+
+    /// add a column for every other field of the files' tags, named by its key
+    ///
+    /// Vorbis comments of FLAC and Ogg files and TXXX frames of MP3s, such as MUSICBRAINZ_TRACKID or
+    /// LABEL, each get a column after the rest, in order by key; apply --all-fields writes them
+    /// back. Fields named as a sheet names an attribute, pictures, and FLACDAT_* fields are left out.
+    #[arg(long)]
+    all_fields: bool,
+
+    /// write paths relative to this directory, separated with '/'
+    ///
+    /// Files outside the directory keep their full paths. Use apply --root to read such a sheet.
+    #[arg(long)]
+    relative_to: Option<PathBuf>,
+
+    /// how to write the listing; apply reads any but table
+    #[arg(long, value_enum, default_value_t)]
+    format: output::Format,
+
+    /// write only these columns, in this order, as track,title,artist
+    ///
+    /// Columns are named as in the header the listing has without this. Naming a technical column
+    /// lists it without --technical. Leave out the path column and apply can't read the sheet.
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// sort the rows by these columns, in turn, as album,track, rather than by path
+    ///
+    /// Any column may be named, whether it's written or not. Whole numbers, such as track numbers,
+    /// sort by value.
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    sort: Vec<String>,
+
+    /// separate the fields of csv with this instead of commas
+    #[arg(long, value_enum)]
+    delimiter: Option<sheet::Delimiter>,
+
+    /// how to report the files which couldn't be read, once the rest are listed
+    ///
+    /// A file which can't be read is left out without stopping the others. Exits with status 3 if
+    /// some files failed, or 4 if every one did.
+    #[arg(long, value_enum, default_value_t)]
+    errors: failures::Report,
+
+    /// stop at the first file which can't be read
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// list only files which are new or changed since this earlier listing
+    ///
+    /// A file is listed if its path isn't in the earlier listing, if any column the two share
+    /// differs, or if it was modified after the earlier listing was written. Reads csv, tsv, and
+    /// json listings.
+    #[arg(long, value_name = "LISTING")]
+    since: Option<PathBuf>,
+}
+
+impl List {
+    const EXTENSIONS: &'static [&'static str] = &["flac", "mp3", "ogg", "oga", "opus", "m4a"];
+
+    /// The columns --technical adds.
+    const TECHNICAL: &'static [&'static str] = &[
+        "sample_rate",
+        "bits_per_sample",
+        "channels",
+        "channel_layout",
+        "has_art",
+        "pictures",
+        "art_type",
+        "art_dimensions",
+        "art_bytes",
+        "dj_data",
+    ];
+
+    /// Whether the technical columns are listed: with --technical, or when
+    /// one is named in --columns or --sort.
+    fn technical(&self) -> bool {
+        self.technical
+            || self.columns.iter().chain(&self.sort).any(|name| {
+                Self::TECHNICAL
+                    .iter()
+                    .any(|column| column.eq_ignore_ascii_case(name))
+            })
+    }
+}
+
+/// list files' tags in an editor, and apply the rows changed once it exits
+///
+/// The files are listed as for list --format tsv, to a temporary sheet opened with $VISUAL or
+/// $EDITOR (or vi). Rows left as they were are dropped, and the rest applied in place as by apply
+/// --in-place, so files whose tags weren't touched are never rewritten. Quitting the editor without
+/// saving a change applies nothing; if the editor fails, or the sheet can't be applied, the edited
+/// sheet is kept and its path printed.
+#[derive(Debug, Parser)]
+struct EditTags {
+    /// FLAC or MP3 files, or directories of them
+    files: Vec<PathBuf>,
+
+    /// edit the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// print the changes the edit would make without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// before rewriting a file, keep the original beside it with this suffix appended
+    #[arg(long)]
+    backup: Option<String>,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// don't show the progress line or the count of files that succeeded and failed
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+impl EditTags {
+    const EXTENSIONS: &'static [&'static str] = &["flac", "mp3"];
+}
+
+/// compare an attribute sheet with the tags of the files it describes
+///
+/// Prints each attribute whose value in the sheet differs from the file's, as
+/// <path> <attribute> <file's> -> <sheet's>, like apply --dry-run. Only attributes the sheet has
+/// columns for are compared, and an empty cell differs from a file's value, though apply would
+/// leave it alone. Rows whose file doesn't exist are printed as <path> no file; with files given,
+/// only rows for those files are compared, and files the sheet has no row for are printed as
+/// <path> no row. Nothing is written, and the command fails if anything differs.
+#[derive(Debug, Parser)]
+struct DiffSheet {
+    /// FLAC or MP3 files, or directories of them, which the sheet should describe
+    files: Vec<PathBuf>,
+
+    /// the attribute sheet: csv, tsv, or json, as list writes; by default, read from stdin
+    #[arg(long)]
+    attributes: Option<PathBuf>,
+
+    /// the character separating the sheet's fields; by default, whichever its header row uses
+    #[arg(long, value_enum)]
+    delimiter: Option<sheet::Delimiter>,
+
+    /// resolve relative paths in the sheet against this directory
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// compare the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+}
+
+/// print the files whose tags match a query
+///
+/// Directories are searched through, subdirectories and all. A file matches if it meets the --where
+/// condition and lacks every attribute given with --missing; with neither, every file matches.
+/// Conditions are as for apply --when, e.g. "year < 1990 && genre == 'Jazz'".
+#[derive(Debug, Parser)]
+struct FindFiles {
+    /// files or directories to search
+    files: Vec<PathBuf>,
+
+    /// a condition the file's tags must meet
+    #[arg(long = "where", value_name = "CONDITION")]
+    condition: Option<Condition>,
+
+    /// an attribute the file must have no value for; may be given more than once
+    #[arg(long, value_name = "ATTRIBUTE")]
+    missing: Vec<Attribute>,
+
+    /// print each file's tags as a row, as list --format tsv does, rather than its path
+    #[arg(long)]
+    rows: bool,
+}
+
+/// summarize the tags of a library
+///
+/// Counts files by artist, album, genre, and year, and those missing an album, artist, title,
+/// track, year, genre, or album artist, along with the total length of the audio. Directories are
+/// searched through, subdirectories and all.
+#[derive(Debug, Parser)]
+struct ShowStats {
+    /// files or directories to summarize
+    files: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: stats::Format,
+
+    /// show only this many of the artists, albums, and genres with the most files, or all of them
+    /// with 0
+    #[arg(long, default_value_t = 20)]
+    top: usize,
+}
+
+/// edit files' tags in a table in the terminal
+///
+/// Each file is a row, and each attribute a column. Move with the arrow keys (or h, j, k, l);
+/// enter edits a cell, s sets the same value in every row of a column, and u undoes the edits to a
+/// cell. Values of artist, language, genre, and composer are separated by commas, or --multi-sep.
+/// w writes the files changed, once confirmed, printing their changes; q quits without writing.
+#[derive(Debug, Parser)]
+struct EditInTerminal {
+    /// FLAC or MP3 files, or directories of them
+    files: Vec<PathBuf>,
+
+    /// edit the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+/// run a pipeline defined in config against a set of files
+#[derive(Debug, Parser)]
+struct RunPipeline {
+    /// the name of the pipeline
+    pipeline: String,
+    files: Vec<PathBuf>,
+
+    /// run it on the files in subdirectories of directories too
+    #[arg(long, short)]
+    recursive: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+
+    /// zero-pad written track numbers to this many digits, or strip padding with 0
+    ///
+    /// A pipeline with no steps can be used to normalize padding on its own.
+    #[arg(long)]
+    track_width: Option<usize>,
+}
+
+/// browse the audit log of mutating operations
+#[derive(Debug, Parser)]
+struct ShowLog {
+    /// show the individual changes made by this operation
+    id: Option<String>,
+}
+
+/// undo an operation recorded in the audit log
+#[derive(Debug, Parser)]
+struct RevertOperation {
+    id: String,
+
+    /// revert even if files have been modified since the operation
+    #[arg(long)]
+    force: bool,
+
+    /// temporarily make read-only files writable
+    #[arg(long)]
+    chmod_if_needed: bool,
+}
+
+#[derive(Debug, Parser)]
+struct ConvertToFlac {
+    /// WAV, AIFF, ALAC (.m4a), APE, or WavPack files, or .zip and .tar archives of them, which
+    /// convert into a directory named after the archive
+    ///
+    /// Tags the source carries are copied into the FLAC, including a WAV's RIFF INFO fields and
+    /// its Broadcast Wave description (as COMMENT) and origination date (as DATE).
+    #[arg(required_unless_present = "cue")]
+    files: Vec<String>,
+
+    /// split the album a cue sheet describes into a FLAC for each track
+    ///
+    /// Tracks run from one INDEX 01 to the next, are named "<track> <title>" (with the performer
+    /// first when it isn't the album's), and are tagged from the sheet's TITLE, PERFORMER, and REM
+    /// DATE and GENRE entries. A sheet naming a WAV finds the rip under the same name in another
+    /// format too.
+    #[arg(long, conflicts_with = "files")]
+    cue: Option<PathBuf>,
+
+    /// the FLAC compression level, from 0 (fastest) to 12 (smallest)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=12))]
+    compression_level: Option<u8>,
+
+    /// resample to this rate, in Hz
+    ///
+    /// Resampling between the 44.1kHz and 48kHz families when a rate of the source's own family
+    /// would do, such as 96kHz to 44.1kHz rather than 48kHz, is warned about (resample).
+    #[arg(long, conflicts_with = "max_sample_rate")]
+    sample_rate: Option<u32>,
+
+    /// resample sources above this rate, in Hz, to the highest rate up to it of their own family:
+    /// 88.2kHz and 176.4kHz masters to 44.1kHz, and 96kHz and 192kHz to 48kHz, given 48000
+    #[arg(long, value_name = "HZ")]
+    max_sample_rate: Option<u32>,
+
+    /// the bits per sample to write; reducing to 16 applies triangular dither
+    #[arg(long, value_enum)]
+    bit_depth: Option<convert::BitDepth>,
+
+    /// how many files to convert at once
+    ///
+    /// What ffmpeg prints is held back and shown under each file's name once it's done. A file
+    /// which fails to convert doesn't stop the others.
+    #[arg(short, long, default_value = "1")]
+    jobs: NonZeroUsize,
+
+    /// write FLACs under this directory rather than beside their sources
+    ///
+    /// Files from different directories keep their places relative to the directory holding them
+    /// all: converting rips/a/1.wav and rips/b/2.wav writes <OUTPUT>/a/1.flac and <OUTPUT>/b/2.flac.
+    #[arg(long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// remove each source once it has been converted; files in archives, and albums split with
+    /// --cue, are left as they are
+    #[arg(long)]
+    delete_source: bool,
+
+    /// encode with flacdat's own encoder rather than ffmpeg
+    ///
+    /// It's used without this flag when ffmpeg isn't installed. It reads only PCM WAV files, up to
+    /// 24 bits and 8 channels, can't resample or change the bit depth, and ignores
+    /// --compression-level: its files are a little larger than ffmpeg's at the default level. It's
+    /// left out of builds without the native-encoder feature.
+    #[arg(long)]
+    no_ffmpeg: bool,
+}
+
+impl ConvertToFlac {
+    fn source_paths(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.files
+            .iter()
+            .map(Path::new)
+            .filter(|file| convert::is_source(file))
+    }
+
+    fn encoding(&self) -> convert::Encoding {
+        convert::Encoding {
+            compression_level: self.compression_level,
+            sample_rate: self.sample_rate,
+            max_sample_rate: self.max_sample_rate,
+            bit_depth: self.bit_depth,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PathGroup<T> {
+    base: T,
+
+    // Retained purely as reference material
+    #[allow(dead_code)]
+    mp3_path: OnceCell<PathBuf>,
+}
+
+impl<T: AsRef<Path>> PathGroup<T> {
+    fn new(base: T) -> Self {
+        PathGroup {
+            base,
+            mp3_path: OnceCell::default(),
+        }
+    }
+
+    fn flac(&self) -> &Path {
+        self.base.as_ref()
+    }
+
+    fn flac_output(&self, output_dir: impl AsRef<Path>) -> PathBuf {
+        let mut dir = output_dir.as_ref().to_owned();
+        dir.push(self.flac().file_name().unwrap());
+        dir
+    }
+
+    // Retained purely as reference material
+    #[allow(dead_code)]
+    fn mp3(&self) -> &Path {
+        self.mp3_path
+            .get_or_init(|| self.base.as_ref().with_extension("mp3"))
+    }
+}
+
+pub fn main() {
+    if let Err(e) = run(Args::parse_from(wild::args_os())) {
+        eprintln!("{e}");
+        process::exit(e.exit_code());
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    let Some(mut command) = args.command else {
+        return Ok(());
+    };
+
+    let mut config = Config::load(args.config.as_deref())?;
+    tools::configure(config.tools.clone());
+    collate::configure(config.collation);
+    sheet::configure(args.multi_sep.or(config.multi_separator.take()));
+    template::configure(config.columns.clone());
+    case::configure(config.case.clone());
+    verify::configure(args.verify_writes);
+    writeback::configure(args.writeback || config.writeback.unwrap_or(false));
+    safety::configure(config.max_files.unwrap_or(safety::DEFAULT_LIMIT), args.yes);
+    if args.offline {
+        config.fetch.offline = true;
+    }
+    fetch::configure(config.fetch.clone());
+    config.warnings.extend(&warning::Policy {
+        allow: args.allow.clone(),
+        deny: args.deny.clone(),
+        all_errors: args.warn_as_error,
+    });
+    warning::configure(config.warnings.clone());
+    if args.unprotect {
+        config.protection.clear();
+    }
+    for pair in &args.path_prefix_map {
+        config.path_map.add(pair.clone());
+    }
+    if args.throttle.is_some() {
+        config.throttle = args.throttle;
+        for root in &mut config.roots.roots {
+            root.throttle = None;
+        }
+    }
+    if args.nice || config.nice {
+        throttle::lower_priority();
+    }
+
+    command.resolve_roots(&config.roots);
+    if !args.keep_order {
+        command.sort_files();
+    }
+    if let Some(path) = &args.manifest {
+        manifest::configure(path.clone());
+    }
+    // Files written before a failure belong in the manifest too.
+    let result = dispatch(&command, &config);
+    let written = manifest::finish();
+    result?;
+    written?;
+    warning::finish()
+}
+
+/// A command's file arguments, which are strings or paths depending on the
+/// command.
+enum Files<'a> {
+    Strings(&'a mut [String]),
+    Paths(&'a mut [PathBuf]),
+    None,
+}
+
+impl Command {
+    fn files(&mut self) -> Files<'_> {
+        match self {
+            Command::List(args) => Files::Paths(&mut args.files),
+            Command::Edit(args) => Files::Paths(&mut args.files),
+            Command::Tui(args) => Files::Paths(&mut args.files),
+            Command::Diff(args) => Files::Paths(&mut args.files),
+            Command::Find(args) => Files::Paths(&mut args.files),
+            Command::Stats(args) => Files::Paths(&mut args.files),
+            Command::Feed(args) => Files::Paths(&mut args.dirs),
+            Command::Nml(args) => Files::Paths(&mut args.files),
+            Command::Nfo(args) => Files::Paths(&mut args.files),
+            Command::Versions(args) => Files::Paths(&mut args.files),
+            Command::Dupes(args) => Files::Paths(&mut args.files),
+            Command::Rename(args) => Files::Paths(&mut args.files),
+            Command::Organize(args) => Files::Paths(&mut args.files),
+            Command::TagFromFilename(args) => Files::Paths(&mut args.files),
+            Command::Set(args) => Files::Paths(&mut args.files),
+            Command::Strip(args) => Files::Paths(&mut args.files),
+            Command::Lookup(args) => Files::Paths(&mut args.files),
+            Command::Plan(args) => Files::Paths(&mut args.files),
+            Command::Playlist(args) => Files::Paths(&mut args.files),
+            Command::Normalize(args) => Files::Paths(&mut args.files),
+            Command::Lyrics(args) => Files::Paths(&mut args.files),
+            Command::Transcode(args) => Files::Paths(&mut args.files),
+            Command::Run(args) => Files::Paths(&mut args.files),
+            Command::Gain(args) => Files::Strings(&mut args.files),
+            Command::Verify(args) => Files::Strings(&mut args.files),
+            Command::Riplog(Riplog::Import(args)) => Files::Strings(&mut args.files),
+            Command::Convert(args) => Files::Strings(&mut args.files),
+            Command::Check(Check::Totals(args)) => Files::Strings(&mut args.files),
+            Command::Check(Check::Hires(args)) => Files::Strings(&mut args.files),
+            Command::Check(Check::Dlna(args)) => Files::Strings(&mut args.files),
+            Command::Check(Check::Tags(args)) => Files::Strings(&mut args.files),
+            Command::Check(Check::Device(args)) => Files::Strings(&mut args.files),
+            Command::Blocks(args) => Files::Strings(&mut args.files),
+            Command::Describe(args) => Files::Strings(&mut args.files),
+            Command::App(App::List(args)) => Files::Strings(&mut args.files),
+            Command::App(App::Remove(args)) => Files::Strings(&mut args.files),
+            Command::Art(Art::Dedupe(args)) => Files::Strings(&mut args.files),
+            Command::Art(Art::Embed(args)) => Files::Strings(&mut args.files),
+            Command::Art(Art::Extract(args)) => Files::Strings(&mut args.files),
+            Command::Art(Art::Sync(args)) => Files::Paths(&mut args.files),
+            Command::Art(Art::Thumbs(args)) => Files::Strings(&mut args.files),
+            Command::Analyze(Analyze::Dr(args)) => Files::Strings(&mut args.files),
+            Command::Analyze(Analyze::Gaps(args)) => Files::Strings(&mut args.files),
+            Command::Analyze(Analyze::Spectrogram(args)) => Files::Strings(&mut args.files),
+            Command::Apply(_)
+            | Command::Log(_)
+            | Command::Revert(_)
+            | Command::App(App::Export(_) | App::Import(_))
+            | Command::Snapshot(_)
+            | Command::Session(_)
+            | Command::Export(_)
+            | Command::Ingest(_)
+            | Command::Watch(_)
+            | Command::Recipe(_)
+            | Command::Tracklist(_)
+            | Command::Auth(_)
+            | Command::Tools(_)
+            | Command::CopyTags(_) => Files::None,
+        }
+    }
+
+    /// Sorts the command's files by path, so that output is the same however
+    /// the shell happened to expand a glob.
+    fn sort_files(&mut self) {
+        match self.files() {
+            Files::Strings(files) => sort_paths(files),
+            Files::Paths(files) => sort_paths(files),
+            Files::None => {}
+        }
+    }
+
+    /// Expands root-qualified file and directory arguments.
+    fn resolve_roots(&mut self, roots: &Roots) {
+        match self.files() {
+            Files::Strings(files) => files.iter_mut().for_each(|file| file.resolve(roots)),
+            Files::Paths(files) => files.iter_mut().for_each(|file| file.resolve(roots)),
+            Files::None => {}
+        }
+
+        let dir = match self {
+            Command::Snapshot(Snapshot::Save(args)) => args.dir.as_mut(),
+            Command::Export(args) => args.dir.as_mut(),
+            Command::Tracklist(args) => Some(&mut args.dir),
+            Command::Organize(args) => Some(&mut args.into),
+            Command::Transcode(args) => args.out.as_mut(),
+            Command::Convert(args) => args.output.as_mut(),
+            Command::Watch(args) => args.into.as_mut(),
+            Command::Check(Check::Device(args)) => args.export.as_mut(),
+            _ => None,
+        };
+        if let Some(dir) = dir {
+            dir.resolve(roots);
+        }
+        if let Command::CopyTags(args) = self {
+            args.from.resolve(roots);
+            args.to.resolve(roots);
+        }
+        if let Command::Watch(args) = self {
+            args.dir.resolve(roots);
+        }
+        if let Command::Convert(ConvertToFlac { cue: Some(cue), .. }) = self {
+            cue.resolve(roots);
+        }
+    }
+}
+
+/// Sorts paths component by component, which keeps each directory's files
+/// together: `a/b.flac` sorts before `a.flac`. Names are ordered by the
+/// configured collation rather than byte by byte.
+fn sort_paths<P: AsRef<Path>>(paths: &mut [P]) {
+    paths.sort_by(|a, b| collate::compare_paths(a.as_ref(), b.as_ref()));
+}
+
+fn dispatch(command: &Command, config: &Config) -> Result<()> {
+    match command {
+        Command::Apply(args) => apply_attributes(args, config),
+        Command::List(args) => list_attributes(args, config, io::stdout().lock()),
+        Command::Edit(args) => edit_attributes(args, config),
+        Command::Tui(args) => edit_in_terminal(args, config),
+        Command::Diff(args) => diff_sheet(args, config),
+        Command::Find(args) => find_files(args, config),
+        Command::Stats(args) => show_stats(args, config),
+        Command::Convert(convert_args) => convert_wav_to_flac(convert_args),
+        Command::Run(args) => run_pipeline(args, config),
+        Command::Log(args) => show_log(args),
+        Command::Revert(args) => revert_operation(args),
+        Command::Check(Check::Totals(args)) => check_totals(args, config),
+        Command::Check(Check::Hires(args)) => check_hires(args, config),
+        Command::Check(Check::Dlna(args)) => check_dlna(args, config),
+        Command::Check(Check::Tags(args)) => check_tags(args, config),
+        Command::Check(Check::Device(args)) => check_device(args, config),
+        Command::Blocks(args) => show_blocks(args),
+        Command::Describe(args) => describe_files(args),
+        Command::App(App::List(args)) => list_applications(args),
+        Command::App(App::Export(args)) => export_application(args),
+        Command::App(App::Import(args)) => import_application(args),
+        Command::App(App::Remove(args)) => remove_application(args),
+        Command::Art(Art::Dedupe(args)) => dedupe_art(args, config),
+        Command::Art(Art::Embed(args)) => embed_art(args),
+        Command::Art(Art::Extract(args)) => extract_art(args),
+        Command::Art(Art::Sync(args)) => sync_art(args, config),
+        Command::Art(Art::Thumbs(args)) => make_thumbnails(args),
+        Command::Lyrics(args) if args.extract => extract_lyrics(args, config),
+        Command::Lyrics(args) => embed_lyrics(args, config),
+        Command::Tools(_) => show_tools(),
+        Command::Analyze(Analyze::Dr(args)) => analyze_dr(args, config),
+        Command::Analyze(Analyze::Spectrogram(args)) => render_spectrograms(args, config),
+        Command::Analyze(Analyze::Gaps(args)) => analyze_gaps(args, config),
+        Command::Snapshot(Snapshot::Save(args)) => save_snapshot(args, config),
+        Command::Snapshot(Snapshot::Diff(args)) => diff_snapshots(args, config),
+        Command::Export(args) => export_tags(args, config),
+        Command::Ingest(args) => ingest_downloads(args, config),
+        Command::Watch(args) => watch_inbox(args, config),
+        Command::Recipe(Recipe::Create(args)) => create_recipe(args, config),
+        Command::Recipe(Recipe::Apply(args)) => apply_recipe(args, config),
+        Command::Feed(args) => make_feeds(args, config),
+        Command::Nml(args) => export_nml(args, config),
+        Command::Nfo(args) => export_nfo(args, config),
+        Command::Riplog(Riplog::Import(args)) => import_riplog(args, config),
+        Command::Tracklist(args) => make_tracklist(args, config),
+        Command::Versions(args) => find_versions(args, config),
+        Command::Dupes(args) => find_dupes(args, config),
+        Command::Rename(args) => rename_files(args),
+        Command::Organize(args) => organize_files(args, config),
+        Command::TagFromFilename(args) => tag_from_filename(args, config),
+        Command::Set(args) => set_attributes(args, config),
+        Command::Strip(args) => strip_tags(args, config),
+        Command::Normalize(args) => normalize_tags(args, config),
+        Command::CopyTags(args) => copy_tags(args, config),
+        Command::Lookup(args) => lookup_release(args, config),
+        Command::Session(Session::List(args)) => list_sessions(args),
+        Command::Session(Session::Show(args)) => show_session_album(args),
+        Command::Session(Session::Approve(args)) => review_albums(args, session::Status::Approved),
+        Command::Session(Session::Reject(args)) => review_albums(args, session::Status::Rejected),
+        Command::Plan(args) => plan_discs(args, config),
+        Command::Playlist(args) => make_playlists(args, config),
+        Command::Gain(args) => replay_gain(args, config),
+        Command::Transcode(args) => transcode_files(args, config),
+        Command::Verify(args) => verify_audio(args, config),
+        Command::Auth(Auth::Set(args)) => set_token(args),
+        Command::Auth(Auth::Remove(args)) => auth::remove(args.service),
+        Command::Auth(Auth::Status(_)) => show_tokens(),
+    }
+}
+
+fn apply_attributes(args: &ApplyAttributes, config: &Config) -> Result<()> {
+    let options = tags::Options {
+        track_width: args.track_width.or(config.track_width).unwrap_or_default(),
+        year_tag: config.year_tag,
+    };
+
+    let output: Cow<_> = match args.output.as_ref() {
+        Some(output) => PathBuf::from(config.path_map.map(output)).into(),
+        None => env::current_dir()?.into(),
+    };
+
+    if !args.in_place && !args.dry_run && !output.exists() {
+        fs::create_dir(&output)?;
+    }
+
+    let searched = args.recursive || args.matching == Matching::Filename;
+    let index = match (&args.root, searched) {
+        (Some(root), true) => Some(matching::Index::new(
+            config
+                .ignore_for("apply")
+                .walk(Path::new(root), &["flac", "mp3"])?,
+        )),
+        _ => None,
+    };
+
+    let mut attributes = HashMap::new();
+    let mut fields = HashMap::new();
+    let mut failures = Failures::default();
+    let mut tried = 0;
+    for row in read_attributes(args)? {
+        let FileAttributes {
+            path,
+            attributes: mut row,
+            fingerprint,
+            artist_missing,
+            fields: row_fields,
+        } = row;
+        let resolved = match (&args.root, config.roots.resolve(&path)) {
+            (_, Some(resolved)) => resolved.to_string_lossy().into_owned(),
+            // Both halves are UTF-8, so the conversion is lossless.
+            (Some(root), None) if Path::new(&path).is_relative() => {
+                Path::new(root).join(&path).to_string_lossy().into_owned()
+            }
+            _ => config.path_map.map(&path),
+        };
+        let found = match (&index, args.matching) {
+            (_, Matching::Path) if Path::new(&resolved).exists() => {
+                matching::Found::One(resolved.into())
+            }
+            (Some(index), matching) => index.find(Path::new(&path), matching),
+            (None, _) => matching::Found::None,
+        };
+        let path = match found {
+            matching::Found::One(found) => match found.into_os_string().into_string() {
+                Ok(found) => found,
+                Err(_) => continue,
+            },
+            matching::Found::None => {
+                warning::emit(
+                    warning::Code::UnmatchedRow,
+                    format_args!("{path}: matches no file; skipping"),
+                );
+                continue;
+            }
+            matching::Found::Many(count) => {
+                let hint = match args.matching {
+                    Matching::Path => "give more of its path in the sheet",
+                    Matching::Filename => "--match path --recursive tells them apart by directory",
+                };
+                warning::emit(
+                    warning::Code::AmbiguousRow,
+                    format_args!("{path}: matches {count} files under --root; skipping ({hint})"),
+                );
+                continue;
+            }
+        };
+        tried += 1;
+        let current = match (&fingerprint, artist_missing) {
+            (None, false) => None,
+            _ => match Attributes::from_path(&path) {
+                Ok(current) => Some(current),
+                Err(e) if !args.fail_fast => {
+                    failures.add(&path, e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        // A file whose tags have changed since the sheet was listed could
+        // lose the changes.
+        if let (Some(fingerprint), Some(current), false) = (fingerprint, &current, args.force) {
+            if current.fingerprint() != fingerprint {
+                warning::emit(
+                    warning::Code::StaleRow,
+                    format_args!(
+                        "{path}: tags have changed since the sheet was listed; skipping (--force \
+                         applies it anyway)"
+                    ),
+                );
+                continue;
+            }
+        }
+        // An empty artist clears the file's, which a sheet without the
+        // column shouldn't; every other attribute is left alone already.
+        if let (true, Some(current)) = (artist_missing, current) {
+            row.artist = current.artist;
+        }
+        fields.insert(path.clone(), row_fields);
+        attributes.insert(path, row);
+    }
+    match (args.in_place, args.fail_fast) {
+        (true, true) => preflight::check_writable(attributes.keys(), args.chmod_if_needed)?,
+        (false, true) => preflight::check_readable(attributes.keys())?,
+        (in_place, false) => {
+            let mut unreachable = Vec::new();
+            for path in attributes.keys() {
+                let problem = match in_place {
+                    true => preflight::unwritable(Path::new(path), args.chmod_if_needed),
+                    false => preflight::unreadable(Path::new(path)),
+                };
+                if let Some(problem) = problem {
+                    unreachable.push((path.clone(), problem?));
+                }
+            }
+            for (path, problem) in unreachable {
+                attributes.remove(&path);
+                failures.add(&path, problem);
+            }
+        }
+    }
+    capability::report(&attributes, args.unrepresentable)?;
+    if !args.dry_run {
+        safety::check("apply", attributes.keys())?;
+    }
+    let _library = (!args.dry_run).then(LibraryLock::acquire).transpose()?;
+    let mut log = AuditLog::begin("apply");
+    let apply = tags::Apply {
+        options,
+        output: (!args.in_place).then_some(&*output),
+        dry_run: args.dry_run,
+        overwrite: args.overwrite,
+        backup: args.backup.as_deref(),
+        when: args.when.as_ref(),
+        unrepresentable: args.unrepresentable,
+        preserve_dj_data: args.preserve_dj_data,
+        protection: &config.protection,
+    };
+
+    // The sheet is keyed by path, so its row order is already lost; sort to
+    // keep the audit log the same from run to run.
+    let mut attributes: Vec<_> = attributes.into_iter().collect();
+    attributes.sort_by(|(a, _), (b, _)| Path::new(a).cmp(Path::new(b)));
+
+    // Workers take the next row in turn, and report back here, where results
+    // are held until those of every earlier row are in, so that the log and
+    // output are in path order however the work is split.
+    let jobs = match args.jobs {
+        Some(jobs) => jobs,
+        None => thread::available_parallelism()?,
+    };
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let mut progress = Progress::new(attributes.len() + failures.len(), args.quiet);
+    progress.fail(failures.len());
+    thread::scope(|scope| {
+        for _ in 0..jobs.get().min(attributes.len()) {
+            let (attributes, fields, next, stop, apply) =
+                (&attributes, &fields, &next, &stop, &apply);
+            let sender = sender.clone();
+            scope.spawn(move || loop {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some((path, attr)) = attributes.get(idx) else {
+                    break;
+                };
+                let mut applied = tags::Applied::default();
+                let result = apply.row(path, (attr, fields[path].as_slice()), &mut applied);
+                if sender.send((idx, result, applied)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = HashMap::new();
+        let mut turn = 0;
+        let mut failed = None;
+        for (idx, result, applied) in receiver {
+            pending.insert(idx, (result, applied));
+            while let Some((result, applied)) = pending.remove(&turn) {
+                let path = &attributes[turn].0;
+                turn += 1;
+
+                progress.clear();
+                for created in &applied.created {
+                    log.create(created)?;
+                }
+                if let Some((attributes, comment)) = &applied.lint {
+                    warning::lint(path, attributes, comment.as_ref());
+                }
+                print!("{}", applied.changes);
+                if let Some((target, before, after)) = &applied.vorbis {
+                    log.vorbis(target, before, after)?;
+                }
+                if let Some((target, before, after)) = &applied.id3 {
+                    log.id3(target, before, after)?;
+                }
+                progress.advance(path, result.is_ok());
+                match result {
+                    Ok(()) => {}
+                    // No more rows are handed out, but those already under
+                    // way finish, and are logged like any other, before the
+                    // first error is returned.
+                    Err(e) if args.fail_fast => {
+                        stop.store(true, Ordering::Relaxed);
+                        failed.get_or_insert(e);
+                    }
+                    Err(e) => failures.add(path, e),
+                }
+            }
+        }
+        match failed {
+            Some(e) => {
+                progress.clear();
+                Err(e)
+            }
+            None => Ok(()),
+        }
+    })?;
+    progress.finish();
+    failures.finish(args.errors, tried)?;
+
+    if let (Some(name), false) = (&args.session, args.dry_run) {
+        let mut session = session::Session::load(name)?;
+        for album in &mut session.albums {
+            if album.status == session::Status::Approved {
+                album.status = session::Status::Applied;
+            }
+        }
+        session.save()?;
+    }
+
+    Ok(())
+}
+
+/// Prints each attribute whose values differ between two sets of tags.
+fn print_changes(path: &str, before: &Attributes, after: &Attributes) {
+    print!("{}", tags::format_changes(path, before, after));
+}
+
+fn run_pipeline(args: &RunPipeline, config: &Config) -> Result<()> {
+    let pipeline = config
+        .pipelines
+        .get(&args.pipeline)
+        .ok_or_else(|| Error::UnknownPipeline(args.pipeline.clone()))?;
+    let rename: Option<template::Template> = pipeline.rename().map(str::parse).transpose()?;
+    let mut log = AuditLog::begin(format!("run {}", args.pipeline));
+    let track_width = args.track_width.or(config.track_width);
+
+    let files = config
+        .ignore_for("run")
+        .expand(&args.files, &["flac"], args.recursive)?;
+    if let Some(path) = files
+        .iter()
+        .find(|path| path.extension() != Some(OsStr::new("flac")))
+    {
+        return Err(Error::UnsupportedFileTye(path.display().to_string()));
+    }
+    preflight::check_writable(&files, args.chmod_if_needed)?;
+    safety::check("run", &files)?;
+    let _library = LibraryLock::acquire()?;
+
+    // Files the pipeline's guard let through, to rename once tagged
+    let mut matched = Vec::new();
+    for path in &files {
+        edit_flac(path, config, &mut log, |comment| {
+            let before = Attributes::from_vorbis(comment);
+            let mut after = before.clone();
+
+            if !pipeline.apply(&mut after)? {
+                return Ok(());
+            }
+            matched.push(path.clone());
+
+            for &attribute in Attribute::ALL {
+                let values = after.values(attribute);
+                if values != before.values(attribute) {
+                    write_vorbis(comment, attribute, values, config.year_tag);
+                }
+            }
+
+            if let (Some(track), Some(width)) = (after.track, track_width) {
+                write_vorbis(
+                    comment,
+                    Attribute::Track,
+                    vec![format_track(track, width)],
+                    config.year_tag,
+                );
+            }
+
+            Ok(())
+        })?;
+    }
+
+    if let Some(template) = &rename {
+        for (path, target) in plan_targets(template, &with_attributes(&matched)?, None, None)? {
+            if target != path {
+                relocate(&path, &target, false, false, &mut log)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Edits a FLAC file's vorbis comments in place while holding its lock.
+/// Protected fields are restored after the edit, and the file is only
+/// rewritten, and the change logged, if the comments differ afterward. Callers
+/// are expected to have run pre-flight checks.
+fn edit_flac(
+    path: impl AsRef<Path>,
+    config: &Config,
+    log: &mut AuditLog,
+    edit: impl FnOnce(&mut metaflac::block::VorbisComment) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let _lock = FileLock::acquire(path)?;
+    let mut flac = metaflac::Tag::read_from_path(path)?;
+    let comment = flac.vorbis_comments_mut();
+    let before = comment.clone();
+
+    edit(comment)?;
+    config.protection.enforce(path, &before, comment);
+
+    if *comment != before {
+        let after = comment.clone();
+        let _writable = preflight::Writable::new(path)?;
+        verify::write_flac(&mut flac, path)?;
+        log.vorbis(path, &before, &after)?;
+    }
+
+    Ok(())
+}
+
+/// Edits an MP3's ID3 tag as edit_flac does a FLAC's comments, starting from
+/// an empty tag if it has none.
+fn edit_mp3(
+    path: impl AsRef<Path>,
+    config: &Config,
+    log: &mut AuditLog,
+    edit: impl FnOnce(&mut id3::Tag) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let _lock = FileLock::acquire(path)?;
+    let mut tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let before = tag.clone();
+
+    edit(&mut tag)?;
+    config.protection.enforce_id3(path, &before, &mut tag)?;
+
+    if tag != before {
+        let _writable = preflight::Writable::new(path)?;
+        verify::write_id3(&tag, path)?;
+        log.id3(path, &before, &tag)?;
+    }
+
+    Ok(())
+}
+
+fn check_totals(args: &CheckTotals, config: &Config) -> Result<()> {
+    if args.fix {
+        preflight::check_writable(&args.files, args.chmod_if_needed)?;
+        safety::check("check totals --fix", &args.files)?;
+        let mut log = AuditLog::begin("check totals --fix");
+        for path in &args.files {
+            edit_flac(path, config, &mut log, |comment| {
+                check::fix_totals(comment, config.totals);
+                Ok(())
+            })?;
+        }
+        return Ok(());
+    }
+
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut count = 0;
+    for path in &args.files {
+        throttle.wait(path)?;
+        let flac = metaflac::Tag::read_from_path(path)?;
+        let Some(comment) = flac.vorbis_comments() else {
+            continue;
+        };
+
+        for issue in check::totals_issues(comment, config.totals) {
+            println!("{path}: {issue}");
+            count += 1;
+        }
+    }
+
+    match count {
+        0 => Ok(()),
+        count => Err(Error::CheckFailed(count)),
+    }
+}
+
+fn check_hires(args: &CheckHires, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+
+    let mut checkpoint = Checkpoint::open("check hires", args.resume.as_deref())?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut count = 0;
+    for path in &args.files {
+        let issues = match checkpoint.get(path) {
+            Some(issues) => issues.to_vec(),
+            None => {
+                throttle.wait(path)?;
+                let issues = check::hires_issues(Path::new(path), &config.hires)?;
+                checkpoint.record(path, &issues)?;
+                issues
+            }
+        };
+        for issue in issues {
+            println!("{path}: {issue}");
+            count += 1;
+        }
+    }
+    checkpoint.finish()?;
+
+    match count {
+        0 => Ok(()),
+        count => Err(Error::CheckFailed(count)),
+    }
+}
+
+fn replay_gain(args: &ReplayGain, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    if !args.dry_run {
+        preflight::check_writable(&args.files, args.chmod_if_needed)?;
+        safety::check("gain", &args.files)?;
+    }
+
+    // The ReplayGain 2.0 reference level, in LUFS
+    const REFERENCE: f64 = -18.0;
+    // Silence gets no gain.
+    let gain = |lufs: Option<f64>| format!("{:+.2} dB", REFERENCE - lufs.unwrap_or(REFERENCE));
+    let peak = |peak: f64| format!("{peak:.6}");
+
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let grouping = args.group_by.clone().unwrap_or_default();
+    let mut log = AuditLog::begin("gain");
+    // path, integrated loudness, and the measurement
+    type Track<'a> = (&'a String, Option<f64>, analyze::Loudness);
+    let mut albums: Vec<(String, Vec<Track>)> = Vec::new();
+    for path in &args.files {
+        // Album gain is measured over the blocks of every track, which
+        // aren't kept.
+        let kept = match args.album {
+            true => None,
+            false => writeback::read(Path::new(path), &[writeback::LOUDNESS, writeback::PEAK])?
+                .and_then(|values| Some((values[0].parse().ok()?, values[1].parse().ok()?))),
+        };
+        let (lufs, loudness) = match kept {
+            Some((lufs, peak)) => (
+                Some(lufs),
+                analyze::Loudness {
+                    blocks: Vec::new(),
+                    peak,
+                },
+            ),
+            None => {
+                throttle.wait(path)?;
+                let loudness = analyze::loudness(Path::new(path))?;
+                let lufs = analyze::integrated(&loudness.blocks);
+                if let (Some(lufs), false) = (lufs, args.dry_run) {
+                    let values = [
+                        (writeback::LOUDNESS, format!("{lufs:.6}")),
+                        (writeback::PEAK, peak(loudness.peak)),
+                    ];
+                    writeback::store(Path::new(path), &values, &mut log)?;
+                }
+                (lufs, loudness)
+            }
+        };
+        println!("{}\t{}\t{path}", gain(lufs), peak(loudness.peak));
+
+        let album = match args.album {
+            true => grouping.album(Path::new(path))?,
+            false => String::new(),
+        };
+        match albums.iter_mut().find(|(name, _)| *name == album) {
+            Some((_, tracks)) => tracks.push((path, lufs, loudness)),
+            None => albums.push((album, vec![(path, lufs, loudness)])),
+        }
+    }
+
+    for (album, tracks) in &albums {
+        let mut album_tags = Vec::new();
+        if args.album {
+            let blocks: Vec<f64> = tracks
+                .iter()
+                .flat_map(|(_, _, loudness)| loudness.blocks.iter().copied())
+                .collect();
+            let album_lufs = analyze::integrated(&blocks);
+            let album_peak = tracks.iter().map(|(_, _, l)| l.peak).fold(0.0, f64::max);
+            println!("{}\t{}\t{album}", gain(album_lufs), peak(album_peak));
+            album_tags.push(("REPLAYGAIN_ALBUM_GAIN", gain(album_lufs)));
+            album_tags.push(("REPLAYGAIN_ALBUM_PEAK", peak(album_peak)));
+        }
+        if args.dry_run {
+            continue;
+        }
+
+        for (path, lufs, loudness) in tracks {
+            let mut tags = vec![
+                ("REPLAYGAIN_TRACK_GAIN", gain(*lufs)),
+                ("REPLAYGAIN_TRACK_PEAK", peak(loudness.peak)),
+            ];
+            tags.extend(album_tags.iter().cloned());
+
+            if Path::new(path).extension() != Some(OsStr::new("mp3")) {
+                edit_flac(path, config, &mut log, |comment| {
+                    for (key, value) in &tags {
+                        comment.set(*key, vec![value.clone()]);
+                    }
+                    Ok(())
+                })?;
+                continue;
+            }
+
+            edit_mp3(path, config, &mut log, |tag| {
+                for (key, value) in tags {
+                    tag.add_frame(id3::frame::ExtendedText {
+                        description: key.into(),
+                        value,
+                    });
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn transcode_files(args: &TranscodeFiles, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    let ignore = config.ignore_for("transcode");
+    let mut jobs = Vec::new();
+    for root in &args.files {
+        for path in ignore.expand(slice::from_ref(root), &["flac"], args.recursive)? {
+            let relative = match path.strip_prefix(root) {
+                Ok(relative) if root.is_dir() => relative.to_owned(),
+                _ => PathBuf::from(path.file_name().unwrap_or_default()),
+            };
+            let target = match &args.out {
+                Some(out) => out.join(relative),
+                None => path.clone(),
+            };
+            let target = target.with_extension(args.to.extension());
+            jobs.push((path, target));
+        }
+    }
+
+    safety::check("transcode", jobs.iter().map(|(_, target)| target))?;
+    let mut log = AuditLog::begin("transcode");
+    for (source, target) in &jobs {
+        if target.exists() && !args.force {
+            eprintln!("{}: already exists; skipping", target.display());
+            continue;
+        }
+        println!("{}\t{}", source.display(), target.display());
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        transcode::transcode(
+            source,
+            target,
+            args.to,
+            &args.bitrate,
+            args.sample_rate,
+            args.max_sample_rate,
+        )?;
+        log.create(target)?;
+    }
+
+    Ok(())
+}
+
+fn verify_audio(args: &VerifyAudio, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    if let Some(path) = args
+        .files
+        .iter()
+        .find(|path| Path::new(path).extension() != Some(OsStr::new("flac")))
+    {
+        return Err(Error::UnsupportedFileTye(path.clone()));
+    }
+
+    let mut checkpoint = Checkpoint::open("verify", args.resume.as_deref())?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut log = AuditLog::begin("verify");
+    let mut count = 0;
+    for path in &args.files {
+        let issues = match checkpoint.get(path) {
+            Some(issues) => issues.to_vec(),
+            None => {
+                throttle.wait(path)?;
+                let issues = check::integrity_issues(Path::new(path), &mut log)?;
+                checkpoint.record(path, &issues)?;
+                issues
+            }
+        };
+        for issue in issues {
+            println!("{path}: {issue}");
+            count += 1;
+        }
+    }
+    checkpoint.finish()?;
+
+    match count {
+        0 => Ok(()),
+        count => Err(Error::CheckFailed(count)),
+    }
+}
+
+fn check_tags(args: &CheckTags, config: &Config) -> Result<()> {
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    for path in &args.files {
+        throttle.wait(path)?;
+        let attributes = Attributes::from_path(path)?;
+        let comment = match Path::new(path).extension() == Some(OsStr::new("flac")) {
+            true => metaflac::Tag::read_from_path(path)?
+                .vorbis_comments()
+                .cloned(),
+            false => None,
+        };
+        warning::lint(path, &attributes, comment.as_ref());
+    }
+    Ok(())
+}
+
+fn check_dlna(args: &CheckDlna, config: &Config) -> Result<()> {
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut count = 0;
+    for path in &args.files {
+        throttle.wait(path)?;
+        for issue in check::dlna_issues(Path::new(path))? {
+            println!("{path}: {issue}");
+            count += 1;
+        }
+    }
+
+    match count {
+        0 => Ok(()),
+        count => Err(Error::CheckFailed(count)),
+    }
+}
+
+fn check_device(args: &CheckDevice, config: &Config) -> Result<()> {
+    let profile = device::find(&config.devices, &args.profile)?;
+    if let Some(dir) = &args.export {
+        fs::create_dir_all(dir)?;
+    }
+    let mut log = args
+        .export
+        .as_ref()
+        .map(|_| AuditLog::begin("check device"));
+
+    let mut count = 0;
+    for path in &args.files {
+        for issue in profile.issues(Path::new(path))? {
+            println!("{path}: {issue}");
+            count += 1;
+        }
+
+        let (Some(dir), Some(log)) = (&args.export, &mut log) else {
+            continue;
+        };
+        let target = PathGroup::new(path).flac_output(dir);
+        if target.exists() {
+            return Err(Error::IO(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.display()),
+            )));
+        }
+        let _lock = FileLock::acquire(&target)?;
+        fs::copy(path, &target)?;
+        {
+            let _writable = preflight::Writable::new(&target)?;
+            match target.extension().and_then(OsStr::to_str) {
+                Some("mp3") => profile.fit_id3(&target)?,
+                Some("flac") => profile.fit_vorbis(&target)?,
+                _ => {}
+            }
+        }
+        // Logged once fitted, which is how revert expects to find it.
+        log.create(&target)?;
+    }
+
+    match (count, &args.export) {
+        (0, _) | (_, Some(_)) => Ok(()),
+        (count, None) => Err(Error::CheckFailed(count)),
+    }
+}
+
+fn show_blocks(args: &ShowBlocks) -> Result<()> {
+    for path in &args.files {
+        for block in blocks::read(Path::new(path))? {
+            println!(
+                "{path}\t{}\t{}\t{}\t{}",
+                block.offset, block.size, block.kind, block.detail
+            );
+        }
+    }
+    Ok(())
+}
+
+fn describe_files(args: &DescribeFiles) -> Result<()> {
+    let pretty = args.format == describe::Format::Json;
+    for path in &args.files {
+        let document = describe::describe(Path::new(path))?;
+        println!("{}", document.render(pretty));
+    }
+    Ok(())
+}
+
+fn list_applications(args: &AppList) -> Result<()> {
+    for path in &args.files {
+        let flac = metaflac::Tag::read_from_path(path)?;
+        for application in application::blocks(&flac) {
+            println!(
+                "{path}\t{}\t{}",
+                ApplicationId::display(&application.id),
+                application.data.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn export_application(args: &AppExport) -> Result<()> {
+    if args.format == DataFormat::Raw && args.out.is_none() {
+        return Err(Error::RawRequiresFile);
+    }
+
+    let flac = metaflac::Tag::read_from_path(&args.file)?;
+    let application = application::blocks(&flac)
+        .find(|application| application.id == args.id.0)
+        .ok_or_else(|| {
+            Error::MissingApplication(args.file.clone(), ApplicationId::display(&args.id.0))
+        })?;
+
+    let encoded = args.format.encode(&application.data);
+    match &args.out {
+        Some(out) => manifest::write(out, encoded)?,
+        None => println!("{}", String::from_utf8_lossy(&encoded)),
+    }
+
+    Ok(())
+}
+
+fn import_application(args: &AppImport) -> Result<()> {
+    let encoded = match (&args.data, &args.from) {
+        (_, Some(from)) => fs::read(from)?,
+        (Some(_), None) if args.format == DataFormat::Raw => return Err(Error::RawRequiresFile),
+        (Some(data), None) => data.clone().into_bytes(),
+        (None, None) => unreachable!("clap requires --data or --from"),
+    };
+    let data = args.format.decode(&encoded)?;
+
+    preflight::check_writable([&args.file], args.chmod_if_needed)?;
+    let mut log = AuditLog::begin("app import");
+    let _lock = FileLock::acquire(&args.file)?;
+    let mut flac = metaflac::Tag::read_from_path(&args.file)?;
+    let before = application::fields(&flac, &args.id.0);
+    application::replace(&mut flac, &args.id, Some(data));
+    let _writable = preflight::Writable::new(&args.file)?;
+    verify::write_flac(&mut flac, Path::new(&args.file))?;
+    log.application(
+        &args.file,
+        &args.id.0,
+        &before,
+        &application::fields(&flac, &args.id.0),
+    )?;
+
+    Ok(())
+}
+
+fn remove_application(args: &AppRemove) -> Result<()> {
+    preflight::check_writable(&args.files, args.chmod_if_needed)?;
+    safety::check("app remove", &args.files)?;
+    let mut log = AuditLog::begin("app remove");
+    for path in &args.files {
+        let _lock = FileLock::acquire(path)?;
+        let mut flac = metaflac::Tag::read_from_path(path)?;
+        let before = application::fields(&flac, &args.id.0);
+        if !before.is_empty() {
+            application::replace(&mut flac, &args.id, None);
+            let _writable = preflight::Writable::new(path)?;
+            verify::write_flac(&mut flac, Path::new(path))?;
+            log.application(path, &args.id.0, &before, &[])?;
+        }
+    }
+    Ok(())
+}
+
+fn dedupe_art(args: &ArtDedupe, config: &Config) -> Result<()> {
+    let duplicates = art::duplicates(&args.files)?;
+
+    if !args.fix {
+        for duplicate in &duplicates {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                duplicate.dir.display(),
+                duplicate.album,
+                duplicate.paths.len(),
+                duplicate.picture.kind,
+                duplicate.picture.data.len(),
+                duplicate.reclaimable()
+            );
+        }
+        return Ok(());
+    }
+
+    let policy = args.policy.unwrap_or(config.dedupe);
+    preflight::check_writable(&args.files, args.chmod_if_needed)?;
+    safety::check("art dedupe --fix", &args.files)?;
+    let mut log = AuditLog::begin("art dedupe --fix");
+
+    for duplicate in &duplicates {
+        let data = &duplicate.picture.data;
+        let strip_from = match policy {
+            art::DedupePolicy::FirstTrack => &duplicate.paths[1..],
+            art::DedupePolicy::Folder => {
+                let folder = duplicate
+                    .dir
+                    .join(format!("folder.{}", art::extension(data)));
+                match fs::read(&folder) {
+                    Ok(existing) if existing == *data => {}
+                    Ok(_) => {
+                        eprintln!(
+                            "{}: a different image already exists; skipping",
+                            folder.display()
+                        );
+                        continue;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        fs::write(&folder, data)?;
+                        log.create(&folder)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+                &duplicate.paths[..]
+            }
+        };
+
+        for path in strip_from {
+            art::strip(path, data, args.preserve_dj_data, &mut log)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn embed_art(args: &ArtEmbed) -> Result<()> {
+    if args.max_size.is_some() {
+        Tool::Ffmpeg.ensure()?;
+    }
+    preflight::check_writable(&args.files, args.chmod_if_needed)?;
+    safety::check("art embed", &args.files)?;
+    let mut log = AuditLog::begin("art embed");
+
+    // image path -> image data, scaled if need be
+    let mut images: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+    for path in &args.files {
+        let image = match &args.image {
+            Some(image) => image.clone(),
+            None => {
+                let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+                let dir = if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir
+                };
+                match art::find_cover(dir)? {
+                    Some(image) => image,
+                    None => {
+                        eprintln!("{path}: no folder.jpg or cover image found; skipping");
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let data = match images.get(&image) {
+            Some(data) => data,
+            None => {
+                let mut data = fs::read(&image)?;
+                if let Some(max_size) = args.max_size {
+                    data = art::shrink(data, max_size)?;
+                }
+                images.entry(image.clone()).or_insert(data)
+            }
+        };
+
+        if art::embed(path, data, args.preserve_dj_data, &mut log)? {
+            println!("{path}\t{}", image.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_art(args: &ArtExtract) -> Result<()> {
+    if let Some(dir) = &args.dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    for path in &args.files {
+        let pictures = art::read(Path::new(path))?;
+        let Some(picture) = art::primary(&pictures) else {
+            eprintln!("{path}: no embedded picture");
+            continue;
+        };
+
+        let path = Path::new(path);
+        let name = path
+            .with_extension(art::extension(&picture.data))
+            .file_name()
+            .unwrap_or_default()
+            .to_owned();
+        let image = match &args.dir {
+            Some(dir) => dir.join(name),
+            None => path.with_file_name(name),
+        };
+        if image.exists() && !args.force {
+            eprintln!("{}: already exists; skipping", image.display());
+            continue;
+        }
+
+        manifest::write(&image, &picture.data)?;
+        println!("{}", image.display());
+    }
+
+    Ok(())
+}
+
+fn sync_art(args: &ArtSync, config: &Config) -> Result<()> {
+    // cover path -> the tracks of its album, in order
+    let mut albums: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    let ignore = config.ignore_for("art");
+    for root in &args.files {
+        for path in ignore.expand(slice::from_ref(root), &["flac", "mp3"], args.recursive)? {
+            let dir = match path.strip_prefix(root) {
+                Ok(relative) if root.is_dir() => relative.parent().unwrap_or(Path::new("")),
+                _ => Path::new(""),
+            };
+            let cover = args.to.join(dir).join("folder.jpg");
+            albums.entry(cover).or_default().push(path);
+        }
+    }
+
+    safety::check("art sync", albums.keys())?;
+    let mut log = AuditLog::begin("art sync");
+    for (cover, tracks) in &albums {
+        if cover.exists() && !args.force {
+            eprintln!("{}: already exists; skipping", cover.display());
+            continue;
+        }
+
+        let mut data = None;
+        for track in tracks {
+            if let Some(picture) = art::primary(&art::read(track)?) {
+                data = Some(picture.data.clone());
+                break;
+            }
+        }
+        if data.is_none() {
+            let dir = tracks[0].parent().unwrap_or(Path::new(""));
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            if let Some(image) = art::find_cover(dir)? {
+                data = Some(fs::read(image)?);
+            }
+        }
+        let Some(mut data) = data else {
+            eprintln!("{}: no cover found; skipping", tracks[0].display());
+            continue;
+        };
+
+        if let Some(max_size) = args.max_size {
+            data = art::shrink(data, max_size)?;
+        }
+        let data = art::jpeg(data)?;
+        if let Some(parent) = cover.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(cover, &data)?;
+        log.create(cover)?;
+        println!("{}", cover.display());
+    }
+
+    Ok(())
+}
+
+fn embed_lyrics(args: &LyricsSidecars, config: &Config) -> Result<()> {
+    let files =
+        config
+            .ignore_for("lyrics")
+            .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+    let files: Vec<_> = files
+        .into_iter()
+        .filter_map(|path| Some((lyrics::sidecar(&path)?, path)))
+        .collect();
+    let paths: Vec<_> = files.iter().map(|(_, path)| path).collect();
+    preflight::check_writable(&paths, args.chmod_if_needed)?;
+    safety::check("lyrics", &paths)?;
+    let mut log = AuditLog::begin("lyrics");
+
+    for (sidecar, path) in &files {
+        let (text, _) = encoding::decode(&fs::read(sidecar)?);
+        if path.extension() == Some(OsStr::new("mp3")) {
+            edit_mp3(path, config, &mut log, |tag| {
+                let lang = lyrics::language(&Attributes::from_id3(tag).language);
+                lyrics::write_id3(tag, &text, &lang);
+                Ok(())
+            })?;
+        } else {
+            edit_flac(path, config, &mut log, |comment| {
+                lyrics::write_vorbis(comment, &text);
+                Ok(())
+            })?;
+        }
+        println!("{}\t{}", path.display(), sidecar.display());
+    }
+
+    Ok(())
+}
+
+fn extract_lyrics(args: &LyricsSidecars, config: &Config) -> Result<()> {
+    let files =
+        config
+            .ignore_for("lyrics")
+            .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+
+    for path in &files {
+        let found = match path.extension() == Some(OsStr::new("mp3")) {
+            true => match id3::Tag::read_from_path(path) {
+                Ok(tag) => lyrics::read_id3(&tag),
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => None,
+                Err(e) => return Err(e.into()),
+            },
+            false => metaflac::Tag::read_from_path(path)?
+                .vorbis_comments()
+                .and_then(lyrics::read_vorbis),
+        };
+        let Some((text, extension)) = found else {
+            eprintln!("{}: no lyrics", path.display());
+            continue;
+        };
+
+        let sidecar = path.with_extension(extension);
+        if sidecar.exists() && !args.force {
+            eprintln!("{}: already exists; skipping", sidecar.display());
+            continue;
+        }
+        manifest::write(&sidecar, text)?;
+        println!("{}", sidecar.display());
+    }
+
+    Ok(())
+}
+
+fn make_thumbnails(args: &ArtThumbs) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    fs::create_dir_all(&args.out)?;
+
+    let mut done = HashSet::new();
+    for path in &args.files {
+        let attributes = Attributes::from_path(path)?;
+        let key = art::album_key(
+            &attributes.artist.join(" "),
+            attributes.album.as_deref().unwrap_or_default(),
+        );
+        if key.is_empty() || !done.insert(key.clone()) {
+            continue;
+        }
+
+        let thumbnail = args.out.join(format!("{key}.jpg"));
+        if thumbnail.exists() && !args.force {
+            continue;
+        }
+
+        let pictures = art::read(Path::new(path))?;
+        let Some(picture) = art::primary(&pictures) else {
+            // Another track of the album may have art.
+            done.remove(&key);
+            continue;
+        };
+
+        let source = args
+            .out
+            .join(format!(".{key}.{}", art::extension(&picture.data)));
+        fs::write(&source, &picture.data)?;
+        let status = Tool::Ffmpeg
+            .command()
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&source)
+            .arg("-vf")
+            .arg(format!(
+                "scale={0}:{0}:force_original_aspect_ratio=decrease",
+                args.size
+            ))
+            .arg(&thumbnail)
+            .status();
+        fs::remove_file(&source)?;
+
+        if !status?.success() {
+            return Err(Error::FfmpegFailed(path.clone()));
+        }
+        manifest::record(manifest::Action::Created, &thumbnail);
+        println!("{}", thumbnail.display());
+    }
+
+    Ok(())
+}
+
+fn analyze_dr(args: &AnalyzeDr, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    if args.write {
+        if let Some(path) = args
+            .files
+            .iter()
+            .find(|path| Path::new(path).extension() != Some(OsStr::new("flac")))
+        {
+            return Err(Error::UnsupportedFileTye(path.clone()));
+        }
+        preflight::check_writable(&args.files, args.chmod_if_needed)?;
+        safety::check("analyze dr --write", &args.files)?;
+    }
+
+    let mut checkpoint = Checkpoint::open("analyze dr", args.resume.as_deref())?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let grouping = args.group_by.clone().unwrap_or_default();
+    let mut log = AuditLog::begin("analyze dr");
+    let mut albums: Vec<(String, Vec<(&String, i64)>)> = Vec::new();
+    for path in &args.files {
+        // dr, peak, rms
+        let keys = [writeback::DR, writeback::DR_PEAK, writeback::DR_RMS];
+        let fields = match checkpoint.get(path) {
+            Some(fields) => fields.to_vec(),
+            None => {
+                let fields = match writeback::read(Path::new(path), &keys)? {
+                    Some(fields) => fields,
+                    None => {
+                        throttle.wait(path)?;
+                        let measured = analyze::dynamic_range(Path::new(path))?;
+                        let fields = vec![
+                            (measured.dr.round() as i64).to_string(),
+                            format!("{:.2}", measured.peak),
+                            format!("{:.2}", measured.rms),
+                        ];
+                        let values: Vec<_> = keys.into_iter().zip(fields.clone()).collect();
+                        writeback::store(Path::new(path), &values, &mut log)?;
+                        fields
+                    }
+                };
+                checkpoint.record(path, &fields)?;
+                fields
+            }
+        };
+        let dr: i64 = fields[0].parse().unwrap_or_default();
+        println!("DR{dr}\t{} dB\t{} dB\t{path}", fields[1], fields[2]);
+
+        let album = grouping.album(Path::new(path))?;
+        match albums.iter_mut().find(|(name, _)| *name == album) {
+            Some((_, tracks)) => tracks.push((path, dr)),
+            None => albums.push((album, vec![(path, dr)])),
+        }
+    }
+
+    for (album, tracks) in &albums {
+        let album_dr =
+            (tracks.iter().map(|&(_, dr)| dr as f64).sum::<f64>() / tracks.len() as f64).round();
+        println!("DR{album_dr}\t\t\t{album}");
+
+        if args.write {
+            for &(path, dr) in tracks {
+                edit_flac(path, config, &mut log, |comment| {
+                    comment.set("DYNAMIC RANGE", vec![dr.to_string()]);
+                    comment.set("ALBUM DYNAMIC RANGE", vec![album_dr.to_string()]);
+                    Ok(())
+                })?;
+            }
+        }
+    }
+
+    checkpoint.finish()
+}
+
+fn analyze_gaps(args: &AnalyzeGaps, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    if args.write {
+        preflight::check_writable(&args.files, args.chmod_if_needed)?;
+        safety::check("analyze gaps --write", &args.files)?;
+    }
+
+    let mut checkpoint = Checkpoint::open("analyze gaps", args.resume.as_deref())?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut log = AuditLog::begin("analyze gaps --write");
+    for path in &args.files {
+        // leading, trailing
+        let fields = match checkpoint.get(path) {
+            Some(fields) => fields.to_vec(),
+            None => {
+                throttle.wait(path)?;
+                let silence = analyze::silence(Path::new(path), args.threshold)?;
+                let fields = [
+                    format!("{:.3}", silence.leading),
+                    format!("{:.3}", silence.trailing),
+                ];
+                checkpoint.record(path, &fields)?;
+                fields.to_vec()
+            }
+        };
+        println!("{}\t{}\t{path}", fields[0], fields[1]);
+
+        if !args.write {
+            continue;
+        }
+        if Path::new(path).extension() != Some(OsStr::new("mp3")) {
+            edit_flac(path, config, &mut log, |comment| {
+                comment.set("LEADING SILENCE", vec![fields[0].clone()]);
+                comment.set("TRAILING SILENCE", vec![fields[1].clone()]);
+                Ok(())
+            })?;
+            continue;
+        }
+
+        edit_mp3(path, config, &mut log, |tag| {
+            for (description, value) in [
+                ("LEADING SILENCE", &fields[0]),
+                ("TRAILING SILENCE", &fields[1]),
+            ] {
+                tag.add_frame(id3::frame::ExtendedText {
+                    description: description.into(),
+                    value: value.clone(),
+                });
+            }
+            if args.gapless {
+                tag.remove_comment(Some("iTunPGAP"), None);
+                tag.add_frame(id3::frame::Comment {
+                    lang: "eng".into(),
+                    description: "iTunPGAP".into(),
+                    text: "1".into(),
+                });
+            }
+            Ok(())
+        })?;
+    }
+
+    checkpoint.finish()
+}
+
+fn render_spectrograms(args: &AnalyzeSpectrogram, config: &Config) -> Result<()> {
+    Tool::Ffmpeg.ensure()?;
+    fs::create_dir_all(&args.out)?;
+
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    for path in &args.files {
+        throttle.wait(path)?;
+        let name = Path::new(path).file_stem().unwrap_or(OsStr::new(path));
+        let image = args.out.join(name).with_extension("png");
+
+        let status = Tool::Ffmpeg
+            .command()
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(path)
+            .arg("-lavfi")
+            .arg(format!("showspectrumpic=s={}:legend=1", args.size))
+            .arg(&image)
+            .status()?;
+        if !status.success() {
+            return Err(Error::FfmpegFailed(path.clone()));
+        }
+        println!("{}", image.display());
+    }
+
+    Ok(())
+}
+
+fn set_token(args: &AuthSet) -> Result<()> {
+    if io::stdin().is_terminal() {
+        eprint!("{} token: ", args.service.name());
+    }
+    let mut token = String::new();
+    io::stdin().read_line(&mut token)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(Error::Keyring("no token given".into()));
+    }
+    auth::store(args.service, token)
+}
+
+fn show_tokens() -> Result<()> {
+    for &service in auth::Service::ALL {
+        let source = match auth::token(service) {
+            Ok(Some((_, auth::Source::Environment))) => "environment",
+            Ok(Some((_, auth::Source::Keyring))) => "keyring",
+            Ok(None) => "not set",
+            Err(Error::Keyring(message)) => {
+                eprintln!("{message}");
+                "not set"
+            }
+            Err(e) => return Err(e),
+        };
+        println!("{}\t{source}", service.name());
+    }
+    Ok(())
+}
+
+fn show_tools() -> Result<()> {
+    for &tool in Tool::ALL {
+        let program = tool.program();
+        let program = program.to_string_lossy();
+        match tool.version() {
+            Some(version) => println!("{}\t{version}\t{program}", tool.name()),
+            None => println!(
+                "{}\tnot found\t{program}\t{}",
+                tool.name(),
+                tool.install_hint()
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn show_log(args: &ShowLog) -> Result<()> {
+    let operations = audit::read_operations()?;
+
+    let Some(id) = &args.id else {
+        for operation in &operations {
+            println!(
+                "{}\t{}\t{}\t{} changes",
+                operation.id,
+                audit::format_timestamp(operation.timestamp),
+                operation.description,
+                operation.changes.len()
+            );
+        }
+        return Ok(());
+    };
+
+    let operation = operations
+        .iter()
+        .find(|operation| &operation.id == id)
+        .ok_or_else(|| Error::UnknownOperation(id.clone()))?;
+
+    for change in &operation.changes {
+        match change {
+            audit::Change::Vorbis {
+                path,
+                key,
+                old,
+                new,
+            }
+            | audit::Change::Id3 {
+                path,
+                key,
+                old,
+                new,
+            } => {
+                println!("{path}\t{key}\t{} -> {}", old.join(";"), new.join(";"))
+            }
+            audit::Change::Pictures { path, old, new } => {
+                println!("{path}\tpictures\t{} -> {}", old.len(), new.len())
+            }
+            audit::Change::Application { path, id, old, new } => {
+                println!("{path}\tapplication {id}\t{} -> {}", old.len(), new.len())
+            }
+            audit::Change::Create { path, .. } => println!("{path}\tcreated"),
+            audit::Change::Rename { from, to } => println!("{from}\trenamed to {to}"),
+            audit::Change::Delete { path } => println!("{path}\tdeleted"),
+        }
+    }
+
+    Ok(())
+}
+
+fn save_snapshot(args: &SnapshotSave, config: &Config) -> Result<()> {
+    let ignore = config.ignore_for("snapshot");
+    let snapshot = match &args.dir {
+        Some(dir) => snapshot::Snapshot::capture(dir, &ignore)?,
+        None if !config.roots.is_empty() => {
+            snapshot::Snapshot::capture_roots(&config.roots, &ignore)?
+        }
+        None => snapshot::Snapshot::capture(Path::new("."), &ignore)?,
+    };
+    let path = snapshot.save(&args.name)?;
+    println!(
+        "{} file(s) saved to {}",
+        snapshot.files.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn diff_snapshots(args: &SnapshotDiff, config: &Config) -> Result<()> {
+    let ignore = config.ignore_for("snapshot");
+    let before = snapshot::Snapshot::load(&args.name)?;
+    let after = match &args.other {
+        Some(other) => snapshot::Snapshot::load(other)?,
+        None if before.root.is_empty() => {
+            snapshot::Snapshot::capture_roots(&config.roots, &ignore)?
+        }
+        None => snapshot::Snapshot::capture(Path::new(&before.root), &ignore)?,
+    };
+
+    for difference in snapshot::diff(&before, &after) {
+        match difference {
+            snapshot::Difference::Added(path) => println!("+\t{path}"),
+            snapshot::Difference::Removed(path) => println!("-\t{path}"),
+            snapshot::Difference::Changed {
+                path,
+                key,
+                old,
+                new,
+            } => println!("{path}\t{key}\t{} -> {}", old.join(";"), new.join(";")),
+        }
+    }
+
+    Ok(())
+}
+
+fn export_tags(args: &ExportTags, config: &Config) -> Result<()> {
+    // Each root exports to its own subdirectory, so that the roots' layouts
+    // can't collide.
+    let trees: Vec<(&Path, PathBuf)> = match &args.dir {
+        Some(dir) => vec![(dir, args.out.clone())],
+        None if !config.roots.is_empty() => config
+            .roots
+            .roots
+            .iter()
+            .map(|root| (root.path.as_path(), args.out.join(&root.name)))
+            .collect(),
+        None => vec![(Path::new("."), args.out.clone())],
+    };
+
+    let ignore = config.ignore_for("export");
+    for (dir, out) in trees {
+        let snapshot = snapshot::Snapshot::capture(dir, &ignore)?;
+        for path in snapshot::export(&snapshot, &out, args.per_track)? {
+            println!("{}", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn ingest_downloads(args: &Ingest, config: &Config) -> Result<()> {
+    let plan = ingest::plan(&args.downloads, &args.into, &config.ignore_for("ingest"))?;
+    for (shown, target) in &plan.skipped {
+        eprintln!("skipping {shown}: {} already exists", target.display());
+    }
+    if plan.items.is_empty() {
+        eprintln!("nothing to ingest");
+        return Ok(());
+    }
+
+    for item in &plan.items {
+        let action = if item.convert { "convert" } else { "copy" };
+        println!("{action}\t{}\t{}", item.shown, item.target.display());
+    }
+    if plan.items.iter().any(|item| item.convert) {
+        Tool::Ffmpeg.ensure()?;
+    }
+
+    if !safety::confirm(&format!("ingest {} file(s)?", plan.items.len()))? {
+        return Ok(());
+    }
+
+    let mut log = AuditLog::begin("ingest");
+    for item in &plan.items {
+        if let Some(parent) = item.target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let _lock = FileLock::acquire(&item.target)?;
+        if item.convert {
+            let status = Tool::Ffmpeg
+                .command()
+                .arg("-i")
+                .arg(&item.source)
+                .arg(&item.target)
+                .status()?;
+            if !status.success() {
+                return Err(Error::FfmpegFailed(item.shown.clone()));
+            }
+        } else {
+            fs::copy(&item.source, &item.target)?;
+        }
+        log.create(&item.target)?;
+    }
+
+    Ok(())
+}
+
+fn watch_inbox(args: &Watch, config: &Config) -> Result<()> {
+    let template: Option<template::Template> =
+        args.organize.as_deref().map(str::parse).transpose()?;
+    let backend = match args.convert {
+        true => Some(Backend::select(args.no_ffmpeg, None)?),
+        false => None,
+    };
+    let options = tags::Options {
+        track_width: config.track_width.unwrap_or_default(),
+        year_tag: config.year_tag,
+    };
+    let mut extensions = vec!["flac", "mp3"];
+    if args.convert {
+        extensions.extend(convert::EXTENSIONS);
+    }
+    let ignore = config.ignore_for("watch");
+    if let Some(addr) = args.metrics {
+        metrics::serve(addr)?;
+    }
+    let mut watcher = Watcher::new(&args.dir, Duration::from_secs(args.settle));
+    if !args.once {
+        eprintln!("watching {}", args.dir.display());
+    }
+
+    loop {
+        let arrived = watcher.next(&ignore, &extensions, !args.once)?;
+        if arrived.is_empty() {
+            return Ok(());
+        }
+        let _library = LibraryLock::acquire()?;
+        let mut log = AuditLog::begin(format!("watch {}", args.dir.display()));
+
+        let mut files = Vec::new();
+        let mut converted = Vec::new();
+        for path in arrived {
+            let Some(backend) = backend.filter(|_| convert::is_source(&path)) else {
+                files.push(path);
+                continue;
+            };
+            match convert_arrival(&path, backend, args.delete_source) {
+                Ok(flac) => {
+                    watcher.claim(&flac);
+                    converted.push(flac.clone());
+                    files.push(flac);
+                }
+                Err(e) => {
+                    eprintln!("{}: {e}", path.display());
+                    metrics::failed("convert", 1);
+                }
+            }
+        }
+
+        // Files mustn't be filed away untagged because the sheet is being
+        // edited; they're left in the inbox instead.
+        if let Some(sheet) = &args.apply {
+            let rows = fs::read(sheet).map_err(Error::from).and_then(|bytes| {
+                let (text, _) = encoding::decode(&bytes);
+                Ok(sheet::read(&text, None, false, false)?.rows)
+            });
+            match rows {
+                Ok(rows) => files.retain(|path| {
+                    match tag_arrival(path, &rows, config, &options, &mut log) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            eprintln!("{}: {e}", path.display());
+                            metrics::failed("tag", 1);
+                            false
+                        }
+                    }
+                }),
+                Err(e) => {
+                    eprintln!("{}: {e}; leaving files where they are", sheet.display());
+                    metrics::failed("sheet", files.len());
+                    files.clear();
+                }
+            }
+        }
+        // Conversions are logged as tagged, which is how revert expects to
+        // find them.
+        for flac in &converted {
+            if let Err(e) = log.create(flac) {
+                eprintln!("{}: {e}", flac.display());
+            }
+        }
+
+        let (Some(template), Some(into)) = (&template, &args.into) else {
+            metrics::processed(files.len());
+            continue;
+        };
+        let mut files: Vec<_> = files
+            .into_iter()
+            .filter_map(|path| match Attributes::from_path(&path) {
+                Ok(attributes) => Some((path, attributes)),
+                Err(e) => {
+                    eprintln!("{}: {e}", path.display());
+                    metrics::failed("organize", 1);
+                    None
+                }
+            })
+            .collect();
+        if template.uses(Attribute::AlbumArtist) {
+            artists::settle(&mut files);
+        }
+        let targets = match plan_targets(template, &files, Some(into), None) {
+            Ok(targets) => targets,
+            Err(e) => {
+                eprintln!("{e}; leaving files where they are");
+                metrics::failed("organize", files.len());
+                continue;
+            }
+        };
+        // Files which can't be named by the template are skipped by the plan.
+        metrics::failed("organize", files.len() - targets.len());
+        for (path, target) in targets {
+            match relocate(&path, &target, false, false, &mut log) {
+                Ok(()) => metrics::processed(1),
+                Err(e) => {
+                    eprintln!("{}: {e}", path.display());
+                    metrics::failed("organize", 1);
+                }
+            }
+        }
+    }
+}
+
+/// Converts a file which arrived in the inbox to a FLAC beside it.
+fn convert_arrival(path: &Path, backend: Backend, delete_source: bool) -> Result<PathBuf> {
+    let job = Conversion::new(path, path.with_extension("flac"));
+    let mut output = String::new();
+    let started = Instant::now();
+    let result = job.run(backend, &[], &mut output);
+    if result.is_ok() {
+        metrics::converted(started.elapsed());
+    }
+    if !output.trim().is_empty() {
+        eprint!("{}:\n{output}", path.display());
+    }
+    result?;
+    println!("{}", job.target.display());
+    if delete_source {
+        fs::remove_file(path)?;
+    }
+    Ok(job.target)
+}
+
+/// Tags a file which arrived in the inbox from the sheet's row for a file of
+/// the same name, ignoring extensions, and prints what changed. Files no row
+/// names are left alone.
+fn tag_arrival(
+    path: &Path,
+    rows: &[FileAttributes],
+    config: &Config,
+    options: &tags::Options,
+    log: &mut AuditLog,
+) -> Result<()> {
+    let stem = path.file_stem();
+    let mut named = rows
+        .iter()
+        .filter(|row| Path::new(&row.path).file_stem() == stem);
+    let Some(row) = named.next() else {
+        return Ok(());
+    };
+    if named.next().is_some() {
+        warning::emit(
+            warning::Code::AmbiguousRow,
+            format_args!(
+                "{}: more than one row of the sheet names it; leaving its tags alone",
+                path.display()
+            ),
+        );
+        return Ok(());
+    }
+
+    let before = Attributes::from_path(path)?;
+    let mut attributes = row.attributes.clone();
+    // As with apply, a sheet without an artist column leaves the artist be.
+    if row.artist_missing {
+        attributes.artist = before.artist.clone();
+    }
+
+    if path.extension() == Some(OsStr::new("mp3")) {
+        edit_mp3(path, config, log, |tag| {
+            write_id3(tag, &attributes);
+            tags::write_id3_extended(tag, &attributes);
+            Ok(())
+        })?;
+    } else {
+        edit_flac(path, config, log, |comment| {
+            tags::write_attributes(comment, &attributes, options);
+            Ok(())
+        })?;
+    }
+    print_changes(
+        &path.to_string_lossy(),
+        &before,
+        &Attributes::from_path(path)?,
+    );
+    Ok(())
+}
+
+fn create_recipe(args: &RecipeCreate, config: &Config) -> Result<()> {
+    let steps = match &args.pipeline {
+        Some(name) => {
+            let pipeline = config
+                .pipelines
+                .get(name)
+                .ok_or_else(|| Error::UnknownPipeline(name.clone()))?;
+            if pipeline.when.is_some() {
+                return Err(Error::Recipe(format!(
+                    "pipeline {name} has a when guard, which a recipe can't carry"
+                )));
+            }
+            if pipeline.rename().is_some() {
+                return Err(Error::Recipe(format!(
+                    "pipeline {name} renames files, which a recipe can't do"
+                )));
+            }
+            pipeline.steps.iter().map(ToString::to_string).collect()
+        }
+        None => Vec::new(),
+    };
+
+    let mut recipe = recipe::Recipe {
+        steps,
+        ..Default::default()
+    };
+    for path in &args.files {
+        if Path::new(path).extension() != Some(OsStr::new("flac")) {
+            return Err(Error::UnsupportedFileTye(path.clone()));
+        }
+        let digest = recipe::audio_digest(Path::new(path))?;
+        recipe.sources.push((path.clone(), digest));
+        recipe
+            .attributes
+            .insert(path.clone(), Attributes::from_path(path)?);
+    }
+
+    recipe.write(&args.out)
+}
+
+fn apply_recipe(args: &RecipeApply, config: &Config) -> Result<()> {
+    let recipe = recipe::Recipe::read(&args.recipe)?;
+    let pipeline = pipeline::Pipeline {
+        when: None,
+        steps: recipe
+            .steps
+            .iter()
+            .map(|step| pipeline::Step::parse(step).map_err(Error::Recipe))
+            .collect::<Result<_>>()?,
+    };
+
+    let paths: Vec<PathBuf> = recipe
+        .sources
+        .iter()
+        .map(|(path, _)| args.root.join(path))
+        .collect();
+    preflight::check_writable(&paths, args.chmod_if_needed)?;
+
+    let mut mismatches = 0;
+    for ((name, expected), path) in recipe.sources.iter().zip(&paths) {
+        if recipe::audio_digest(path)? != *expected {
+            eprintln!("{name}: audio differs from the recipe's");
+            mismatches += 1;
+        }
+    }
+    if mismatches > 0 && !args.force {
+        return Err(Error::Recipe(format!(
+            "{mismatches} file(s) don't match the recipe; use --force to apply anyway"
+        )));
+    }
+
+    let _library = LibraryLock::acquire()?;
+    let mut log = AuditLog::begin(format!("recipe {}", args.recipe.display()));
+    for ((name, _), path) in recipe.sources.iter().zip(&paths) {
+        let mut target = recipe.attributes.get(name).cloned().unwrap_or_default();
+        pipeline.apply(&mut target)?;
+
+        edit_flac(path, config, &mut log, |comment| {
+            let before = Attributes::from_vorbis(comment);
+            for &attribute in Attribute::ALL {
+                let values = target.values(attribute);
+                if values != before.values(attribute) {
+                    write_vorbis(comment, attribute, values, config.year_tag);
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+fn rename_files(args: &RenameFiles) -> Result<()> {
+    let template: template::Template = args.pattern.parse()?;
+    if !args.dry_run {
+        safety::check("rename", &args.files)?;
+    }
+    let _library = (!args.dry_run).then(LibraryLock::acquire).transpose()?;
+    let mut log = AuditLog::begin(format!("rename --pattern {}", args.pattern));
+
+    let files = with_attributes(&args.files)?;
+    for (path, target) in plan_targets(&template, &files, None, args.max_path)? {
+        if target != path {
+            relocate(&path, &target, false, args.dry_run, &mut log)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn organize_files(args: &Organize, config: &Config) -> Result<()> {
+    let template: template::Template = args.pattern.parse()?;
+    let files =
+        config
+            .ignore_for("organize")
+            .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+    if !args.dry_run {
+        safety::check("organize", &files)?;
+    }
+    let _library = (!args.dry_run).then(LibraryLock::acquire).transpose()?;
+    let mut log = AuditLog::begin(format!(
+        "organize --into {} --pattern {}",
+        args.into.display(),
+        args.pattern
+    ));
+
+    let mut files = with_attributes(&files)?;
+    if template.uses(Attribute::AlbumArtist) {
+        artists::settle(&mut files);
+    }
+    for (path, target) in plan_targets(&template, &files, Some(&args.into), args.max_path)? {
+        if target != path {
+            relocate(&path, &target, args.copy, args.dry_run, &mut log)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn tag_from_filename(args: &TagFromFilename, config: &Config) -> Result<()> {
+    let template: template::Template = args.pattern.parse()?;
+    if !args.dry_run {
+        preflight::check_writable(&args.files, args.chmod_if_needed)?;
+        safety::check("tag-from-filename", &args.files)?;
+    }
+    let mut log = AuditLog::begin(format!("tag-from-filename --pattern {}", args.pattern));
+
+    for path in &args.files {
+        let Some(parsed) = template.parse(path) else {
+            eprintln!("{}: doesn't match the pattern; skipping", path.display());
+            continue;
+        };
+        let shown = path.to_string_lossy();
+        let before = Attributes::from_path(path)?;
+        let mut after = before.clone();
+        for (attribute, value) in &parsed {
+            after.set_values(*attribute, vec![value.clone()])?;
+        }
+
+        if args.dry_run {
+            print_changes(&shown, &before, &after);
+            continue;
+        }
+
+        if path.extension() == Some(OsStr::new("mp3")) {
+            edit_mp3(path, config, &mut log, |tag| {
+                write_id3(tag, &after);
+                Ok(())
+            })?;
+            continue;
+        }
+
+        let track_width = config.track_width.unwrap_or_default();
+        edit_flac(path, config, &mut log, |comment| {
+            for &(attribute, _) in &parsed {
+                write_attribute(comment, attribute, &after, track_width, config);
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+fn set_attributes(args: &SetAttributes, config: &Config) -> Result<()> {
+    let mut assignments = Vec::new();
+    let mut single = |attribute, value: &Option<String>| {
+        if let Some(value) = value {
+            assignments.push((attribute, vec![value.clone()]));
+        }
+    };
+    single(Attribute::Album, &args.album);
+    single(Attribute::Title, &args.title);
+    single(Attribute::Track, &args.track);
+    single(Attribute::Year, &args.year);
+    single(Attribute::Work, &args.work);
+    single(Attribute::MovementName, &args.movement_name);
+    single(Attribute::Movement, &args.movement);
+    single(Attribute::MovementTotal, &args.movement_total);
+    single(Attribute::ShowMovement, &args.show_movement);
+    single(Attribute::Grouping, &args.grouping);
+    single(Attribute::Media, &args.media);
+    single(Attribute::ReleaseCountry, &args.release_country);
+    single(Attribute::AlbumArtist, &args.album_artist);
+    single(Attribute::Disc, &args.disc);
+    single(Attribute::Comment, &args.comment);
+    for (attribute, values) in [
+        (Attribute::Artist, &args.artist),
+        (Attribute::Language, &args.language),
+        (Attribute::Genre, &args.genre),
+        (Attribute::Composer, &args.composer),
+    ] {
+        if !values.is_empty() {
+            assignments.push((attribute, values.clone()));
+        }
+    }
+
+    if !args.dry_run {
+        preflight::check_writable(&args.files, args.chmod_if_needed)?;
+        safety::check("set", &args.files)?;
+    }
+    let mut log = AuditLog::begin("set");
+    let track_width = args.track_width.or(config.track_width).unwrap_or_default();
+
+    for path in &args.files {
+        let shown = path.to_string_lossy();
+        // An MP3 without a tag is given one.
+        let before = match path.extension() == Some(OsStr::new("mp3")) {
+            true => match id3::Tag::read_from_path(path) {
+                Ok(tag) => Attributes::from_id3(&tag),
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => Attributes::default(),
+                Err(e) => return Err(e.into()),
+            },
+            false => Attributes::from_path(path)?,
+        };
+        let mut after = before.clone();
+        for (attribute, values) in &assignments {
+            after.set_values(*attribute, values.clone())?;
+        }
+        for artist in &args.append_artist {
+            if !after.artist.contains(artist) {
+                after.artist.push(artist.clone());
+            }
+        }
+
+        if args.dry_run {
+            print_changes(&shown, &before, &after);
+            continue;
+        }
+
+        if path.extension() == Some(OsStr::new("mp3")) {
+            edit_mp3(path, config, &mut log, |tag| {
+                let before = tag.clone();
+                write_id3(tag, &after);
+                if args.preserve_dj_data {
+                    dj::verify(path, &before, tag)?;
+                }
+                Ok(())
+            })?;
+            continue;
+        }
+
+        edit_flac(path, config, &mut log, |comment| {
+            for &(attribute, _) in &assignments {
+                write_attribute(comment, attribute, &after, track_width, config);
+            }
+            if !args.append_artist.is_empty() {
+                write_attribute(comment, Attribute::Artist, &after, track_width, config);
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+fn strip_tags(args: &StripTags, config: &Config) -> Result<()> {
+    preflight::check_writable(&args.files, args.chmod_if_needed)?;
+    safety::check("strip", &args.files)?;
+    let fields = (!args.all).then_some(args.field.as_slice());
+    let mut log = AuditLog::begin("strip");
+
+    for path in &args.files {
+        if path.extension() == Some(OsStr::new("mp3")) {
+            // An MP3 without a tag is left as it is.
+            edit_mp3(path, config, &mut log, |tag| {
+                let before = tag.clone();
+                if args.all || !args.field.is_empty() {
+                    strip::strip_id3(tag, fields);
+                }
+                if args.preserve_dj_data {
+                    dj::verify(path, &before, tag)?;
+                }
+                Ok(())
+            })?;
+            continue;
+        }
+
+        let _lock = FileLock::acquire(path)?;
+        let mut flac = metaflac::Tag::read_from_path(path)?;
+        let comment = flac.vorbis_comments_mut();
+        let before = comment.clone();
+        if args.all || !args.field.is_empty() {
+            strip::strip_vorbis(comment, fields);
+        }
+        config.protection.enforce(path, &before, comment);
+        let after = comment.clone();
+        let pictures = art::flac_picture_fields(&flac);
+        if args.all {
+            flac.remove_blocks(metaflac::BlockType::Picture);
+        }
+        let padded = flac
+            .get_blocks(metaflac::BlockType::Padding)
+            .next()
+            .is_some();
+        // Nothing to strip leaves the file untouched.
+        if after == before && (!args.all || pictures.is_empty()) && !(args.padding && padded) {
+            continue;
+        }
+
+        let _writable = preflight::Writable::new(path)?;
+        match args.padding {
+            true => strip::write_without_padding(&mut flac, path)?,
+            false => verify::write_flac(&mut flac, path)?,
+        }
+        log.vorbis(path, &before, &after)?;
+        log.pictures(path, &pictures, &art::flac_picture_fields(&flac))?;
+    }
+
+    Ok(())
+}
+
+fn normalize_tags(args: &NormalizeTags, config: &Config) -> Result<()> {
+    let files = config.ignore_for("normalize").expand(
+        &args.files,
+        NormalizeTags::EXTENSIONS,
+        args.recursive,
+    )?;
+    if !args.dry_run {
+        preflight::check_writable(&files, args.chmod_if_needed)?;
+        safety::check("normalize", &files)?;
+    }
+    let rules = normalize::Rules {
+        trim: !args.no_trim,
+        collapse: !args.no_collapse,
+        nfc: !args.no_nfc,
+        case: args.case,
+        feat: args.feat,
+    };
+    let track_width = args.track_width.or(config.track_width);
+    let mut log = AuditLog::begin("normalize");
+
+    for path in &files {
+        let shown = path.to_string_lossy();
+        let before = Attributes::from_path(path)?;
+        let mut after = before.clone();
+        rules.apply(&mut after)?;
+        let changes = tags::format_changes(&shown, &before, &after);
+
+        if path.extension() == Some(OsStr::new("mp3")) {
+            print!("{changes}");
+            if args.dry_run || changes.is_empty() {
+                continue;
+            }
+            edit_mp3(path, config, &mut log, |tag| {
+                write_id3(tag, &after);
+                Ok(())
+            })?;
+            continue;
+        }
+
+        if args.dry_run {
+            let flac = metaflac::Tag::read_from_path(path)?;
+            print!("{changes}");
+            if let (Some(comment), Some(width)) = (flac.vorbis_comments(), track_width) {
+                if let Some((old, new)) = padded_track(comment, width) {
+                    println!("{shown}\ttrack\t{old} -> {new}");
+                }
+            }
+            continue;
+        }
+
+        edit_flac(path, config, &mut log, |comment| {
+            print!("{changes}");
+            for &attribute in Attribute::ALL {
+                let values = after.values(attribute);
+                if values != before.values(attribute) {
+                    write_vorbis(comment, attribute, values, config.year_tag);
+                }
+            }
+            if let Some(width) = track_width {
+                if let Some((old, new)) = padded_track(comment, width) {
+                    println!("{shown}\ttrack\t{old} -> {new}");
+                    comment.set("TRACKNUMBER", vec![new]);
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// A FLAC file's TRACKNUMBER and the same number zero-padded to width, if
+/// they differ. A number written with its total is left alone.
+fn padded_track(
+    comment: &metaflac::block::VorbisComment,
+    width: usize,
+) -> Option<(String, String)> {
+    let old = tags::first_vorbis(comment, "TRACKNUMBER")?;
+    let new = format_track(old.trim().parse().ok()?, width);
+    (new != old).then_some((old, new))
+}
+
+fn copy_tags(args: &CopyTags, config: &Config) -> Result<()> {
+    let ignore = config.ignore_for("copy-tags");
+    let extensions = &["flac", "mp3"];
+    let from = ignore.expand(slice::from_ref(&args.from), extensions, args.recursive)?;
+    let to = ignore.expand(slice::from_ref(&args.to), extensions, args.recursive)?;
+    let mut paired = copy::pair(&args.from, from, &args.to, to, args.by)?;
+    sort_paths(&mut paired.unpaired);
+    for path in &paired.unpaired {
+        eprintln!("{}: no file to pair with; skipping", path.display());
+    }
+
+    if !args.dry_run {
+        let targets = paired.pairs.iter().map(|(_, target)| target);
+        preflight::check_writable(targets, args.chmod_if_needed)?;
+        safety::check("copy-tags", paired.pairs.iter().map(|(_, target)| target))?;
+    }
+    let mut log = AuditLog::begin("copy-tags");
+    let track_width = config.track_width.unwrap_or_default();
+
+    for (source, target) in &paired.pairs {
+        println!("{}\t{}", source.display(), target.display());
+        let attributes = Attributes::from_path(source)?;
+        let pictures = art::read(source)?;
+        let _lock = FileLock::acquire(target)?;
+
+        if target.extension() == Some(OsStr::new("mp3")) {
+            let mut tag = match id3::Tag::read_from_path(target) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(e.into()),
+            };
+            let before = tag.clone();
+            write_id3(&mut tag, &attributes);
+            tag.remove("APIC");
+            for picture in pictures {
+                tag.add_frame(id3::frame::Picture {
+                    mime_type: art::mime_type(&picture.data).into(),
+                    picture_type: picture.kind,
+                    description: String::new(),
+                    data: picture.data,
+                });
+            }
+            if args.preserve_dj_data {
+                dj::verify(target, &before, &tag)?;
+            }
+            config.protection.enforce_id3(target, &before, &mut tag)?;
+            if args.dry_run {
+                let shown = target.to_string_lossy();
+                print_changes(
+                    &shown,
+                    &Attributes::from_id3(&before),
+                    &Attributes::from_id3(&tag),
+                );
+                continue;
+            }
+            let _writable = preflight::Writable::new(target)?;
+            verify::write_id3(&tag, target)?;
+            log.id3(target, &before, &tag)?;
+            continue;
+        }
+
+        let mut flac = metaflac::Tag::read_from_path(target)?;
+        let comment = flac.vorbis_comments_mut();
+        let before = comment.clone();
+        if source.extension() == Some(OsStr::new("flac")) {
+            let source = metaflac::Tag::read_from_path(source)?;
+            comment.comments = source
+                .vorbis_comments()
+                .map(|source| source.comments.clone())
+                .unwrap_or_default();
+            comment
+                .comments
+                .retain(|key, _| !key.to_ascii_uppercase().starts_with("REPLAYGAIN_"));
+        } else {
+            for &attribute in Attribute::ALL {
+                write_attribute(comment, attribute, &attributes, track_width, config);
+            }
+        }
+        config.protection.enforce(target, &before, comment);
+        let after = comment.clone();
+
+        if args.dry_run {
+            let shown = target.to_string_lossy();
+            print_changes(
+                &shown,
+                &Attributes::from_vorbis(&before),
+                &Attributes::from_vorbis(&after),
+            );
+            continue;
+        }
+
+        flac.remove_blocks(metaflac::BlockType::Picture);
+        for picture in pictures {
+            let mut block = metaflac::block::Picture::new();
+            block.picture_type = art::to_flac_type(picture.kind);
+            block.mime_type = art::mime_type(&picture.data).into();
+            (block.width, block.height) = (picture.width, picture.height);
+            block.data = picture.data;
+            flac.push_block(metaflac::Block::Picture(block));
+        }
+        let _writable = preflight::Writable::new(target)?;
+        verify::write_flac(&mut flac, target)?;
+        log.vorbis(target, &before, &after)?;
+    }
+
+    Ok(())
+}
+
+fn lookup_release(args: &LookupRelease, config: &Config) -> Result<()> {
+    let id = match (&args.release_id, &args.artist, &args.album) {
+        (Some(id), _, _) => id.clone(),
+        (None, Some(artist), Some(album)) => {
+            let candidates = musicbrainz::search(artist, album)?;
+            let Some(best) = candidates.first() else {
+                return Err(Error::Lookup(format!(
+                    "no release on MusicBrainz matches {artist} - {album}"
+                )));
+            };
+            for candidate in &candidates {
+                let chosen = if candidate.id == best.id { "*" } else { " " };
+                eprintln!(
+                    "{chosen} {}\t{}\t{} - {}\t{}\t{}\t{} tracks",
+                    candidate.id,
+                    candidate.score,
+                    candidate.artist,
+                    candidate.title,
+                    candidate.date.as_deref().unwrap_or_default(),
+                    candidate.country.as_deref().unwrap_or_default(),
+                    candidate.track_count
+                );
+            }
+            best.id.clone()
+        }
+        _ => unreachable!("clap requires --release-id or --artist and --album"),
+    };
+    let release = musicbrainz::release(&id)?;
+
+    let mut files =
+        config
+            .ignore_for("lookup")
+            .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+    // A session may be applied from another directory.
+    if args.session.is_some() {
+        files = files
+            .into_iter()
+            .map(path::absolute)
+            .collect::<io::Result<_>>()?;
+    }
+    if !files.is_empty() && files.len() != release.tracks.len() {
+        warning::emit(
+            warning::Code::TrackCount,
+            format_args!(
+                "{} file(s) given for {} track(s); paths are paired in order",
+                files.len(),
+                release.tracks.len()
+            ),
+        );
+    }
+
+    let columns = [
+        Attribute::Album,
+        Attribute::AlbumArtist,
+        Attribute::Artist,
+        Attribute::Title,
+        Attribute::Track,
+        Attribute::Disc,
+        Attribute::Year,
+        Attribute::ReleaseCountry,
+        Attribute::Media,
+    ];
+    let mut header = vec!["path".to_string()];
+    header.extend(columns.iter().map(|attribute| attribute.name().to_string()));
+    let mut sheet = Vec::new();
+    let out: Box<dyn io::Write> = match args.session {
+        Some(_) => Box::new(&mut sheet),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = output::writer(args.format, args.delimiter, header, out)?;
+
+    let track_width = args.track_width.or(config.track_width).unwrap_or_default();
+    let year = release
+        .date
+        .as_deref()
+        .and_then(|date| date.get(..4))
+        .unwrap_or_default();
+    for (idx, track) in release.tracks.iter().enumerate() {
+        let path = files
+            .get(idx)
+            .map(|path| config.path_map.unmap(&path.to_string_lossy()))
+            .unwrap_or_default();
+        writer.write_record(&[
+            path,
+            release.title.clone(),
+            release.artist.clone(),
+            track.artists.join(sheet::separator()),
+            track.title.clone(),
+            format_track(track.number, track_width),
+            track.disc.to_string(),
+            year.to_string(),
+            release.country.clone().unwrap_or_default(),
+            track.media.clone().unwrap_or_default(),
+        ])?;
+    }
+    writer.finish()?;
+    drop(writer);
+
+    if let Some(name) = &args.session {
+        let mut session = session::Session::open(name)?;
+        let description = format!("{} - {}", release.artist, release.title);
+        let number = session.add(&id, &description, &sheet)?;
+        println!("{number}\t{description}");
+    }
+    Ok(())
+}
+
+fn list_sessions(args: &SessionList) -> Result<()> {
+    let Some(name) = &args.name else {
+        for name in session::Session::names()? {
+            let session = session::Session::load(&name)?;
+            let count = |status| {
+                session
+                    .albums
+                    .iter()
+                    .filter(|album| album.status == status)
+                    .count()
+            };
+            println!(
+                "{name}\t{} pending\t{} approved\t{} rejected\t{} applied",
+                count(session::Status::Pending),
+                count(session::Status::Approved),
+                count(session::Status::Rejected),
+                count(session::Status::Applied)
+            );
+        }
+        return Ok(());
+    };
+
+    let session = session::Session::load(name)?;
+    for album in &session.albums {
+        println!(
+            "{}\t{}\t{}\t{}",
+            album.number,
+            album.status.name(),
+            album.release,
+            album.description
+        );
+    }
+    Ok(())
+}
+
+fn show_session_album(args: &SessionShow) -> Result<()> {
+    let session = session::Session::load(&args.name)?;
+    let album = session.album(args.album)?;
+    let path = session.sheet(album.number)?;
+    eprintln!(
+        "{}\t{}\t{}",
+        album.description,
+        album.status.name(),
+        path.display()
+    );
+    io::Write::write_all(&mut io::stdout(), &fs::read(path)?)?;
+    Ok(())
+}
+
+fn review_albums(args: &SessionReview, status: session::Status) -> Result<()> {
+    let mut session = session::Session::load(&args.name)?;
+    let numbers: Vec<usize> = match args.pending {
+        true => session
+            .albums
+            .iter()
+            .filter(|album| album.status == session::Status::Pending)
+            .map(|album| album.number)
+            .collect(),
+        false => args.albums.clone(),
+    };
+    for &number in &numbers {
+        if session.album(number)?.status == session::Status::Applied {
+            return Err(Error::Session(format!(
+                "album {number} in session {} has already been applied",
+                session.name
+            )));
+        }
+        session.set_status(number, status)?;
+    }
+    session.save()?;
+    for number in numbers {
+        let album = session.album(number)?;
+        println!("{number}\t{}\t{}", status.name(), album.description);
+    }
+    Ok(())
+}
+
+fn plan_discs(args: &PlanDiscs, config: &Config) -> Result<()> {
+    let max = args.max_duration;
+    let files = config
+        .ignore_for("plan")
+        .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+
+    let mut tracks = Vec::new();
+    for path in files {
+        let path = path::absolute(path)?;
+        let seconds = audio::duration(&path)?
+            .ok_or_else(|| Error::Plan(format!("{}: unknown duration", path.display())))?;
+        if seconds > max.0 {
+            return Err(Error::Plan(format!(
+                "{} is {}, longer than a disc",
+                path.display(),
+                plan::Length(seconds)
+            )));
+        }
+        tracks.push((path, seconds));
+    }
+
+    // Each unit stays on one disc: a track, or a directory of them.
+    let mut units: Vec<&[(PathBuf, f64)]> = match args.split_by {
+        plan::Split::Track => tracks.chunks(1).collect(),
+        plan::Split::Album => tracks
+            .chunk_by(|(a, _), (b, _)| a.parent() == b.parent())
+            .collect(),
+    };
+    units = units
+        .into_iter()
+        .flat_map(|unit| {
+            let length: f64 = unit.iter().map(|(_, seconds)| seconds).sum();
+            match length > max.0 {
+                true => {
+                    let dir = unit[0].0.parent().unwrap_or(Path::new(""));
+                    warning::emit(
+                        warning::Code::SplitAlbum,
+                        format_args!(
+                            "{} is {}, longer than a disc; splitting it",
+                            dir.display(),
+                            plan::Length(length)
+                        ),
+                    );
+                    unit.chunks(1).collect()
+                }
+                false => vec![unit],
+            }
+        })
+        .collect();
+
+    let lengths: Vec<f64> = units
+        .iter()
+        .map(|unit| unit.iter().map(|(_, seconds)| seconds).sum())
+        .collect();
+    fs::create_dir_all(&args.out)?;
+    let mut units = units.into_iter();
+    for (idx, count) in plan::partition(&lengths, max.0).into_iter().enumerate() {
+        let disc: Vec<_> = units.by_ref().take(count).flatten().collect();
+        let length: f64 = disc.iter().map(|(_, seconds)| seconds).sum();
+
+        let mut tracks = Vec::new();
+        for (path, seconds) in &disc {
+            tracks.push(playlist::Track {
+                path: path.clone(),
+                attributes: Attributes::from_path(path)?,
+                seconds: Some(*seconds),
+            });
+        }
+
+        let playlist = args.out.join(format!("{}-{:02}.m3u", args.name, idx + 1));
+        manifest::write(&playlist, playlist::render(&tracks, None))?;
+        println!(
+            "{}\t{} tracks\t{}",
+            playlist.display(),
+            disc.len(),
+            plan::Length(length)
+        );
+    }
+
+    Ok(())
+}
+
+fn make_playlists(args: &MakePlaylist, config: &Config) -> Result<()> {
+    let files =
+        config
+            .ignore_for("playlist")
+            .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+    let mut tracks = Vec::new();
+    for path in files {
+        let path = path::absolute(path)?;
+        tracks.push(playlist::Track {
+            attributes: Attributes::from_path(&path)?,
+            seconds: audio::duration(&path)?,
+            path,
+        });
+    }
+
+    let mut playlists: Vec<(PathBuf, Vec<playlist::Track>)> = Vec::new();
+    match args.by {
+        playlist::By::All => {
+            tracks.sort_by(|a, b| args.sort.compare(a, b));
+            playlists.push((path::absolute(&args.out)?, tracks));
+        }
+        playlist::By::Album => {
+            let out = path::absolute(&args.out)?;
+            let mut albums: HashMap<String, Vec<playlist::Track>> = HashMap::new();
+            for track in tracks {
+                // Named as a file can be, so an album such as "AC/DC" or ".." stays in --out;
+                // albums which come out the same share a playlist.
+                match track.album() {
+                    Some(album) => albums
+                        .entry(template::component(&album))
+                        .or_default()
+                        .push(track),
+                    None => eprintln!("{}: no album; leaving it out", track.path.display()),
+                }
+            }
+            for (album, mut tracks) in albums {
+                tracks.sort_by(|a, b| playlist::Sort::Track.compare(a, b));
+                playlists.push((out.join(format!("{album}.m3u8")), tracks));
+            }
+            playlists.sort_by(|(a, _), (b, _)| collate::compare_paths(a, b));
+            fs::create_dir_all(&out)?;
+        }
+    }
+
+    for (target, tracks) in playlists {
+        let dir = target.parent().filter(|_| args.relative);
+        manifest::write(&target, playlist::render(&tracks, dir))?;
+        println!("{}", target.display());
+    }
+    Ok(())
+}
+
+/// Writes one attribute's values to a vorbis comment, padding track numbers.
+fn write_attribute(
+    comment: &mut metaflac::block::VorbisComment,
+    attribute: Attribute,
+    attributes: &Attributes,
+    track_width: usize,
+    config: &Config,
+) {
+    match (attribute, attributes.track) {
+        (Attribute::Track, Some(track)) => {
+            comment.set("TRACKNUMBER", vec![format_track(track, track_width)])
+        }
+        _ => write_vorbis(
+            comment,
+            attribute,
+            attributes.values(attribute),
+            config.year_tag,
+        ),
+    }
+}
+
+/// A file's name under a template, keeping the file's extension when the
+/// template has none. Files missing an attribute the template needs are
+/// reported and skipped.
+fn templated_name(
+    template: &template::Template,
+    path: &Path,
+    attributes: &Attributes,
+) -> Result<Option<String>> {
+    let mut name = match template.render(attributes) {
+        Ok(name) => name,
+        Err(field) => {
+            eprintln!("{}: no {field}; skipping", path.display());
+            return Ok(None);
+        }
+    };
+    if !template.has_extension() {
+        if let Some(extension) = path.extension() {
+            name = format!("{name}.{}", extension.to_string_lossy());
+        }
+    }
+    Ok(Some(name))
+}
+
+fn with_attributes(files: &[PathBuf]) -> Result<Vec<(PathBuf, Attributes)>> {
+    files
+        .iter()
+        .map(|path| Ok((path.clone(), Attributes::from_path(path)?)))
+        .collect()
+}
+
+/// Works out where each file goes, under `into` or else its own directory,
+/// shortening names too long for the filesystem. Fails before anything is
+/// moved if any path can't be made short enough.
+fn plan_targets(
+    template: &template::Template,
+    files: &[(PathBuf, Attributes)],
+    into: Option<&Path>,
+    max_path: Option<usize>,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut targets = Vec::new();
+    let mut too_long = 0;
+    for (path, attributes) in files {
+        let Some(name) = templated_name(template, path, attributes)? else {
+            continue;
+        };
+        let base = into.unwrap_or_else(|| path.parent().unwrap_or(Path::new("")));
+        let Some(fitted) = budget::fit(base, &name, budget::Budget::for_dir(base, max_path)) else {
+            eprintln!(
+                "{}: {} is too long, even shortened",
+                path.display(),
+                base.join(&name).display()
+            );
+            too_long += 1;
+            continue;
+        };
+        for (before, after) in &fitted.shortened {
+            eprintln!("{}: shortened {before:?} to {after:?}", path.display());
+        }
+        targets.push((path.clone(), base.join(fitted.relative)));
+    }
+
+    match too_long {
+        0 => Ok(targets),
+        count => Err(Error::PathTooLong(count)),
+    }
+}
+
+/// Moves or copies a file to a new path, creating directories as needed.
+/// Never overwrites: a file already at the target is reported and skipped.
+fn relocate(
+    path: &Path,
+    target: &Path,
+    copy: bool,
+    dry_run: bool,
+    log: &mut AuditLog,
+) -> Result<()> {
+    if target.exists() {
+        eprintln!(
+            "{}: {} already exists; skipping",
+            path.display(),
+            target.display()
+        );
+        return Ok(());
+    }
+    println!("{}\t{}", path.display(), target.display());
+    if dry_run {
+        return Ok(());
+    }
+
+    let _lock = FileLock::acquire(path)?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if copy {
+        fs::copy(path, target)?;
+        return log.create(target);
+    }
+    match fs::rename(path, target) {
+        Ok(()) => {}
+        // Moving to another filesystem takes a copy.
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(path, target)?;
+            fs::remove_file(path)?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    log.rename(path, target)
+}
+
+fn find_dupes(args: &FindDupes, config: &Config) -> Result<()> {
+    let files = config
+        .ignore_for("dupes")
+        .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut seen = HashSet::new();
+    let mut digests = Vec::new();
+    for path in files {
+        // A file named twice mustn't be taken for its own duplicate.
+        if !seen.insert(fs::canonicalize(&path)?) {
+            continue;
+        }
+        throttle.wait(&path)?;
+        match dupes::digest(&path) {
+            Ok(digest) => digests.push((path, digest)),
+            Err(Error::FfmpegFailed(_)) => {
+                eprintln!("{}: doesn't decode cleanly; skipping", path.display())
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let groups = dupes::group(digests);
+    let extra = groups.iter().flat_map(|group| &group.files[1..]);
+
+    if let Some(dir) = &args.move_to {
+        if !args.dry_run {
+            safety::check("dupes --move-to", extra.clone())?;
+        }
+        let mut log = AuditLog::begin("dupes --move-to");
+        for path in extra {
+            relocate(
+                path,
+                &dupes::moved_to(dir, path),
+                false,
+                args.dry_run,
+                &mut log,
+            )?;
+        }
+        return Ok(());
+    }
+
+    if args.delete {
+        if !args.dry_run {
+            safety::check("dupes --delete", extra.clone())?;
+        }
+        let mut log = AuditLog::begin("dupes --delete");
+        for path in extra {
+            println!("{}\tdeleted", path.display());
+            if !args.dry_run {
+                let _lock = FileLock::acquire(path)?;
+                fs::remove_file(path)?;
+                log.delete(path)?;
+            }
+        }
+        return Ok(());
+    }
+
+    match args.format {
+        dupes::Format::Text => {
+            let groups: Vec<_> = groups
+                .iter()
+                .map(|group| {
+                    let files: Vec<_> = group
+                        .files
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect();
+                    files.join("\n")
+                })
+                .collect();
+            if !groups.is_empty() {
+                println!("{}", groups.join("\n\n"));
+            }
+        }
+        dupes::Format::Json => println!("{}", dupes::json(&groups).render(true)),
+    }
+    Ok(())
+}
+
+fn find_versions(args: &FindVersions, config: &Config) -> Result<()> {
+    let files =
+        config
+            .ignore_for("versions")
+            .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+
+    let mut tracks = Vec::new();
+    for path in files {
+        let attributes = Attributes::from_path(&path)?;
+        let Some(title) = attributes.title else {
+            continue;
+        };
+        tracks.push(versions::Track {
+            artist: attributes.artist.join(", "),
+            title,
+            seconds: audio::duration(&path)?,
+            path,
+        });
+    }
+
+    for versions in versions::find(&tracks).values() {
+        let shown = versions
+            .iter()
+            .find(|version| version.canonical)
+            .unwrap_or(&versions[0])
+            .track;
+        println!("{} - {}", shown.artist, shown.title);
+        for version in versions {
+            let length = version.track.seconds.map_or("?:??".into(), |seconds| {
+                let seconds = seconds.round() as u64;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            });
+            println!(
+                "{}\t{}\t{}\t{length}\t{}",
+                if version.canonical { "*" } else { "" },
+                version.track.path.display(),
+                version.kind.unwrap_or("-"),
+                version.note.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn make_tracklist(args: &MakeTracklist, config: &Config) -> Result<()> {
+    let bytes = match &args.tracklist {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            buf
+        }
+    };
+    let (text, _) = encoding::decode(&bytes);
+    let lines: Vec<_> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let files = config.ignore_for("tracklist").expand(
+        std::slice::from_ref(&args.dir),
+        &["flac", "mp3"],
+        false,
+    )?;
+    if files.len() != lines.len() {
+        return Err(Error::Tracklist(format!(
+            "the tracklist has {} titles, but {} has {} files",
+            lines.len(),
+            args.dir.display(),
+            files.len()
+        )));
+    }
+
+    let columns = ["path", "track", "title"].map(String::from).to_vec();
+    let mut writer = output::writer(output::Format::Csv, None, columns, io::stdout().lock())?;
+    for (idx, (line, path)) in lines.iter().zip(&files).enumerate() {
+        let (number, title) = split_track_number(line);
+        writer.write_record(&[
+            path.to_string_lossy().into_owned(),
+            number.unwrap_or(idx as u32 + 1).to_string(),
+            title.to_string(),
+        ])?;
+    }
+    writer.finish()
+}
+
+/// Splits a leading track number such as `1.`, `01)`, or `1 -` from a title.
+fn split_track_number(line: &str) -> (Option<u32>, &str) {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = line[digits..].trim_start();
+    match (line[..digits].parse(), rest.strip_prefix(['.', ')', '-'])) {
+        (Ok(number), Some(title)) => (Some(number), title.trim_start()),
+        _ => (None, line),
+    }
+}
+
+fn import_riplog(args: &RiplogImport, config: &Config) -> Result<()> {
+    let riplog = riplog::RipLog::read(&args.log)?;
+    if let Some(path) = args
+        .files
+        .iter()
+        .find(|path| Path::new(path).extension() != Some(OsStr::new("flac")))
+    {
+        return Err(Error::UnsupportedFileTye(path.clone()));
+    }
+    if riplog.tracks.len() != args.files.len() {
+        return Err(Error::RipLog(format!(
+            "the log has {} tracks, but {} files were given",
+            riplog.tracks.len(),
+            args.files.len()
+        )));
+    }
+    preflight::check_writable(&args.files, args.chmod_if_needed)?;
+
+    if riplog.tracks.iter().any(|track| track.copy_crc.is_some()) {
+        Tool::Ffmpeg.ensure()?;
+    }
+    let mut mismatches = 0;
+    for (track, path) in riplog.tracks.iter().zip(&args.files) {
+        let Some(expected) = track.copy_crc else {
+            continue;
+        };
+        let actual = riplog::copy_crc(Path::new(path))?;
+        if actual != expected {
+            eprintln!(
+                "{path}: CRC is {actual:08X}, but the log has {expected:08X} for track {}",
+                track.number
+            );
+            mismatches += 1;
+        }
+    }
+    if mismatches > 0 && !args.force {
+        return Err(Error::RipLog(format!(
+            "{mismatches} file(s) don't match the log; use --force to tag them anyway"
+        )));
+    }
+
+    let mut log = AuditLog::begin(format!("riplog import {}", args.log.display()));
+    for (track, path) in riplog.tracks.iter().zip(&args.files) {
+        edit_flac(path, config, &mut log, |comment| {
+            let provenance = [
+                ("RIPPER", riplog.ripper.as_ref()),
+                ("RIPDRIVE", riplog.drive.as_ref()),
+                ("RIPDATE", riplog.date.as_ref()),
+            ];
+            for (key, value) in provenance {
+                if let Some(value) = value {
+                    comment.set(key, vec![value.clone()]);
+                }
+            }
+            comment.set("ACCURATERIP", vec![track.accuraterip().to_string()]);
+            Ok(())
+        })?;
+    }
+
+    // Keep the log with the album it describes.
+    if let (Some(first), Some(name)) = (args.files.first(), args.log.file_name()) {
+        let target = Path::new(first)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(name);
+        if !target.exists() {
+            fs::copy(&args.log, &target)?;
+            log.create(&target)?;
+        }
+    }
+
+    println!(
+        "{} tracks, AccurateRip {}",
+        riplog.tracks.len(),
+        riplog.accuraterip()
+    );
+    Ok(())
+}
+
+fn export_nml(args: &ExportNml, config: &Config) -> Result<()> {
+    let files = config
+        .ignore_for("nml")
+        .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+
+    let mut entries = Vec::new();
+    for path in files {
+        let path = path::absolute(path)?;
+        let attributes = Attributes::from_path(&path)?;
+        let tags = snapshot::read_tags(&path)?;
+        let tag = |keys: &[&str]| {
+            keys.iter().find_map(|key| {
+                tags.iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                    .and_then(|(_, values)| values.first().cloned())
+            })
+        };
+
+        entries.push(nml::Entry {
+            bpm: tag(&["BPM", "TBPM"]).and_then(|bpm| bpm.trim().parse().ok()),
+            key: tag(&["INITIALKEY", "KEY", "TKEY"]),
+            seconds: audio::duration(&path)?,
+            title: attributes.title,
+            artist: (!attributes.artist.is_empty()).then(|| attributes.artist.join(", ")),
+            album: attributes.album,
+            track: attributes.track,
+            year: attributes.year,
+            path,
+        });
+    }
+    manifest::write(&args.out, nml::render(&entries))?;
+
+    Ok(())
+}
+
+fn export_nfo(args: &ExportNfo, config: &Config) -> Result<()> {
+    let files = config
+        .ignore_for("nfo")
+        .expand(&args.files, &["flac", "mp3"], args.recursive)?;
+
+    let mut albums: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for path in files {
+        let path = path::absolute(path)?;
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        albums.entry(dir).or_default().push(path);
+    }
+    let mut dirs: Vec<_> = albums.into_iter().collect();
+    dirs.sort_by(|(a, _), (b, _)| collate::compare_paths(a, b));
+
+    let mut artists: Vec<(PathBuf, Option<nfo::Artist>)> = Vec::new();
+    for (dir, mut paths) in dirs {
+        sort_paths(&mut paths);
+
+        let mut album = nfo::Album {
+            title: String::new(),
+            artist: String::new(),
+            compilation: false,
+            genres: Vec::new(),
+            year: None,
+            musicbrainz_album_id: None,
+            musicbrainz_release_group_id: None,
+            musicbrainz_artist_id: None,
+            tracks: Vec::new(),
+        };
+        let mut album_artist = None;
+        let mut track_artists = Vec::new();
+        let mut tracks = Vec::new();
+        for path in &paths {
+            let attributes = Attributes::from_path(path)?;
+            let tags = snapshot::read_tags(path)?;
+            let tag = |keys: &[&str]| {
+                keys.iter().find_map(|key| {
+                    tags.iter()
+                        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                        .and_then(|(_, values)| values.first().cloned())
+                })
+            };
+
+            if album.title.is_empty() {
+                album.title = attributes.album.clone().unwrap_or_default();
+            }
+            album.year = album.year.or(attributes.year);
+            for genre in &attributes.genre {
+                if !album.genres.contains(genre) {
+                    album.genres.push(genre.clone());
+                }
+            }
+            album.musicbrainz_album_id = album
+                .musicbrainz_album_id
+                .or_else(|| tag(&["MUSICBRAINZ_ALBUMID", "TXXX:MusicBrainz Album Id"]));
+            album.musicbrainz_release_group_id = album.musicbrainz_release_group_id.or_else(|| {
+                tag(&[
+                    "MUSICBRAINZ_RELEASEGROUPID",
+                    "TXXX:MusicBrainz Release Group Id",
+                ])
+            });
+            album.musicbrainz_artist_id = album.musicbrainz_artist_id.or_else(|| {
+                tag(&[
+                    "MUSICBRAINZ_ALBUMARTISTID",
+                    "TXXX:MusicBrainz Album Artist Id",
+                ])
+            });
+            album_artist = album_artist.or(attributes.album_artist.clone());
+            if !track_artists.contains(&attributes.artist) {
+                track_artists.push(attributes.artist.clone());
+            }
+
+            tracks.push((
+                attributes.disc.unwrap_or(1),
+                nfo::Track {
+                    position: attributes.track,
+                    title: attributes.title,
+                    seconds: audio::duration(path)?,
+                },
+            ));
+        }
+
+        if album.title.is_empty() {
+            eprintln!("{}: no album tag; skipping", dir.display());
+            continue;
+        }
+        tracks.sort_by_key(|(disc, track)| (*disc, track.position));
+        album.tracks = tracks.into_iter().map(|(_, track)| track).collect();
+        album.artist = match (album_artist, &track_artists[..]) {
+            (Some(artist), _) => artist,
+            (None, [artist]) if !artist.is_empty() => artist.join(", "),
+            _ => {
+                album.compilation = true;
+                "Various Artists".into()
+            }
+        };
+        album.compilation |= album.artist.eq_ignore_ascii_case("Various Artists");
+
+        // An artist.nfo is only written where every album agrees on the artist.
+        if let Some(parent) = dir.parent() {
+            let artist = (!album.compilation).then(|| nfo::Artist {
+                name: album.artist.clone(),
+                genres: album.genres.clone(),
+                musicbrainz_artist_id: album.musicbrainz_artist_id.clone(),
+            });
+            match artists.iter_mut().find(|(dir, _)| dir == parent) {
+                Some((_, Some(existing)))
+                    if artist.as_ref().is_some_and(|a| a.name == existing.name) =>
+                {
+                    for genre in &album.genres {
+                        if !existing.genres.contains(genre) {
+                            existing.genres.push(genre.clone());
+                        }
+                    }
+                }
+                Some((_, existing)) => *existing = None,
+                None => artists.push((parent.to_path_buf(), artist)),
+            }
+        }
+
+        write_nfo(&dir.join("album.nfo"), &nfo::render_album(&album), args)?;
+    }
+
+    for (dir, artist) in artists {
+        if let Some(artist) = artist {
+            write_nfo(&dir.join("artist.nfo"), &nfo::render_artist(&artist), args)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_nfo(path: &Path, xml: &str, args: &ExportNfo) -> Result<()> {
+    if path.exists() && !args.force {
+        eprintln!("{}: already exists; skipping", path.display());
+        return Ok(());
+    }
+    println!("{}", path.display());
+    if !args.dry_run {
+        manifest::write(path, xml)?;
+    }
+    Ok(())
+}
+
+fn make_feeds(args: &MakeFeed, config: &Config) -> Result<()> {
+    let base_url = args
+        .base_url
+        .as_ref()
+        .or(config.feed_base_url.as_ref())
+        .ok_or_else(|| Error::Feed("no base URL; pass --base-url or set [feed] base-url".into()))?;
+    let root = path::absolute(&args.root)?;
+    let out = path::absolute(&args.out)?;
+    let ignore = config.ignore_for("feed");
+    feed::url(base_url, &root, &out)?;
+    fs::create_dir_all(&out)?;
+
+    let mut feeds = Vec::new();
+    for dir in &args.dirs {
+        let dir = path::absolute(dir)?;
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "feed".into());
+
+        let mut feed = feed::Feed {
+            title: name.clone(),
+            author: None,
+            image_url: None,
+            episodes: Vec::new(),
+        };
+        for (idx, path) in ignore.walk(&dir, &["flac", "mp3"])?.iter().enumerate() {
+            let attributes = Attributes::from_path(path)?;
+            if idx == 0 {
+                if let Some(album) = &attributes.album {
+                    feed.title = album.clone();
+                }
+                feed.author = attributes.artist.first().cloned();
+            }
+
+            if feed.image_url.is_none() {
+                let pictures = art::read(path)?;
+                if let Some(picture) = art::primary(&pictures) {
+                    let image = out.join(format!("{name}.{}", art::extension(&picture.data)));
+                    manifest::write(&image, &picture.data)?;
+                    feed.image_url = Some(feed::url(base_url, &root, &image)?);
+                }
+            }
+
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            feed.episodes.push(feed::Episode {
+                title: attributes.title.unwrap_or_else(|| stem.into_owned()),
+                url: feed::url(base_url, &root, path)?,
+                bytes: fs::metadata(path)?.len(),
+                mime: match path.extension() == Some(OsStr::new("flac")) {
+                    true => "audio/flac",
+                    false => "audio/mpeg",
+                },
+                seconds: audio::duration(path)?,
+            });
+        }
+
+        let target = out.join(format!("{name}.xml"));
+        manifest::write(&target, feed.render())?;
+        println!("{}", target.display());
+        feeds.push((feed.title, feed::url(base_url, &root, &target)?));
+    }
+
+    let index = out.join("feeds.opml");
+    manifest::write(&index, feed::opml(&feeds))?;
+    println!("{}", index.display());
+    Ok(())
+}
+
+fn revert_operation(args: &RevertOperation) -> Result<()> {
+    let _library = LibraryLock::acquire()?;
+    let operations = audit::read_operations()?;
+    let operation = operations
+        .iter()
+        .find(|operation| operation.id == args.id)
+        .ok_or_else(|| Error::UnknownOperation(args.id.clone()))?;
+    audit::revert(operation, args.force, args.chmod_if_needed)
+}
+
+fn list_attributes(args: &List, config: &Config, out: impl io::Write) -> Result<()> {
+    let track_width = args.track_width.or(config.track_width).unwrap_or_default();
+
+    // Sheets hold paths as text, so a name that isn't UTF-8 couldn't be
+    // applied back to the same file.
+    // Archives are only opened when named; walks pick up tracks alone.
+    let files = config
+        .ignore_for("list")
+        .expand(&args.files, List::EXTENSIONS, args.recursive)?;
+    preflight::check_utf8(&files)?;
+
+    // Archive members are listed as <archive>!/<entry>.
+    let mut extracted = Vec::new();
+    let mut members = HashMap::new();
+    let mut paths = Vec::new();
+    for file in &files {
+        if !archive::is_archive(file) {
+            paths.push(file.clone());
+            continue;
+        }
+        let archive = archive::extract(file, List::EXTENSIONS)?;
+        for (name, path) in &archive.entries {
+            members.insert(path.clone(), (file.to_string_lossy(), name.clone()));
+            paths.push(path.clone());
+        }
+        extracted.push(archive);
+    }
+    let files = preflight::check_utf8(&paths)?;
+
+    // Files are read before anything is written, so that one which can't be
+    // is left out of the listing, and its fields out of the columns.
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut failures = Failures::default();
+    let mut collection = Vec::new();
+    for &path in &files {
+        throttle.wait(path)?;
+        match Listed::read(path, args.all_fields) {
+            Ok(listed) => collection.push((path, listed)),
+            Err(e) if !args.fail_fast => failures.add(path, e),
+            Err(e) => return Err(e),
+        }
+    }
+    let keys: BTreeSet<&String> = collection
+        .iter()
+        .flat_map(|(_, listed)| listed.fields.keys())
+        .collect();
+    let technical = args.technical();
+    let mut formats = match technical {
+        true => {
+            let listed: Vec<&str> = collection.iter().map(|&(path, _)| path).collect();
+            audio::read_many(&listed)?
+        }
+        false => Vec::new(),
+    }
+    .into_iter();
+
+    let mut columns = vec!["path".to_string()];
+    columns.extend(
+        Attribute::ALL
+            .iter()
+            .map(|attribute| attribute.name().to_string()),
+    );
+    columns.extend(template::columns().iter().map(|(name, _)| name.clone()));
+    columns.push("fingerprint".into());
+    if technical {
+        columns.extend(List::TECHNICAL.iter().map(|column| column.to_string()));
+    }
+    columns.extend(keys.iter().map(|key| key.to_string()));
+    let layout = output::Layout::new(&columns, &args.columns, &args.sort)?;
+    let previous = args
+        .since
+        .as_deref()
+        .map(output::Previous::read)
+        .transpose()?;
+    let mut writer = output::writer(args.format, args.delimiter, layout.pick(&columns), out)?;
+
+    let root = args
+        .relative_to
+        .as_deref()
+        .map(path::absolute)
+        .transpose()?;
+    let mut sorted = Vec::new();
+    for (path, listed) in &collection {
+        let (path, item, comment) = (*path, &listed.attributes, &listed.comment);
+        let (shown, member) = match members.get(Path::new(path)) {
+            Some((archive, name)) => (archive.as_ref(), format!("!/{name}")),
+            None => (path, String::new()),
+        };
+        let extension = Path::new(path).extension().and_then(OsStr::to_str);
+        let name = format!("{shown}{member}");
+        let modified = fs::metadata(shown)?.modified()?;
+        let relative = match &root {
+            Some(root) => path::absolute(shown)?
+                .strip_prefix(root)
+                .ok()
+                .map(|relative| {
+                    let parts: Vec<_> = relative
+                        .components()
+                        .map(|part| part.as_os_str().to_string_lossy())
+                        .collect();
+                    parts.join("/")
+                }),
+            None => config.roots.qualify(Path::new(shown))?,
+        };
+        let shown = relative.unwrap_or_else(|| config.path_map.unmap(shown));
+        let mut record = vec![shown + &member];
+
+        for &attribute in Attribute::ALL {
+            match (attribute, item.track) {
+                (Attribute::Track, Some(track)) => record.push(format_track(track, track_width)),
+                _ => record.push(item.values(attribute).join(sheet::separator())),
+            }
+        }
+        for (_, column) in template::columns() {
+            record.push(column.text(item).unwrap_or_default());
+        }
+        record.push(item.fingerprint());
+
+        if technical {
+            let format = formats.next().expect("a format for every file");
+            record.push(format.sample_rate.to_string());
+            record.push(format.bits.map(|b| b.to_string()).unwrap_or_default());
+            record.push(format.channels.to_string());
+            record.push(format.layout());
+
+            // Pictures and DJ data are only read from FLAC and MP3.
+            if matches!(extension, Some("flac" | "mp3")) {
+                let pictures = art::read(Path::new(path))?;
+                record.push(if pictures.is_empty() { "no" } else { "yes" }.into());
+                record.push(pictures.len().to_string());
+                match art::primary(&pictures) {
+                    Some(picture) => {
+                        record.push(picture.kind.to_string());
+                        record.push(format!("{}x{}", picture.width, picture.height));
+                        record.push(picture.data.len().to_string());
+                    }
+                    None => record.extend([String::new(), String::new(), String::new()]),
+                }
+                record.push(dj::vendors(Path::new(path))?.join(","));
+            } else {
+                record.extend(std::iter::repeat_n(String::new(), 6));
+            }
+        }
+        for &key in &keys {
+            let values = listed
+                .fields
+                .get(key)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            record.push(values.join(sheet::separator()));
+        }
+
+        if previous
+            .as_ref()
+            .is_some_and(|previous| !previous.changed(&columns, &record, modified))
+        {
+            continue;
+        }
+        warning::lint(&name, item, comment.as_ref());
+        match layout.sorts() {
+            true => sorted.push(record),
+            false => writer.write_record(&layout.pick(&record))?,
+        }
+    }
+
+    // The sort is stable, so rows which tie stay in path order.
+    sorted.sort_by(|a, b| layout.compare(a, b));
+    for record in &sorted {
+        writer.write_record(&layout.pick(record))?;
+    }
+    writer.finish()?;
+    failures.finish(args.errors, files.len())
+}
+
+/// What `list` reads of a file.
+struct Listed {
+    attributes: Attributes,
+    /// Its vorbis comments, for a FLAC or Ogg file
+    comment: Option<VorbisComment>,
+    /// Its fields no attribute covers, with --all-fields
+    fields: BTreeMap<String, Vec<String>>,
+}
+
+impl Listed {
+    fn read(path: &str, all_fields: bool) -> Result<Self> {
+        let comment = match Path::new(path).extension().and_then(OsStr::to_str) {
+            Some("flac") => metaflac::Tag::read_from_path(path)?
+                .vorbis_comments()
+                .cloned(),
+            Some("ogg" | "oga" | "opus") => Some(ogg::read_comments(Path::new(path))?),
+            _ => None,
+        };
+        Ok(Listed {
+            attributes: Attributes::from_path(path)?,
+            comment,
+            fields: match all_fields {
+                true => tags::read_fields(Path::new(path))?,
+                false => BTreeMap::new(),
+            },
+        })
+    }
+}
+
+fn edit_attributes(args: &EditTags, config: &Config) -> Result<()> {
+    let files =
+        config
+            .ignore_for("edit")
+            .expand(&args.files, EditTags::EXTENSIONS, args.recursive)?;
+    let list = List {
+        files,
+        recursive: false,
+        track_width: None,
+        technical: false,
+        all_fields: false,
+        relative_to: None,
+        format: output::Format::Tsv,
+        delimiter: None,
+        errors: failures::Report::Text,
+        fail_fast: false,
+        columns: Vec::new(),
+        sort: Vec::new(),
+        since: None,
+    };
+    let mut listed = Vec::new();
+    list_attributes(&list, config, &mut listed)?;
+
+    // Created afresh, so that nothing already in the temporary directory,
+    // such as a link planted there, is written through.
+    let sheet = env::temp_dir().join(format!("flacdat-edit-{}.tsv", process::id()));
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&sheet)?
+        .write_all(&listed)?;
+
+    let edit = || {
+        open_editor(&sheet)?;
+        let Some(changed) = changed_rows(&listed, &fs::read(&sheet)?)? else {
+            eprintln!("no changes");
+            return Ok(());
+        };
+        fs::write(&sheet, changed)?;
+        let apply = ApplyAttributes {
+            attributes: Some(sheet.to_string_lossy().into_owned()),
+            in_place: true,
+            backup: args.backup.clone(),
+            chmod_if_needed: args.chmod_if_needed,
+            dry_run: args.dry_run,
+            quiet: args.quiet,
+            ..Default::default()
+        };
+        apply_attributes(&apply, config)
+    };
+    match edit() {
+        Ok(()) => Ok(fs::remove_file(&sheet)?),
+        Err(e) => {
+            eprintln!(
+                "the edited sheet is kept at {0}; apply it with flacdat apply --in-place \
+                 --attributes {0}",
+                sheet.display()
+            );
+            Err(e)
+        }
+    }
+}
+
+fn diff_sheet(args: &DiffSheet, config: &Config) -> Result<()> {
+    let bytes = match &args.attributes {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            buf
+        }
+    };
+    let (text, _) = encoding::decode(&bytes);
+    let sheet = sheet::read(&text, args.delimiter, false, false)?;
+
+    // Files are told apart by their canonical paths, however the sheet and
+    // the arguments happen to spell them.
+    let files =
+        config
+            .ignore_for("diff")
+            .expand(&args.files, EditTags::EXTENSIONS, args.recursive)?;
+    let mut unlisted: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for file in files {
+        unlisted.push((fs::canonicalize(&file)?, file));
+    }
+
+    let mut differences = 0;
+    for row in &sheet.rows {
+        let path = &row.path;
+        let resolved = match (&args.root, config.roots.resolve(path)) {
+            (_, Some(resolved)) => resolved,
+            (Some(root), None) if Path::new(path).is_relative() => root.join(path),
+            _ => config.path_map.map(path).into(),
+        };
+        let Ok(canonical) = fs::canonicalize(&resolved) else {
+            println!("{path}\tno file");
+            differences += 1;
+            continue;
+        };
+        if !args.files.is_empty() {
+            match unlisted.iter().position(|(file, _)| *file == canonical) {
+                Some(idx) => {
+                    unlisted.remove(idx);
+                }
+                None => continue,
+            }
+        }
+
+        let on_disk = Attributes::from_path(&canonical)?;
+        for &attribute in &sheet.attributes {
+            let (old, new) = (on_disk.values(attribute), row.attributes.values(attribute));
+            if old != new {
+                println!(
+                    "{path}\t{}\t{} -> {}",
+                    attribute.name(),
+                    old.join(";"),
+                    new.join(";")
+                );
+                differences += 1;
+            }
+        }
+    }
+
+    for (_, file) in unlisted {
+        println!("{}\tno row", file.display());
+        differences += 1;
+    }
+
+    match differences {
+        0 => Ok(()),
+        differences => Err(Error::SheetDiffers(differences)),
+    }
+}
+
+fn find_files(args: &FindFiles, config: &Config) -> Result<()> {
+    let files = config
+        .ignore_for("find")
+        .expand(&args.files, List::EXTENSIONS, true)?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut found = Vec::new();
+    for file in files {
+        throttle.wait(&file)?;
+        let attributes = Attributes::from_path(&file)?;
+        let matches = args
+            .condition
+            .as_ref()
+            .is_none_or(|condition| condition.matches(&attributes))
+            && args
+                .missing
+                .iter()
+                .all(|&attribute| attributes.values(attribute).is_empty());
+        if matches {
+            found.push(file);
+        }
+    }
+
+    if !args.rows {
+        for file in found {
+            println!("{}", file.display());
+        }
+        return Ok(());
+    }
+    if found.is_empty() {
+        return Ok(());
+    }
+    let list = List {
+        files: found,
+        recursive: false,
+        track_width: None,
+        technical: false,
+        all_fields: false,
+        relative_to: None,
+        format: output::Format::Tsv,
+        delimiter: None,
+        errors: failures::Report::Text,
+        fail_fast: false,
+        columns: Vec::new(),
+        sort: Vec::new(),
+        since: None,
+    };
+    list_attributes(&list, config, io::stdout().lock())
+}
+
+fn show_stats(args: &ShowStats, config: &Config) -> Result<()> {
+    let files = config
+        .ignore_for("stats")
+        .expand(&args.files, List::EXTENSIONS, true)?;
+    let mut throttle = Throttle::new(config.throttle, &config.roots);
+    let mut stats = stats::Stats::default();
+    for file in &files {
+        throttle.wait(file)?;
+        stats.add(&Attributes::from_path(file)?, audio::duration(file)?);
+    }
+
+    match args.format {
+        stats::Format::Table => print!("{}", stats.table(args.top)),
+        stats::Format::Json => println!("{}", stats.json(args.top).render(true)),
+    }
+    Ok(())
+}
+
+fn edit_in_terminal(args: &EditInTerminal, config: &Config) -> Result<()> {
+    let paths =
+        config
+            .ignore_for("tui")
+            .expand(&args.files, EditTags::EXTENSIONS, args.recursive)?;
+    let mut files = Vec::new();
+    for path in preflight::check_utf8(&paths)? {
+        let before = Attributes::from_path(path)?;
+        files.push(tui::File {
+            path: path.into(),
+            after: before.clone(),
+            before,
+        });
+    }
+    if !tui::run(&mut files)? {
+        return Ok(());
+    }
+
+    let changed: Vec<_> = files.iter().filter(|file| file.changed()).collect();
+    let paths: Vec<_> = changed.iter().map(|file| &file.path).collect();
+    preflight::check_writable(&paths, args.chmod_if_needed)?;
+    safety::check("tui", &paths)?;
+    let mut log = AuditLog::begin("tui");
+    let track_width = config.track_width.unwrap_or_default();
+
+    for file in changed {
+        let path = Path::new(&file.path);
+        print_changes(&file.path, &file.before, &file.after);
+        if path.extension() == Some(OsStr::new("mp3")) {
+            edit_mp3(path, config, &mut log, |tag| {
+                write_id3(tag, &file.after);
+                Ok(())
+            })?;
+            continue;
+        }
+
+        edit_flac(path, config, &mut log, |comment| {
+            for &attribute in Attribute::ALL {
+                if file.after.values(attribute) != file.before.values(attribute) {
+                    write_attribute(comment, attribute, &file.after, track_width, config);
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Opens a file in $VISUAL or $EDITOR, or vi, and waits for the editor to
+/// exit.
+fn open_editor(path: &Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".into());
+    // Editors are often given with arguments, as "code --wait".
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(Error::EditorFailed(editor)),
+    }
+}
+
+/// The rows of an edited tsv sheet which differ from the sheet as listed,
+/// under the edited header row, or `None` if every row is as it was. A row
+/// is unchanged if the listed row of the same path has the same value in
+/// each of its columns, so columns may be reordered or deleted freely.
+fn changed_rows(listed: &[u8], edited: &[u8]) -> Result<Option<Vec<u8>>> {
+    let reader = |sheet| {
+        csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .from_reader(sheet)
+    };
+
+    let mut before = reader(listed);
+    let listed_headers = before.headers()?.clone();
+    let mut rows = HashMap::new();
+    for record in before.records() {
+        let record = record?;
+        rows.insert(record.get(0).unwrap_or_default().to_owned(), record);
+    }
+
+    let mut after = reader(edited);
+    let headers = after.headers()?.clone();
+    let path = headers
+        .iter()
+        .position(|header| header == "path")
+        .ok_or(Error::MissingPathColumn)?;
+    // Where each edited column was in the listing.
+    let columns: Vec<_> = headers
+        .iter()
+        .map(|header| listed_headers.iter().position(|listed| listed == header))
+        .collect();
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .flexible(true)
+        .from_writer(Vec::new());
+    writer.write_record(&headers)?;
+    let mut changed = 0;
+    for record in after.records() {
+        let record = record?;
+        let unchanged = rows
+            .get(record.get(path).unwrap_or_default())
+            .is_some_and(|listed| {
+                columns.iter().zip(&record).all(|(column, value)| {
+                    column.and_then(|column| listed.get(column)) == Some(value)
+                })
+            });
+        if !unchanged {
+            writer.write_record(&record)?;
+            changed += 1;
+        }
+    }
+    if changed == 0 {
+        return Ok(None);
+    }
+    writer
+        .into_inner()
+        .map(Some)
+        .map_err(|e| Error::IO(e.into_error()))
+}
+
+fn convert_wav_to_flac(args: &ConvertToFlac) -> Result<()> {
+    // With --output, each file keeps its place below the directory holding
+    // every source.
+    let base = match args.output {
+        Some(_) => common_dir(&args.files)?,
+        None => PathBuf::new(),
+    };
+    let place = |source: &Path| -> Result<PathBuf> {
+        let Some(output) = &args.output else {
+            return Ok(source.to_owned());
+        };
+        let source = path::absolute(source)?;
+        Ok(output.join(source.strip_prefix(&base).unwrap_or(&source)))
+    };
+
+    if let Some(file) = args.files.iter().find(|file| {
+        let file = Path::new(file);
+        !convert::is_source(file) && !archive::is_archive(file)
+    }) {
+        return Err(Error::UnsupportedFileTye(file.clone()));
+    }
+
+    let mut jobs: Vec<Conversion> = args
+        .source_paths()
+        .map(|path| Ok(Conversion::new(path, place(path)?.with_extension("flac"))))
+        .collect::<Result<_>>()?;
+    let sources = jobs.len();
+
+    // Files in an archive are converted into a directory named after it,
+    // alongside it.
+    let mut extracted = Vec::new();
+    for file in &args.files {
+        let file = Path::new(file);
+        if !archive::is_archive(file) {
+            continue;
+        }
+        let archive = archive::extract(file, convert::EXTENSIONS)?;
+        let out = place(file)?.with_file_name(archive::stem(file));
+        for (name, path) in &archive.entries {
+            jobs.push(Conversion::new(path, out.join(name).with_extension("flac")));
+        }
+        extracted.push(archive);
+    }
+
+    if let Some(cue) = &args.cue {
+        let dir = match &args.output {
+            Some(output) => output.clone(),
+            None => cue.parent().unwrap_or(Path::new("")).to_owned(),
+        };
+        jobs.extend(convert::cue_tracks(cue, &dir)?);
+    }
+
+    let encoding = args.encoding();
+    let needs_ffmpeg = match encoding.needs_ffmpeg() {
+        Some(option) => Some(option.to_string()),
+        None => jobs
+            .iter()
+            .find(|job| {
+                !job.source
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"))
+            })
+            .map(|job| format!("converting {}", job.source.display())),
+    };
+    let backend = Backend::select(args.no_ffmpeg, needs_ffmpeg.as_deref())?;
+
+    let mut log = AuditLog::begin("convert");
+    let mut failed = 0;
+    convert::run_all(
+        &jobs,
+        backend,
+        &encoding,
+        args.jobs,
+        |idx, result, output| {
+            let Conversion {
+                source: path,
+                target: flac_path,
+                ..
+            } = &jobs[idx];
+            if !output.trim().is_empty() {
+                eprint!("{}:\n{output}", path.display());
+            }
+            match result {
+                Ok(()) => {
+                    log.create(flac_path)?;
+                    println!("{}", flac_path.display());
+                    if args.delete_source && idx < sources {
+                        fs::remove_file(path)?;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}: {e}", path.display());
+                    failed += 1;
+                }
+            }
+            Ok(())
+        },
+    )?;
+
+    match failed {
+        0 => Ok(()),
+        count => Err(Error::ConvertFailed(count)),
+    }
+}
+
+/// The deepest directory holding every file given.
+fn common_dir(files: &[String]) -> Result<PathBuf> {
+    let mut common: Option<PathBuf> = None;
+    for file in files {
+        let dir = path::absolute(file)?
+            .parent()
+            .map(Path::to_owned)
+            .unwrap_or_default();
+        common = Some(match common {
+            None => dir,
+            Some(common) => common
+                .components()
+                .zip(dir.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    Ok(common.unwrap_or_default())
+}
+
+fn read_attributes(args: &ApplyAttributes) -> Result<Vec<FileAttributes>> {
+    if let Some(name) = &args.session {
+        let session = session::Session::load(name)?;
+        let mut rows = Vec::new();
+        for album in &session.albums {
+            if album.status == session::Status::Approved {
+                let text = fs::read_to_string(session.sheet(album.number)?)?;
+                rows.extend(sheet::read(&text, None, args.skip_invalid, args.all_fields)?.rows);
+            }
+        }
+        return Ok(rows);
+    }
+
+    let bytes = match &args.attributes {
+        Some(url) if fetch::is_url(url) => fetch::get(url)?,
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut buf = Vec::new();
+            io::stdin().lock().read_to_end(&mut buf)?;
+            buf
+        }
+    };
+
+    let source_name = args.attributes.as_deref().unwrap_or("stdin");
+    let actual = hex::encode(digest::sha256(&bytes));
+    match &args.sha256 {
+        Some(expected) if !expected.eq_ignore_ascii_case(&actual) => {
+            return Err(Error::ChecksumMismatch {
+                source_name: source_name.into(),
+                expected: expected.clone(),
+                actual,
+            })
+        }
+        Some(_) => {}
+        None if fetch::is_url(source_name) => warning::emit(
+            warning::Code::UnpinnedSheet,
+            format_args!("{source_name} is not pinned; pass --sha256 {actual} to pin it"),
+        ),
+        None => {}
+    }
+
+    let (text, encoding) = encoding::decode(&bytes);
+    if let Some(encoding) = encoding {
+        warning::emit(
+            warning::Code::SheetEncoding,
+            format_args!("attribute sheet decoded as {encoding}"),
+        );
+    }
+
+    Ok(sheet::read(&text, args.delimiter, args.skip_invalid, args.all_fields)?.rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_paths_groups_directories() {
+        let mut paths = vec!["b.flac", "a.flac", "a/b.flac", "a/a.flac", "A.flac"];
+        sort_paths(&mut paths);
+        assert_eq!(
+            paths,
+            ["a/a.flac", "a/b.flac", "a.flac", "A.flac", "b.flac"]
+        );
+    }
+
+    #[test]
+    fn sort_paths_files_accents_with_their_letter() {
+        let mut paths = vec!["Zola.flac", "Étienne.flac", "Eve.flac", "Edith.flac"];
+        sort_paths(&mut paths);
+        assert_eq!(
+            paths,
+            ["Edith.flac", "Étienne.flac", "Eve.flac", "Zola.flac"]
+        );
+    }
+
+    #[test]
+    fn sort_paths_ignores_input_order() {
+        let sorted = {
+            let mut paths = vec!["x/2.flac", "x/10.flac", "x/1.flac"];
+            sort_paths(&mut paths);
+            paths
+        };
+
+        for mut paths in [
+            vec!["x/1.flac", "x/10.flac", "x/2.flac"],
+            vec!["x/10.flac", "x/2.flac", "x/1.flac"],
+            vec!["x/2.flac", "x/1.flac", "x/10.flac"],
+        ] {
+            sort_paths(&mut paths);
+            assert_eq!(paths, sorted);
+        }
+    }
+
+    #[test]
+    fn sort_files_sorts_command_arguments() {
+        let mut args = Args::parse_from(["flacdat", "list", "z.flac", "m/a.flac", "a.flac"]);
+        args.command.as_mut().unwrap().sort_files();
+        let Some(Command::List(list)) = args.command else {
+            panic!("expected list");
+        };
+        assert_eq!(
+            list.files,
+            [
+                PathBuf::from("a.flac"),
+                PathBuf::from("m/a.flac"),
+                PathBuf::from("z.flac")
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::{cmp::Ordering, path::Path, str::FromStr, sync::OnceLock};
+
+/// How names are ordered when files are sorted, from `[format] collation` in
+/// config.
+///
+/// Letters sort together whatever their accents or case, so "Étienne" files
+/// among the Es, and hiragana and katakana spellings of a Japanese name sort
+/// together. Accents and then case only break ties. Some languages move
+/// letters elsewhere in the alphabet, which the locale selects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Collation {
+    #[default]
+    Root,
+    /// å, ä, ö after z, as in Swedish and Finnish
+    Swedish,
+    /// æ, ø, å after z, as in Danish and Norwegian
+    Danish,
+    /// ñ after n
+    Spanish,
+    /// raw byte order
+    Bytes,
+}
+
+impl FromStr for Collation {
+    type Err = String;
+
+    /// Accepts a locale such as `sv`, `sv-SE`, or `sv_SE.UTF-8`, or `bytes`.
+    /// Languages without tailoring of their own use the root order.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let language = s
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "bytes" | "binary" | "c" | "posix" => Ok(Collation::Bytes),
+            "sv" | "fi" => Ok(Collation::Swedish),
+            "da" | "nb" | "nn" | "no" => Ok(Collation::Danish),
+            "es" => Ok(Collation::Spanish),
+            language if language.chars().all(|c| c.is_ascii_alphabetic()) => Ok(Collation::Root),
+            _ => Err(format!(
+                "expected a locale such as en or sv-SE, or bytes; found {s}"
+            )),
+        }
+    }
+}
+
+static COLLATION: OnceLock<Collation> = OnceLock::new();
+
+/// Records the collation given in config. Only the first call has any effect.
+pub(crate) fn configure(collation: Collation) {
+    let _ = COLLATION.set(collation);
+}
+
+/// Compares paths component by component, which keeps each directory's
+/// files together.
+pub(crate) fn compare_paths(a: &Path, b: &Path) -> Ordering {
+    let collation = COLLATION.get().copied().unwrap_or_default();
+    if collation == Collation::Bytes {
+        return a.cmp(b);
+    }
+
+    let mut a_parts = a.components();
+    let mut b_parts = b.components();
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (Some(a), Some(b)) => {
+                let ordering = compare(
+                    collation,
+                    &a.as_os_str().to_string_lossy(),
+                    &b.as_os_str().to_string_lossy(),
+                );
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (a, b) => return a.is_some().cmp(&b.is_some()),
+        }
+    }
+}
+
+/// Compares text, such as names, by the configured collation.
+pub(crate) fn compare_text(a: &str, b: &str) -> Ordering {
+    match COLLATION.get().copied().unwrap_or_default() {
+        Collation::Bytes => a.cmp(b),
+        collation => compare(collation, a, b),
+    }
+}
+
+fn compare(collation: Collation, a: &str, b: &str) -> Ordering {
+    let (a_key, b_key) = (key(collation, a), key(collation, b));
+    let primary = |key: &[(u32, u8, bool)]| key.iter().map(|weights| weights.0).collect::<Vec<_>>();
+    let accents = |key: &[(u32, u8, bool)]| key.iter().map(|weights| weights.1).collect::<Vec<_>>();
+    let case = |key: &[(u32, u8, bool)]| key.iter().map(|weights| weights.2).collect::<Vec<_>>();
+
+    primary(&a_key)
+        .cmp(&primary(&b_key))
+        .then_with(|| accents(&a_key).cmp(&accents(&b_key)))
+        .then_with(|| case(&a_key).cmp(&case(&b_key)))
+        .then_with(|| a.cmp(b))
+}
+
+/// Letters with accents, and what they sort as.
+const FOLDS: &[(&str, &str)] = &[
+    ("àáâãäåāăą", "a"),
+    ("çćĉċč", "c"),
+    ("ďđð", "d"),
+    ("èéêëēĕėęě", "e"),
+    ("ĝğġģ", "g"),
+    ("ĥħ", "h"),
+    ("ìíîïĩīĭįı", "i"),
+    ("ĵ", "j"),
+    ("ķ", "k"),
+    ("ĺļľŀł", "l"),
+    ("ñńņňŉ", "n"),
+    ("òóôõöøōŏő", "o"),
+    ("ŕŗř", "r"),
+    ("śŝşšș", "s"),
+    ("ţťŧț", "t"),
+    ("ùúûüũūŭůűų", "u"),
+    ("ŵ", "w"),
+    ("ýÿŷ", "y"),
+    ("źżž", "z"),
+    ("æ", "ae"),
+    ("œ", "oe"),
+    ("ß", "ss"),
+    ("þ", "th"),
+];
+
+/// Letters a locale moves to the end of the alphabet, in order.
+fn after_z(collation: Collation) -> &'static [&'static str] {
+    match collation {
+        Collation::Swedish => &["å", "äæ", "öø"],
+        Collation::Danish => &["æä", "øö", "å"],
+        _ => &[],
+    }
+}
+
+/// Primary, accent, and case weights for each letter of a name.
+fn key(collation: Collation, s: &str) -> Vec<(u32, u8, bool)> {
+    let mut key = Vec::new();
+    for c in s.chars() {
+        let upper = c.is_uppercase();
+        let lower = c.to_lowercase().next().unwrap_or(c);
+
+        // Katakana sorts with the matching hiragana.
+        if ('\u{30a1}'..='\u{30f6}').contains(&lower) {
+            key.push(((lower as u32 - 0x60) * 4, 0, true));
+            continue;
+        }
+
+        if let Some(position) = after_z(collation)
+            .iter()
+            .position(|letters| letters.contains(lower))
+        {
+            key.push(('z' as u32 * 4 + 1 + position as u32, 0, upper));
+            continue;
+        }
+        if collation == Collation::Spanish && lower == 'ñ' {
+            key.push(('n' as u32 * 4 + 1, 0, upper));
+            continue;
+        }
+
+        match FOLDS.iter().find(|(letters, _)| letters.contains(lower)) {
+            Some((letters, base)) => {
+                let accent = letters
+                    .chars()
+                    .position(|letter| letter == lower)
+                    .unwrap_or(0);
+                for letter in base.chars() {
+                    key.push((letter as u32 * 4, accent as u8 + 1, upper));
+                }
+            }
+            None => key.push((lower as u32 * 4, 0, upper)),
+        }
+    }
+    key
+}
@@ -0,0 +1,353 @@
+use std::{cmp::Ordering, iter::Peekable, str::Chars, str::FromStr};
+
+use crate::{Attribute, Attributes, Error, Result};
+
+/// A guard expression evaluated against a file's existing tags, e.g.
+/// `artist == 'Prince' && year < 1990`.
+///
+/// A bare field name is true when the field has a value. Multi-valued fields
+/// match a comparison when any of their values does, except for `!=`, which
+/// requires that no value be equal.
+#[derive(Clone, Debug)]
+pub(crate) enum Condition {
+    Present(Attribute),
+    Compare(Attribute, Op, Literal),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Literal {
+    Number(i64),
+    Text(String),
+}
+
+impl Condition {
+    pub(crate) fn matches(&self, attributes: &Attributes) -> bool {
+        match self {
+            Condition::Present(attribute) => !attributes.values(*attribute).is_empty(),
+            Condition::Compare(attribute, Op::Ne, literal) => !attributes
+                .values(*attribute)
+                .iter()
+                .any(|value| literal.compare(value) == Some(Ordering::Equal)),
+            Condition::Compare(attribute, op, literal) => {
+                attributes.values(*attribute).iter().any(|value| {
+                    literal.compare(value).is_some_and(|ordering| match op {
+                        Op::Eq => ordering.is_eq(),
+                        Op::Ne => ordering.is_ne(),
+                        Op::Lt => ordering.is_lt(),
+                        Op::Le => ordering.is_le(),
+                        Op::Gt => ordering.is_gt(),
+                        Op::Ge => ordering.is_ge(),
+                    })
+                })
+            }
+            Condition::Not(inner) => !inner.matches(attributes),
+            Condition::And(left, right) => left.matches(attributes) && right.matches(attributes),
+            Condition::Or(left, right) => left.matches(attributes) || right.matches(attributes),
+        }
+    }
+}
+
+impl Literal {
+    /// Compares a tag value against this literal, returning the ordering of the
+    /// tag value relative to the literal. Numeric literals never match values
+    /// which are not themselves numeric.
+    fn compare(&self, value: &str) -> Option<Ordering> {
+        match self {
+            Literal::Number(n) => value.trim().parse::<i64>().ok().map(|value| value.cmp(n)),
+            Literal::Text(text) => Some(value.cmp(text)),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let condition = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(condition),
+            Some(token) => Err(Error::Condition(format!("unexpected {token:?}"))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Text(String),
+    Number(i64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '\'' | '"' => {
+                chars.next();
+                tokens.push(Token::Text(read_quoted(&mut chars, c)?));
+            }
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Some(c) {
+                    return Err(Error::Condition(format!("expected '{c}{c}'")));
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.next_if_eq(&'=').is_some();
+                tokens.push(match (c, followed_by_eq) {
+                    ('=', true) => Token::Op(Op::Eq),
+                    ('!', true) => Token::Op(Op::Ne),
+                    ('!', false) => Token::Not,
+                    ('<', true) => Token::Op(Op::Le),
+                    ('<', false) => Token::Op(Op::Lt),
+                    ('>', true) => Token::Op(Op::Ge),
+                    ('>', false) => Token::Op(Op::Gt),
+                    _ => return Err(Error::Condition("expected '=='".into())),
+                });
+            }
+            // Only a value follows an operator, so only there is a word read
+            // as a number, and then only if it's all digits: 2Pac is text.
+            c if matches!(tokens.last(), Some(Token::Op(_))) && is_word(c) => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| is_word(*c)) {
+                    word.push(c);
+                }
+                let digits = word.strip_prefix('-').unwrap_or(&word);
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    tokens.push(Token::Ident(word));
+                    continue;
+                }
+                let number = word
+                    .parse()
+                    .map_err(|_| Error::Condition(format!("invalid number: {word}")))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    ident.push(c);
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c => return Err(Error::Condition(format!("unexpected character: {c}"))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn read_quoted(chars: &mut Peekable<Chars>, quote: char) -> Result<String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('\\') => match chars.next() {
+                Some(c) => text.push(c),
+                None => break,
+            },
+            Some(c) if c == quote => return Ok(text),
+            Some(c) => text.push(c),
+            None => break,
+        }
+    }
+    Err(Error::Condition("unterminated string".into()))
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Condition> {
+        let mut condition = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition> {
+        let mut condition = self.unary()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition> {
+        if self.tokens.next_if_eq(&Token::Not).is_some() {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+
+        match self.tokens.next() {
+            Some(Token::Open) => {
+                let condition = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(condition),
+                    _ => Err(Error::Condition("expected ')'".into())),
+                }
+            }
+            Some(Token::Ident(ident)) => {
+                let attribute = ident.parse()?;
+                let op = match self.tokens.peek() {
+                    Some(Token::Op(op)) => *op,
+                    _ => return Ok(Condition::Present(attribute)),
+                };
+                self.tokens.next();
+                let literal = match self.tokens.next() {
+                    Some(Token::Number(n)) => Literal::Number(n),
+                    Some(Token::Text(text)) => Literal::Text(text),
+                    Some(Token::Ident(text)) => Literal::Text(text),
+                    _ => return Err(Error::Condition(format!("expected a value after {op:?}"))),
+                };
+                Ok(Condition::Compare(attribute, op, literal))
+            }
+            Some(token) => Err(Error::Condition(format!("unexpected {token:?}"))),
+            None => Err(Error::Condition("unexpected end of expression".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes() -> Attributes {
+        Attributes {
+            artist: vec!["Prince".into(), "The Revolution".into()],
+            album: Some("Purple Rain".into()),
+            year: Some(1984),
+            ..Default::default()
+        }
+    }
+
+    fn matches(condition: &str, attributes: &Attributes) -> bool {
+        condition.parse::<Condition>().unwrap().matches(attributes)
+    }
+
+    fn error(condition: &str) -> String {
+        condition.parse::<Condition>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let attributes = attributes();
+        assert!(matches(
+            "year == 1984 || year == 1 && year == 2",
+            &attributes
+        ));
+        assert!(!matches(
+            "(year == 1984 || year == 1) && year == 2",
+            &attributes
+        ));
+        assert!(!matches("!year || title", &attributes));
+        assert!(matches("!(title && album)", &attributes));
+    }
+
+    #[test]
+    fn quoted_values_take_either_quote_and_escapes() {
+        let mut attributes = attributes();
+        assert!(matches("album == 'Purple Rain'", &attributes));
+        assert!(matches("album == \"Purple Rain\"", &attributes));
+        attributes.album = Some("Don't Stop".into());
+        assert!(matches("album == 'Don\\'t Stop'", &attributes));
+        assert!(matches("album == \"Don't Stop\"", &attributes));
+    }
+
+    #[test]
+    fn numbers_are_only_read_as_values() {
+        let mut attributes = attributes();
+        attributes.artist = vec!["2Pac".into()];
+        assert!(matches("artist==2Pac", &attributes));
+        assert!(matches("artist == 2Pac && year>-1", &attributes));
+        attributes.album = Some("1999".into());
+        assert!(matches("album == 1999", &attributes));
+    }
+
+    #[test]
+    fn comparisons() {
+        let attributes = attributes();
+        assert!(matches(
+            "year < 1990 && year >= 1984 && year <= 1984",
+            &attributes
+        ));
+        assert!(!matches("year > 1984", &attributes));
+        assert!(matches("artist == 'The Revolution'", &attributes));
+        assert!(!matches("artist != 'The Revolution'", &attributes));
+        assert!(matches("artist != 'Wendy'", &attributes));
+        // A number never matches a value which isn't one.
+        assert!(!matches("album < 5", &attributes));
+        assert!(matches("album > 'Purple'", &attributes));
+    }
+
+    #[test]
+    fn errors_say_what_went_wrong() {
+        assert_eq!(
+            error("album == 'Purple"),
+            "invalid condition: unterminated string"
+        );
+        assert_eq!(error("year & 1"), "invalid condition: expected '&&'");
+        assert_eq!(error("year = 1"), "invalid condition: expected '=='");
+        assert_eq!(
+            error("year == 99999999999999999999"),
+            "invalid condition: invalid number: 99999999999999999999"
+        );
+        assert_eq!(error("(year"), "invalid condition: expected ')'");
+        assert_eq!(
+            error("year =="),
+            "invalid condition: expected a value after Eq"
+        );
+        assert_eq!(
+            error("year &&"),
+            "invalid condition: unexpected end of expression"
+        );
+        assert_eq!(
+            error("year year"),
+            "invalid condition: unexpected Ident(\"year\")"
+        );
+        assert_eq!(
+            error("year == 1 ;"),
+            "invalid condition: unexpected character: ;"
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    art::DedupePolicy,
+    case::Dictionary,
+    check::{HiresPolicy, RateAndBits, TotalsSpelling},
+    collate::Collation,
+    device::Profile,
+    fetch,
+    ignore::Ignore,
+    pathmap::PathMap,
+    pipeline::Pipeline,
+    protect::Protection,
+    roots::{Root, Roots},
+    template::Template,
+    throttle::Rate,
+    tools::Tool,
+    warning::Policy,
+    Attribute, Error, Result,
+};
+
+/// User configuration, read from an INI-style file:
+///
+/// ```text
+/// # comments start with '#'
+/// [pipeline cleanup-incoming]
+/// when = artist == 'Prince'
+/// step = trim
+/// step = replace title " (Remastered)" ""
+/// step = rename "{track:2} {title}"
+/// ```
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    pub(crate) pipelines: HashMap<String, Pipeline>,
+
+    /// `[format] track-width`: digits to zero-pad track numbers to
+    pub(crate) track_width: Option<usize>,
+
+    /// `[format] totals`: which spellings of TRACKTOTAL/DISCTOTAL to write
+    pub(crate) totals: TotalsSpelling,
+
+    /// `[format] year-tag`: whether to write YEAR alongside DATE
+    pub(crate) year_tag: bool,
+
+    /// `[format] collation`: the locale whose alphabet file arguments are sorted by
+    pub(crate) collation: Collation,
+
+    /// `[format] multi-separator`: what separates the values of an attribute in a sheet's cell
+    pub(crate) multi_separator: Option<String>,
+
+    /// `[case] keep = iPhone AC/DC` and `words = <file>`: spellings title case keeps
+    pub(crate) case: Dictionary,
+
+    /// `[columns] name = <pattern>`: computed columns, in definition order
+    pub(crate) columns: Vec<(String, Template)>,
+
+    /// `[protect] field`: fields no operation may overwrite
+    pub(crate) protection: Protection,
+
+    /// `[art] dedupe`: what `art dedupe --fix` does with shared covers
+    pub(crate) dedupe: DedupePolicy,
+
+    /// `[hires] allow`: sample rate and bit depth combinations `check hires` accepts
+    pub(crate) hires: HiresPolicy,
+
+    /// `[tools] ffmpeg = <path>`: where to find external programs
+    pub(crate) tools: Vec<(Tool, PathBuf)>,
+
+    /// `[paths] map = /host=/data`: prefixes to rewrite in sheet and output paths
+    pub(crate) path_map: PathMap,
+
+    /// `[scan] throttle`: how fast scans may read files
+    pub(crate) throttle: Option<Rate>,
+
+    /// `[scan] nice`: whether scans run at low priority
+    pub(crate) nice: bool,
+
+    /// `[scan] writeback`: whether computed values are kept in FLACDAT_* tags;
+    /// off unless set to true
+    pub(crate) writeback: Option<bool>,
+
+    /// `[safety] max-files`: how many files an operation may change before it
+    /// asks to go ahead; 0 for no limit
+    pub(crate) max_files: Option<usize>,
+
+    /// `[device name]`: what playback devices can show, for `check device`
+    pub(crate) devices: Vec<Profile>,
+
+    /// `[root name] path = <dir>`: named library locations
+    pub(crate) roots: Roots,
+
+    /// `[fetch]`: how web services are reached
+    pub(crate) fetch: fetch::Settings,
+
+    /// `[feed] base-url`: the URL the feed root is served at
+    pub(crate) feed_base_url: Option<String>,
+
+    /// `[ignore]`: names every directory walk skips
+    pub(crate) ignore: Ignore,
+
+    /// `[ignore <operation>]`: names skipped by one operation's walks
+    pub(crate) operation_ignores: HashMap<String, Ignore>,
+
+    /// `[warnings] allow = W001`: warnings silenced or made errors
+    pub(crate) warnings: Policy,
+}
+
+/// A `[kind name]` section and its entries, in file order.
+struct Section {
+    kind: String,
+    name: Option<String>,
+    entries: Vec<Entry>,
+}
+
+pub(crate) struct Entry {
+    pub(crate) line: usize,
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
+
+impl Entry {
+    pub(crate) fn error(&self, message: impl Into<String>) -> Error {
+        Error::Config {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    /// Parses the entry's value, reporting failures against its line.
+    pub(crate) fn parse<T>(&self) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.value
+            .parse()
+            .map_err(|e| self.error(format!("invalid value for {}: {e}", self.key)))
+    }
+}
+
+impl Config {
+    /// Loads configuration from an explicit path, or from the default location
+    /// when none is given. A missing default config is not an error.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut config = Config::default();
+
+        for section in parse_sections(text)? {
+            match (section.kind.as_str(), section.name) {
+                ("pipeline", Some(name)) => {
+                    let pipeline = Pipeline::from_entries(&section.entries)?;
+                    config.pipelines.insert(name, pipeline);
+                }
+                ("root", Some(name)) => {
+                    if config.roots.get(&name).is_some() {
+                        let line = section.entries.first().map_or(0, |entry| entry.line);
+                        return Err(Error::Config {
+                            line,
+                            message: format!("root {name} is defined twice"),
+                        });
+                    }
+                    let root = Root::from_entries(name, &section.entries)?;
+                    config.roots.roots.push(root);
+                }
+                ("device", Some(name)) => {
+                    let profile = Profile::from_entries(name, &section.entries)?;
+                    config.devices.push(profile);
+                }
+                ("ignore", None) => config.ignore = Ignore::from_entries(&section.entries)?,
+                ("ignore", Some(operation)) => {
+                    let ignore = Ignore::from_entries(&section.entries)?;
+                    config.operation_ignores.insert(operation, ignore);
+                }
+                ("feed", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "base-url" => config.feed_base_url = Some(entry.value.clone()),
+                            key => return Err(entry.error(format!("unknown feed key: {key}"))),
+                        }
+                    }
+                }
+                ("fetch", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "user-agent" => config.fetch.user_agent = Some(entry.value.clone()),
+                            "offline" => config.fetch.offline = entry.parse()?,
+                            key => return Err(entry.error(format!("unknown fetch key: {key}"))),
+                        }
+                    }
+                }
+                ("fetch", Some(service)) => {
+                    let mut limits = config.fetch.limits(&service);
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "interval-ms" => {
+                                limits.interval = Duration::from_millis(entry.parse()?)
+                            }
+                            "cache-days" => {
+                                let days: u64 = entry.parse()?;
+                                limits.cache_age = Duration::from_secs(days * 86400);
+                            }
+                            key => return Err(entry.error(format!("unknown fetch key: {key}"))),
+                        }
+                    }
+                    config.fetch.services.push((service, limits));
+                }
+                ("protect", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "field" => config.protection.add(&entry.value),
+                            key => return Err(entry.error(format!("unknown protect key: {key}"))),
+                        }
+                    }
+                }
+                ("columns", None) => {
+                    for entry in &section.entries {
+                        let name = &entry.key;
+                        if name.parse::<Attribute>().is_ok() {
+                            return Err(entry.error(format!("column {name} is an attribute")));
+                        }
+                        if config.columns.iter().any(|(column, _)| column == name) {
+                            return Err(entry.error(format!("column {name} is defined twice")));
+                        }
+                        // Columns may use those defined before them, never later ones,
+                        // so no column can refer to itself.
+                        let template = Template::with_columns(&entry.value, &config.columns)
+                            .map_err(|e| entry.error(e.to_string()))?;
+                        config.columns.push((name.clone(), template));
+                    }
+                }
+                ("warnings", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "allow" => config.warnings.allow.push(entry.parse()?),
+                            "deny" => config.warnings.deny.push(entry.parse()?),
+                            "all-errors" => config.warnings.all_errors = entry.parse()?,
+                            key => return Err(entry.error(format!("unknown warnings key: {key}"))),
+                        }
+                    }
+                }
+                ("case", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "keep" => {
+                                let words = split_words(&entry.value)
+                                    .ok_or_else(|| entry.error("unterminated quote"))?;
+                                for word in words {
+                                    config.case.add(&word);
+                                }
+                            }
+                            // A list kept apart from config, so that it can
+                            // be shared.
+                            "words" => {
+                                let text = fs::read_to_string(&entry.value).map_err(|e| {
+                                    entry.error(format!("unable to read {}: {e}", entry.value))
+                                })?;
+                                config.case.add_list(&text);
+                            }
+                            key => return Err(entry.error(format!("unknown case key: {key}"))),
+                        }
+                    }
+                }
+                ("art", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "dedupe" => config.dedupe = entry.parse()?,
+                            key => return Err(entry.error(format!("unknown art key: {key}"))),
+                        }
+                    }
+                }
+                ("scan", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "throttle" => config.throttle = Some(entry.parse()?),
+                            "nice" => config.nice = entry.parse()?,
+                            "writeback" => config.writeback = Some(entry.parse()?),
+                            key => return Err(entry.error(format!("unknown scan key: {key}"))),
+                        }
+                    }
+                }
+                ("safety", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "max-files" => config.max_files = Some(entry.parse()?),
+                            key => return Err(entry.error(format!("unknown safety key: {key}"))),
+                        }
+                    }
+                }
+                ("paths", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "map" => config.path_map.add(entry.parse()?),
+                            key => return Err(entry.error(format!("unknown paths key: {key}"))),
+                        }
+                    }
+                }
+                ("tools", None) => {
+                    for entry in &section.entries {
+                        let tool = Tool::from_name(&entry.key)
+                            .ok_or_else(|| entry.error(format!("unknown tool: {}", entry.key)))?;
+                        config.tools.push((tool, entry.value.clone().into()));
+                    }
+                }
+                ("hires", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "allow" => {
+                                let RateAndBits(rate, bits) = entry.parse()?;
+                                config.hires.allowed.push((rate, bits));
+                            }
+                            key => return Err(entry.error(format!("unknown hires key: {key}"))),
+                        }
+                    }
+                }
+                ("format", None) => {
+                    for entry in &section.entries {
+                        match entry.key.as_str() {
+                            "track-width" => config.track_width = Some(entry.parse()?),
+                            "totals" => config.totals = entry.parse()?,
+                            "year-tag" => config.year_tag = entry.parse()?,
+                            "collation" => config.collation = entry.parse()?,
+                            "multi-separator" if entry.value.is_empty() => {
+                                return Err(entry.error("multi-separator may not be empty"))
+                            }
+                            "multi-separator" => config.multi_separator = Some(entry.value.clone()),
+                            key => return Err(entry.error(format!("unknown format key: {key}"))),
+                        }
+                    }
+                }
+                (kind, _) => {
+                    let line = section.entries.first().map_or(0, |entry| entry.line);
+                    return Err(Error::Config {
+                        line,
+                        message: format!("unknown section: {kind}"),
+                    });
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// The ignore rules for an operation's directory walks.
+    pub(crate) fn ignore_for(&self, operation: &str) -> Ignore {
+        self.ignore.with(self.operation_ignores.get(operation))
+    }
+}
+
+/// `$FLACDAT_CONFIG`, falling back to the platform configuration directory.
+fn default_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("FLACDAT_CONFIG") {
+        return Some(path.into());
+    }
+
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+
+    base.map(|base| base.join("flacdat").join("config"))
+}
+
+/// The platform cache directory for responses from web services.
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(|dir| Path::new(&dir).join("cache"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+
+    base.map(|base| base.join("flacdat"))
+}
+
+/// The platform data directory for flacdat's own state (audit log, locks).
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share"))
+        })
+    };
+
+    base.map(|base| base.join("flacdat"))
+}
+
+fn parse_sections(text: &str) -> Result<Vec<Section>> {
+    let mut sections: Vec<Section> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.strip_suffix(']').ok_or_else(|| Error::Config {
+                line: line_number,
+                message: "unterminated section header".into(),
+            })?;
+            let mut parts = header.split_whitespace();
+            let kind = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let name = parts.next().map(String::from);
+            sections.push(Section {
+                kind,
+                name,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(section) = sections.last_mut() else {
+            return Err(Error::Config {
+                line: line_number,
+                message: "entry outside of any section".into(),
+            });
+        };
+
+        let (key, value) = line.split_once('=').unwrap_or((line, ""));
+        section.entries.push(Entry {
+            line: line_number,
+            key: key.trim().to_ascii_lowercase(),
+            value: value.trim().into(),
+        });
+    }
+
+    Ok(sections)
+}
+
+/// Splits a line into whitespace-separated words, honoring single and double
+/// quotes so that values may contain spaces or be empty.
+pub(crate) fn split_words(s: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let Some(&first) = chars.peek() else {
+            return Some(words);
+        };
+
+        let mut word = String::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '\\' => word.push(chars.next()?),
+                    c if c == first => break,
+                    c => word.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+        }
+        words.push(word);
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+#[cfg(feature = "native-encoder")]
+use crate::encoder;
+use crate::{audio, cue, lock::FileLock, rate, template, tools::Tool, warning, Error, Result};
+
+/// The lossless formats ffmpeg reads which convert losslessly to FLAC.
+pub const EXTENSIONS: &[&str] = &["wav", "aiff", "aif", "m4a", "ape", "wv"];
+
+/// Whether a file is in one of the formats of [`EXTENSIONS`].
+pub fn is_source(file: &Path) -> bool {
+    file.extension().is_some_and(|extension| {
+        EXTENSIONS
+            .iter()
+            .any(|source| extension.eq_ignore_ascii_case(source))
+    })
+}
+
+/// The sample sizes ffmpeg's FLAC encoder writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum BitDepth {
+    #[value(name = "16")]
+    Sixteen,
+    #[value(name = "24")]
+    TwentyFour,
+}
+
+/// How ffmpeg encodes FLACs, beyond its defaults.
+#[derive(Clone, Debug, Default)]
+pub struct Encoding {
+    /// From 0 (fastest) to 12 (smallest)
+    pub compression_level: Option<u8>,
+    /// The rate to resample to, in Hz
+    pub sample_rate: Option<u32>,
+    /// The rate to resample sources above to, or below to keep within their
+    /// own family, in Hz
+    pub max_sample_rate: Option<u32>,
+    pub bit_depth: Option<BitDepth>,
+}
+
+impl Encoding {
+    /// The option asking for what only ffmpeg can do, if any.
+    pub fn needs_ffmpeg(&self) -> Option<&'static str> {
+        if self.sample_rate.is_some() {
+            Some("--sample-rate")
+        } else if self.max_sample_rate.is_some() {
+            Some("--max-sample-rate")
+        } else if self.bit_depth.is_some() {
+            Some("--bit-depth")
+        } else {
+            None
+        }
+    }
+
+    /// The rate to resample a source to, if any: the sample rate given, or a
+    /// rate of the source's family no higher than the maximum.
+    fn output_rate(&self, source: &Path) -> Result<Option<u32>> {
+        if self.sample_rate.is_none() && self.max_sample_rate.is_none() {
+            return Ok(None);
+        }
+        let rate = audio::read(source)?.sample_rate;
+        let target = match self.sample_rate {
+            Some(target) => {
+                rate::warn_avoidable(source, rate, target);
+                target
+            }
+            None => rate::pick(rate, self.max_sample_rate, &[]),
+        };
+        Ok((target != rate).then_some(target))
+    }
+
+    /// The ffmpeg output options for a source. Resampling and requantizing
+    /// share one aresample filter.
+    pub fn args(&self, source: &Path) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        if let Some(level) = self.compression_level {
+            args.extend(["-compression_level".into(), level.to_string()]);
+        }
+
+        let mut resample = Vec::new();
+        if let Some(rate) = self.output_rate(source)? {
+            resample.push(format!("osr={rate}"));
+        }
+        match self.bit_depth {
+            Some(BitDepth::Sixteen) => {
+                resample.extend(["osf=s16".into(), "dither_method=triangular".into()]);
+            }
+            // ffmpeg holds 24-bit samples in 32 bits.
+            Some(BitDepth::TwentyFour) => {
+                resample.push("osf=s32".into());
+                args.extend(["-bits_per_raw_sample".into(), "24".into()]);
+            }
+            None => {}
+        }
+        if !resample.is_empty() {
+            args.extend(["-af".into(), format!("aresample={}", resample.join(":"))]);
+        }
+        Ok(args)
+    }
+}
+
+/// How `convert` encodes FLAC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// ffmpeg, which reads any format and can resample
+    Ffmpeg,
+    /// flacdat's own encoder, which reads only PCM WAV files; built with the
+    /// native-encoder feature
+    #[cfg(feature = "native-encoder")]
+    Native,
+}
+
+impl Backend {
+    /// ffmpeg if it's installed, unless `no_ffmpeg`; otherwise the native
+    /// encoder, as long as it can do the job and flacdat was built with it.
+    /// `needs_ffmpeg` names what only ffmpeg can do, if anything.
+    pub fn select(no_ffmpeg: bool, needs_ffmpeg: Option<&str>) -> Result<Backend> {
+        if !no_ffmpeg && Tool::Ffmpeg.ensure().is_ok() {
+            return Ok(Backend::Ffmpeg);
+        }
+        match needs_ffmpeg {
+            Some(what) if no_ffmpeg => Err(Error::Encode(format!("{what} needs ffmpeg"))),
+            Some(_) => Err(Tool::Ffmpeg.missing()),
+            #[cfg(feature = "native-encoder")]
+            None => Ok(Backend::Native),
+            #[cfg(not(feature = "native-encoder"))]
+            None if no_ffmpeg => Err(Error::Encode(
+                "flacdat was built without its own encoder".into(),
+            )),
+            #[cfg(not(feature = "native-encoder"))]
+            None => Err(Tool::Ffmpeg.missing()),
+        }
+    }
+}
+
+/// A file `convert` writes: a FLAC encoded from the source, carrying its
+/// tags.
+pub struct Conversion {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// The part of the source to convert, as start and end in seconds, for a
+    /// track of a cue sheet
+    pub span: Option<(f64, Option<f64>)>,
+    /// Tags to write over any the source carries
+    pub tags: Vec<(&'static str, String)>,
+}
+
+/// Conversions splitting the album a cue sheet describes into a FLAC for
+/// each track, written to `dir`. Tracks run from one INDEX 01 to the next,
+/// are named "<track> <title>", with the performer first when it isn't the
+/// album's, and are tagged from the sheet.
+pub fn cue_tracks(sheet: &Path, dir: &Path) -> Result<Vec<Conversion>> {
+    let sheet = cue::Sheet::read(sheet)?;
+    let mut jobs = Vec::new();
+    for track in &sheet.tracks {
+        let title = track.title.clone().unwrap_or_else(|| "Untitled".into());
+        let performer = track.performer.as_ref().or(sheet.performer.as_ref());
+        let name = match &track.performer {
+            Some(performer) if sheet.performer.as_ref() != Some(performer) => {
+                format!("{:02} {performer} - {title}", track.number)
+            }
+            _ => format!("{:02} {title}", track.number),
+        };
+
+        let mut job = Conversion::new(&track.file, dir.join(template::component(&name) + ".flac"));
+        job.span = Some((track.start, track.end));
+        job.tags = [
+            ("TITLE", Some(&title)),
+            ("ARTIST", performer),
+            ("ALBUM", sheet.title.as_ref()),
+            ("ALBUMARTIST", sheet.performer.as_ref()),
+            ("TRACKNUMBER", Some(&track.number.to_string())),
+            ("DATE", sheet.date.as_ref()),
+            ("GENRE", sheet.genre.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?.clone())))
+        .collect();
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+/// Runs conversions, `threads` at a time. Each worker takes the next file in
+/// turn, and reports back to `done` with its index, result, and what ffmpeg
+/// printed, one file at a time. A file which fails to convert doesn't stop
+/// the others, but an error from `done` does.
+pub fn run_all(
+    jobs: &[Conversion],
+    backend: Backend,
+    encoding: &Encoding,
+    threads: NonZeroUsize,
+    mut done: impl FnMut(usize, Result<()>, String) -> Result<()>,
+) -> Result<()> {
+    let encoder_args = jobs
+        .iter()
+        .map(|job| encoding.args(&job.source))
+        .collect::<Result<Vec<_>>>()?;
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads.get().min(jobs.len()) {
+            let (next, encoder_args) = (&next, &encoder_args);
+            let sender = sender.clone();
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(idx) else {
+                    break;
+                };
+                let mut output = String::new();
+                let result = job.run(backend, &encoder_args[idx], &mut output);
+                if sender.send((idx, result, output)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        for (idx, result, output) in receiver {
+            done(idx, result, output)?;
+        }
+        Ok(())
+    })
+}
+
+impl Conversion {
+    pub fn new(source: &Path, target: PathBuf) -> Self {
+        Conversion {
+            source: source.to_owned(),
+            target,
+            span: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Converts the file, collecting what ffmpeg prints in `output` so that
+    /// files converted side by side don't interleave their output.
+    /// `encoder_args` are passed to ffmpeg ahead of the target path.
+    pub fn run(
+        &self,
+        backend: Backend,
+        encoder_args: &[String],
+        output: &mut String,
+    ) -> Result<()> {
+        let (path, flac_path) = (self.source.as_path(), self.target.as_path());
+        let format = audio::read(path)?;
+        if format.channels > 8 {
+            warning::emit(
+                warning::Code::Downmix,
+                format_args!(
+                    "{}: FLAC holds at most 8 channels; {} will be downmixed",
+                    path.display(),
+                    format.layout()
+                ),
+            );
+        }
+
+        if let Some(parent) = flac_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // ffmpeg can't ask before overwriting without a terminal.
+        if flac_path.exists() {
+            return Err(Error::IO(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", flac_path.display()),
+            )));
+        }
+
+        let _lock = FileLock::acquire(flac_path)?;
+        match backend {
+            Backend::Ffmpeg => self.ffmpeg(encoder_args, output)?,
+            #[cfg(feature = "native-encoder")]
+            Backend::Native => {
+                if let Err(e) = encoder::encode_wav(path, flac_path, self.span) {
+                    let _ = fs::remove_file(flac_path);
+                    return Err(e);
+                }
+            }
+        }
+
+        // ffmpeg carries tags over under its own lower-case names; FLAC readers
+        // expect vorbis comment keys in upper case.
+        let mut flac = metaflac::Tag::read_from_path(flac_path)?;
+        let comment = flac.vorbis_comments_mut();
+        let mut comments: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, values) in comment.comments.drain() {
+            comments
+                .entry(key.to_ascii_uppercase())
+                .or_default()
+                .extend(values);
+        }
+        // Field recorders keep their notes in RIFF INFO and bext chunks,
+        // which neither encoder carries over in full. A track cut from an
+        // album would inherit the album's.
+        let wav = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+        if wav && self.span.is_none() {
+            for (key, value) in audio::wav_tags(path)? {
+                comments.entry(key.into()).or_insert_with(|| vec![value]);
+            }
+        }
+        // A track cut from an album leaves the album's sheet behind.
+        if self.span.is_some() {
+            comments.remove("CUESHEET");
+        }
+        for (key, value) in &self.tags {
+            comments.insert(key.to_string(), vec![value.clone()]);
+        }
+        comment.comments = comments;
+        flac.save()?;
+        Ok(())
+    }
+
+    fn ffmpeg(&self, encoder_args: &[String], output: &mut String) -> Result<()> {
+        let (path, flac_path) = (self.source.as_path(), self.target.as_path());
+        let mut command = Tool::Ffmpeg.command();
+        command
+            .args(["-hide_banner", "-nostats", "-loglevel", "warning", "-i"])
+            .arg(path);
+        if let Some((start, end)) = self.span {
+            command.args(["-ss", &format!("{start:.6}")]);
+            if let Some(end) = end {
+                command.args(["-to", &format!("{end:.6}")]);
+            }
+        }
+        let ran = command
+            .args(encoder_args)
+            .arg(flac_path)
+            .stdin(Stdio::null())
+            .output()?;
+        output.push_str(&String::from_utf8_lossy(&ran.stderr));
+        if !ran.status.success() {
+            let _ = fs::remove_file(flac_path);
+            return Err(Error::FfmpegFailed(path.display().to_string()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{Attributes, Result};
+
+/// How `copy-tags` decides which old file a new file takes its tags from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Pairing {
+    /// the same disc and track number
+    #[default]
+    Track,
+    /// the same file name, less its extension, at the same place under the
+    /// directory
+    Stem,
+}
+
+/// Files paired by `copy-tags`, along with those left over on either side.
+#[derive(Debug, Default)]
+pub(crate) struct Pairs {
+    pub(crate) pairs: Vec<(PathBuf, PathBuf)>,
+    pub(crate) unpaired: Vec<PathBuf>,
+}
+
+/// Pairs each file under `to` with the file under `from` which has the same
+/// key. A key shared by more than one file on a side is ambiguous, and those
+/// files are left unpaired. Two single files are always paired.
+pub(crate) fn pair(
+    from_root: &Path,
+    from: Vec<PathBuf>,
+    to_root: &Path,
+    to: Vec<PathBuf>,
+    by: Pairing,
+) -> Result<Pairs> {
+    let mut result = Pairs::default();
+    if from_root.is_file() && to_root.is_file() {
+        result.pairs.push((from_root.into(), to_root.into()));
+        return Ok(result);
+    }
+
+    let mut sources = keyed(from_root, from, by, &mut result.unpaired)?;
+    let targets = keyed(to_root, to, by, &mut result.unpaired)?;
+
+    let mut keys: Vec<_> = targets.keys().cloned().collect();
+    keys.sort();
+    for key in keys {
+        let target = targets[&key].clone();
+        match sources.remove(&key) {
+            Some(source) => result.pairs.push((source, target)),
+            None => result.unpaired.push(target),
+        }
+    }
+    result.unpaired.extend(sources.into_values());
+    Ok(result)
+}
+
+fn keyed(
+    root: &Path,
+    paths: Vec<PathBuf>,
+    by: Pairing,
+    unpaired: &mut Vec<PathBuf>,
+) -> Result<HashMap<String, PathBuf>> {
+    let mut keyed: HashMap<_, Vec<_>> = HashMap::new();
+    for path in paths {
+        match key(root, &path, by)? {
+            Some(key) => keyed.entry(key).or_default().push(path),
+            None => unpaired.push(path),
+        }
+    }
+
+    let mut single = HashMap::new();
+    for (key, mut paths) in keyed {
+        match paths.len() {
+            1 => {
+                single.insert(key, paths.remove(0));
+            }
+            _ => unpaired.extend(paths),
+        }
+    }
+    Ok(single)
+}
+
+fn key(root: &Path, path: &Path, by: Pairing) -> Result<Option<String>> {
+    match by {
+        Pairing::Track => {
+            let attributes = Attributes::from_path(path)?;
+            Ok(attributes
+                .track
+                .map(|track| format!("{}/{track}", attributes.disc.unwrap_or(1))))
+        }
+        Pairing::Stem => {
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            Ok(Some(
+                relative.with_extension("").to_string_lossy().into_owned(),
+            ))
+        }
+    }
+}
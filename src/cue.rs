@@ -0,0 +1,172 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{encoding, Error, Result};
+
+/// A cue sheet describing an album ripped to one file (or a few), as written
+/// by EAC and most other rippers.
+#[derive(Debug, Default)]
+pub(crate) struct Sheet {
+    pub(crate) title: Option<String>,
+    pub(crate) performer: Option<String>,
+    pub(crate) date: Option<String>,
+    pub(crate) genre: Option<String>,
+    pub(crate) tracks: Vec<Track>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Track {
+    pub(crate) number: u32,
+    pub(crate) title: Option<String>,
+    pub(crate) performer: Option<String>,
+    /// The audio file the track is in, resolved against the sheet's directory.
+    pub(crate) file: PathBuf,
+    /// Where the track starts in its file (INDEX 01), in seconds.
+    pub(crate) start: f64,
+    /// Where the track ends: where the next track in the same file starts,
+    /// so that any pregap stays with the track before it. Nothing for the
+    /// last track of a file.
+    pub(crate) end: Option<f64>,
+}
+
+impl Sheet {
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        let (text, _) = encoding::decode(&fs::read(path)?);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let invalid = |line: usize, message: &str| {
+            Error::Cue(format!("{}, line {}: {message}", path.display(), line + 1))
+        };
+
+        let mut sheet = Sheet::default();
+        let mut file = None;
+        // Whether each track has been given a start.
+        let mut indexed = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let (command, rest) = line
+                .trim()
+                .split_once(char::is_whitespace)
+                .unwrap_or((line.trim(), ""));
+            let rest = rest.trim();
+            match command.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    // The file type follows the name.
+                    let name = match rest.rsplit_once(char::is_whitespace) {
+                        Some((name, _)) => unquote(name.trim()),
+                        None => unquote(rest),
+                    };
+                    file = Some(locate(&dir.join(name)));
+                }
+                "TRACK" => {
+                    let number = rest
+                        .split_whitespace()
+                        .next()
+                        .and_then(|number| number.parse().ok())
+                        .ok_or_else(|| invalid(idx, "bad track number"))?;
+                    let file = file
+                        .clone()
+                        .ok_or_else(|| invalid(idx, "TRACK before any FILE"))?;
+                    sheet.tracks.push(Track {
+                        number,
+                        title: None,
+                        performer: None,
+                        file,
+                        start: 0.0,
+                        end: None,
+                    });
+                    indexed.push(false);
+                }
+                "INDEX" => {
+                    let (index, time) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    if index.parse::<u32>() != Ok(1) {
+                        continue;
+                    }
+                    let start = timestamp(time.trim())
+                        .ok_or_else(|| invalid(idx, &format!("bad index time {time}")))?;
+                    let track = sheet
+                        .tracks
+                        .last_mut()
+                        .ok_or_else(|| invalid(idx, "INDEX before any TRACK"))?;
+                    track.start = start;
+                    *indexed.last_mut().expect("one for every track") = true;
+                }
+                "TITLE" | "PERFORMER" => {
+                    let value = Some(unquote(rest).to_string());
+                    let target = match (
+                        sheet.tracks.last_mut(),
+                        command.eq_ignore_ascii_case("TITLE"),
+                    ) {
+                        (Some(track), true) => &mut track.title,
+                        (Some(track), false) => &mut track.performer,
+                        (None, true) => &mut sheet.title,
+                        (None, false) => &mut sheet.performer,
+                    };
+                    *target = value;
+                }
+                "REM" => {
+                    let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    let value = Some(unquote(value.trim()).to_string());
+                    match key.to_ascii_uppercase().as_str() {
+                        "DATE" => sheet.date = value,
+                        "GENRE" => sheet.genre = value,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((track, _)) = sheet
+            .tracks
+            .iter()
+            .zip(&indexed)
+            .find(|(_, &indexed)| !indexed)
+        {
+            return Err(Error::Cue(format!(
+                "{}: track {} has no INDEX 01",
+                path.display(),
+                track.number
+            )));
+        }
+        for idx in 1..sheet.tracks.len() {
+            let (before, after) = sheet.tracks.split_at_mut(idx);
+            let (previous, track) = (&mut before[idx - 1], &after[0]);
+            if previous.file == track.file {
+                previous.end = Some(track.start);
+            }
+        }
+
+        Ok(sheet)
+    }
+}
+
+/// A value without the double quotes it may be in.
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+/// Reads an MM:SS:FF time, counting 75 frames to the second, as seconds.
+fn timestamp(time: &str) -> Option<f64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= 75 {
+        return None;
+    }
+    Some(f64::from(minutes * 60 + seconds) + f64::from(frames) / 75.0)
+}
+
+/// The file a sheet names, or one with the same name in another format:
+/// sheets often name the WAV a rip was made to after it has been compressed.
+fn locate(named: &Path) -> PathBuf {
+    if named.exists() {
+        return named.into();
+    }
+    ["flac", "wav", "aiff", "aif", "ape", "wv", "m4a"]
+        .iter()
+        .map(|extension| named.with_extension(extension))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| named.into())
+}
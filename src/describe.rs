@@ -0,0 +1,123 @@
+use std::{ffi::OsStr, fs, path::Path};
+
+use crate::{art, audio, blocks, digest, json::Value, snapshot, Attribute, Attributes, Result};
+
+/// How `describe` writes its documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// indented JSON, one document after another
+    #[default]
+    Json,
+    /// one document per line (JSON Lines)
+    JsonLines,
+}
+
+/// Everything flacdat can say about a file, as a single JSON object: its
+/// mapped attributes, its raw tags, the shape of its audio, its pictures,
+/// its metadata blocks, and hashes of the file and of each picture.
+pub(crate) fn describe(path: &Path) -> Result<Value> {
+    let data = fs::read(path)?;
+    let attributes = Attributes::from_path(path)?;
+
+    let mapped = Attribute::ALL
+        .iter()
+        .filter_map(|&attribute| {
+            let values = attributes.values(attribute);
+            (!values.is_empty()).then(|| (attribute.name().to_string(), strings(values)))
+        })
+        .collect();
+
+    // Pictures are described on their own.
+    let tags = snapshot::read_tags(path)?
+        .into_iter()
+        .filter(|(key, _)| key != "PICTURE")
+        .map(|(key, values)| (key, strings(values)))
+        .collect();
+
+    let pictures = art::read(path)?
+        .into_iter()
+        .map(|picture| {
+            Value::Object(vec![
+                ("type".into(), format!("{:?}", picture.kind).into()),
+                ("mime".into(), art::mime_type(&picture.data).into()),
+                ("width".into(), u64::from(picture.width).into()),
+                ("height".into(), u64::from(picture.height).into()),
+                ("size".into(), (picture.data.len() as u64).into()),
+                (
+                    "sha256".into(),
+                    hex::encode(digest::sha256(&picture.data)).into(),
+                ),
+            ])
+        })
+        .collect();
+
+    let blocks = blocks::read(path)?
+        .into_iter()
+        .map(|block| {
+            Value::Object(vec![
+                ("offset".into(), block.offset.into()),
+                ("size".into(), block.size.into()),
+                ("kind".into(), block.kind.into()),
+                ("detail".into(), block.detail.into()),
+            ])
+        })
+        .collect();
+
+    let mut hashes = vec![("sha256".into(), hex::encode(digest::sha256(&data)).into())];
+    if let Some(md5) = streaminfo_md5(path)? {
+        hashes.push(("streaminfo_md5".into(), md5.into()));
+    }
+
+    Ok(Value::Object(vec![
+        ("path".into(), path.to_string_lossy().into_owned().into()),
+        ("size".into(), (data.len() as u64).into()),
+        ("attributes".into(), Value::Object(mapped)),
+        ("tags".into(), Value::Object(tags)),
+        ("audio".into(), technical(path)?),
+        ("pictures".into(), Value::Array(pictures)),
+        ("blocks".into(), Value::Array(blocks)),
+        ("hashes".into(), Value::Object(hashes)),
+    ]))
+}
+
+fn technical(path: &Path) -> Result<Value> {
+    let format = audio::read(path)?;
+    let mut members = vec![
+        ("channels".into(), u64::from(format.channels).into()),
+        ("layout".into(), format.layout().into()),
+        ("sample_rate".into(), u64::from(format.sample_rate).into()),
+        ("bits".into(), format.bits.map(u64::from).into()),
+        ("duration".into(), audio::duration(path)?.into()),
+    ];
+    if path.extension() == Some(OsStr::new("flac")) {
+        let flac = metaflac::Tag::read_from_path(path)?;
+        if let Some(info) = flac.get_streaminfo() {
+            members.push(("total_samples".into(), info.total_samples.into()));
+            members.push((
+                "min_block_size".into(),
+                u64::from(info.min_block_size).into(),
+            ));
+            members.push((
+                "max_block_size".into(),
+                u64::from(info.max_block_size).into(),
+            ));
+        }
+    }
+    Ok(Value::Object(members))
+}
+
+/// The audio MD5 the encoder stored in STREAMINFO, unless it left it unset.
+fn streaminfo_md5(path: &Path) -> Result<Option<String>> {
+    if path.extension() != Some(OsStr::new("flac")) {
+        return Ok(None);
+    }
+    let flac = metaflac::Tag::read_from_path(path)?;
+    Ok(flac
+        .get_streaminfo()
+        .filter(|info| info.md5.iter().any(|&b| b != 0))
+        .map(|info| hex::encode(&info.md5)))
+}
+
+fn strings(values: Vec<String>) -> Value {
+    Value::Array(values.into_iter().map(Value::String).collect())
+}
@@ -0,0 +1,258 @@
+use std::path::Path;
+
+use id3::{Content, TagLike, Version};
+
+use crate::{config::Entry, snapshot, Error, Result};
+
+/// What a playback device can make of tags: the ID3 version it reads, the
+/// characters it can show, and how long a field it shows before cutting it
+/// off. Defined in config as `[device name]` sections:
+///
+/// ```text
+/// [device car]
+/// id3 = 2.3
+/// charset = latin-1
+/// max-length = 30
+/// ```
+#[derive(Clone, Debug)]
+pub(crate) struct Profile {
+    pub(crate) name: String,
+    pub(crate) id3: Version,
+    pub(crate) charset: Charset,
+    pub(crate) max_length: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Charset {
+    Unicode,
+    Latin1,
+    Ascii,
+}
+
+impl Charset {
+    fn name(self) -> &'static str {
+        match self {
+            Charset::Unicode => "unicode",
+            Charset::Latin1 => "latin-1",
+            Charset::Ascii => "ascii",
+        }
+    }
+
+    fn holds(self, c: char) -> bool {
+        match self {
+            Charset::Unicode => true,
+            // The C1 controls are in Latin-1's range but not printable.
+            Charset::Latin1 => (c as u32) < 0x80 || (0xa0..0x100).contains(&(c as u32)),
+            Charset::Ascii => c.is_ascii(),
+        }
+    }
+}
+
+impl Profile {
+    /// Profiles available without any config.
+    pub(crate) fn built_in() -> Vec<Profile> {
+        vec![
+            Profile {
+                name: "old-car".into(),
+                id3: Version::Id3v23,
+                charset: Charset::Latin1,
+                max_length: Some(30),
+            },
+            Profile {
+                name: "ascii".into(),
+                id3: Version::Id3v23,
+                charset: Charset::Ascii,
+                max_length: None,
+            },
+            Profile {
+                name: "id3v23".into(),
+                id3: Version::Id3v23,
+                charset: Charset::Unicode,
+                max_length: None,
+            },
+        ]
+    }
+
+    pub(crate) fn from_entries(name: String, entries: &[Entry]) -> Result<Self> {
+        let mut profile = Profile {
+            name,
+            id3: Version::Id3v24,
+            charset: Charset::Unicode,
+            max_length: None,
+        };
+        for entry in entries {
+            match entry.key.as_str() {
+                "id3" => {
+                    profile.id3 = match entry.value.as_str() {
+                        "2.2" => Version::Id3v22,
+                        "2.3" => Version::Id3v23,
+                        "2.4" => Version::Id3v24,
+                        value => return Err(entry.error(format!("unknown ID3 version: {value}"))),
+                    }
+                }
+                "charset" => {
+                    profile.charset = match entry.value.to_ascii_lowercase().as_str() {
+                        "unicode" | "utf-8" | "utf-16" => Charset::Unicode,
+                        "latin-1" | "latin1" | "iso-8859-1" => Charset::Latin1,
+                        "ascii" => Charset::Ascii,
+                        value => return Err(entry.error(format!("unknown charset: {value}"))),
+                    }
+                }
+                "max-length" => profile.max_length = Some(entry.parse()?),
+                key => return Err(entry.error(format!("unknown device key: {key}"))),
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Describes each field the device would cut short or show wrongly, and
+    /// an ID3 tag newer than it reads.
+    pub(crate) fn issues(&self, path: &Path) -> Result<Vec<String>> {
+        let mut issues = Vec::new();
+        if is_mp3(path) {
+            match id3::Tag::read_from_path(path) {
+                Ok(tag) if newer(tag.version(), self.id3) => issues.push(format!(
+                    "tag is {}, which the device doesn't read",
+                    tag.version()
+                )),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        for (key, values) in snapshot::read_tags(path)? {
+            if key == "PICTURE" {
+                continue;
+            }
+            for value in values {
+                let mut unshown = String::new();
+                for c in value.chars().filter(|&c| !self.charset.holds(c)) {
+                    if !unshown.contains(c) {
+                        unshown.push(c);
+                    }
+                }
+                if !unshown.is_empty() {
+                    issues.push(format!(
+                        "{key} {value:?} has characters {} can't hold: {unshown}",
+                        self.charset.name()
+                    ));
+                }
+                if let Some(max) = self.max_length.filter(|&max| value.chars().count() > max) {
+                    let shown: String = value.chars().take(max).collect();
+                    issues.push(format!("{key} {value:?} would be cut to {shown:?}"));
+                }
+            }
+        }
+        Ok(issues)
+    }
+
+    /// A value as the device can show it: characters outside its charset
+    /// folded to the nearest it has, or `?`, and cut to its length.
+    pub(crate) fn fit(&self, value: &str) -> String {
+        let mut fitted = String::new();
+        for c in value.chars() {
+            if self.charset.holds(c) {
+                fitted.push(c);
+                continue;
+            }
+            let folded = match c {
+                '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{2032}' => "'",
+                '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{2033}' => "\"",
+                '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+                '\u{2026}' => "...",
+                '\u{00a0}' | '\u{2000}'..='\u{200a}' => " ",
+                '\u{00c0}'..='\u{017f}' => {
+                    let idx = c as usize - 0xc0;
+                    &FOLDED[idx..idx + 1]
+                }
+                _ => "?",
+            };
+            fitted.push_str(folded);
+        }
+        match self.max_length {
+            Some(max) => fitted.chars().take(max).collect(),
+            None => fitted,
+        }
+    }
+
+    /// Rewrites an MP3's text frames to fit the device, and writes the tag
+    /// in the ID3 version it reads.
+    pub(crate) fn fit_id3(&self, path: &Path) -> Result<()> {
+        let mut tag = match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let frames: Vec<id3::Frame> = tag.frames().cloned().collect();
+        for frame in frames {
+            let content = match frame.content() {
+                // Text frames hold multiple values separated by NULs.
+                Content::Text(text) => {
+                    let values: Vec<String> =
+                        text.split('\0').map(|value| self.fit(value)).collect();
+                    Content::Text(values.join("\0"))
+                }
+                Content::ExtendedText(text) => Content::ExtendedText(id3::frame::ExtendedText {
+                    description: text.description.clone(),
+                    value: self.fit(&text.value),
+                }),
+                Content::Comment(comment) => Content::Comment(id3::frame::Comment {
+                    lang: comment.lang.clone(),
+                    description: comment.description.clone(),
+                    text: self.fit(&comment.text),
+                }),
+                _ => continue,
+            };
+            tag.add_frame(id3::Frame::with_content(frame.id(), content));
+        }
+
+        id3::Encoder::new()
+            .version(self.id3)
+            .encode_to_path(&tag, path)?;
+        Ok(())
+    }
+
+    /// Rewrites a FLAC's vorbis comments to fit the device.
+    pub(crate) fn fit_vorbis(&self, path: &Path) -> Result<()> {
+        let mut flac = metaflac::Tag::read_from_path(path)?;
+        let comment = flac.vorbis_comments_mut();
+        for values in comment.comments.values_mut() {
+            for value in values {
+                *value = self.fit(value);
+            }
+        }
+        flac.save()?;
+        Ok(())
+    }
+}
+
+/// Finds a profile by name, among those in config and then the built-in
+/// ones.
+pub(crate) fn find(profiles: &[Profile], name: &str) -> Result<Profile> {
+    profiles
+        .iter()
+        .cloned()
+        .chain(Profile::built_in())
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| Error::Device(format!("no device profile named {name}")))
+}
+
+fn is_mp3(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"))
+}
+
+fn newer(version: Version, than: Version) -> bool {
+    let rank = |version| match version {
+        Version::Id3v22 => 2,
+        Version::Id3v23 => 3,
+        Version::Id3v24 => 4,
+    };
+    rank(version) > rank(than)
+}
+
+/// The ASCII letter each of U+00C0 to U+017F is based on, as Unicode
+/// decomposes them; `x` and `/` for the multiplication and division signs.
+const FOLDED: &str = "AAAAAAACEEEEIIIIDNOOOOOxOUUUUYTsaaaaaaaceeeeiiiidnooooo/ouuuuytyAaAaAaCcCcCcCcDdDdEeEeEeEeEeGgGgGgGgHhHhIiIiIiIiIiJjJjKkkLlLlLlLlLlNnNnNnnNnOoOoOoOoRrRrRrSsSsSsSsTtTtTtUuUuUuUuUuUuWwYyYZzZzZzs";
@@ -0,0 +1,80 @@
+use std::{ffi::OsStr, io::Cursor, path::Path};
+
+use id3::{Content, Frame};
+
+use crate::{Error, Result};
+
+/// Applications whose cue points, loops, and beatgrids live in the file's
+/// tag, by the prefix of the frame descriptions (or vorbis comment keys) they
+/// write: Serato uses GEOB objects such as `Serato Markers2` and
+/// `Serato BeatGrid`, and Rekordbox TXXX frames.
+const VENDORS: [(&str, &str); 2] = [("serato", "serato"), ("rekordbox", "rekordbox")];
+
+fn vendor(description: &str) -> Option<&'static str> {
+    let description = description.to_ascii_lowercase();
+    VENDORS
+        .iter()
+        .find(|(prefix, _)| description.starts_with(prefix))
+        .map(|&(_, vendor)| vendor)
+}
+
+fn frame_vendor(frame: &Frame) -> Option<&'static str> {
+    match frame.content() {
+        Content::EncapsulatedObject(object) => vendor(&object.description),
+        Content::ExtendedText(text) => vendor(&text.description),
+        _ => None,
+    }
+}
+
+/// The frames of a tag holding DJ software data.
+pub(crate) fn frames(tag: &id3::Tag) -> Vec<&Frame> {
+    tag.frames()
+        .filter(|frame| frame_vendor(frame).is_some())
+        .collect()
+}
+
+/// The DJ applications which have stored data in a file, for `list`.
+pub(crate) fn vendors(path: &Path) -> Result<Vec<&'static str>> {
+    let mut vendors: Vec<_> = match path.extension().and_then(OsStr::to_str) {
+        Some("flac") => {
+            let flac = metaflac::Tag::read_from_path(path)?;
+            flac.vorbis_comments()
+                .map(|comment| {
+                    comment
+                        .comments
+                        .keys()
+                        .filter_map(|key| vendor(key))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+        Some("mp3") => match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag.frames().filter_map(frame_vendor).collect(),
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => Vec::new(),
+            Err(e) => return Err(e.into()),
+        },
+        _ => return Err(Error::UnsupportedFileTye(path.display().to_string())),
+    };
+    vendors.sort_unstable();
+    vendors.dedup();
+    Ok(vendors)
+}
+
+/// Encodes `after` the way it will be written and reads it back, failing
+/// unless every DJ frame of `before` comes out unchanged. Run before writing,
+/// so a tag that would lose a prepared set is never written at all.
+pub(crate) fn verify(path: &Path, before: &id3::Tag, after: &id3::Tag) -> Result<()> {
+    let mut encoded = Vec::new();
+    after.write_to(&mut encoded, after.version())?;
+    let written = id3::Tag::read_from(Cursor::new(encoded))?;
+
+    let kept = |frame: &Frame| {
+        written
+            .frames()
+            .any(|other| other.id() == frame.id() && other.content() == frame.content())
+    };
+    match frames(before).into_iter().all(kept) {
+        true => Ok(()),
+        false => Err(Error::DjDataLost(path.display().to_string())),
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{analyze, digest, json::Value, tools::Tool, verify, writeback, Error, Result};
+
+/// How `dupes` prints the groups it finds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// each group's files a line apiece, with a blank line between groups
+    #[default]
+    Text,
+    /// an array of groups, each with its digest and files
+    Json,
+}
+
+/// Files holding the same audio, the one to keep first.
+pub(crate) struct Group {
+    pub(crate) digest: String,
+    pub(crate) files: Vec<PathBuf>,
+}
+
+/// Groups files by the digest of their audio, in the order of the first file
+/// of each group, and leaves out files with no duplicate. Files are kept in
+/// the order given within a group.
+pub(crate) fn group(digests: impl IntoIterator<Item = (PathBuf, String)>) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (path, digest) in digests {
+        match index.get(&digest) {
+            Some(&idx) => groups[idx].files.push(path),
+            None => {
+                index.insert(digest.clone(), groups.len());
+                groups.push(Group {
+                    digest,
+                    files: vec![path],
+                });
+            }
+        }
+    }
+    groups.retain(|group| group.files.len() > 1);
+    groups
+}
+
+/// The hex digest a file's audio is compared by. A FLAC's is the MD5 of its
+/// decoded samples, as STREAMINFO records it, or as `verify` kept it, or
+/// else as decoding the file finds it; an MP3's is the SHA-256 of its
+/// frames, less its tags. The two never match, as they're of different
+/// lengths.
+pub(crate) fn digest(path: &Path) -> Result<String> {
+    if path.extension() == Some(OsStr::new("mp3")) {
+        let data = fs::read(path)?;
+        let audio = verify::mp3_audio(&data).unwrap_or_default();
+        return Ok(hex::encode(digest::sha256(audio)));
+    }
+
+    let flac = metaflac::Tag::read_from_path(path)?;
+    let Some(info) = flac.get_streaminfo() else {
+        return Err(Error::UnsupportedFileTye(path.display().to_string()));
+    };
+    if info.md5.iter().any(|&b| b != 0) {
+        return Ok(hex::encode(&info.md5));
+    }
+    if let Some(kept) = writeback::read(path, &[writeback::AUDIO_MD5])? {
+        return Ok(kept[0].clone());
+    }
+    Tool::Ffmpeg.ensure()?;
+    Ok(hex::encode(analyze::audio_md5(path, info.bits_per_sample)?))
+}
+
+pub(crate) fn json(groups: &[Group]) -> Value {
+    Value::Array(
+        groups
+            .iter()
+            .map(|group| {
+                let files = group
+                    .files
+                    .iter()
+                    .map(|path| Value::String(path.display().to_string()))
+                    .collect();
+                Value::Object(vec![
+                    ("digest".into(), Value::String(group.digest.clone())),
+                    ("files".into(), Value::Array(files)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Where a duplicate moved into `dir` goes: under its own path, less any
+/// root, prefix, or `..`, so copies of the same name don't collide.
+pub(crate) fn moved_to(dir: &Path, path: &Path) -> PathBuf {
+    let parts = path.components().filter_map(|part| match part {
+        Component::Normal(part) => Some(part),
+        _ => None,
+    });
+    dir.join(parts.collect::<PathBuf>())
+}
@@ -0,0 +1,687 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{digest::Md5, Error, Result};
+
+/// Frames hold this many samples per channel, as libFLAC's default.
+const BLOCK_SIZE: usize = 4096;
+
+/// The highest partition order tried when Rice coding residuals.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// PCM audio in a WAV file, with the reader at the start of its samples.
+struct Wav {
+    channels: usize,
+    sample_rate: u32,
+    /// Bits per sample which carry audio
+    bits: u32,
+    /// Bytes per sample in the file
+    width: usize,
+    /// Bytes of samples
+    len: u64,
+}
+
+/// Encodes a PCM WAV file as FLAC, using fixed predictors and Rice coding.
+/// `span` limits it to part of the file, as start and end in seconds.
+pub(crate) fn encode_wav(
+    source: &Path,
+    target: &Path,
+    span: Option<(f64, Option<f64>)>,
+) -> Result<()> {
+    let mut reader = BufReader::new(File::open(source)?);
+    let wav = read_wav(&mut reader)
+        .ok_or_else(|| Error::Encode(format!("{}: not a PCM WAV file", source.display())))?;
+    if !(1..=8).contains(&wav.channels) || !(4..=24).contains(&wav.bits) {
+        return Err(Error::Encode(format!(
+            "{}: {} channels of {}-bit audio can't be encoded without ffmpeg",
+            source.display(),
+            wav.channels,
+            wav.bits
+        )));
+    }
+
+    let frame_bytes = (wav.channels * wav.width) as u64;
+    let mut remaining = wav.len / frame_bytes;
+    if let Some((start, end)) = span {
+        let at = |seconds: f64| (seconds * f64::from(wav.sample_rate)).round() as u64;
+        let skip = at(start).min(remaining);
+        reader.seek(SeekFrom::Current((skip * frame_bytes) as i64))?;
+        remaining = match end {
+            Some(end) => at(end).saturating_sub(skip).min(remaining - skip),
+            None => remaining - skip,
+        };
+    }
+
+    let mut out = BufWriter::new(File::create(target)?);
+    out.write_all(b"fLaC")?;
+    // Rewritten once the whole file has been encoded.
+    out.write_all(&streaminfo(&wav, &StreamStats::default()))?;
+    out.write_all(&vorbis_comment())?;
+
+    let mut stats = StreamStats {
+        min_frame: u32::MAX,
+        ..Default::default()
+    };
+    let mut md5 = Md5::new();
+    let mut raw = vec![0; BLOCK_SIZE * frame_bytes as usize];
+    let mut number = 0;
+    while remaining > 0 {
+        let count = remaining.min(BLOCK_SIZE as u64) as usize;
+        let raw = &mut raw[..count * frame_bytes as usize];
+        reader.read_exact(raw)?;
+        remaining -= count as u64;
+
+        let channels = deinterleave(&wav, raw);
+        update_md5(&mut md5, &channels, wav.bits);
+        let frame = frame(number, &channels, wav.bits);
+        out.write_all(&frame)?;
+
+        stats.samples += count as u64;
+        stats.min_frame = stats.min_frame.min(frame.len() as u32);
+        stats.max_frame = stats.max_frame.max(frame.len() as u32);
+        // The last block may be shorter than the rest, and only counts
+        // towards the smallest when it's the only one.
+        if remaining > 0 || number == 0 {
+            stats.min_block = match number {
+                0 => count,
+                _ => stats.min_block.min(count),
+            };
+        }
+        stats.max_block = stats.max_block.max(count);
+        number += 1;
+    }
+    if number == 0 {
+        stats.min_frame = 0;
+    }
+    // STREAMINFO can't give a block size under 16.
+    stats.min_block = stats.min_block.max(16);
+    stats.max_block = stats.max_block.max(stats.min_block);
+    stats.md5 = md5.finish();
+
+    out.seek(SeekFrom::Start(4))?;
+    out.write_all(&streaminfo(&wav, &stats))?;
+    out.flush()?;
+    Ok(())
+}
+
+fn read_wav(reader: &mut (impl Read + Seek)) -> Option<Wav> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header).ok()?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return None;
+    }
+
+    let mut format = None;
+    loop {
+        let mut chunk = [0; 8];
+        reader.read_exact(&mut chunk).ok()?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+        match &chunk[..4] {
+            b"fmt " => {
+                if size < 16 {
+                    return None;
+                }
+                let mut fmt = vec![0; size as usize];
+                reader.read_exact(&mut fmt).ok()?;
+                if size % 2 == 1 {
+                    reader.seek(SeekFrom::Current(1)).ok()?;
+                }
+                let word = |at: usize| u16::from_le_bytes([fmt[at], fmt[at + 1]]);
+                let tag = match word(0) {
+                    // WAVE_FORMAT_EXTENSIBLE names the real format in its
+                    // sub-format GUID.
+                    0xfffe if fmt.len() >= 26 => word(24),
+                    tag => tag,
+                };
+                let channels = usize::from(word(2));
+                let block_align = usize::from(word(12));
+                let valid = match word(0) {
+                    0xfffe if fmt.len() >= 20 && word(18) > 0 => word(18),
+                    _ => word(14),
+                };
+                if tag != 1 || channels == 0 || block_align % channels != 0 {
+                    return None;
+                }
+                format = Some((
+                    channels,
+                    u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                    u32::from(valid),
+                    block_align / channels,
+                ));
+            }
+            b"data" => {
+                let (channels, sample_rate, bits, width) = format?;
+                if width == 0 || width > 4 || bits as usize > width * 8 {
+                    return None;
+                }
+                return Some(Wav {
+                    channels,
+                    sample_rate,
+                    bits,
+                    width,
+                    len: u64::from(size),
+                });
+            }
+            // Chunks are padded to an even length.
+            _ => {
+                reader
+                    .seek(SeekFrom::Current(i64::from(size + size % 2)))
+                    .ok()?;
+            }
+        }
+    }
+}
+
+/// Splits interleaved little-endian samples into channels. Samples narrower
+/// than their container are held in its top bits; 8-bit samples are
+/// unsigned.
+fn deinterleave(wav: &Wav, raw: &[u8]) -> Vec<Vec<i64>> {
+    let mut channels = vec![Vec::with_capacity(BLOCK_SIZE); wav.channels];
+    let unused = wav.width as u32 * 8 - wav.bits;
+    for (idx, sample) in raw.chunks_exact(wav.width).enumerate() {
+        let value = match wav.width {
+            1 => i64::from(sample[0]) - 128,
+            _ => {
+                let mut bytes = [0; 4];
+                bytes[4 - wav.width..].copy_from_slice(sample);
+                i64::from(i32::from_le_bytes(bytes) >> ((4 - wav.width) * 8))
+            }
+        };
+        channels[idx % wav.channels].push(value >> unused);
+    }
+    channels
+}
+
+/// The STREAMINFO MD5 covers the samples interleaved, little-endian, in as
+/// few whole bytes as hold them.
+fn update_md5(md5: &mut Md5, channels: &[Vec<i64>], bits: u32) {
+    let width = bits.div_ceil(8) as usize;
+    let mut bytes = Vec::with_capacity(channels.len() * channels[0].len() * width);
+    for idx in 0..channels[0].len() {
+        for channel in channels {
+            bytes.extend_from_slice(&channel[idx].to_le_bytes()[..width]);
+        }
+    }
+    md5.update(&bytes);
+}
+
+#[derive(Default)]
+struct StreamStats {
+    min_block: usize,
+    max_block: usize,
+    min_frame: u32,
+    max_frame: u32,
+    samples: u64,
+    md5: [u8; 16],
+}
+
+/// The STREAMINFO block, with its header, always the first metadata block.
+fn streaminfo(wav: &Wav, stats: &StreamStats) -> Vec<u8> {
+    let mut block = BitWriter::default();
+    block.write(0, 1);
+    block.write(0, 7);
+    block.write(34, 24);
+    block.write(stats.min_block as u64, 16);
+    block.write(stats.max_block as u64, 16);
+    block.write(u64::from(stats.min_frame), 24);
+    block.write(u64::from(stats.max_frame), 24);
+    block.write(u64::from(wav.sample_rate), 20);
+    block.write(wav.channels as u64 - 1, 3);
+    block.write(u64::from(wav.bits) - 1, 5);
+    block.write(stats.samples >> 32, 4);
+    block.write(stats.samples & 0xffff_ffff, 32);
+    for byte in stats.md5 {
+        block.write(u64::from(byte), 8);
+    }
+    block.bytes
+}
+
+/// An empty VORBIS_COMMENT block, with its header, naming flacdat as the
+/// encoder. It's the last metadata block; tags are written afterwards.
+fn vorbis_comment() -> Vec<u8> {
+    let vendor = format!("flacdat {}", env!("CARGO_PKG_VERSION"));
+    let len = 4 + vendor.len() + 4;
+    let mut block = vec![0x80 | 4];
+    block.extend_from_slice(&(len as u32).to_be_bytes()[1..]);
+    block.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    block.extend_from_slice(vendor.as_bytes());
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block
+}
+
+/// Encodes one frame. Stereo is tried as left/right, left/side, side/right,
+/// and mid/side, keeping whichever is smallest.
+fn frame(number: u64, channels: &[Vec<i64>], bits: u32) -> Vec<u8> {
+    let count = channels[0].len();
+    let mut out = BitWriter::default();
+    out.write(0xfff8, 16);
+
+    let block_code = match count {
+        BLOCK_SIZE => 12,
+        ..=256 => 6,
+        _ => 7,
+    };
+    out.write(block_code, 4);
+    // The sample rate is given in STREAMINFO.
+    out.write(0, 4);
+
+    let mut subframes: Vec<(Vec<i64>, u32)> = channels
+        .iter()
+        .map(|channel| (channel.clone(), bits))
+        .collect();
+    let mut assignment = channels.len() as u64 - 1;
+    if let [left, right] = channels {
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let cost = |samples: &[i64], bits| plan(samples, bits).cost;
+        let (l, r, s, m) = (
+            cost(left, bits),
+            cost(right, bits),
+            cost(&side, bits + 1),
+            cost(&mid, bits),
+        );
+        let options = [(l + r, 1), (l + s, 8), (s + r, 9), (m + s, 10)];
+        let (_, best) = options.into_iter().min_by_key(|&(cost, _)| cost).unwrap();
+        assignment = best;
+        subframes = match best {
+            8 => vec![(left.clone(), bits), (side, bits + 1)],
+            9 => vec![(side, bits + 1), (right.clone(), bits)],
+            10 => vec![(mid, bits), (side, bits + 1)],
+            _ => subframes,
+        };
+    }
+    out.write(assignment, 4);
+
+    let size_code = match bits {
+        8 => 1,
+        12 => 2,
+        16 => 4,
+        20 => 5,
+        24 => 6,
+        // Anything else is given in STREAMINFO.
+        _ => 0,
+    };
+    out.write(size_code, 3);
+    out.write(0, 1);
+    write_utf8(&mut out, number);
+    match block_code {
+        6 => out.write(count as u64 - 1, 8),
+        7 => out.write(count as u64 - 1, 16),
+        _ => {}
+    }
+    let crc = crc8(&out.bytes);
+    out.write(u64::from(crc), 8);
+
+    for (samples, bits) in &subframes {
+        write_subframe(&mut out, samples, *bits);
+    }
+    out.align();
+    let crc = crc16(&out.bytes);
+    out.write(u64::from(crc), 16);
+    out.bytes
+}
+
+/// How a subframe is best encoded, and roughly how many bits that takes.
+struct Plan {
+    kind: Kind,
+    cost: u64,
+}
+
+enum Kind {
+    Constant,
+    Verbatim,
+    Fixed {
+        order: usize,
+        partition_order: u32,
+        parameters: Vec<u32>,
+    },
+}
+
+fn plan(samples: &[i64], bits: u32) -> Plan {
+    if samples.iter().all(|&sample| sample == samples[0]) {
+        return Plan {
+            kind: Kind::Constant,
+            cost: u64::from(bits),
+        };
+    }
+
+    let mut best = Plan {
+        kind: Kind::Verbatim,
+        cost: samples.len() as u64 * u64::from(bits),
+    };
+    for order in 0..=4.min(samples.len() - 1) {
+        let residuals = residuals(samples, order);
+        let (partition_order, parameters, rice) = rice_parameters(&residuals, samples.len(), order);
+        let cost = order as u64 * u64::from(bits) + rice;
+        if cost < best.cost {
+            best = Plan {
+                kind: Kind::Fixed {
+                    order,
+                    partition_order,
+                    parameters,
+                },
+                cost,
+            };
+        }
+    }
+    best
+}
+
+/// What the fixed predictor of an order leaves unpredicted, from the first
+/// sample after its warm-up samples.
+fn residuals(samples: &[i64], order: usize) -> Vec<i64> {
+    let s = samples;
+    (order..s.len())
+        .map(|i| match order {
+            0 => s[i],
+            1 => s[i] - s[i - 1],
+            2 => s[i] - 2 * s[i - 1] + s[i - 2],
+            3 => s[i] - 3 * s[i - 1] + 3 * s[i - 2] - s[i - 3],
+            _ => s[i] - 4 * s[i - 1] + 6 * s[i - 2] - 4 * s[i - 3] + s[i - 4],
+        })
+        .collect()
+}
+
+fn zigzag(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+/// Chooses the partition order and each partition's Rice parameter, by the
+/// estimated cost of the codes: the partition order, then the parameters,
+/// then the residuals.
+fn rice_parameters(residuals: &[i64], count: usize, order: usize) -> (u32, Vec<u32>, u64) {
+    let mut best: Option<(u32, Vec<u32>, u64)> = None;
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1 << partition_order;
+        if !count.is_multiple_of(partitions) || count / partitions <= order {
+            break;
+        }
+        let mut parameters = Vec::with_capacity(partitions);
+        let mut cost = 2 + 4;
+        let mut start = 0;
+        for partition in 0..partitions {
+            let len = count / partitions - if partition == 0 { order } else { 0 };
+            let sum: u64 = residuals[start..start + len]
+                .iter()
+                .map(|&r| zigzag(r))
+                .sum();
+            start += len;
+            let (parameter, bits) = (0..=30)
+                .map(|k: u32| (k, len as u64 * u64::from(k + 1) + (sum >> k)))
+                .min_by_key(|&(_, bits)| bits)
+                .expect("parameters to try");
+            parameters.push(parameter);
+            cost += 5 + bits;
+        }
+        if best.as_ref().is_none_or(|(_, _, best)| cost < *best) {
+            best = Some((partition_order, parameters, cost));
+        }
+    }
+    best.expect("partition order 0 always fits")
+}
+
+fn write_subframe(out: &mut BitWriter, samples: &[i64], bits: u32) {
+    let plan = plan(samples, bits);
+    out.write(0, 1);
+    match plan.kind {
+        Kind::Constant => {
+            out.write(0, 6);
+            out.write(0, 1);
+            out.write_signed(samples[0], bits);
+        }
+        Kind::Verbatim => {
+            out.write(1, 6);
+            out.write(0, 1);
+            for &sample in samples {
+                out.write_signed(sample, bits);
+            }
+        }
+        Kind::Fixed {
+            order,
+            partition_order,
+            parameters,
+        } => {
+            out.write(0b001000 | order as u64, 6);
+            out.write(0, 1);
+            for &sample in &samples[..order] {
+                out.write_signed(sample, bits);
+            }
+
+            // Parameters above 14 need the five-bit form.
+            let wide = parameters.iter().any(|&k| k > 14);
+            out.write(u64::from(wide), 2);
+            out.write(u64::from(partition_order), 4);
+            let residuals = residuals(samples, order);
+            let partitions = parameters.len();
+            let mut start = 0;
+            for (partition, &k) in parameters.iter().enumerate() {
+                let len = samples.len() / partitions - if partition == 0 { order } else { 0 };
+                out.write(u64::from(k), if wide { 5 } else { 4 });
+                for &residual in &residuals[start..start + len] {
+                    let value = zigzag(residual);
+                    out.unary(value >> k);
+                    out.write(value & ((1 << k) - 1), k);
+                }
+                start += len;
+            }
+        }
+    }
+}
+
+/// Frame numbers are coded like UTF-8, extended to 36 bits.
+fn write_utf8(out: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        out.write(value, 8);
+        return;
+    }
+    let continuation = match value {
+        0x80..0x800 => 1,
+        0x800..0x1_0000 => 2,
+        0x1_0000..0x20_0000 => 3,
+        0x20_0000..0x400_0000 => 4,
+        0x400_0000..0x8000_0000 => 5,
+        _ => 6,
+    };
+    let lead = (0xff00_u64 >> (continuation + 1)) & 0xff;
+    out.write(lead | (value >> (6 * continuation)), 8);
+    for idx in (0..continuation).rev() {
+        out.write(0x80 | ((value >> (6 * idx)) & 0x3f), 8);
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = match crc & 0x80 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ 0x07,
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = match crc & 0x8000 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ 0x8005,
+            };
+        }
+        crc
+    })
+}
+
+/// Writes values most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `bits` bits of `value`, at most 32 at a time.
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.pending = (self.pending << bits) | (value & ((1 << bits) - 1));
+        self.len += bits;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.pending >> self.len) as u8);
+        }
+        self.pending &= (1 << self.len) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// Writes `value` zeros and a one.
+    fn unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    fn align(&mut self) {
+        if self.len > 0 {
+            self.write(0, 8 - self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn bit_writer_packs_bits_most_significant_first() {
+        let mut out = BitWriter::default();
+        out.write(0b101, 3);
+        out.write(0b11111, 5);
+        out.unary(3);
+        out.write_signed(-1, 4);
+        out.write(1, 1);
+        out.align();
+        assert_eq!(out.bytes, [0xbf, 0x1f, 0x80]);
+
+        let mut out = BitWriter::default();
+        out.unary(40);
+        out.align();
+        assert_eq!(out.bytes, [0, 0, 0, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn crcs_match_the_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xfee8);
+    }
+
+    #[test]
+    fn frames_end_in_their_crcs() {
+        let ramp: Vec<i64> = (0..300).map(|i| i * 3 - 400).collect();
+        let frame = frame(130, &[ramp.clone(), ramp], 16);
+        // Sync, block size and rate, assignment and size, two bytes of
+        // frame number, and the 16-bit block size.
+        let header = 2 + 1 + 1 + 2 + 2;
+        assert_eq!(crc8(&frame[..header]), frame[header]);
+        // A CRC taken over the data and the CRC itself leaves nothing.
+        assert_eq!(crc8(&frame[..=header]), 0);
+        assert_eq!(crc16(&frame), 0);
+    }
+
+    #[test]
+    fn residuals_of_a_ramp_vanish_at_order_two() {
+        let ramp = [1, 4, 7, 10, 13];
+        assert_eq!(residuals(&ramp, 0), ramp);
+        assert_eq!(residuals(&ramp, 1), [3, 3, 3, 3]);
+        assert_eq!(residuals(&ramp, 2), [0, 0, 0]);
+        assert_eq!([zigzag(0), zigzag(-1), zigzag(1), zigzag(-2)], [0, 1, 2, 3]);
+
+        let ramp: Vec<i64> = (0..4096).map(|i| i * 3).collect();
+        assert!(matches!(
+            plan(&ramp, 16).kind,
+            Kind::Fixed {
+                order: 2,
+                partition_order: 0,
+                ..
+            }
+        ));
+        assert!(matches!(plan(&[5; 64], 16).kind, Kind::Constant));
+    }
+
+    #[test]
+    fn rice_parameters_suit_the_residuals() {
+        let (partition_order, parameters, _) = rice_parameters(&[0; 64], 64, 0);
+        assert_eq!((partition_order, parameters), (0, vec![0]));
+
+        // Each zigzagged residual of 2000 costs k + 1 + (2000 >> k) bits,
+        // least at k = 10.
+        let (partition_order, parameters, _) = rice_parameters(&[1000; 64], 64, 0);
+        assert_eq!((partition_order, parameters), (0, vec![10]));
+
+        // Halves needing different parameters are worth splitting.
+        let mut residuals = vec![0; 512];
+        residuals[256..].fill(1 << 20);
+        let (partition_order, parameters, _) = rice_parameters(&residuals, 512, 0);
+        assert_eq!((partition_order, parameters), (1, vec![0, 20]));
+    }
+
+    /// The smallest and largest block sizes STREAMINFO gives for a mono WAV
+    /// of `frames` samples.
+    fn block_sizes(frames: usize) -> (u16, u16) {
+        let dir = env::temp_dir().join(format!("flacdat-encoder-{}-{frames}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (wav, flac) = (dir.join("in.wav"), dir.join("out.flac"));
+
+        let data: Vec<u8> = (0..frames)
+            .flat_map(|i| ((i % 100) as i16 * 50).to_le_bytes())
+            .collect();
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&88200u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+        fs::write(&wav, bytes).unwrap();
+
+        encode_wav(&wav, &flac, None).unwrap();
+        let encoded = fs::read(&flac).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        // fLaC, then the block header.
+        let sizes = &encoded[8..12];
+        (
+            u16::from_be_bytes([sizes[0], sizes[1]]),
+            u16::from_be_bytes([sizes[2], sizes[3]]),
+        )
+    }
+
+    #[test]
+    fn streaminfo_block_sizes_leave_out_the_short_last_block() {
+        assert_eq!(block_sizes(2 * BLOCK_SIZE + 100), (4096, 4096));
+        assert_eq!(block_sizes(BLOCK_SIZE), (4096, 4096));
+        assert_eq!(block_sizes(1000), (1000, 1000));
+        assert_eq!(block_sizes(10), (16, 16));
+    }
+}
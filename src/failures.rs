@@ -0,0 +1,64 @@
+use std::fmt::Display;
+
+use crate::{json::Value, Error, Result};
+
+/// How the files which failed are reported once a run finishes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Report {
+    /// a line on stderr for each, as <path>: <error>
+    #[default]
+    Text,
+    /// a JSON array on stderr of objects with each file's path and error
+    Json,
+}
+
+/// The files which failed in a run that carries on past them, each with
+/// what went wrong.
+#[derive(Debug, Default)]
+pub(crate) struct Failures {
+    failures: Vec<(String, String)>,
+}
+
+impl Failures {
+    pub(crate) fn add(&mut self, path: &str, error: impl Display) {
+        let error = error.to_string();
+        // Errors about reaching a file name it already.
+        let error = match error.strip_prefix(path).and_then(|e| e.strip_prefix(": ")) {
+            Some(error) => error.to_string(),
+            None => error,
+        };
+        self.failures.push((path.into(), error));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// Reports every failure, and fails with how many of the `total` files
+    /// tried there were, if any.
+    pub(crate) fn finish(self, report: Report, total: usize) -> Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        match report {
+            Report::Text => {
+                for (path, error) in &self.failures {
+                    eprintln!("{path}: {error}");
+                }
+            }
+            Report::Json => {
+                let failures = self.failures.iter().map(|(path, error)| {
+                    Value::Object(vec![
+                        ("path".into(), Value::String(path.clone())),
+                        ("error".into(), Value::String(error.clone())),
+                    ])
+                });
+                eprintln!("{}", Value::Array(failures.collect()).render(true));
+            }
+        }
+        Err(Error::FilesFailed {
+            failed: self.failures.len(),
+            total,
+        })
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt::Write, path::Path};
+
+use crate::{Error, Result};
+
+/// A podcast-style RSS feed for one folder of spoken-word audio, so podcast
+/// apps can play an audiobook library.
+pub(crate) struct Feed {
+    pub(crate) title: String,
+    pub(crate) author: Option<String>,
+    pub(crate) image_url: Option<String>,
+    pub(crate) episodes: Vec<Episode>,
+}
+
+pub(crate) struct Episode {
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) bytes: u64,
+    pub(crate) mime: &'static str,
+    pub(crate) seconds: Option<f64>,
+}
+
+impl Feed {
+    /// Renders the feed as RSS 2.0 with the iTunes podcast extensions. The
+    /// feed is marked serial and episodes are numbered in order, so apps
+    /// play them front to back rather than newest first.
+    pub(crate) fn render(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(
+            "<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\n",
+        );
+        xml.push_str("<channel>\n");
+        let _ = writeln!(xml, "  <title>{}</title>", escape(&self.title));
+        xml.push_str("  <itunes:type>serial</itunes:type>\n");
+        if let Some(author) = &self.author {
+            let _ = writeln!(xml, "  <itunes:author>{}</itunes:author>", escape(author));
+        }
+        if let Some(image) = &self.image_url {
+            let _ = writeln!(xml, "  <itunes:image href=\"{}\"/>", escape(image));
+        }
+
+        for (idx, episode) in self.episodes.iter().enumerate() {
+            xml.push_str("  <item>\n");
+            let _ = writeln!(xml, "    <title>{}</title>", escape(&episode.title));
+            let _ = writeln!(
+                xml,
+                "    <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>",
+                escape(&episode.url),
+                episode.bytes,
+                episode.mime
+            );
+            let _ = writeln!(
+                xml,
+                "    <guid isPermaLink=\"false\">{}</guid>",
+                escape(&episode.url)
+            );
+            let _ = writeln!(xml, "    <itunes:episode>{}</itunes:episode>", idx + 1);
+            if let Some(seconds) = episode.seconds {
+                let _ = writeln!(
+                    xml,
+                    "    <itunes:duration>{}</itunes:duration>",
+                    seconds.round() as u64
+                );
+            }
+            xml.push_str("  </item>\n");
+        }
+
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+}
+
+/// An OPML outline listing feeds by title and URL, which podcast apps can
+/// import to subscribe to all of them at once.
+pub(crate) fn opml(feeds: &[(String, String)]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<opml version=\"2.0\">\n");
+    xml.push_str("<head><title>flacdat feeds</title></head>\n<body>\n");
+    for (title, url) in feeds {
+        let _ = writeln!(
+            xml,
+            "  <outline type=\"rss\" text=\"{}\" xmlUrl=\"{}\"/>",
+            escape(title),
+            escape(url)
+        );
+    }
+    xml.push_str("</body>\n</opml>\n");
+    xml
+}
+
+/// The URL of a file under `root`, which is served at `base_url`.
+pub(crate) fn url(base_url: &str, root: &Path, path: &Path) -> Result<String> {
+    let relative = path.strip_prefix(root).map_err(|_| {
+        Error::Feed(format!(
+            "{} is outside {}, so it has no URL",
+            path.display(),
+            root.display()
+        ))
+    })?;
+
+    let mut url = base_url.trim_end_matches('/').to_string();
+    for part in relative.components() {
+        url.push('/');
+        for byte in part.as_os_str().to_string_lossy().bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    url.push(byte as char)
+                }
+                byte => {
+                    let _ = write!(url, "%{byte:02X}");
+                }
+            }
+        }
+    }
+    Ok(url)
+}
+
+/// Escapes text for use in XML content or attribute values.
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,267 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{config, digest, tools::Tool, warning, Error, Result};
+
+/// The web services flacdat talks to, with the least time allowed between
+/// requests and how long responses are kept. MusicBrainz asks for no more
+/// than one request a second, and Discogs for no more than one a second
+/// without a token. Attribute sheets are never cached, since they are edited
+/// in place.
+const SERVICES: &[(&str, Limits)] = &[
+    ("sheets", Limits::new(0, 0)),
+    ("musicbrainz", Limits::new(1000, 30)),
+    ("discogs", Limits::new(1000, 30)),
+    ("coverartarchive", Limits::new(0, 90)),
+    ("lrclib", Limits::new(0, 90)),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Limits {
+    /// The least time between two requests to the service, across every
+    /// running flacdat.
+    pub(crate) interval: Duration,
+    /// How long a cached response is used before it's fetched again.
+    pub(crate) cache_age: Duration,
+}
+
+impl Limits {
+    const fn new(interval_ms: u64, cache_days: u64) -> Self {
+        Limits {
+            interval: Duration::from_millis(interval_ms),
+            cache_age: Duration::from_secs(cache_days * 86400),
+        }
+    }
+}
+
+/// `[fetch]` and `[fetch <service>]` settings from config.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Settings {
+    /// `[fetch] user-agent`: sent with every request
+    pub(crate) user_agent: Option<String>,
+
+    /// `[fetch] offline`: answer only from the cache
+    pub(crate) offline: bool,
+
+    /// `[fetch <service>] interval-ms` and `cache-days`: per-service limits
+    pub(crate) services: Vec<(String, Limits)>,
+}
+
+impl Settings {
+    /// The limits for a service, with any configured overrides applied.
+    pub(crate) fn limits(&self, service: &str) -> Limits {
+        self.services
+            .iter()
+            .rev()
+            .find(|(name, _)| name == service)
+            .map(|&(_, limits)| limits)
+            .or_else(|| {
+                SERVICES
+                    .iter()
+                    .find(|(name, _)| *name == service)
+                    .map(|&(_, limits)| limits)
+            })
+            .unwrap_or(Limits::new(0, 0))
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Records the settings given in config. Only the first call has any effect.
+pub(crate) fn configure(settings: Settings) {
+    let _ = SETTINGS.set(settings);
+}
+
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+/// Whether a sheet argument names a remote file rather than a local one.
+pub(crate) fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+/// Downloads an attribute sheet.
+pub(crate) fn get(url: &str) -> Result<Vec<u8>> {
+    Client::new("sheets").get(url)
+}
+
+/// Makes requests to one web service, within its rate limit, answering from
+/// the on-disk cache when it can.
+pub(crate) struct Client {
+    service: &'static str,
+    limits: Limits,
+}
+
+impl Client {
+    pub(crate) fn new(service: &'static str) -> Self {
+        Client {
+            service,
+            limits: settings().limits(service),
+        }
+    }
+
+    /// Fetches a URL. When the network can't be reached, a cached response
+    /// past its age is used rather than failing, with a warning.
+    pub(crate) fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let cached = self.cache_path(url);
+        if let Some(path) = &cached {
+            if let Some(body) = read_cache(path, self.limits.cache_age, settings().offline)? {
+                return Ok(body);
+            }
+        }
+        if settings().offline {
+            return Err(Error::Offline(url.into()));
+        }
+
+        self.wait_turn()?;
+        let body = match download(url) {
+            Ok(body) => body,
+            Err(e @ Error::Unreachable { .. }) => {
+                let stale = match &cached {
+                    Some(path) => read_cache(path, Duration::MAX, true)?,
+                    None => None,
+                };
+                let Some(body) = stale else {
+                    return Err(e);
+                };
+                warning::emit(
+                    warning::Code::StaleCache,
+                    format_args!("{e}; using a cached copy"),
+                );
+                return Ok(body);
+            }
+            Err(e) => return Err(e),
+        };
+
+        if let Some(path) = &cached {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, &body)?;
+        }
+        Ok(body)
+    }
+
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        if self.limits.cache_age.is_zero() {
+            return None;
+        }
+        let key = hex::encode(digest::sha256(url.as_bytes()));
+        config::cache_dir().map(|dir| dir.join("http").join(self.service).join(key))
+    }
+
+    /// Sleeps until the service's interval has passed since the last request
+    /// any flacdat made to it. The time of each request is kept in a file,
+    /// locked while it's read and updated.
+    fn wait_turn(&self) -> Result<()> {
+        if self.limits.interval.is_zero() {
+            return Ok(());
+        }
+        let Some(dir) = config::data_dir() else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir.join("rate"))?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join("rate").join(self.service))?;
+        file.lock()?;
+        let result = wait_and_record(&mut file, self.limits.interval);
+        file.unlock()?;
+        result
+    }
+}
+
+fn wait_and_record(file: &mut File, interval: Duration) -> Result<()> {
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    };
+
+    let mut last = String::new();
+    file.read_to_string(&mut last)?;
+    if let Ok(millis) = last.trim().parse() {
+        let next = Duration::from_millis(millis) + interval;
+        if let Some(wait) = next.checked_sub(now()) {
+            thread::sleep(wait.min(interval));
+        }
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", now().as_millis())?;
+    Ok(())
+}
+
+/// A cached response, if there is one young enough to use. Offline, any
+/// cached response will do.
+fn read_cache(path: &Path, max_age: Duration, offline: bool) -> Result<Option<Vec<u8>>> {
+    let modified = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let age = modified.elapsed().unwrap_or_default();
+    if age > max_age && !offline {
+        return Ok(None);
+    }
+    Ok(Some(fs::read(path)?))
+}
+
+/// How long to wait for a connection before giving up, so a dead network
+/// fails quickly rather than hanging on DNS.
+const CONNECT_TIMEOUT_SECONDS: &str = "10";
+
+/// curl's exit statuses for an unresolvable host, a refused connection, and a
+/// timeout.
+const UNREACHABLE: [i32; 3] = [6, 7, 28];
+
+/// Downloads a file with curl, following redirects and failing on HTTP
+/// errors rather than returning the error page as content.
+fn download(url: &str) -> Result<Vec<u8>> {
+    Tool::Curl.ensure()?;
+    let user_agent = settings().user_agent.clone().unwrap_or_else(|| {
+        format!(
+            "flacdat/{} ( https://github.com/archer884/flacdat )",
+            env!("CARGO_PKG_VERSION")
+        )
+    });
+    let output = Tool::Curl
+        .command()
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--proto", "=http,https"])
+        .args(["--connect-timeout", CONNECT_TIMEOUT_SECONDS])
+        .arg("--user-agent")
+        .arg(user_agent)
+        .arg(url)
+        .output()?;
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        if output
+            .status
+            .code()
+            .is_some_and(|code| UNREACHABLE.contains(&code))
+        {
+            return Err(Error::Unreachable {
+                url: url.into(),
+                message: message.trim().into(),
+            });
+        }
+        return Err(Error::FetchFailed {
+            url: url.into(),
+            message: message.trim().into(),
+        });
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,71 @@
+use std::{path::Path, str::FromStr};
+
+use crate::{Attribute, Attributes, Error, Result};
+
+/// How tracks are gathered into albums for album-wide measurements: by the
+/// values of one or more attributes, such as `albumartist,album`, by
+/// directory with `dir`, or by both. Tracks grouped by tag alone may be
+/// spread over any number of directories.
+#[derive(Clone, Debug)]
+pub(crate) struct Grouping(Vec<Key>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Dir,
+    Attribute(Attribute),
+}
+
+/// Albums are grouped by album tag unless told otherwise.
+impl Default for Grouping {
+    fn default() -> Self {
+        Grouping(vec![Key::Attribute(Attribute::Album)])
+    }
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    /// Parses a comma-separated list of attribute names and `dir`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let keys = s
+            .split(',')
+            .map(|key| match key.trim() {
+                "dir" | "directory" => Ok(Key::Dir),
+                name => name
+                    .parse()
+                    .map(Key::Attribute)
+                    .map_err(|_| format!("expected attribute names or dir; found {name}")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Grouping(keys))
+    }
+}
+
+impl Grouping {
+    /// The name of the album a file belongs to: its value for each key,
+    /// joined with " / ". Files which hold no tags that flacdat reads group
+    /// as though their attributes were empty.
+    pub(crate) fn album(&self, path: &Path) -> Result<String> {
+        let attributes = match self.0.iter().any(|key| *key != Key::Dir) {
+            true => match Attributes::from_path(path) {
+                Ok(attributes) => attributes,
+                Err(Error::UnsupportedFileTye(_)) => Attributes::default(),
+                Err(e) => return Err(e),
+            },
+            false => Attributes::default(),
+        };
+
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|key| match key {
+                Key::Dir => match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.display().to_string(),
+                    _ => ".".into(),
+                },
+                Key::Attribute(attribute) => attributes.values(*attribute).join(","),
+            })
+            .collect();
+        Ok(parts.join(" / "))
+    }
+}
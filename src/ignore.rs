@@ -0,0 +1,176 @@
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{collate, config::Entry, Result};
+
+/// Names skipped by every walk: Synology index directories, recycle bins,
+/// and partial downloads.
+const BUILT_IN: &[&str] = &["@eaDir", "#recycle", "*.partial"];
+
+/// Per-directory ignore file, one pattern per line.
+const IGNORE_FILE: &str = ".flacdatignore";
+
+/// Files and directories to skip when walking a tree, from config:
+///
+/// ```text
+/// [ignore]
+/// pattern = *.tmp
+/// hidden = false
+///
+/// [ignore export]
+/// pattern = Podcasts
+/// ```
+///
+/// Patterns are matched against each file or directory name, with `*` and
+/// `?` wildcards; an ignored directory is skipped entirely. Hidden names
+/// (starting with `.`, which covers `.stfolder` and `._` resource forks) are
+/// ignored unless `hidden = false`. A `.flacdatignore` file adds its patterns
+/// for its own directory and everything below it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Ignore {
+    patterns: Vec<String>,
+    hidden: Option<bool>,
+}
+
+impl Ignore {
+    pub(crate) fn from_entries(entries: &[Entry]) -> Result<Self> {
+        let mut ignore = Ignore::default();
+        for entry in entries {
+            match entry.key.as_str() {
+                "pattern" => ignore.patterns.push(entry.value.clone()),
+                "hidden" => ignore.hidden = Some(entry.parse()?),
+                key => return Err(entry.error(format!("unknown ignore key: {key}"))),
+            }
+        }
+        Ok(ignore)
+    }
+
+    /// These rules with an operation's own rules layered on top.
+    pub(crate) fn with(&self, other: Option<&Ignore>) -> Ignore {
+        let mut merged = self.clone();
+        if let Some(other) = other {
+            merged.patterns.extend(other.patterns.iter().cloned());
+            merged.hidden = other.hidden.or(self.hidden);
+        }
+        merged
+    }
+
+    fn skips(&self, name: &str, local: &[String]) -> bool {
+        (self.hidden != Some(false) && name.starts_with('.'))
+            || BUILT_IN
+                .iter()
+                .copied()
+                .chain(self.patterns.iter().map(String::as_str))
+                .chain(local.iter().map(String::as_str))
+                .any(|pattern| matches(pattern, name))
+    }
+
+    /// Every file under `dir` with one of the given extensions, in path order.
+    pub(crate) fn walk(&self, dir: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        self.walk_into(dir, extensions, true, &[], &mut paths)?;
+        Ok(paths)
+    }
+
+    /// Replaces each directory among `paths` with the files in it which have
+    /// one of the given extensions, and with those in its subdirectories too
+    /// when `recursive` is set. Other paths are kept as they are.
+    pub(crate) fn expand(
+        &self,
+        paths: &[PathBuf],
+        extensions: &[&str],
+        recursive: bool,
+    ) -> Result<Vec<PathBuf>> {
+        let mut expanded = Vec::new();
+        for path in paths {
+            match path.is_dir() {
+                true => self.walk_into(path, extensions, recursive, &[], &mut expanded)?,
+                false => expanded.push(path.clone()),
+            }
+        }
+        Ok(expanded)
+    }
+
+    fn walk_into(
+        &self,
+        dir: &Path,
+        extensions: &[&str],
+        recursive: bool,
+        inherited: &[String],
+        paths: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let mut local = inherited.to_vec();
+        match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(text) => local.extend(
+                text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from),
+            ),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| collate::compare_paths(a, b));
+
+        for path in entries {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if self.skips(&name, &local) {
+                continue;
+            }
+
+            if path.is_dir() {
+                if recursive {
+                    self.walk_into(&path, extensions, true, &local, paths)?;
+                }
+            } else if path
+                .extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|extension| extensions.contains(&extension))
+            {
+                paths.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Matches a name against a pattern in which `*` stands for any run of
+/// characters and `?` for any one character.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // On a mismatch, backtrack to the most recent star and let it absorb one
+    // more character.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
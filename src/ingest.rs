@@ -0,0 +1,133 @@
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    archive::{self, Extracted},
+    ignore::Ignore,
+    template::component,
+    Attributes, Error, Result,
+};
+
+/// One file to bring into the library.
+pub(crate) struct Item {
+    /// Where to read the file from: the download itself, or its extracted
+    /// copy when it came from an archive.
+    pub(crate) source: PathBuf,
+
+    /// How to name the file to the user: its path, or `<archive>!/<entry>`.
+    pub(crate) shown: String,
+
+    pub(crate) target: PathBuf,
+
+    /// Whether the file is a WAV to be converted to FLAC on the way in.
+    pub(crate) convert: bool,
+}
+
+/// Everything an ingest will do, worked out before anything is written.
+/// Holds the extracted archives, so their files remain readable until the
+/// plan is dropped.
+pub(crate) struct Plan {
+    pub(crate) items: Vec<Item>,
+
+    /// Files whose target already exists, which are left alone.
+    pub(crate) skipped: Vec<(String, PathBuf)>,
+
+    _extracted: Vec<Extracted>,
+}
+
+/// Works out where each audio file under `downloads` belongs in `library`.
+///
+/// Tagged FLAC and MP3 files are organized as
+/// `<artist>/<album>/<track> - <title>.<ext>`; WAVs, and files missing an
+/// artist or album, keep their names under a directory named after the
+/// archive or download folder they came in.
+pub(crate) fn plan(downloads: &Path, library: &Path, ignore: &Ignore) -> Result<Plan> {
+    let mut candidates = Vec::new();
+    let mut extracted = Vec::new();
+
+    for path in ignore.walk(
+        downloads,
+        &["flac", "mp3", "wav", "zip", "tar", "gz", "tgz"],
+    )? {
+        let relative = path.strip_prefix(downloads).unwrap_or(&path);
+        if archive::is_archive(&path) {
+            let group = relative.with_file_name(archive::stem(&path));
+            let contents = archive::extract(&path, &["flac", "mp3", "wav"])?;
+            for (name, source) in &contents.entries {
+                let shown = format!("{}!/{name}", path.display());
+                candidates.push((source.clone(), shown, group.join(name)));
+            }
+            extracted.push(contents);
+        } else if path.extension() != Some(OsStr::new("gz")) {
+            let shown = path.display().to_string();
+            candidates.push((path.clone(), shown, relative.to_owned()));
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut skipped = Vec::new();
+    let mut targets = HashSet::new();
+    for (source, shown, fallback) in candidates {
+        let convert = source
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("wav"));
+        let target = match convert {
+            true => library.join(fallback.with_extension("flac")),
+            false => library.join(organized(&source)?.unwrap_or(fallback)),
+        };
+
+        if !targets.insert(target.clone()) {
+            return Err(Error::Ingest(format!(
+                "{shown} and another download both belong at {}",
+                target.display()
+            )));
+        }
+        if target.exists() {
+            skipped.push((shown, target));
+            continue;
+        }
+
+        items.push(Item {
+            source,
+            shown,
+            target,
+            convert,
+        });
+    }
+
+    Ok(Plan {
+        items,
+        skipped,
+        _extracted: extracted,
+    })
+}
+
+/// `<artist>/<album>/<track> - <title>.<ext>`, if the file has an artist and
+/// an album to organize it by.
+fn organized(path: &Path) -> Result<Option<PathBuf>> {
+    let attributes = Attributes::from_path(path)?;
+    let (Some(artist), Some(album)) = (attributes.artist.first(), &attributes.album) else {
+        return Ok(None);
+    };
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let title = attributes.title.as_deref().unwrap_or(&stem);
+    let name = match attributes.track {
+        Some(track) => format!("{track:02} - {title}"),
+        None => title.to_string(),
+    };
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+
+    // The extension is appended rather than set, since titles often contain
+    // dots of their own.
+    let name = format!("{}.{extension}", component(&name));
+    Ok(Some(
+        [&component(artist), &component(album), &name]
+            .iter()
+            .collect(),
+    ))
+}
@@ -0,0 +1,438 @@
+use std::fmt::Write;
+
+/// A JSON value, as returned by the web services flacdat reads or written
+/// by `describe`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in document order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member of an object with the given name.
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array; anything else has none.
+    pub(crate) fn as_array(&self) -> &[Value] {
+        match self {
+            Value::Array(elements) => elements,
+            _ => &[],
+        }
+    }
+
+    /// A string member, if the object has one.
+    pub(crate) fn str(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(Value::as_str)
+    }
+
+    /// Writes the value as JSON text: on one line, or indented two spaces a
+    /// level when `pretty`.
+    pub(crate) fn render(&self, pretty: bool) -> String {
+        let mut text = String::new();
+        self.write(&mut text, pretty.then_some(0));
+        text
+    }
+
+    fn write(&self, text: &mut String, depth: Option<usize>) {
+        let newline = |text: &mut String, depth: Option<usize>| {
+            if let Some(depth) = depth {
+                let _ = write!(text, "\n{:indent$}", "", indent = depth * 2);
+            }
+        };
+        let inner = depth.map(|depth| depth + 1);
+
+        match self {
+            Value::Null => text.push_str("null"),
+            Value::Bool(b) => text.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) if !n.is_finite() => text.push_str("null"),
+            Value::Number(n) => {
+                let _ = write!(text, "{n}");
+            }
+            Value::String(s) => text.push_str(&quote(s)),
+            Value::Array(elements) if elements.is_empty() => text.push_str("[]"),
+            Value::Array(elements) => {
+                text.push('[');
+                for (idx, element) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        text.push(',');
+                    }
+                    newline(text, inner);
+                    element.write(text, inner);
+                }
+                newline(text, depth);
+                text.push(']');
+            }
+            Value::Object(members) if members.is_empty() => text.push_str("{}"),
+            Value::Object(members) => {
+                text.push('{');
+                for (idx, (name, value)) in members.iter().enumerate() {
+                    if idx > 0 {
+                        text.push(',');
+                    }
+                    newline(text, inner);
+                    let _ = write!(text, "{}:", quote(name));
+                    if depth.is_some() {
+                        text.push(' ');
+                    }
+                    value.write(text, inner);
+                }
+                newline(text, depth);
+                text.push('}');
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Quotes a string as a JSON string literal.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses a JSON document, describing where it went wrong if it isn't one.
+pub(crate) fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, at: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    match parser.at == text.len() {
+        true => Ok(value),
+        false => Err(parser.error("trailing characters")),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at byte {}", self.at)
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.at..]
+    }
+
+    fn whitespace(&mut self) {
+        let rest = self.rest();
+        self.at += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest().starts_with(token) {
+            true => {
+                self.at += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        self.whitespace();
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected {token}"))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.whitespace();
+        match self.rest().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.eat("}") {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.whitespace();
+            if self.eat("}") {
+                return Ok(Value::Object(members));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect("[")?;
+        let mut elements = Vec::new();
+        self.whitespace();
+        if self.eat("]") {
+            return Ok(Value::Array(elements));
+        }
+        loop {
+            elements.push(self.value()?);
+            self.whitespace();
+            if self.eat("]") {
+                return Ok(Value::Array(elements));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    /// A number as JSON writes it: an optional minus, an integer part without
+    /// leading zeros, then an optional fraction and exponent.
+    fn number(&mut self) -> Result<Value, String> {
+        let rest = self.rest().as_bytes();
+        let digits = |at: usize| {
+            rest[at..]
+                .iter()
+                .position(|b| !b.is_ascii_digit())
+                .unwrap_or(rest.len() - at)
+        };
+
+        let mut len = usize::from(rest.first() == Some(&b'-'));
+        match (rest.get(len), digits(len)) {
+            (Some(b'0'), _) => len += 1,
+            (_, 0) => return Err(self.error("invalid number")),
+            (_, count) => len += count,
+        }
+        if rest.get(len) == Some(&b'.') {
+            match digits(len + 1) {
+                0 => return Err(self.error("invalid number")),
+                count => len += 1 + count,
+            }
+        }
+        if matches!(rest.get(len), Some(b'e' | b'E')) {
+            len += 1;
+            if matches!(rest.get(len), Some(b'+' | b'-')) {
+                len += 1;
+            }
+            match digits(len) {
+                0 => return Err(self.error("invalid number")),
+                count => len += count,
+            }
+        }
+
+        let number = self.rest()[..len]
+            .parse()
+            .map_err(|_| self.error("invalid number"))?;
+        self.at += len;
+        Ok(Value::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat("\"") {
+            return Err(self.error("expected a string"));
+        }
+        let mut s = String::new();
+        loop {
+            let rest = self.rest();
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.at += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => s.push(self.escape()?),
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let Some(c) = self.rest().chars().next() else {
+            return Err(self.error("unterminated string"));
+        };
+        self.at += c.len_utf8();
+        Ok(match c {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.hex4()?;
+                // Characters outside the BMP are written as surrogate pairs.
+                let code = match (0xD800..0xDC00).contains(&high) && self.eat("\\u") {
+                    true => {
+                        let low = self.hex4()?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return Err(self.error("invalid surrogate pair"));
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    }
+                    false => high,
+                };
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            _ => return Err(self.error("invalid escape")),
+        })
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .rest()
+            .get(..4)
+            .ok_or_else(|| self.error("short \\u escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid \\u escape"))?;
+        self.at += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_what_it_renders() {
+        let value = Value::Object(vec![
+            ("title".into(), "Purple \"Rain\"\n\u{1}".into()),
+            ("year".into(), 1984.0.into()),
+            ("gain".into(), (-6.5).into()),
+            ("missing".into(), Value::Null),
+            (
+                "flags".into(),
+                Value::Array(vec![Value::Bool(true), Value::Bool(false)]),
+            ),
+            ("empty".into(), Value::Object(Vec::new())),
+        ]);
+        assert_eq!(parse(&value.render(false)), Ok(value.clone()));
+        assert_eq!(parse(&value.render(true)), Ok(value));
+    }
+
+    #[test]
+    fn numbers_follow_the_grammar() {
+        for (text, number) in [
+            ("0", 0.0),
+            ("-0", 0.0),
+            ("12", 12.0),
+            ("-1.5", -1.5),
+            ("1e3", 1000.0),
+            ("2.5E-1", 0.25),
+            ("1e+2", 100.0),
+        ] {
+            assert_eq!(parse(text), Ok(Value::Number(number)), "{text}");
+        }
+        for text in [
+            "1-2", "01", "-", "1.", ".5", "-.5", "1e", "1e+", "+1", "1.2.3",
+        ] {
+            assert!(parse(text).is_err(), "{text}");
+        }
+        assert_eq!(parse("[1-2]"), Err("expected , at byte 2".into()));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            parse(r#""\"\\\/\b\f\n\r\t\u00e9""#),
+            Ok("\"\\/\u{8}\u{c}\n\r\t\u{e9}".into())
+        );
+        assert_eq!(parse(r#""\ud83d\ude00""#), Ok("\u{1f600}".into()));
+        assert_eq!(
+            parse(r#""\ud83d\u0041""#),
+            Err("invalid surrogate pair at byte 13".into())
+        );
+        assert_eq!(parse(r#""\x""#), Err("invalid escape at byte 3".into()));
+        assert_eq!(parse(r#""\u12""#), Err("short \\u escape at byte 3".into()));
+        assert_eq!(
+            parse(r#""\u12zz""#),
+            Err("invalid \\u escape at byte 3".into())
+        );
+    }
+
+    #[test]
+    fn errors_say_where() {
+        assert_eq!(parse(""), Err("expected a value at byte 0".into()));
+        assert_eq!(parse("\"abc"), Err("unterminated string at byte 4".into()));
+        assert_eq!(parse("[1,]"), Err("expected a value at byte 3".into()));
+        assert_eq!(parse("{\"a\" 1}"), Err("expected : at byte 5".into()));
+        assert_eq!(parse("{1:2}"), Err("expected a string at byte 1".into()));
+        assert_eq!(parse("null x"), Err("trailing characters at byte 5".into()));
+        assert_eq!(parse("nul"), Err("expected a value at byte 0".into()));
+    }
+}
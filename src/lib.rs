@@ -0,0 +1,318 @@
+//! Reading, writing, and converting the tags of FLAC and MP3 files.
+//!
+//! [`Attributes`] is the tag model every flacdat command works with, read
+//! from a file with [`Attributes::from_path`]. The [`tags`] module writes
+//! attributes back to vorbis comments and ID3 tags, and [`convert`] turns WAV
+//! and other audio into FLAC.
+//!
+//! ```no_run
+//! let mut attributes = flacdat::Attributes::from_path("01 Intro.flac")?;
+//! attributes.genre = vec!["Jazz".into()];
+//! flacdat::tags::apply("01 Intro.flac", &attributes, &Default::default())?;
+//! # Ok::<(), flacdat::Error>(())
+//! ```
+
+use std::io;
+
+mod analyze;
+mod application;
+mod archive;
+mod art;
+mod artists;
+pub mod attributes;
+mod audio;
+mod audit;
+mod auth;
+mod blocks;
+mod budget;
+mod capability;
+mod case;
+mod check;
+mod checkpoint;
+mod cli;
+mod collate;
+mod condition;
+mod config;
+pub mod convert;
+mod copy;
+mod cue;
+mod describe;
+mod device;
+mod digest;
+mod dj;
+mod dupes;
+#[cfg(feature = "native-encoder")]
+mod encoder;
+mod encoding;
+mod failures;
+mod feed;
+mod fetch;
+mod grouping;
+mod ignore;
+mod ingest;
+mod json;
+mod lock;
+mod lyrics;
+mod manifest;
+mod matching;
+mod metrics;
+mod mp4;
+mod musicbrainz;
+mod nfo;
+mod nml;
+mod normalize;
+mod ogg;
+mod output;
+mod pathmap;
+mod pipeline;
+mod plan;
+mod playlist;
+mod preflight;
+mod progress;
+mod protect;
+mod rate;
+mod recipe;
+mod riplog;
+mod roots;
+mod safety;
+mod session;
+mod sheet;
+mod snapshot;
+mod staging;
+mod stats;
+mod strip;
+pub mod tags;
+mod template;
+mod throttle;
+mod tools;
+mod transcode;
+mod tui;
+mod verify;
+mod versions;
+mod warning;
+mod watch;
+mod writeback;
+
+pub use attributes::{Attribute, Attributes, FileAttributes};
+#[doc(hidden)]
+pub use cli::main;
+pub use preflight::AccessError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong reading, writing, or converting files.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+
+    #[error(transparent)]
+    Id3(#[from] id3::Error),
+
+    #[error(transparent)]
+    Vorbis(#[from] metaflac::Error),
+
+    #[error("{name} must be installed ({hint}), or its path set with [tools] {name} in config")]
+    ToolNotInstalled {
+        name: &'static str,
+        hint: &'static str,
+    },
+
+    #[error("ffmpeg failed on {0}")]
+    FfmpegFailed(String),
+
+    #[error("{0} file(s) failed to convert")]
+    ConvertFailed(usize),
+
+    #[error("{failed} of {total} file(s) failed")]
+    FilesFailed { failed: usize, total: usize },
+
+    #[error("{0}")]
+    Archive(String),
+
+    #[error("{0}")]
+    Recipe(String),
+
+    #[error("{0}")]
+    RipLog(String),
+
+    #[error("{0}")]
+    Tracklist(String),
+
+    #[error("invalid pattern {0}")]
+    Template(String),
+
+    #[error("{0} already exists")]
+    RenameConflict(String),
+
+    #[error("{0} warning(s) denied")]
+    WarningsDenied(usize),
+
+    #[error("{0} target path(s) too long for the filesystem, even shortened; nothing was moved")]
+    PathTooLong(usize),
+
+    #[error("{0}")]
+    Feed(String),
+
+    #[error("{0}")]
+    Listing(String),
+
+    #[error("{0}")]
+    Cue(String),
+
+    #[error("unable to fetch {url}: {message}")]
+    FetchFailed { url: String, message: String },
+
+    #[error("{0}: not cached, and working offline")]
+    Offline(String),
+
+    #[error("{url}: network unreachable: {message}")]
+    Unreachable { url: String, message: String },
+
+    #[error("{0}")]
+    Keyring(String),
+
+    #[error("{0}")]
+    Lookup(String),
+
+    #[error("{0}")]
+    Plan(String),
+
+    #[error("{source_name}: SHA-256 is {actual}, expected {expected}")]
+    ChecksumMismatch {
+        source_name: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("{0}")]
+    Ingest(String),
+
+    #[error("{0}: rewriting the tag would lose or alter Serato or Rekordbox data")]
+    DjDataLost(String),
+
+    #[error("write verification failed for {0}")]
+    WriteVerification(String),
+
+    #[error("unsupported file type: {0}")]
+    UnsupportedFileTye(String),
+
+    #[error("{0}")]
+    Encode(String),
+
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    #[error("unrecognized column(s) in attribute sheet: {}", .0.join(", "))]
+    UnrecognizedColumns(Vec<String>),
+
+    #[error("line {line}, column {column}: invalid value {value:?}, expected {expected}")]
+    InvalidCell {
+        line: u64,
+        column: String,
+        value: String,
+        expected: &'static str,
+    },
+
+    #[error("line {line}: {message}")]
+    InvalidRow { line: u64, message: String },
+
+    #[error("attribute sheet has no path column")]
+    MissingPathColumn,
+
+    #[error("unknown attribute: {0}")]
+    UnknownAttribute(String),
+
+    #[error("invalid condition: {0}")]
+    Condition(String),
+
+    #[error("invalid value for {attribute:?}: {value}")]
+    InvalidValue { attribute: Attribute, value: String },
+
+    #[error("config error at line {line}: {message}")]
+    Config { line: usize, message: String },
+
+    #[error("no pipeline named {0} in config")]
+    UnknownPipeline(String),
+
+    #[error(transparent)]
+    Access(#[from] preflight::AccessError),
+
+    #[error("{0} file(s) failed pre-flight checks")]
+    Preflight(usize),
+
+    #[error("invalid application id: {0} (expected four characters or eight hex digits)")]
+    InvalidApplicationId(String),
+
+    #[error("{0}: no APPLICATION block with id {1}")]
+    MissingApplication(String, String),
+
+    #[error("invalid {0} data")]
+    InvalidData(&'static str),
+
+    #[error("raw data can only be read from or written to a file")]
+    RawRequiresFile,
+
+    #[error("{0} issue(s) found")]
+    CheckFailed(usize),
+
+    #[error("{0}")]
+    Checkpoint(String),
+
+    #[error("{0}")]
+    Snapshot(String),
+
+    #[error("{0}")]
+    Session(String),
+
+    #[error("{0}")]
+    Device(String),
+
+    #[error("{0}")]
+    Ogg(String),
+
+    #[error("{0}")]
+    Mp4(String),
+
+    #[error("no operation with id {0} in the audit log")]
+    UnknownOperation(String),
+
+    #[error("{path}: {key} has changed since the operation; use --force to revert anyway")]
+    RevertConflict { path: String, key: String },
+
+    #[error("{0} has changed since the operation created it; use --force to remove it anyway")]
+    RevertCreated(String),
+
+    #[error("{0} was deleted by the operation and can't be restored")]
+    RevertDeleted(String),
+
+    #[error("{format} has no field for {attributes}; use --unrepresentable txxx or drop to apply anyway")]
+    Unrepresentable { format: String, attributes: String },
+
+    #[error("{operation} of {count} files not confirmed; use --yes to go ahead")]
+    NotConfirmed { operation: String, count: usize },
+
+    #[error("{0} exited unsuccessfully; nothing was applied")]
+    EditorFailed(String),
+
+    #[error("{0} needs a terminal")]
+    NoTerminal(&'static str),
+
+    #[error("{0} difference(s) between the sheet and the files")]
+    SheetDiffers(usize),
+}
+
+impl Error {
+    /// The process exit status for the error. Network failures get
+    /// EX_TEMPFAIL, since trying again later may well succeed; a run which
+    /// carried on past files that failed gets 3 if others succeeded, or 4 if
+    /// none did.
+    fn exit_code(&self) -> i32 {
+        match self {
+            Error::Offline(_) | Error::Unreachable { .. } => 75,
+            Error::FilesFailed { failed, total } if failed < total => 3,
+            Error::FilesFailed { .. } => 4,
+            _ => 1,
+        }
+    }
+}
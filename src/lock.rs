@@ -0,0 +1,129 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{config, digest, Result};
+
+/// An advisory lock on an audio file, held until dropped.
+///
+/// Locks live in the data directory rather than beside the audio so that they
+/// neither litter the library nor conflict with the file handles used to
+/// rewrite tags. Every flacdat process takes one before modifying a file, and
+/// removes it again once done, on platforms which allow it.
+pub(crate) struct FileLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    pub(crate) fn acquire(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let canonical = canonical(path);
+        let name = hex::encode(digest::sha256(canonical.as_os_str().as_encoded_bytes()));
+
+        let lock = locks_dir()?.join(format!("{name}.lock"));
+        loop {
+            let file = lock_file(&lock, path)?;
+            // One removed by its last holder while this waited for it locks
+            // nothing anyone else will see; take the new one instead.
+            if is_current(&file, &lock)? {
+                return Ok(FileLock { file, path: lock });
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Removed while still held, so no one can take it between the two.
+        #[cfg(unix)]
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+/// An advisory lock on the whole library, held until dropped, which bulk
+/// operations such as apply, organize, and watch take so that they never
+/// interleave with one another. Each still locks the files it writes.
+///
+/// Unlike a file's lock, library.lock is left in place when dropped: there's
+/// only ever the one, and keeping it spares the dance of checking that the
+/// one locked is still the one at its path.
+pub(crate) struct LibraryLock {
+    file: File,
+}
+
+impl LibraryLock {
+    pub(crate) fn acquire() -> Result<Self> {
+        let file = lock_file(&locks_dir()?.join("library.lock"), Path::new("the library"))?;
+        Ok(LibraryLock { file })
+    }
+}
+
+impl Drop for LibraryLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// The path a file's lock is named for. A file which doesn't exist yet, such
+/// as the target of a conversion, is named for its canonical directory, so
+/// that every spelling of its path takes the same lock.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_owned()),
+    }
+}
+
+fn locks_dir() -> Result<PathBuf> {
+    let dir = config::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unable to locate data dir"))?
+        .join("locks");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Opens a lock file and locks it, saying what's being waited for if another
+/// process holds it.
+fn lock_file(lock: &Path, locked: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock)?;
+
+    if file.try_lock().is_err() {
+        eprintln!("waiting for lock on {}", locked.display());
+        file.lock()?;
+    }
+
+    Ok(file)
+}
+
+/// Whether a lock file is still the one at its path.
+#[cfg(unix)]
+fn is_current(file: &File, lock: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let held = file.metadata()?;
+    match fs::metadata(lock) {
+        Ok(current) => Ok(current.dev() == held.dev() && current.ino() == held.ino()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(unix))]
+fn is_current(_: &File, _: &Path) -> Result<bool> {
+    Ok(true)
+}
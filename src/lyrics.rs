@@ -0,0 +1,156 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use id3::{
+    frame::{self, SynchronisedLyricsType, TimestampFormat},
+    TagLike,
+};
+use metaflac::block::VorbisComment;
+
+/// The extensions of lyrics sidecars, in the order they're looked for.
+const SIDECARS: &[&str] = &["lrc", "txt"];
+
+/// The sidecar of the same name beside an audio file, if it has one: its
+/// `.lrc`, or failing that its `.txt`.
+pub(crate) fn sidecar(path: &Path) -> Option<PathBuf> {
+    SIDECARS
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|sidecar| sidecar.is_file())
+}
+
+/// The lines of LRC lyrics with the time each is sung at, in milliseconds,
+/// in order; `None` for lyrics without timestamps. A line may carry several
+/// timestamps, for a chorus sung more than once, and an `[offset:]` tag
+/// moves every line, earlier for positive offsets. Other tags, such as
+/// `[ar:]` for the artist, are passed over.
+fn timed(text: &str) -> Option<Vec<(u32, String)>> {
+    let mut offset = 0;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut stamps = Vec::new();
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|tag| tag.split_once(']')) {
+            if let Some(time) = timestamp(tag) {
+                stamps.push(time);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(offset);
+            }
+            rest = after;
+        }
+        for time in stamps {
+            let time = (i64::from(time) - offset).clamp(0, i64::from(u32::MAX));
+            lines.push((time as u32, rest.trim().to_string()));
+        }
+    }
+    lines.sort_by_key(|&(time, _)| time);
+    (!lines.is_empty()).then_some(lines)
+}
+
+/// A timestamp such as `01:23.45`, `01:23.456`, or `01:23`, in milliseconds.
+fn timestamp(tag: &str) -> Option<u32> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let number = |text: &str| match text.bytes().all(|b| b.is_ascii_digit()) {
+        true => text.parse::<u32>().ok(),
+        false => None,
+    };
+    let millis = match fraction.len() {
+        0 => 0,
+        1..=3 => number(fraction)? * 10u32.pow(3 - fraction.len() as u32),
+        _ => number(&fraction[..3])?,
+    };
+    number(minutes)?
+        .checked_mul(60_000)?
+        .checked_add(number(seconds)?.checked_mul(1000)?)?
+        .checked_add(millis)
+}
+
+/// Timed lines as LRC, with timestamps to the hundredth of a second.
+fn render(lines: &[(u32, String)]) -> String {
+    let mut lrc = String::new();
+    for (time, line) in lines {
+        let (minutes, seconds, hundredths) = (time / 60_000, time / 1000 % 60, time / 10 % 100);
+        let _ = writeln!(lrc, "[{minutes:02}:{seconds:02}.{hundredths:02}]{line}");
+    }
+    lrc
+}
+
+/// The words of lyrics alone, without LRC timestamps or tags.
+fn words(text: &str) -> String {
+    match timed(text) {
+        Some(lines) => {
+            let lines: Vec<_> = lines.into_iter().map(|(_, line)| line).collect();
+            lines.join("\n")
+        }
+        None => text.trim_end().to_string(),
+    }
+}
+
+/// The language code ID3 lyrics frames are marked with: the file's first
+/// language, when it's given as a three-letter ISO 639-2 code, and `XXX`,
+/// for unknown, otherwise.
+pub(crate) fn language(languages: &[String]) -> String {
+    match languages.first() {
+        Some(code) if code.len() == 3 && code.bytes().all(|b| b.is_ascii_alphabetic()) => {
+            code.to_ascii_lowercase()
+        }
+        _ => "XXX".into(),
+    }
+}
+
+/// Sets a FLAC's LYRICS comment to lyrics as a sidecar holds them, keeping
+/// any LRC timestamps, which players that show synchronized lyrics read.
+pub(crate) fn write_vorbis(comment: &mut VorbisComment, text: &str) {
+    comment.set("LYRICS", vec![text.trim_end().to_string()]);
+}
+
+/// A FLAC's lyrics, from its LYRICS comment or UNSYNCEDLYRICS as some
+/// taggers name it, with the extension of the sidecar to write them to.
+pub(crate) fn read_vorbis(comment: &VorbisComment) -> Option<(String, &'static str)> {
+    let text = ["LYRICS", "UNSYNCEDLYRICS"]
+        .iter()
+        .find_map(|key| comment.get(key)?.first())?;
+    let extension = match timed(text) {
+        Some(_) => "lrc",
+        None => "txt",
+    };
+    Some((format!("{}\n", text.trim_end()), extension))
+}
+
+/// Sets an MP3's lyrics: an unsynchronised lyrics (USLT) frame of the words
+/// alone and, for timed lyrics, a synchronised (SYLT) frame of their lines,
+/// timed in milliseconds. Lyrics frames already there are replaced.
+pub(crate) fn write_id3(tag: &mut id3::Tag, text: &str, lang: &str) {
+    tag.remove_all_lyrics();
+    tag.remove_all_synchronised_lyrics();
+    tag.add_frame(frame::Lyrics {
+        lang: lang.into(),
+        description: String::new(),
+        text: words(text),
+    });
+    if let Some(lines) = timed(text) {
+        tag.add_frame(frame::SynchronisedLyrics {
+            lang: lang.into(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: lines,
+        });
+    }
+}
+
+/// An MP3's lyrics, with the extension of the sidecar to write them to: LRC
+/// from a SYLT frame timed in milliseconds, or else the text of a USLT frame.
+pub(crate) fn read_id3(tag: &id3::Tag) -> Option<(String, &'static str)> {
+    let synchronised = tag
+        .synchronised_lyrics()
+        .find(|lyrics| lyrics.timestamp_format == TimestampFormat::Ms);
+    if let Some(lyrics) = synchronised {
+        return Some((render(&lyrics.content), "lrc"));
+    }
+    let lyrics = tag.lyrics().next()?;
+    Some((format!("{}\n", lyrics.text.trim_end()), "txt"))
+}
@@ -0,0 +1,154 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{digest, json::Value, Result};
+
+/// A record of every file a run writes, for `--manifest`: what happened to
+/// it, the audit log operation it was part of, and its final size and
+/// SHA-256.
+struct Manifest {
+    out: PathBuf,
+    /// The audit log operation under way
+    operation: Option<String>,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    action: Action,
+    path: PathBuf,
+    operation: Option<String>,
+}
+
+#[derive(Clone, PartialEq)]
+pub(crate) enum Action {
+    Created,
+    Modified,
+    Renamed { from: PathBuf },
+    Deleted,
+}
+
+static MANIFEST: Mutex<Option<Manifest>> = Mutex::new(None);
+
+/// Starts recording files written, to be described in `out` when the run
+/// finishes.
+pub(crate) fn configure(out: PathBuf) {
+    *MANIFEST.lock().unwrap_or_else(|e| e.into_inner()) = Some(Manifest {
+        out,
+        operation: None,
+        entries: Vec::new(),
+    });
+}
+
+/// Notes the audit log operation which later writes belong to.
+pub(crate) fn begin(operation: &str) {
+    if let Some(manifest) = MANIFEST.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        manifest.operation = Some(operation.into());
+    }
+}
+
+/// Records a file written, if a manifest is being kept. A file is listed
+/// once, however many times it's written: a file created and then tagged
+/// stays created, and one moved after it was written is listed at its new
+/// path.
+pub(crate) fn record(action: Action, path: &Path) {
+    let mut manifest = MANIFEST.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(manifest) = manifest.as_mut() else {
+        return;
+    };
+    let operation = manifest.operation.clone();
+
+    let existing = match &action {
+        Action::Renamed { from } => manifest.entries.iter_mut().find(|e| e.path == *from),
+        _ => manifest.entries.iter_mut().find(|e| e.path == path),
+    };
+    match (existing, action) {
+        (Some(entry), Action::Renamed { from }) => {
+            if entry.action == Action::Modified {
+                entry.action = Action::Renamed { from };
+            }
+            entry.path = path.into();
+        }
+        (Some(_), _) => {}
+        (None, action) => manifest.entries.push(Entry {
+            action,
+            path: path.into(),
+            operation,
+        }),
+    }
+}
+
+/// Records a file written under a temporary name and then moved into place,
+/// which is listed under the name it ends up with, as whatever was done to
+/// it there.
+pub(crate) fn moved(temporary: &Path, path: &Path) {
+    let mut manifest = MANIFEST.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(manifest) = manifest.as_mut() else {
+        return;
+    };
+    let Some(idx) = manifest.entries.iter().position(|e| e.path == temporary) else {
+        return;
+    };
+    let entry = manifest.entries.remove(idx);
+    if !manifest.entries.iter().any(|e| e.path == path) {
+        manifest.entries.push(Entry {
+            path: path.into(),
+            ..entry
+        });
+    }
+}
+
+/// Writes a file, recording it as created.
+pub(crate) fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, contents)?;
+    record(Action::Created, path);
+    Ok(())
+}
+
+/// Writes the manifest, if one is being kept: a JSON array with an object
+/// for each file.
+pub(crate) fn finish() -> Result<()> {
+    let Some(manifest) = MANIFEST.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return Ok(());
+    };
+
+    let mut files = Vec::new();
+    for entry in &manifest.entries {
+        let action = match &entry.action {
+            Action::Created => "created",
+            Action::Modified => "modified",
+            Action::Renamed { .. } => "renamed",
+            Action::Deleted => "deleted",
+        };
+        let mut members = vec![
+            (
+                "path".into(),
+                entry.path.to_string_lossy().into_owned().into(),
+            ),
+            ("action".into(), action.into()),
+        ];
+        if let Action::Renamed { from } = &entry.action {
+            members.push(("from".into(), from.to_string_lossy().into_owned().into()));
+        }
+        members.push(("operation".into(), entry.operation.clone().into()));
+        // A file removed again before the run ended has neither.
+        let data = fs::read(&entry.path).ok();
+        members.push((
+            "size".into(),
+            data.as_ref().map(|data| data.len() as u64).into(),
+        ));
+        members.push((
+            "sha256".into(),
+            data.map(|data| hex::encode(digest::sha256(&data))).into(),
+        ));
+        files.push(Value::Object(members));
+    }
+
+    let mut text = Value::Array(files).render(true);
+    text.push('\n');
+    fs::write(&manifest.out, text)?;
+    Ok(())
+}
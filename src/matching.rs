@@ -0,0 +1,70 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// How `apply` finds the file each row of a sheet is for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Matching {
+    /// the row's path, against --root when it's relative; with --recursive, a
+    /// path which doesn't exist is looked for under --root by as much of its
+    /// end as matches, as for a sheet listed on another machine
+    #[default]
+    Path,
+    /// the row's file name alone, anywhere under --root
+    Filename,
+}
+
+/// What a row matched.
+pub(crate) enum Found {
+    One(PathBuf),
+    None,
+    Many(usize),
+}
+
+/// The files under a directory, by name.
+pub(crate) struct Index {
+    by_name: HashMap<OsString, Vec<PathBuf>>,
+}
+
+impl Index {
+    pub(crate) fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut by_name: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            if let Some(name) = path.file_name() {
+                by_name.entry(name.to_owned()).or_default().push(path);
+            }
+        }
+        Index { by_name }
+    }
+
+    /// The file named as `path` is. With `Matching::Path`, files sharing
+    /// more of the end of `path`, its parent directories and so on, are
+    /// preferred; failing that, every file of the name is a match.
+    pub(crate) fn find(&self, path: &Path, matching: Matching) -> Found {
+        let Some(found) = path.file_name().and_then(|name| self.by_name.get(name)) else {
+            return Found::None;
+        };
+        let found: Vec<&PathBuf> = match matching {
+            Matching::Filename => found.iter().collect(),
+            Matching::Path => {
+                let shared = |candidate: &PathBuf| {
+                    candidate
+                        .components()
+                        .rev()
+                        .zip(path.components().rev())
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                };
+                let most = found.iter().map(shared).max().unwrap_or_default();
+                found.iter().filter(|&c| shared(c) == most).collect()
+            }
+        };
+        match found.as_slice() {
+            [] => Found::None,
+            [one] => Found::One((*one).clone()),
+            many => Found::Many(many.len()),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use crate::Result;
+
+/// The upper bounds of the conversion time histogram's buckets, in seconds.
+const BUCKETS: &[f64] = &[0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Counts of what `watch` has done, for `--metrics`.
+#[derive(Default)]
+struct Metrics {
+    processed: u64,
+    /// Files which couldn't be brought in, by the stage they failed at
+    errors: Vec<(&'static str, u64)>,
+    /// Files found in the inbox and not yet brought in
+    queued: usize,
+    /// Conversions which took no longer than each bucket's bound
+    buckets: [u64; BUCKETS.len()],
+    conversions: u64,
+    conversion_seconds: f64,
+}
+
+static METRICS: Mutex<Option<Metrics>> = Mutex::new(None);
+
+/// Starts counting, and serves the counts in the Prometheus text format at
+/// `/metrics` on `addr` until the process exits.
+pub(crate) fn serve(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    *METRICS.lock().unwrap_or_else(|e| e.into_inner()) = Some(Metrics::default());
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A scraper which hangs mustn't stop the others.
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let _ = respond(stream);
+        }
+    });
+    Ok(())
+}
+
+fn update(f: impl FnOnce(&mut Metrics)) {
+    if let Some(metrics) = METRICS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        f(metrics);
+    }
+}
+
+/// Records how many files are waiting to be brought in.
+pub(crate) fn queued(count: usize) {
+    update(|metrics| metrics.queued = count);
+}
+
+/// Records files brought in.
+pub(crate) fn processed(count: usize) {
+    update(|metrics| {
+        metrics.processed += count as u64;
+        metrics.queued = metrics.queued.saturating_sub(count);
+    });
+}
+
+/// Records files which couldn't be brought in, failing at `stage`.
+pub(crate) fn failed(stage: &'static str, count: usize) {
+    update(|metrics| {
+        match metrics.errors.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, errors)) => *errors += count as u64,
+            None => metrics.errors.push((stage, count as u64)),
+        }
+        metrics.queued = metrics.queued.saturating_sub(count);
+    });
+}
+
+/// Records how long a conversion took.
+pub(crate) fn converted(took: Duration) {
+    update(|metrics| {
+        let seconds = took.as_secs_f64();
+        for (bucket, bound) in metrics.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        metrics.conversions += 1;
+        metrics.conversion_seconds += seconds;
+    });
+}
+
+fn respond(stream: TcpStream) -> Result<()> {
+    let mut request = String::new();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request)?;
+    // The headers don't matter, but are read so that the client sees its
+    // request taken in full.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let (status, body) = match request.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", render()),
+        _ => ("404 Not Found", String::from("not found\n")),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+fn render() -> String {
+    let guard = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(metrics) = guard.as_ref() else {
+        return String::new();
+    };
+
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for (suffix, value) in samples {
+            let _ = writeln!(out, "{name}{suffix} {value}");
+        }
+    };
+
+    family(
+        "flacdat_watch_files_processed_total",
+        "counter",
+        "Files brought into the library.",
+        &[(String::new(), metrics.processed.to_string())],
+    );
+    let errors: Vec<_> = metrics
+        .errors
+        .iter()
+        .map(|(stage, errors)| (format!("{{stage=\"{stage}\"}}"), errors.to_string()))
+        .collect();
+    family(
+        "flacdat_watch_errors_total",
+        "counter",
+        "Files which couldn't be brought in, by the stage they failed at.",
+        &errors,
+    );
+    family(
+        "flacdat_watch_queue_depth",
+        "gauge",
+        "Files found in the inbox and not yet brought in.",
+        &[(String::new(), metrics.queued.to_string())],
+    );
+    let mut conversions: Vec<_> = metrics
+        .buckets
+        .iter()
+        .zip(BUCKETS)
+        .map(|(bucket, bound)| (format!("_bucket{{le=\"{bound}\"}}"), bucket.to_string()))
+        .collect();
+    conversions.extend([
+        (
+            "_bucket{le=\"+Inf\"}".into(),
+            metrics.conversions.to_string(),
+        ),
+        ("_sum".into(), metrics.conversion_seconds.to_string()),
+        ("_count".into(), metrics.conversions.to_string()),
+    ]);
+    family(
+        "flacdat_watch_conversion_seconds",
+        "histogram",
+        "Time taken to convert a file to FLAC.",
+        &conversions,
+    );
+    out
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use metaflac::block::VorbisComment;
+
+use crate::{Error, Result};
+
+/// The iTunes-style tags of an MP4 file (`.m4a`), from the item list at
+/// `moov/udta/meta/ilst`, under the vorbis comment keys they correspond to.
+/// Freeform `----` items, as Picard writes, are kept under their names.
+pub(crate) fn read_comments(path: &Path) -> Result<VorbisComment> {
+    let invalid = |reason: &str| Error::Mp4(format!("{}: {reason}", path.display()));
+    let mut reader = BufReader::new(File::open(path)?);
+    let len = reader.get_ref().metadata()?.len();
+
+    // The audio can be hundreds of megabytes, so only moov is read whole.
+    let moov = find_atom(&mut reader, len, b"moov")?.ok_or_else(|| invalid("no moov atom"))?;
+    let mut comments: HashMap<String, Vec<String>> = HashMap::new();
+    let ilst = child(&moov, b"udta")
+        .and_then(|udta| child(udta, b"meta"))
+        // meta is a full atom: its children follow a version and flags.
+        .and_then(|meta| child(meta.get(4..)?, b"ilst"));
+    for (name, item) in atoms(ilst.unwrap_or_default()) {
+        let key = match &name {
+            b"----" => child(item, b"name")
+                .and_then(|name| name.get(4..))
+                .map(|name| freeform_key(&String::from_utf8_lossy(name))),
+            name => KEYS
+                .iter()
+                .find(|(atom, _)| atom == name)
+                .map(|(_, key)| key.to_string()),
+        };
+        let Some(key) = key else {
+            continue;
+        };
+
+        for (name, data) in atoms(item) {
+            if &name != b"data" || data.len() < 8 {
+                continue;
+            }
+            let (kind, value) = (data[3], &data[8..]);
+            match (key.as_str(), kind) {
+                // Track and disc numbers are binary: two reserved bytes, the
+                // number, and the total.
+                ("TRACKNUMBER" | "DISCNUMBER", _) if value.len() >= 6 => {
+                    let number = u16::from_be_bytes([value[2], value[3]]);
+                    let total = u16::from_be_bytes([value[4], value[5]]);
+                    if number > 0 {
+                        comments
+                            .entry(key.clone())
+                            .or_default()
+                            .push(number.to_string());
+                    }
+                    if total > 0 {
+                        let total_key = key.replace("NUMBER", "TOTAL");
+                        comments
+                            .entry(total_key)
+                            .or_default()
+                            .push(total.to_string());
+                    }
+                }
+                // UTF-8 text
+                (_, 1) => comments
+                    .entry(key.clone())
+                    .or_default()
+                    .push(String::from_utf8_lossy(value).into_owned()),
+                // A big-endian signed integer
+                (_, 21) if (1..=8).contains(&value.len()) => {
+                    let mut bytes = [if value[0] & 0x80 != 0 { 0xff } else { 0 }; 8];
+                    bytes[8 - value.len()..].copy_from_slice(value);
+                    comments
+                        .entry(key.clone())
+                        .or_default()
+                        .push(i64::from_be_bytes(bytes).to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(VorbisComment {
+        vendor_string: String::new(),
+        comments,
+    })
+}
+
+/// Item atoms and the vorbis comment keys they're read as.
+const KEYS: &[([u8; 4], &str)] = &[
+    (*b"\xa9nam", "TITLE"),
+    (*b"\xa9ART", "ARTIST"),
+    (*b"\xa9alb", "ALBUM"),
+    (*b"aART", "ALBUMARTIST"),
+    (*b"\xa9day", "DATE"),
+    (*b"\xa9gen", "GENRE"),
+    (*b"\xa9wrt", "COMPOSER"),
+    (*b"\xa9cmt", "COMMENT"),
+    (*b"\xa9grp", "GROUPING"),
+    (*b"\xa9wrk", "WORK"),
+    (*b"\xa9mvn", "MOVEMENTNAME"),
+    (*b"\xa9mvi", "MOVEMENT"),
+    (*b"\xa9mvc", "MOVEMENTTOTAL"),
+    (*b"shwm", "SHOWMOVEMENT"),
+    (*b"trkn", "TRACKNUMBER"),
+    (*b"disk", "DISCNUMBER"),
+];
+
+/// Picard names freeform items much as it names vorbis comments, except for
+/// the MusicBrainz ones.
+fn freeform_key(name: &str) -> String {
+    match name {
+        "MusicBrainz Album Release Country" => "RELEASECOUNTRY".into(),
+        name => name.to_ascii_uppercase(),
+    }
+}
+
+/// Reads the body of the first top-level atom with a given name.
+fn find_atom(reader: &mut (impl Read + Seek), len: u64, name: &[u8; 4]) -> Result<Option<Vec<u8>>> {
+    let mut at = 0;
+    while at + 8 <= len {
+        let mut header = [0; 8];
+        reader.seek(SeekFrom::Start(at))?;
+        reader.read_exact(&mut header)?;
+        let (mut size, mut body) = (
+            u64::from(u32::from_be_bytes([
+                header[0], header[1], header[2], header[3],
+            ])),
+            8,
+        );
+        if size == 1 {
+            let mut large = [0; 8];
+            reader.read_exact(&mut large)?;
+            (size, body) = (u64::from_be_bytes(large), 16);
+        } else if size == 0 {
+            size = len - at;
+        }
+        if size < body {
+            return Ok(None);
+        }
+        if &header[4..] == name {
+            let mut data = vec![0; (size - body) as usize];
+            reader.read_exact(&mut data)?;
+            return Ok(Some(data));
+        }
+        at += size;
+    }
+    Ok(None)
+}
+
+/// The atoms directly within `data`, by name. Stops at the first malformed
+/// one.
+fn atoms(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut atoms = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if size < 8 || size > data.len() {
+            break;
+        }
+        let name = [data[4], data[5], data[6], data[7]];
+        atoms.push((name, &data[8..size]));
+        data = &data[size..];
+    }
+    atoms
+}
+
+fn child<'a>(data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data)
+        .into_iter()
+        .find(|(atom, _)| atom == name)
+        .map(|(_, body)| body)
+}
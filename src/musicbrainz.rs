@@ -0,0 +1,181 @@
+use std::fmt::Write;
+
+use crate::{
+    fetch::Client,
+    json::{self, Value},
+    Error, Result,
+};
+
+const API: &str = "https://musicbrainz.org/ws/2";
+
+/// A release as MusicBrainz lists it, with its tracks in order.
+#[derive(Clone, Debug)]
+pub(crate) struct Release {
+    pub(crate) title: String,
+    /// The release's artist credit, joined as MusicBrainz displays it.
+    pub(crate) artist: String,
+    pub(crate) date: Option<String>,
+    pub(crate) country: Option<String>,
+    pub(crate) tracks: Vec<Track>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Track {
+    pub(crate) disc: u32,
+    pub(crate) number: u32,
+    pub(crate) title: String,
+    /// Each credited artist, without join phrases.
+    pub(crate) artists: Vec<String>,
+    pub(crate) media: Option<String>,
+}
+
+/// A search result.
+#[derive(Clone, Debug)]
+pub(crate) struct Candidate {
+    pub(crate) id: String,
+    pub(crate) score: u32,
+    pub(crate) title: String,
+    pub(crate) artist: String,
+    pub(crate) date: Option<String>,
+    pub(crate) country: Option<String>,
+    pub(crate) track_count: u32,
+}
+
+/// Looks up a release by its MBID.
+pub(crate) fn release(id: &str) -> Result<Release> {
+    let url = format!(
+        "{API}/release/{}?inc=recordings+artist-credits&fmt=json",
+        encode(id.trim())
+    );
+    let release = get(&url)?;
+
+    let mut tracks = Vec::new();
+    for (idx, medium) in release
+        .get("media")
+        .map_or(&[][..], Value::as_array)
+        .iter()
+        .enumerate()
+    {
+        let disc = medium
+            .get("position")
+            .and_then(Value::as_f64)
+            .map_or(idx as u32 + 1, |n| n as u32);
+        let format = medium.str("format").map(String::from);
+        for (idx, track) in medium
+            .get("tracks")
+            .map_or(&[][..], Value::as_array)
+            .iter()
+            .enumerate()
+        {
+            let credit = track
+                .get("artist-credit")
+                .or_else(|| track.get("recording").and_then(|r| r.get("artist-credit")));
+            tracks.push(Track {
+                disc,
+                number: track
+                    .get("position")
+                    .and_then(Value::as_f64)
+                    .map_or(idx as u32 + 1, |n| n as u32),
+                title: track.str("title").unwrap_or_default().into(),
+                artists: credit.map(credited).unwrap_or_default(),
+                media: format.clone(),
+            });
+        }
+    }
+
+    Ok(Release {
+        title: release.str("title").unwrap_or_default().into(),
+        artist: release.get("artist-credit").map(joined).unwrap_or_default(),
+        date: release
+            .str("date")
+            .filter(|date| !date.is_empty())
+            .map(String::from),
+        country: release.str("country").map(String::from),
+        tracks,
+    })
+}
+
+/// Searches for releases by artist and title, best matches first.
+pub(crate) fn search(artist: &str, album: &str) -> Result<Vec<Candidate>> {
+    let query = format!(
+        "release:\"{}\" AND artist:\"{}\"",
+        quote(album),
+        quote(artist)
+    );
+    let url = format!("{API}/release/?query={}&limit=10&fmt=json", encode(&query));
+    let results = get(&url)?;
+
+    Ok(results
+        .get("releases")
+        .map_or(&[][..], Value::as_array)
+        .iter()
+        .map(|release| Candidate {
+            id: release.str("id").unwrap_or_default().into(),
+            score: release
+                .get("score")
+                .and_then(Value::as_f64)
+                .unwrap_or_default() as u32,
+            title: release.str("title").unwrap_or_default().into(),
+            artist: release.get("artist-credit").map(joined).unwrap_or_default(),
+            date: release.str("date").map(String::from),
+            country: release.str("country").map(String::from),
+            track_count: release
+                .get("track-count")
+                .and_then(Value::as_f64)
+                .unwrap_or_default() as u32,
+        })
+        .collect())
+}
+
+fn get(url: &str) -> Result<Value> {
+    let body = Client::new("musicbrainz").get(url)?;
+    json::parse(&String::from_utf8_lossy(&body))
+        .map_err(|e| Error::Lookup(format!("unreadable response from MusicBrainz: {e}")))
+}
+
+/// The artists of a credit, one per name.
+fn credited(credit: &Value) -> Vec<String> {
+    credit
+        .as_array()
+        .iter()
+        .filter_map(|part| part.str("name"))
+        .map(String::from)
+        .collect()
+}
+
+/// A credit as displayed: names joined by their join phrases, as in
+/// "Simon & Garfunkel".
+fn joined(credit: &Value) -> String {
+    credit
+        .as_array()
+        .iter()
+        .map(|part| {
+            format!(
+                "{}{}",
+                part.str("name").unwrap_or_default(),
+                part.str("joinphrase").unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+/// Escapes quotes and backslashes inside a quoted Lucene term.
+fn quote(term: &str) -> String {
+    term.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Percent-encodes a URL path segment or query value.
+fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(b))
+            }
+            b => {
+                let _ = write!(encoded, "%{b:02X}");
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,141 @@
+use std::fmt::Write;
+
+use crate::feed::escape;
+
+/// One album directory, as Kodi and Jellyfin read it from `album.nfo`.
+pub(crate) struct Album {
+    pub(crate) title: String,
+    /// The album artist, or "Various Artists" for a compilation.
+    pub(crate) artist: String,
+    pub(crate) compilation: bool,
+    pub(crate) genres: Vec<String>,
+    pub(crate) year: Option<i32>,
+    pub(crate) musicbrainz_album_id: Option<String>,
+    pub(crate) musicbrainz_release_group_id: Option<String>,
+    pub(crate) musicbrainz_artist_id: Option<String>,
+    pub(crate) tracks: Vec<Track>,
+}
+
+pub(crate) struct Track {
+    pub(crate) position: Option<u32>,
+    pub(crate) title: Option<String>,
+    pub(crate) seconds: Option<f64>,
+}
+
+/// An artist directory's `artist.nfo`.
+pub(crate) struct Artist {
+    pub(crate) name: String,
+    pub(crate) genres: Vec<String>,
+    pub(crate) musicbrainz_artist_id: Option<String>,
+}
+
+/// Renders an `album.nfo`. Kodi takes an NFO's MusicBrainz IDs over its own
+/// lookups, which is what keeps it from matching a rip to the wrong release.
+pub(crate) fn render_album(album: &Album) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n");
+    xml.push_str("<album>\n");
+    element(&mut xml, 1, "title", Some(&album.title));
+    element(
+        &mut xml,
+        1,
+        "musicbrainzalbumid",
+        album.musicbrainz_album_id.as_deref(),
+    );
+    element(
+        &mut xml,
+        1,
+        "musicbrainzreleasegroupid",
+        album.musicbrainz_release_group_id.as_deref(),
+    );
+    element(&mut xml, 1, "artistdesc", Some(&album.artist));
+    for genre in &album.genres {
+        element(&mut xml, 1, "genre", Some(genre));
+    }
+    element(
+        &mut xml,
+        1,
+        "compilation",
+        Some(if album.compilation { "true" } else { "false" }),
+    );
+    element(
+        &mut xml,
+        1,
+        "year",
+        album.year.map(|year| year.to_string()).as_deref(),
+    );
+
+    if !album.compilation {
+        xml.push_str("  <albumArtistCredits>\n");
+        element(&mut xml, 2, "artist", Some(&album.artist));
+        element(
+            &mut xml,
+            2,
+            "musicBrainzArtistID",
+            album.musicbrainz_artist_id.as_deref(),
+        );
+        xml.push_str("  </albumArtistCredits>\n");
+    }
+
+    for track in &album.tracks {
+        xml.push_str("  <track>\n");
+        element(
+            &mut xml,
+            2,
+            "position",
+            track.position.map(|n| n.to_string()).as_deref(),
+        );
+        element(&mut xml, 2, "title", track.title.as_deref());
+        element(
+            &mut xml,
+            2,
+            "duration",
+            track.seconds.map(duration).as_deref(),
+        );
+        xml.push_str("  </track>\n");
+    }
+
+    xml.push_str("</album>\n");
+    xml
+}
+
+/// Renders an `artist.nfo`.
+pub(crate) fn render_artist(artist: &Artist) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n");
+    xml.push_str("<artist>\n");
+    element(&mut xml, 1, "name", Some(&artist.name));
+    element(
+        &mut xml,
+        1,
+        "musicBrainzArtistID",
+        artist.musicbrainz_artist_id.as_deref(),
+    );
+    for genre in &artist.genres {
+        element(&mut xml, 1, "genre", Some(genre));
+    }
+    xml.push_str("</artist>\n");
+    xml
+}
+
+/// Writes an element, or nothing if there is no value.
+fn element(xml: &mut String, depth: usize, name: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        let _ = writeln!(
+            xml,
+            "{:indent$}<{name}>{}</{name}>",
+            "",
+            escape(value),
+            indent = depth * 2
+        );
+    }
+}
+
+/// A duration as Kodi writes it: m:ss, or h:mm:ss.
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{hours}:{:02}:{:02}", seconds / 60 % 60, seconds % 60),
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::feed::escape;
+
+/// One track of a Traktor collection.
+pub(crate) struct Entry {
+    /// An absolute path.
+    pub(crate) path: PathBuf,
+    pub(crate) title: Option<String>,
+    pub(crate) artist: Option<String>,
+    pub(crate) album: Option<String>,
+    pub(crate) track: Option<u32>,
+    pub(crate) year: Option<i32>,
+    pub(crate) bpm: Option<f64>,
+    pub(crate) key: Option<String>,
+    pub(crate) seconds: Option<f64>,
+}
+
+/// Renders a Traktor NML collection. Traktor merges an imported collection
+/// into its own, matching tracks by location, so tags edited in flacdat show
+/// up without Traktor re-reading the files.
+pub(crate) fn render(entries: &[Entry]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\" ?>\n");
+    xml.push_str("<NML VERSION=\"19\">\n");
+    xml.push_str("  <HEAD COMPANY=\"www.native-instruments.com\" PROGRAM=\"Traktor\"></HEAD>\n");
+    xml.push_str("  <MUSICFOLDERS></MUSICFOLDERS>\n");
+    let _ = writeln!(xml, "  <COLLECTION ENTRIES=\"{}\">", entries.len());
+
+    for entry in entries {
+        xml.push_str("    <ENTRY");
+        attribute(&mut xml, "TITLE", entry.title.as_deref());
+        attribute(&mut xml, "ARTIST", entry.artist.as_deref());
+        xml.push_str(">\n");
+
+        let (volume, dir, file) = location(&entry.path);
+        let _ = writeln!(
+            xml,
+            "      <LOCATION DIR=\"{}\" FILE=\"{}\" VOLUME=\"{}\"></LOCATION>",
+            escape(&dir),
+            escape(&file),
+            escape(&volume)
+        );
+
+        xml.push_str("      <ALBUM");
+        attribute(
+            &mut xml,
+            "TRACK",
+            entry.track.map(|n| n.to_string()).as_deref(),
+        );
+        attribute(&mut xml, "TITLE", entry.album.as_deref());
+        xml.push_str("></ALBUM>\n");
+
+        xml.push_str("      <INFO");
+        attribute(&mut xml, "KEY", entry.key.as_deref());
+        attribute(
+            &mut xml,
+            "PLAYTIME",
+            entry
+                .seconds
+                .map(|s| (s.round() as u64).to_string())
+                .as_deref(),
+        );
+        attribute(
+            &mut xml,
+            "RELEASE_DATE",
+            entry.year.map(|year| format!("{year}/1/1")).as_deref(),
+        );
+        xml.push_str("></INFO>\n");
+
+        if let Some(bpm) = entry.bpm {
+            let _ = writeln!(
+                xml,
+                "      <TEMPO BPM=\"{bpm:.6}\" BPM_QUALITY=\"100.000000\"></TEMPO>"
+            );
+        }
+        xml.push_str("    </ENTRY>\n");
+    }
+
+    xml.push_str("  </COLLECTION>\n</NML>\n");
+    xml
+}
+
+fn attribute(xml: &mut String, name: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        let _ = write!(xml, " {name}=\"{}\"", escape(value));
+    }
+}
+
+/// Traktor's spelling of a path: the volume (a drive letter on Windows,
+/// empty elsewhere), the directory with every component preceded by `/:`,
+/// and the file name. `/music/a/b.flac` is `("", "/:music/:a/:", "b.flac")`.
+fn location(path: &Path) -> (String, String, String) {
+    let mut volume = String::new();
+    let mut dir = String::new();
+    if let Some(parent) = path.parent() {
+        for component in parent.components() {
+            match component {
+                Component::Prefix(prefix) => {
+                    volume = prefix.as_os_str().to_string_lossy().into_owned()
+                }
+                Component::Normal(part) => {
+                    let _ = write!(dir, "/:{}", part.to_string_lossy());
+                }
+                _ => {}
+            }
+        }
+    }
+    dir.push_str("/:");
+
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    (volume, dir, file.into_owned())
+}
@@ -0,0 +1,372 @@
+use crate::{case, Attribute, Attributes, Result};
+
+/// How `normalize` recases titles and albums.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Case {
+    /// capitalize each word, as the titlecase pipeline step does
+    Title,
+    /// capitalize the first word, and any after a colon, and lower-case the rest
+    Sentence,
+}
+
+/// Where `normalize` credits featured artists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Feat {
+    /// in the title, as "Title (feat. Artist)", taking them out of the artist
+    Title,
+    /// among the artists, taking them out of the title
+    Artist,
+}
+
+/// The rules `normalize` cleans tags by.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Rules {
+    /// Trim whitespace from the ends of values.
+    pub(crate) trim: bool,
+    /// Collapse runs of spaces into one.
+    pub(crate) collapse: bool,
+    /// Compose accented letters written as a letter and a combining mark.
+    pub(crate) nfc: bool,
+    pub(crate) case: Option<Case>,
+    pub(crate) feat: Option<Feat>,
+}
+
+impl Rules {
+    /// Cleans every attribute's values, then credits featured artists and
+    /// recases the title and album as the rules say. Values left empty are
+    /// removed.
+    pub(crate) fn apply(&self, attributes: &mut Attributes) -> Result<()> {
+        for &attribute in Attribute::ALL {
+            let values = attributes.values(attribute);
+            let cleaned: Vec<_> = values.iter().map(|value| self.clean(value)).collect();
+            if cleaned != values {
+                attributes.set_values(attribute, cleaned)?;
+            }
+        }
+
+        match self.feat {
+            Some(Feat::Title) => feat_in_title(attributes),
+            Some(Feat::Artist) => feat_in_artist(attributes),
+            None => (),
+        }
+
+        if let Some(case) = self.case {
+            for value in [&mut attributes.title, &mut attributes.album]
+                .into_iter()
+                .flatten()
+            {
+                *value = recase(value, case);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clean(&self, value: &str) -> String {
+        let mut value = match self.nfc {
+            true => compose(value),
+            false => value.to_string(),
+        };
+        if self.collapse {
+            let mut collapsed = String::with_capacity(value.len());
+            for c in value.chars() {
+                if !(c == ' ' && collapsed.ends_with(' ')) {
+                    collapsed.push(c);
+                }
+            }
+            value = collapsed;
+        }
+        match self.trim {
+            true => value.trim().to_string(),
+            false => value,
+        }
+    }
+}
+
+/// Recases text, leaving the names in a credit of featured artists as
+/// they're spelled.
+fn recase(text: &str, case: Case) -> String {
+    let recase = |text: &str| match case {
+        Case::Title => case::title(text),
+        Case::Sentence => case::sentence(text),
+    };
+    match credit(text) {
+        Some(credit) => {
+            let credited = &text[credit.before.len()..text.len() - credit.after.len()];
+            format!(
+                "{}{credited}{}",
+                recase(credit.before),
+                recase(credit.after)
+            )
+        }
+        None => recase(text),
+    }
+}
+
+/// Words which credit featured artists, matched without regard to case.
+const FEATURING: &[&str] = &["feat.", "feat", "ft.", "featuring"];
+
+/// A credit of featured artists within text.
+struct Credit<'a> {
+    /// What comes before the credit, without the space between
+    before: &'a str,
+    /// The artists featured
+    names: &'a str,
+    /// What follows the credit, if it's closed by a parenthesis or bracket
+    after: &'a str,
+}
+
+impl Credit<'_> {
+    /// The text with the credit taken out.
+    fn without(&self) -> String {
+        format!("{}{}", self.before, self.after)
+    }
+}
+
+/// Finds a credit such as "feat. Artist", "(ft. Artist)", or "[featuring
+/// Artist]" after the first word of text. A credit opened by a parenthesis
+/// or bracket ends at the one closing it; otherwise it runs to the end.
+fn credit(text: &str) -> Option<Credit<'_>> {
+    let mut at = 0;
+    for (idx, word) in text.split(' ').enumerate() {
+        let start = at;
+        at += word.len() + 1;
+        let bare = word.trim_start_matches(['(', '[']);
+        if idx == 0 || !FEATURING.contains(&&*bare.to_lowercase()) {
+            continue;
+        }
+
+        let rest = text.get(at..).unwrap_or_default();
+        let (names, after) = match word.chars().next() {
+            Some(open @ ('(' | '[')) => {
+                let close = if open == '(' { ')' } else { ']' };
+                let end = rest.find(close)?;
+                (&rest[..end], &rest[end + 1..])
+            }
+            _ => (rest, ""),
+        };
+        let names = names.trim();
+        if names.is_empty() {
+            return None;
+        }
+        return Some(Credit {
+            before: text[..start].trim_end(),
+            names,
+            after,
+        });
+    }
+    None
+}
+
+/// Spells the title's credit as "(feat. Artist)", and moves credits out of
+/// the artists into it.
+fn feat_in_title(attributes: &mut Attributes) {
+    let mut featured = Vec::new();
+    for artist in &mut attributes.artist {
+        if let Some(credit) = credit(artist) {
+            featured.push(credit.names.to_string());
+            *artist = credit.without().trim().to_string();
+        }
+    }
+
+    let Some(title) = &mut attributes.title else {
+        return;
+    };
+    let (before, mut names, after) = match credit(title) {
+        Some(credit) => (credit.before, credit.names.to_string(), credit.after),
+        None if !featured.is_empty() => (title.as_str(), String::new(), ""),
+        None => return,
+    };
+    for name in featured {
+        if !names.contains(&name) {
+            if !names.is_empty() {
+                names += ", ";
+            }
+            names += &name;
+        }
+    }
+    *title = format!("{before} (feat. {names}){after}");
+}
+
+/// Moves credits out of the title, and out of artists such as "Artist feat.
+/// Other", into artists of their own. The artists credited together, such
+/// as "A & B", are kept as one.
+fn feat_in_artist(attributes: &mut Attributes) {
+    let mut featured = Vec::new();
+    if let Some(title) = &mut attributes.title {
+        if let Some(credit) = credit(title) {
+            featured.push(credit.names.to_string());
+            *title = credit.without().trim().to_string();
+        }
+    }
+    for artist in &mut attributes.artist {
+        if let Some(credit) = credit(artist) {
+            featured.push(credit.names.to_string());
+            *artist = credit.without().trim().to_string();
+        }
+    }
+    for name in featured {
+        if !attributes.artist.contains(&name) {
+            attributes.artist.push(name);
+        }
+    }
+}
+
+/// Composes each letter followed by a combining mark into the accented
+/// letter, as Unicode's NFC does, for the Latin, Greek, and Cyrillic letters
+/// in [`COMPOSITIONS`]. Text tagged from file names on macOS often holds
+/// letters decomposed this way.
+fn compose(text: &str) -> String {
+    let mut composed = String::with_capacity(text.len());
+    for c in text.chars() {
+        let accented = composed
+            .chars()
+            .next_back()
+            .and_then(|last| accent(last, c));
+        match accented {
+            Some(accented) => {
+                composed.pop();
+                composed.push(accented);
+            }
+            None => composed.push(c),
+        }
+    }
+    composed
+}
+
+fn accent(letter: char, mark: char) -> Option<char> {
+    let (_, pairs) = COMPOSITIONS.iter().find(|(m, _)| *m == mark)?;
+    let mut pairs = pairs.chars();
+    while let (Some(base), Some(accented)) = (pairs.next(), pairs.next()) {
+        if base == letter {
+            return Some(accented);
+        }
+    }
+    None
+}
+
+/// Combining marks, each with the letters it composes with followed by the
+/// letter they compose into. Taken from Unicode's canonical decompositions
+/// of Latin-1 Supplement, Latin Extended-A and -B, Greek, Cyrillic, and
+/// Latin Extended Additional.
+const COMPOSITIONS: &[(char, &str)] = &[
+    // combining grave accent
+    (
+        '\u{300}',
+        concat!(
+            "AÀEÈIÌOÒUÙaàeèiìoòuùÜǛüǜNǸnǹЕЀИЍеѐиѝĒḔēḕ",
+            "ŌṐōṑWẀwẁÂẦâầĂẰăằÊỀêềÔỒôồƠỜơờƯỪưừYỲyỳ",
+        ),
+    ),
+    // combining acute accent
+    (
+        '\u{301}',
+        concat!(
+            "AÁEÉIÍOÓUÚYÝaáeéiíoóuúyýCĆcćLĹlĺNŃnńRŔrŕ",
+            "SŚsśZŹzźÜǗüǘGǴgǵÅǺåǻÆǼæǽØǾøǿΑΆΕΈΗΉΙΊΟΌΥΎ",
+            "ΩΏϊΐαάεέηήιίϋΰοόυύωώГЃКЌгѓкќÇḈçḉĒḖēḗÏḮïḯ",
+            "KḰkḱMḾmḿÕṌõṍŌṒōṓPṔpṕŨṸũṹWẂwẃÂẤâấĂẮăắÊẾêế",
+            "ÔỐôốƠỚơớƯỨưứ",
+        ),
+    ),
+    // combining circumflex accent
+    (
+        '\u{302}',
+        concat!(
+            "AÂEÊIÎOÔUÛaâeêiîoôuûCĈcĉGĜgĝHĤhĥJĴjĵSŜsŝ",
+            "WŴwŵYŶyŷZẐzẑẠẬạậẸỆẹệỌỘọộ",
+        ),
+    ),
+    // combining tilde
+    (
+        '\u{303}',
+        concat!(
+            "AÃNÑOÕaãnñoõIĨiĩUŨuũVṼvṽÂẪâẫĂẴăẵEẼeẽÊỄêễ",
+            "ÔỖôỗƠỠơỡƯỮưữYỸyỹ",
+        ),
+    ),
+    // combining macron
+    (
+        '\u{304}',
+        concat!(
+            "AĀaāEĒeēIĪiīOŌoōUŪuūÜǕüǖÄǞäǟȦǠȧǡÆǢæǣǪǬǫǭ",
+            "ÖȪöȫÕȬõȭȮȰȯȱYȲyȳИӢиӣУӮуӯGḠgḡḶḸḷḹṚṜṛṝ",
+        ),
+    ),
+    // combining breve
+    (
+        '\u{306}',
+        concat!("AĂaăEĔeĕGĞgğIĬiĭOŎoŏUŬuŭУЎИЙийуўЖӁжӂАӐаӑ", "ЕӖеӗȨḜȩḝẠẶạặ",),
+    ),
+    // combining dot above
+    (
+        '\u{307}',
+        concat!(
+            "CĊcċEĖeėGĠgġIİZŻzżAȦaȧOȮoȯBḂbḃDḊdḋFḞfḟHḢ",
+            "hḣMṀmṁNṄnṅPṖpṗRṘrṙSṠsṡŚṤśṥŠṦšṧṢṨṣṩTṪtṫWẆ",
+            "wẇXẊxẋYẎyẏſẛ",
+        ),
+    ),
+    // combining diaeresis
+    (
+        '\u{308}',
+        concat!(
+            "AÄEËIÏOÖUÜaäeëiïoöuüyÿYŸΙΪΥΫιϊυϋЕЁІЇеёії",
+            "АӒаӓӘӚәӛЖӜжӝЗӞзӟИӤиӥОӦоӧӨӪөӫЭӬэӭУӰуӱЧӴчӵ",
+            "ЫӸыӹHḦhḧÕṎõṏŪṺūṻWẄwẅXẌxẍtẗ",
+        ),
+    ),
+    // combining hook above
+    (
+        '\u{309}',
+        concat!("AẢaảÂẨâẩĂẲăẳEẺeẻÊỂêểIỈiỉOỎoỏÔỔôổƠỞơởUỦuủ", "ƯỬưửYỶyỷ",),
+    ),
+    // combining ring above
+    ('\u{30a}', "AÅaåUŮuůwẘyẙ"),
+    // combining double acute accent
+    ('\u{30b}', "OŐoőUŰuűУӲуӳ"),
+    // combining caron
+    (
+        '\u{30c}',
+        concat!(
+            "CČcčDĎdďEĚeěLĽlľNŇnňRŘrřSŠsšTŤtťZŽzžAǍaǎ",
+            "IǏiǐOǑoǒUǓuǔÜǙüǚGǦgǧKǨkǩƷǮʒǯjǰHȞhȟ",
+        ),
+    ),
+    // combining double grave accent
+    ('\u{30f}', "AȀaȁEȄeȅIȈiȉOȌoȍRȐrȑUȔuȕѴѶѵѷ"),
+    // combining inverted breve
+    ('\u{311}', "AȂaȃEȆeȇIȊiȋOȎoȏRȒrȓUȖuȗ"),
+    // combining horn
+    ('\u{31b}', "OƠoơUƯuư"),
+    // combining dot below
+    (
+        '\u{323}',
+        concat!(
+            "BḄbḅDḌdḍHḤhḥKḲkḳLḶlḷMṂmṃNṆnṇRṚrṛSṢsṣTṬtṭ",
+            "VṾvṿWẈwẉZẒzẓAẠaạEẸeẹIỊiịOỌoọƠỢơợUỤuụƯỰưự",
+            "YỴyỵ",
+        ),
+    ),
+    // combining diaeresis below
+    ('\u{324}', "UṲuṳ"),
+    // combining ring below
+    ('\u{325}', "AḀaḁ"),
+    // combining comma below
+    ('\u{326}', "SȘsșTȚtț"),
+    // combining cedilla
+    (
+        '\u{327}',
+        concat!("CÇcçGĢgģKĶkķLĻlļNŅnņRŖrŗSŞsşTŢtţEȨeȩDḐdḑ", "HḨhḩ",),
+    ),
+    // combining ogonek
+    ('\u{328}', "AĄaąEĘeęIĮiįUŲuųOǪoǫ"),
+    // combining circumflex accent below
+    ('\u{32d}', "DḒdḓEḘeḙLḼlḽNṊnṋTṰtṱUṶuṷ"),
+    // combining breve below
+    ('\u{32e}', "HḪhḫ"),
+    // combining tilde below
+    ('\u{330}', "EḚeḛIḬiḭUṴuṵ"),
+    // combining macron below
+    ('\u{331}', "BḆbḇDḎdḏKḴkḵLḺlḻNṈnṉRṞrṟTṮtṯZẔzẕhẖ"),
+];
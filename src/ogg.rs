@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use metaflac::block::VorbisComment;
+
+use crate::{Error, Result};
+
+/// The comment header of an Ogg Vorbis or Opus file: the second packet of
+/// its first stream, which holds the same vorbis comments FLAC does.
+pub(crate) fn read_comments(path: &Path) -> Result<VorbisComment> {
+    let invalid = |reason: &str| Error::Ogg(format!("{}: {reason}", path.display()));
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut serial = None;
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    while packets.len() < 2 {
+        let mut header = [0; 27];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| invalid("ended before its comment header"))?;
+        if &header[..4] != b"OggS" {
+            return Err(invalid("not an Ogg stream"));
+        }
+        let mut segments = vec![0; usize::from(header[26])];
+        reader.read_exact(&mut segments)?;
+        let mut body = vec![0; segments.iter().map(|&len| usize::from(len)).sum()];
+        reader.read_exact(&mut body)?;
+
+        // Pages of other streams, such as a video track, are skipped.
+        let page_serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+
+        // A packet ends with the first segment shorter than 255 bytes.
+        let mut start = 0;
+        for &len in &segments {
+            packet.extend_from_slice(&body[start..start + usize::from(len)]);
+            start += usize::from(len);
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+
+    let comments = &packets[1];
+    let fields = comments
+        .strip_prefix(b"\x03vorbis")
+        .or_else(|| comments.strip_prefix(b"OpusTags"))
+        .ok_or_else(|| invalid("no Vorbis or Opus comment header"))?;
+    parse(fields).ok_or_else(|| invalid("truncated comment header"))
+}
+
+/// Reads the vendor string and `KEY=value` fields, each preceded by its
+/// length. Keys are upper-cased, as FLAC's are read.
+fn parse(mut data: &[u8]) -> Option<VorbisComment> {
+    let vendor_string = String::from_utf8_lossy(field(&mut data)?).into_owned();
+    let count = u32::from_le_bytes(data.get(..4)?.try_into().ok()?);
+    data = &data[4..];
+
+    let mut comments: HashMap<String, Vec<String>> = HashMap::new();
+    for _ in 0..count {
+        let text = String::from_utf8_lossy(field(&mut data)?).into_owned();
+        if let Some((key, value)) = text.split_once('=') {
+            comments
+                .entry(key.to_ascii_uppercase())
+                .or_default()
+                .push(value.into());
+        }
+    }
+    Some(VorbisComment {
+        vendor_string,
+        comments,
+    })
+}
+
+/// Takes a length-prefixed field off the front of `data`.
+fn field<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let field = data.get(4..4 + len)?;
+    *data = &data[4 + len..];
+    Some(field)
+}
@@ -0,0 +1,282 @@
+use std::{
+    cmp::Ordering, collections::HashMap, fmt::Write as _, fs, io::Write, path::Path,
+    time::SystemTime,
+};
+
+use crate::{
+    collate,
+    json::{self, quote},
+    sheet::Delimiter,
+    Error, Result,
+};
+
+/// How tabular output such as `list` is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// comma-separated values, readable by apply
+    #[default]
+    Csv,
+    /// tab-separated values
+    Tsv,
+    /// an array of objects keyed by column name
+    Json,
+    /// columns aligned for reading in a terminal
+    Table,
+}
+
+/// Writes rows of fields under a fixed set of column names.
+pub(crate) trait RecordWriter {
+    fn write_record(&mut self, fields: &[String]) -> Result<()>;
+
+    /// Writes anything held back until every row is known, and flushes.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// A writer for the format, which writes the column names immediately where
+/// the format has a header row. `delimiter` overrides the one csv and tsv
+/// are written with.
+pub(crate) fn writer<'a>(
+    format: Format,
+    delimiter: Option<Delimiter>,
+    columns: Vec<String>,
+    out: impl Write + 'a,
+) -> Result<Box<dyn RecordWriter + 'a>> {
+    Ok(match format {
+        Format::Csv | Format::Tsv => {
+            let delimiter = match (delimiter, format) {
+                (Some(delimiter), _) => delimiter.byte(),
+                (None, Format::Tsv) => b'\t',
+                (None, _) => b',',
+            };
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(out);
+            writer.write_record(&columns)?;
+            Box::new(writer)
+        }
+        Format::Json => Box::new(JsonWriter {
+            out,
+            columns,
+            rows: 0,
+        }),
+        Format::Table => Box::new(TableWriter {
+            out,
+            rows: vec![columns],
+        }),
+    })
+}
+
+impl<W: Write> RecordWriter for csv::Writer<W> {
+    fn write_record(&mut self, fields: &[String]) -> Result<()> {
+        Ok(csv::Writer::write_record(self, fields)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.flush()?)
+    }
+}
+
+/// Streams one object per row, so large listings needn't be held in memory.
+struct JsonWriter<W> {
+    out: W,
+    columns: Vec<String>,
+    rows: usize,
+}
+
+impl<W: Write> RecordWriter for JsonWriter<W> {
+    fn write_record(&mut self, fields: &[String]) -> Result<()> {
+        let mut object = String::new();
+        for (idx, (column, field)) in self.columns.iter().zip(fields).enumerate() {
+            if idx > 0 {
+                object.push_str(", ");
+            }
+            let _ = write!(object, "{}: {}", quote(column), quote(field));
+        }
+
+        let open = if self.rows == 0 { "[" } else { "," };
+        write!(self.out, "{open}\n  {{{object}}}")?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.rows {
+            0 => writeln!(self.out, "[]")?,
+            _ => writeln!(self.out, "\n]")?,
+        }
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Holds every row until the end, since a column is as wide as its widest
+/// field.
+struct TableWriter<W> {
+    out: W,
+    rows: Vec<Vec<String>>,
+}
+
+impl<W: Write> RecordWriter for TableWriter<W> {
+    fn write_record(&mut self, fields: &[String]) -> Result<()> {
+        self.rows.push(fields.to_vec());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let mut widths = Vec::new();
+        for row in &self.rows {
+            widths.resize(widths.len().max(row.len()), 0);
+            for (width, field) in widths.iter_mut().zip(row) {
+                *width = (*width).max(field.chars().count());
+            }
+        }
+
+        for row in &self.rows {
+            let mut line = String::new();
+            for (idx, (field, width)) in row.iter().zip(&widths).enumerate() {
+                if idx > 0 {
+                    line.push_str("  ");
+                }
+                let _ = write!(line, "{field:width$}");
+            }
+            writeln!(self.out, "{}", line.trim_end())?;
+        }
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Which columns of a listing are written, in what order, and what its rows
+/// are sorted by, each picked by name from every column the listing has.
+pub(crate) struct Layout {
+    /// Indexes of the columns written, or `None` for all of them as they are
+    picked: Option<Vec<usize>>,
+    sort: Vec<usize>,
+}
+
+impl Layout {
+    /// Names match columns whatever their case. Fails on a name the listing
+    /// has no column for.
+    pub(crate) fn new(available: &[String], columns: &[String], sort: &[String]) -> Result<Self> {
+        let find = |name: &String| {
+            available
+                .iter()
+                .position(|column| column.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    Error::Listing(format!(
+                        "no {name} column; expected one of {}",
+                        available.join(", ")
+                    ))
+                })
+        };
+        Ok(Layout {
+            picked: match columns.is_empty() {
+                true => None,
+                false => Some(columns.iter().map(find).collect::<Result<_>>()?),
+            },
+            sort: sort.iter().map(find).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Whether rows are sorted, and so have to be held until every one is
+    /// known.
+    pub(crate) fn sorts(&self) -> bool {
+        !self.sort.is_empty()
+    }
+
+    /// The fields of a record, or the names of the columns, to be written.
+    pub(crate) fn pick(&self, record: &[String]) -> Vec<String> {
+        match &self.picked {
+            Some(picked) => picked.iter().map(|&idx| record[idx].clone()).collect(),
+            None => record.to_vec(),
+        }
+    }
+
+    /// Orders two whole records by the sort columns, in turn. Whole numbers,
+    /// such as track numbers, compare by value, and other fields as text.
+    pub(crate) fn compare(&self, a: &[String], b: &[String]) -> Ordering {
+        self.sort
+            .iter()
+            .map(
+                |&idx| match (a[idx].parse::<u64>(), b[idx].parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    _ => collate::compare_text(&a[idx], &b[idx]),
+                },
+            )
+            .fold(Ordering::Equal, Ordering::then)
+    }
+}
+
+/// An earlier listing, for `list --since`: its rows by path, and when it was
+/// written. CSV, TSV, and JSON listings can be read back; which one is told
+/// from the text.
+pub(crate) struct Previous {
+    rows: HashMap<String, HashMap<String, String>>,
+    written: SystemTime,
+}
+
+impl Previous {
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let written = fs::metadata(path)?.modified()?;
+        let invalid = |message: &str| Error::Listing(format!("{}: {message}", path.display()));
+
+        let mut rows = HashMap::new();
+        if text.trim_start().starts_with('[') {
+            let document = json::parse(&text).map_err(|message| invalid(&message))?;
+            for row in document.as_array() {
+                let json::Value::Object(members) = row else {
+                    return Err(invalid("expected an array of objects"));
+                };
+                let fields: HashMap<String, String> = members
+                    .iter()
+                    .filter_map(|(column, value)| Some((column.clone(), value.as_str()?.into())))
+                    .collect();
+                let path = fields
+                    .get("path")
+                    .ok_or_else(|| invalid("no path column"))?;
+                rows.insert(path.clone(), fields);
+            }
+        } else {
+            let header = text.lines().next().unwrap_or_default();
+            let delimiter = match header.contains('\t') {
+                true => b'\t',
+                false => b',',
+            };
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .from_reader(text.as_bytes());
+            let columns = reader.headers()?.clone();
+            if !columns.iter().any(|column| column == "path") {
+                return Err(invalid("no path column"));
+            }
+            for record in reader.records() {
+                let fields: HashMap<_, _> = columns
+                    .iter()
+                    .zip(record?.iter())
+                    .map(|(column, field)| (column.to_string(), field.to_string()))
+                    .collect();
+                rows.insert(fields["path"].clone(), fields);
+            }
+        }
+
+        Ok(Previous { rows, written })
+    }
+
+    /// Whether a row is worth listing again: its path is new, a column both
+    /// listings share has changed, or its file was modified after the earlier
+    /// listing was written.
+    pub(crate) fn changed(
+        &self,
+        columns: &[String],
+        record: &[String],
+        modified: SystemTime,
+    ) -> bool {
+        let Some(previous) = self.rows.get(&record[0]) else {
+            return true;
+        };
+        modified > self.written
+            || columns
+                .iter()
+                .zip(record)
+                .any(|(column, field)| previous.get(column).is_some_and(|old| old != field))
+    }
+}
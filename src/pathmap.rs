@@ -0,0 +1,62 @@
+use std::str::FromStr;
+
+/// Prefix rewrites between two views of the same files, such as a desktop's
+/// paths and a container's mount points. Mapping goes from the first path of
+/// each pair to the second; unmapping goes back.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PathMap {
+    pairs: Vec<PrefixPair>,
+}
+
+/// A `/host=/data` pair.
+#[derive(Clone, Debug)]
+pub(crate) struct PrefixPair {
+    from: String,
+    to: String,
+}
+
+impl FromStr for PrefixPair {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(PrefixPair {
+                from: from.trim_end_matches(['/', '\\']).into(),
+                to: to.trim_end_matches(['/', '\\']).into(),
+            }),
+            _ => Err(format!("expected a mapping such as /host=/data; found {s}")),
+        }
+    }
+}
+
+impl PathMap {
+    pub(crate) fn add(&mut self, pair: PrefixPair) {
+        self.pairs.push(pair);
+    }
+
+    /// Rewrites a path from the outside view to this machine's view.
+    pub(crate) fn map(&self, path: &str) -> String {
+        rewrite(path, self.pairs.iter().map(|pair| (&pair.from, &pair.to)))
+    }
+
+    /// Rewrites a path from this machine's view back to the outside view.
+    pub(crate) fn unmap(&self, path: &str) -> String {
+        rewrite(path, self.pairs.iter().map(|pair| (&pair.to, &pair.from)))
+    }
+}
+
+/// Replaces the longest matching prefix. Prefixes only match whole path
+/// components, so `/data` doesn't rewrite `/database`.
+fn rewrite<'a>(path: &str, pairs: impl Iterator<Item = (&'a String, &'a String)>) -> String {
+    let best = pairs
+        .filter(|(from, _)| {
+            path.strip_prefix(from.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '\\']))
+        })
+        .max_by_key(|(from, _)| from.len());
+
+    match best {
+        Some((from, to)) => format!("{to}{}", &path[from.len()..]),
+        None => path.into(),
+    }
+}
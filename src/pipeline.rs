@@ -0,0 +1,229 @@
+use std::fmt;
+
+use crate::{
+    case,
+    condition::Condition,
+    config::{split_words, Entry},
+    normalize::Rules,
+    Attribute, Attributes, Error, Result,
+};
+
+/// A named sequence of tag transformations defined in config.
+#[derive(Debug, Default)]
+pub(crate) struct Pipeline {
+    pub(crate) when: Option<Condition>,
+    pub(crate) steps: Vec<Step>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Step {
+    /// Replace a field with a literal value.
+    Set(Attribute, String),
+    /// Set a field only when it is currently empty.
+    Fill(Attribute, String),
+    /// Replace every occurrence of a substring within a field.
+    Replace(Attribute, String, String),
+    /// Remove a field.
+    Clear(Attribute),
+    /// Trim surrounding whitespace from the given fields, or from all of them.
+    Trim(Vec<Attribute>),
+    /// Title-case the given fields, or the title and album, keeping the
+    /// spellings in the `[case]` dictionary.
+    TitleCase(Vec<Attribute>),
+    /// Clean every field as `normalize` does by default: trim it, collapse
+    /// runs of spaces, and compose accented letters.
+    Normalize,
+    /// Rename the file by a pattern, as `rename` does, with the tags the
+    /// other steps leave. Only the last step may be a rename.
+    Rename(String),
+}
+
+impl Pipeline {
+    pub(crate) fn from_entries(entries: &[Entry]) -> Result<Self> {
+        let mut pipeline = Pipeline::default();
+
+        for entry in entries {
+            match entry.key.as_str() {
+                "when" => pipeline.when = Some(entry.parse()?),
+                "step" => {
+                    if pipeline.rename().is_some() {
+                        return Err(entry.error("rename must be the last step"));
+                    }
+                    pipeline
+                        .steps
+                        .push(Step::parse(&entry.value).map_err(|e| entry.error(e))?)
+                }
+                key => return Err(entry.error(format!("unknown pipeline key: {key}"))),
+            }
+        }
+
+        Ok(pipeline)
+    }
+
+    /// The pattern files are renamed by once their tags are written, if the
+    /// last step is a rename.
+    pub(crate) fn rename(&self) -> Option<&str> {
+        match self.steps.last() {
+            Some(Step::Rename(pattern)) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    /// Runs each step in order, leaving any rename to the caller. Returns `false` without modifying anything when
+    /// the pipeline's guard doesn't match.
+    pub(crate) fn apply(&self, attributes: &mut Attributes) -> Result<bool> {
+        if let Some(condition) = &self.when {
+            if !condition.matches(attributes) {
+                return Ok(false);
+            }
+        }
+
+        for step in &self.steps {
+            step.apply(attributes)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl Step {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        let words = split_words(s).ok_or_else(|| format!("unterminated quote in step: {s}"))?;
+        let (name, args) = words.split_first().ok_or("empty step")?;
+        let attribute = |idx: usize| -> Result<Attribute, String> {
+            args.get(idx)
+                .ok_or_else(|| format!("{name} requires a field"))?
+                .parse()
+                .map_err(|e: Error| e.to_string())
+        };
+        let arg = |idx: usize| -> Result<String, String> {
+            args.get(idx)
+                .cloned()
+                .ok_or_else(|| format!("{name} is missing an argument"))
+        };
+
+        let (step, arity) = match name.as_str() {
+            "set" => (Step::Set(attribute(0)?, arg(1)?), 2),
+            "fill" => (Step::Fill(attribute(0)?, arg(1)?), 2),
+            "replace" => (Step::Replace(attribute(0)?, arg(1)?, arg(2)?), 3),
+            "clear" => (Step::Clear(attribute(0)?), 1),
+            "trim" => {
+                let attributes = (0..args.len()).map(attribute).collect::<Result<_, _>>()?;
+                (Step::Trim(attributes), args.len())
+            }
+            "titlecase" => {
+                let attributes = (0..args.len()).map(attribute).collect::<Result<_, _>>()?;
+                (Step::TitleCase(attributes), args.len())
+            }
+            "normalize" => (Step::Normalize, 0),
+            "rename" => (Step::Rename(arg(0)?), 1),
+            _ => return Err(format!("unknown step: {name}")),
+        };
+
+        if args.len() > arity {
+            return Err(format!("too many arguments for {name}"));
+        }
+
+        Ok(step)
+    }
+
+    fn apply(&self, attributes: &mut Attributes) -> Result<()> {
+        match self {
+            Step::Set(attribute, value) => attributes.set_values(*attribute, vec![value.clone()]),
+            Step::Fill(attribute, value) => {
+                if attributes.values(*attribute).is_empty() {
+                    attributes.set_values(*attribute, vec![value.clone()])?;
+                }
+                Ok(())
+            }
+            Step::Replace(attribute, from, to) => {
+                let values = attributes
+                    .values(*attribute)
+                    .iter()
+                    .map(|value| value.replace(from.as_str(), to))
+                    .collect();
+                attributes.set_values(*attribute, values)
+            }
+            Step::Clear(attribute) => attributes.set_values(*attribute, Vec::new()),
+            Step::Trim(selected) => {
+                let selected = if selected.is_empty() {
+                    Attribute::ALL
+                } else {
+                    selected.as_slice()
+                };
+                for &attribute in selected {
+                    let values = attributes
+                        .values(attribute)
+                        .iter()
+                        .map(|value| value.trim().to_string())
+                        .collect();
+                    attributes.set_values(attribute, values)?;
+                }
+                Ok(())
+            }
+            Step::TitleCase(selected) => {
+                let selected = if selected.is_empty() {
+                    &[Attribute::Title, Attribute::Album][..]
+                } else {
+                    selected.as_slice()
+                };
+                for &attribute in selected {
+                    let values = attributes
+                        .values(attribute)
+                        .iter()
+                        .map(|value| case::title(value))
+                        .collect();
+                    attributes.set_values(attribute, values)?;
+                }
+                Ok(())
+            }
+            Step::Normalize => Rules {
+                trim: true,
+                collapse: true,
+                nfc: true,
+                case: None,
+                feat: None,
+            }
+            .apply(attributes),
+            Step::Rename(_) => Ok(()),
+        }
+    }
+}
+
+/// Writes a step back out in the form `Step::parse` reads.
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn quote(s: &str) -> String {
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+
+        match self {
+            Step::Set(attribute, value) => write!(f, "set {} {}", attribute.name(), quote(value)),
+            Step::Fill(attribute, value) => write!(f, "fill {} {}", attribute.name(), quote(value)),
+            Step::Replace(attribute, from, to) => write!(
+                f,
+                "replace {} {} {}",
+                attribute.name(),
+                quote(from),
+                quote(to)
+            ),
+            Step::Clear(attribute) => write!(f, "clear {}", attribute.name()),
+            Step::Trim(attributes) => {
+                write!(f, "trim")?;
+                for attribute in attributes {
+                    write!(f, " {}", attribute.name())?;
+                }
+                Ok(())
+            }
+            Step::TitleCase(attributes) => {
+                write!(f, "titlecase")?;
+                for attribute in attributes {
+                    write!(f, " {}", attribute.name())?;
+                }
+                Ok(())
+            }
+            Step::Normalize => write!(f, "normalize"),
+            Step::Rename(pattern) => write!(f, "rename {}", quote(pattern)),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{fmt, str::FromStr};
+
+/// A length of time given as seconds, m:ss, or h:mm:ss.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Length(pub(crate) f64);
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("expected seconds, m:ss, or h:mm:ss; found {s}");
+        let mut seconds = 0.0;
+        for (idx, part) in s.trim().split(':').enumerate() {
+            let part: f64 = part.parse().map_err(|_| invalid())?;
+            if idx > 2 || part < 0.0 || (idx > 0 && part >= 60.0) {
+                return Err(invalid());
+            }
+            seconds = seconds * 60.0 + part;
+        }
+        match seconds > 0.0 {
+            true => Ok(Length(seconds)),
+            false => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.round() as u64;
+        match seconds / 3600 {
+            0 => write!(f, "{}:{:02}", seconds / 60, seconds % 60),
+            hours => write!(f, "{hours}:{:02}:{:02}", seconds / 60 % 60, seconds % 60),
+        }
+    }
+}
+
+/// What `plan` keeps together on one disc.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Split {
+    /// tracks may be split anywhere
+    #[default]
+    Track,
+    /// each directory of tracks stays on one disc, unless it's too long for any
+    Album,
+}
+
+/// Splits a run of lengths, in order, into as few discs as fit within `max`,
+/// then evens the discs out: the longest disc is made as short as it can be
+/// without needing another. Returns the number of lengths on each disc. No
+/// length may be over `max`.
+pub(crate) fn partition(lengths: &[f64], max: f64) -> Vec<usize> {
+    let discs = fill(lengths, max).len();
+
+    // The shortest capacity which still needs no more discs, to the
+    // millisecond.
+    let longest = lengths.iter().copied().fold(0.0, f64::max);
+    let (mut low, mut high) = ((longest * 1000.0).ceil() as u64, (max * 1000.0) as u64);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match fill(lengths, mid as f64 / 1000.0).len() <= discs {
+            true => high = mid,
+            false => low = mid + 1,
+        }
+    }
+    fill(lengths, high as f64 / 1000.0)
+}
+
+/// Fills each disc as far as it will go before starting the next.
+fn fill(lengths: &[f64], capacity: f64) -> Vec<usize> {
+    let mut discs = Vec::new();
+    let (mut count, mut used) = (0, 0.0);
+    for &length in lengths {
+        if count > 0 && used + length > capacity {
+            discs.push(count);
+            (count, used) = (0, 0.0);
+        }
+        count += 1;
+        used += length;
+    }
+    if count > 0 {
+        discs.push(count);
+    }
+    discs
+}
@@ -0,0 +1,139 @@
+use std::{
+    cmp::Ordering,
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{collate, template, Attributes};
+
+/// A track to be listed in a playlist.
+pub(crate) struct Track {
+    /// The absolute path of the file
+    pub(crate) path: PathBuf,
+    pub(crate) attributes: Attributes,
+    pub(crate) seconds: Option<f64>,
+}
+
+impl Track {
+    /// What the track is shown as: its artists and title, or the file's name
+    /// without a title.
+    fn shown(&self) -> String {
+        let title = self.attributes.title.clone().unwrap_or_else(|| {
+            let stem = self.path.file_stem().unwrap_or_default();
+            stem.to_string_lossy().into_owned()
+        });
+        match self.attributes.artist.is_empty() {
+            true => title,
+            false => format!("{} - {title}", self.attributes.artist.join(", ")),
+        }
+    }
+
+    /// The name of the track's album playlist, from its album artist, or
+    /// failing that its first artist, and its album; `None` without an
+    /// album.
+    pub(crate) fn album(&self) -> Option<String> {
+        let album = self.attributes.album.as_ref()?;
+        let artist = self
+            .attributes
+            .album_artist
+            .as_ref()
+            .or(self.attributes.artist.first());
+        Some(template::component(&match artist {
+            Some(artist) => format!("{artist} - {album}"),
+            None => album.clone(),
+        }))
+    }
+}
+
+/// How `playlist` divides files between playlists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum By {
+    /// one playlist of every file
+    #[default]
+    All,
+    /// a playlist for each album, by album artist, or artist, and album
+    Album,
+}
+
+/// What a playlist of every file is ordered by. Ties are broken by disc and
+/// track number, then by path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Sort {
+    #[default]
+    Path,
+    /// artist, then album
+    Artist,
+    Album,
+    Title,
+    /// year, then album
+    Year,
+    /// disc and track number alone
+    Track,
+}
+
+impl Sort {
+    pub(crate) fn compare(self, a: &Track, b: &Track) -> Ordering {
+        let text = |a: Option<&String>, b: Option<&String>| {
+            collate::compare_text(a.map_or("", String::as_str), b.map_or("", String::as_str))
+        };
+        let (x, y) = (&a.attributes, &b.attributes);
+        let album = || text(x.album.as_ref(), y.album.as_ref());
+        let key = match self {
+            Sort::Path => Ordering::Equal,
+            Sort::Artist => text(x.artist.first(), y.artist.first()).then_with(album),
+            Sort::Album => album(),
+            Sort::Title => text(x.title.as_ref(), y.title.as_ref()),
+            Sort::Year => x.year.cmp(&y.year).then_with(album),
+            Sort::Track => Ordering::Equal,
+        };
+        let number = |t: &Track| (t.attributes.disc, t.attributes.track);
+        let number = match self {
+            Sort::Path => Ordering::Equal,
+            _ => number(a).cmp(&number(b)),
+        };
+        key.then(number)
+            .then_with(|| collate::compare_paths(&a.path, &b.path))
+    }
+}
+
+/// Writes tracks as an extended M3U playlist, each under an #EXTINF line of
+/// its length and what it's shown as. With `relative_to`, paths are written
+/// relative to that directory and separated with '/', as portable players
+/// expect; otherwise they're written whole.
+pub(crate) fn render<'a>(
+    tracks: impl IntoIterator<Item = &'a Track>,
+    relative_to: Option<&Path>,
+) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for track in tracks {
+        let path = match relative_to {
+            Some(dir) => relative(&track.path, dir),
+            None => track.path.display().to_string(),
+        };
+        let seconds = track.seconds.map_or(-1.0, f64::round);
+        let _ = write!(m3u, "#EXTINF:{seconds},{}\n{path}\n", track.shown());
+    }
+    m3u
+}
+
+/// The way from a directory to a path, both absolute, as '/'-separated
+/// components, climbing out of the directory with `..` as far as needed.
+fn relative(path: &Path, dir: &Path) -> String {
+    let (mut path_parts, mut dir_parts) = (path.components().peekable(), dir.components());
+    let mut up = 0;
+    loop {
+        match dir_parts.next() {
+            Some(part) if path_parts.peek() == Some(&part) && up == 0 => {
+                path_parts.next();
+            }
+            Some(_) => up += 1,
+            None => break,
+        }
+    }
+    let mut parts: Vec<_> = std::iter::repeat_n("..".to_string(), up).collect();
+    parts.extend(path_parts.filter_map(|part| match part {
+        Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+        _ => None,
+    }));
+    parts.join("/")
+}
@@ -0,0 +1,176 @@
+use std::{
+    fs::{self, OpenOptions, Permissions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Result};
+
+/// Problems with reaching a file, as opposed to problems with its contents.
+#[derive(Debug, thiserror::Error)]
+pub enum AccessError {
+    #[error("{0}: file not found")]
+    NotFound(String),
+
+    #[error("{0}: file is read-only (use --chmod-if-needed to write it anyway)")]
+    ReadOnly(String),
+
+    #[error("{0}: permission denied")]
+    PermissionDenied(String),
+
+    #[error("{0}: file is in use by another program")]
+    InUse(String),
+
+    #[error("{0}: file name is not valid UTF-8, so it can't be written to an attribute sheet")]
+    NotUtf8(String),
+}
+
+/// Checks that every file can be read, reporting each problem before failing.
+pub(crate) fn check_readable<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Result<()> {
+    report(
+        paths
+            .into_iter()
+            .filter_map(|path| unreadable(path.as_ref())),
+    )
+}
+
+/// What keeps a file from being read, if anything.
+pub(crate) fn unreadable(path: &Path) -> Option<Result<AccessError, io::Error>> {
+    OpenOptions::new()
+        .read(true)
+        .open(path)
+        .err()
+        .map(|e| classify(path, e))
+}
+
+/// Checks that every path is valid UTF-8, reporting each one which isn't
+/// before failing.
+pub(crate) fn check_utf8(paths: &[PathBuf]) -> Result<Vec<&str>> {
+    let valid: Vec<&str> = paths.iter().filter_map(|path| path.to_str()).collect();
+    report(
+        paths
+            .iter()
+            .filter(|path| path.to_str().is_none())
+            .map(|path| Ok(AccessError::NotUtf8(path.display().to_string()))),
+    )?;
+    Ok(valid)
+}
+
+/// Checks that every file can be modified in place, reporting each problem
+/// before failing. Read-only files pass when `chmod_if_needed` is set, since
+/// they will be made writable for the duration of the write.
+pub(crate) fn check_writable<P: AsRef<Path>>(
+    paths: impl IntoIterator<Item = P>,
+    chmod_if_needed: bool,
+) -> Result<()> {
+    report(
+        paths
+            .into_iter()
+            .filter_map(|path| unwritable(path.as_ref(), chmod_if_needed)),
+    )
+}
+
+/// What keeps a file from being modified in place, if anything.
+pub(crate) fn unwritable(
+    path: &Path,
+    chmod_if_needed: bool,
+) -> Option<Result<AccessError, io::Error>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return Some(classify(path, e)),
+    };
+
+    if metadata.permissions().readonly() {
+        return (!chmod_if_needed).then(|| Ok(AccessError::ReadOnly(path.display().to_string())));
+    }
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .err()
+        .map(|e| classify(path, e))
+}
+
+fn report(problems: impl Iterator<Item = Result<AccessError, io::Error>>) -> Result<()> {
+    let mut count = 0;
+    for problem in problems {
+        eprintln!("{}", problem?);
+        count += 1;
+    }
+
+    match count {
+        0 => Ok(()),
+        count => Err(Error::Preflight(count)),
+    }
+}
+
+fn classify(path: &Path, e: io::Error) -> Result<AccessError, io::Error> {
+    let path = path.display().to_string();
+
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    if cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33)) {
+        return Ok(AccessError::InUse(path));
+    }
+
+    match e.kind() {
+        io::ErrorKind::NotFound => Ok(AccessError::NotFound(path)),
+        io::ErrorKind::PermissionDenied => Ok(AccessError::PermissionDenied(path)),
+        _ => Err(e),
+    }
+}
+
+/// Makes a read-only file writable, restoring its original permissions when
+/// dropped.
+pub(crate) struct Writable {
+    path: PathBuf,
+    original: Option<Permissions>,
+}
+
+impl Writable {
+    pub(crate) fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let permissions = fs::metadata(&path)?.permissions();
+
+        if !permissions.readonly() {
+            return Ok(Writable {
+                path,
+                original: None,
+            });
+        }
+
+        fs::set_permissions(&path, owner_writable(&permissions))?;
+
+        Ok(Writable {
+            path,
+            original: Some(permissions),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn owner_writable(permissions: &Permissions) -> Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    Permissions::from_mode(permissions.mode() | 0o200)
+}
+
+#[cfg(not(unix))]
+fn owner_writable(permissions: &Permissions) -> Permissions {
+    let mut writable = permissions.clone();
+    #[allow(clippy::permissions_set_readonly_false)]
+    writable.set_readonly(false);
+    writable
+}
+
+impl Drop for Writable {
+    fn drop(&mut self) {
+        if let Some(permissions) = self.original.take() {
+            if let Err(e) = fs::set_permissions(&self.path, permissions) {
+                eprintln!(
+                    "unable to restore permissions on {}: {e}",
+                    self.path.display()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::{self, IsTerminal};
+
+/// A line on stderr counting files as they're done, redrawn in place after
+/// each one with the number done, the total, and the file's name. Drawn
+/// only when stderr is a terminal; the summary at the end is printed either
+/// way, unless quiet.
+pub(crate) struct Progress {
+    total: usize,
+    done: usize,
+    failed: usize,
+    quiet: bool,
+    drawn: bool,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize, quiet: bool) -> Self {
+        Progress {
+            total,
+            done: 0,
+            failed: 0,
+            quiet,
+            drawn: !quiet && io::stderr().is_terminal(),
+        }
+    }
+
+    /// Counts a file as done, and shows it.
+    pub(crate) fn advance(&mut self, name: &str, ok: bool) {
+        self.done += 1;
+        if !ok {
+            self.failed += 1;
+        }
+        if self.drawn {
+            let counter = format!("[{}/{}] ", self.done, self.total);
+            // A line which wraps can't be redrawn in place.
+            let room = width().saturating_sub(counter.len() + 1);
+            let skip = name.chars().count().saturating_sub(room);
+            let name: String = name.chars().skip(skip).collect();
+            eprint!("\r\x1b[K{counter}{name}");
+        }
+    }
+
+    /// Counts files as failed before they were started.
+    pub(crate) fn fail(&mut self, count: usize) {
+        self.done += count;
+        self.failed += count;
+    }
+
+    /// Clears the line, so that other output starts at the left edge; the
+    /// next file done draws it again.
+    pub(crate) fn clear(&self) {
+        if self.drawn {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    /// Clears the line and prints how many files succeeded and failed.
+    pub(crate) fn finish(self) {
+        self.clear();
+        if !self.quiet {
+            eprintln!(
+                "{} succeeded, {} failed",
+                self.done - self.failed,
+                self.failed
+            );
+        }
+    }
+}
+
+/// The terminal's width in columns.
+#[cfg(unix)]
+fn width() -> usize {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ writes a winsize, which size is, and nothing else.
+    let ok = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    match ok && size.ws_col > 0 {
+        true => usize::from(size.ws_col),
+        false => 80,
+    }
+}
+
+#[cfg(not(unix))]
+fn width() -> usize {
+    80
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+
+use metaflac::block::VorbisComment;
+
+use crate::{strip, tags, warning, Attribute, Result};
+
+/// Vorbis comment keys which no operation may overwrite once they hold a
+/// value, configured with `[protect] field = KEY` entries. A trailing `*`
+/// matches any key with that prefix, e.g. `MUSICBRAINZ_*`. MP3 frames are
+/// protected under the same keys; see [`Protection::enforce_id3`].
+///
+/// Empty protected fields may still be filled. The global `--unprotect` flag
+/// lifts all protection for a single invocation.
+#[derive(Debug, Default)]
+pub(crate) struct Protection {
+    patterns: Vec<String>,
+}
+
+impl Protection {
+    pub(crate) fn add(&mut self, pattern: &str) {
+        self.patterns.push(pattern.to_ascii_uppercase());
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.patterns.clear();
+    }
+
+    fn is_protected(&self, key: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => key.starts_with(prefix),
+                None => key == pattern,
+            })
+    }
+
+    /// Restores any protected values in `after` which differ from `before`,
+    /// warning about each one.
+    pub(crate) fn enforce(&self, path: &Path, before: &VorbisComment, after: &mut VorbisComment) {
+        if self.patterns.is_empty() {
+            return;
+        }
+
+        let mut keys: Vec<String> = before
+            .comments
+            .iter()
+            .filter(|(key, values)| !values.is_empty() && self.is_protected(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+
+        for key in keys {
+            let original = &before.comments[&key];
+            if after.get(&key) != Some(original) {
+                warning::emit(
+                    warning::Code::ProtectedField,
+                    format_args!(
+                        "{}: {key} is protected; leaving it unchanged",
+                        path.display()
+                    ),
+                );
+                after.set(key, original.clone());
+            }
+        }
+    }
+
+    /// Restores any protected frames in `after` which differ from `before`,
+    /// warning about each one. Frames are protected under the vorbis key of
+    /// the attribute they hold, TXXX frames under their descriptions too, and
+    /// any frame under its ID.
+    pub(crate) fn enforce_id3(
+        &self,
+        path: &Path,
+        before: &id3::Tag,
+        after: &mut id3::Tag,
+    ) -> Result<()> {
+        if self.patterns.is_empty() {
+            return Ok(());
+        }
+
+        let current = tags::id3_frame_fields(after);
+        for (key, original) in tags::id3_frame_fields(before) {
+            if original.is_empty() || current.get(&key) == Some(&original) {
+                continue;
+            }
+            if frame_keys(&key).iter().any(|name| self.is_protected(name)) {
+                warning::emit(
+                    warning::Code::ProtectedField,
+                    format_args!(
+                        "{}: {key} is protected; leaving it unchanged",
+                        path.display()
+                    ),
+                );
+                tags::set_id3_frame_field(after, &key, &original)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The keys a frame is protected under, in upper case, from its key as
+/// [`tags::id3_frame_fields`] gives it.
+fn frame_keys(key: &str) -> Vec<String> {
+    let (id, described) = key.split_once(':').unwrap_or((key, ""));
+    let mut keys = vec![id.to_string()];
+    match id {
+        "TXXX" => keys.push(described.to_ascii_uppercase()),
+        "COMM" => keys.push("COMMENT".into()),
+        "USLT" => keys.push("LYRICS".into()),
+        _ => {}
+    }
+    for &attribute in Attribute::ALL {
+        let (ids, descriptions) = strip::id3_frames(attribute);
+        let holds = match id {
+            "TXXX" => descriptions
+                .iter()
+                .any(|description| description.eq_ignore_ascii_case(described)),
+            id => ids.contains(&id),
+        };
+        if holds {
+            keys.push(attribute.vorbis_key().into());
+        }
+    }
+    keys
+}
@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use crate::warning;
+
+/// The rates of the 44.1kHz family, from CD, smallest first.
+const CD_RATES: &[u32] = &[11025, 22050, 44100, 88200, 176400, 352800];
+
+/// The rates of the 48kHz family, from video and studio gear, smallest first.
+const STUDIO_RATES: &[u32] = &[
+    8000, 12000, 16000, 24000, 32000, 48000, 96000, 192000, 384000,
+];
+
+/// Which family a sample rate belongs to. Resampling within a family is by
+/// a small whole ratio; across them it's by 147:160, which costs more and
+/// filters more of the audio for nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Family {
+    Cd,
+    Studio,
+}
+
+impl Family {
+    fn of(rate: u32) -> Option<Family> {
+        match rate {
+            _ if rate.is_multiple_of(11025) => Some(Family::Cd),
+            _ if rate.is_multiple_of(4000) => Some(Family::Studio),
+            _ => None,
+        }
+    }
+
+    fn rates(self) -> &'static [u32] {
+        match self {
+            Family::Cd => CD_RATES,
+            Family::Studio => STUDIO_RATES,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Family::Cd => "44.1kHz",
+            Family::Studio => "48kHz",
+        }
+    }
+}
+
+/// The rate to encode audio sampled at `source` at: its own, if it's no
+/// higher than `max` and `supported` takes it (any rate does when it's
+/// empty); otherwise the highest rate up to `max` of the source's family,
+/// and only failing that one of the other family.
+pub(crate) fn pick(source: u32, max: Option<u32>, supported: &[u32]) -> u32 {
+    let max = max.unwrap_or(u32::MAX);
+    let takes = |rate: &u32| *rate <= max && (supported.is_empty() || supported.contains(rate));
+    if takes(&source) {
+        return source;
+    }
+
+    let candidates: Vec<u32> = match supported.is_empty() {
+        true => CD_RATES.iter().chain(STUDIO_RATES).copied().collect(),
+        false => supported.to_vec(),
+    };
+    let family = Family::of(source);
+    let fitting = || candidates.iter().copied().filter(takes);
+    fitting()
+        .filter(|&rate| family.is_some() && Family::of(rate) == family)
+        .max()
+        .or_else(|| fitting().max())
+        .or_else(|| candidates.iter().copied().min())
+        .unwrap_or(source)
+}
+
+/// The rate of the source's family to resample to instead of `target`, when
+/// `target` is of the other family and its counterpart in the source's
+/// would do without upsampling: 48kHz rather than 44.1kHz from 96kHz.
+fn avoidable(source: u32, target: u32) -> Option<u32> {
+    let (family, target_family) = (Family::of(source)?, Family::of(target)?);
+    let counterpart = match target_family {
+        _ if target_family == family => return None,
+        Family::Cd => target / 147 * 160,
+        Family::Studio => target / 160 * 147,
+    };
+    (family.rates().contains(&counterpart) && counterpart <= source).then_some(counterpart)
+}
+
+/// Warns about resampling `path` from `source` to `target` across families
+/// when a rate of its own family would do.
+pub(crate) fn warn_avoidable(path: &Path, source: u32, target: u32) {
+    let Some(instead) = avoidable(source, target) else {
+        return;
+    };
+    let family = Family::of(source).expect("avoidable rates have a family");
+    warning::emit(
+        warning::Code::Resample,
+        format_args!(
+            "{}: resampling {source}Hz to {target}Hz leaves the {} family; {instead}Hz wouldn't",
+            path.display(),
+            family.name()
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_keeps_to_the_source_family() {
+        assert_eq!(pick(96000, Some(48000), &[]), 48000);
+        assert_eq!(pick(176400, Some(48000), &[]), 44100);
+        assert_eq!(pick(88200, None, &[]), 88200);
+        assert_eq!(pick(44100, Some(48000), &[44100, 48000]), 44100);
+    }
+
+    #[test]
+    fn pick_crosses_families_only_when_it_must() {
+        assert_eq!(
+            pick(44100, Some(48000), &[8000, 12000, 16000, 24000, 48000]),
+            48000
+        );
+        assert_eq!(pick(37800, Some(32000), &[]), 32000);
+    }
+
+    #[test]
+    fn avoidable_names_the_counterpart() {
+        assert_eq!(avoidable(96000, 44100), Some(48000));
+        assert_eq!(avoidable(44100, 48000), Some(44100));
+        assert_eq!(avoidable(44100, 22050), None);
+        // 48kHz from 44.1kHz would upsample.
+        assert_eq!(avoidable(44100, 96000), None);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{blocks, digest, pipeline::Step, Attribute, Attributes, Error, Result};
+
+/// Everything needed to reproduce a set of tags on someone else's copy of the
+/// same rip, written as tab-separated records:
+///
+/// ```text
+/// recipe
+/// source <path>   <sha256 of the audio>
+/// attr   <path>   <attribute> <values...>
+/// step   <pipeline step>
+/// ```
+///
+/// Paths are as given when the recipe was created, usually relative to the
+/// album directory. Audio digests cover only the audio data, not the tags, so
+/// they match however the copy is currently tagged.
+#[derive(Debug, Default)]
+pub(crate) struct Recipe {
+    pub(crate) sources: Vec<(String, String)>,
+    pub(crate) attributes: BTreeMap<String, Attributes>,
+    pub(crate) steps: Vec<String>,
+}
+
+impl Recipe {
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        let invalid =
+            |line: usize, message: String| Error::Recipe(format!("line {line}: {message}"));
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .has_headers(false)
+            .from_path(path)?;
+
+        let mut records = reader.records();
+        match records.next().transpose()? {
+            Some(header) if &header[0] == "recipe" => {}
+            _ => return Err(Error::Recipe(format!("{} is not a recipe", path.display()))),
+        }
+
+        let mut recipe = Recipe::default();
+        for (idx, record) in records.enumerate() {
+            let record = record?;
+            let line = idx + 2;
+            let field = |idx: usize| record.get(idx).unwrap_or_default().to_string();
+            match &record[0] {
+                "source" => recipe.sources.push((field(1), field(2))),
+                "attr" => {
+                    let attribute: Attribute = record
+                        .get(2)
+                        .unwrap_or_default()
+                        .parse()
+                        .map_err(|e: Error| invalid(line, e.to_string()))?;
+                    let values = record.iter().skip(3).map(String::from).collect();
+                    recipe
+                        .attributes
+                        .entry(field(1))
+                        .or_default()
+                        .set_values(attribute, values)
+                        .map_err(|e| invalid(line, e.to_string()))?;
+                }
+                "step" => {
+                    let step = Step::parse(&field(1)).map_err(|e| invalid(line, e))?;
+                    if matches!(step, Step::Rename(_)) {
+                        return Err(invalid(line, "a recipe can't rename files".into()));
+                    }
+                    recipe.steps.push(field(1));
+                }
+                kind => return Err(invalid(line, format!("unknown record: {kind}"))),
+            }
+        }
+
+        Ok(recipe)
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .from_path(path)?;
+
+        writer.write_record(["recipe"])?;
+        for (path, digest) in &self.sources {
+            writer.write_record(["source", path, digest])?;
+        }
+        for (path, attributes) in &self.attributes {
+            for &attribute in Attribute::ALL {
+                let values = attributes.values(attribute);
+                if values.is_empty() {
+                    continue;
+                }
+                let mut record = vec!["attr".to_string(), path.clone(), attribute.name().into()];
+                record.extend(values);
+                writer.write_record(&record)?;
+            }
+        }
+        for step in &self.steps {
+            writer.write_record(["step", step])?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// The SHA-256 of a file's audio data: everything after the FLAC metadata
+/// blocks or ID3v2 tag, and before any ID3v1 tag.
+pub(crate) fn audio_digest(path: &Path) -> Result<String> {
+    let blocks = blocks::read(path)?;
+    let mut file = fs::File::open(path)?;
+    let length = file.seek(SeekFrom::End(0))?;
+
+    let start = match blocks.iter().find(|block| block.kind == "AUDIO") {
+        Some(audio) => audio.offset,
+        None => blocks
+            .iter()
+            .find(|block| block.kind.starts_with("ID3v2"))
+            .map_or(0, |tag| tag.size),
+    };
+    let end = match blocks.iter().any(|block| block.kind == "ID3v1") {
+        true => length - 128,
+        false => length,
+    };
+
+    let mut audio = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.take(end.saturating_sub(start))
+        .read_to_end(&mut audio)?;
+    Ok(hex::encode(digest::sha256(&audio)))
+}
@@ -0,0 +1,184 @@
+use std::{fs, io::Read, path::Path, process::Stdio};
+
+use crate::{digest, tools::Tool, Error, Result};
+
+/// What a CD ripper's log says about how an album was ripped. EAC and whipper
+/// logs are understood.
+#[derive(Debug, Default)]
+pub(crate) struct RipLog {
+    pub(crate) ripper: Option<String>,
+    pub(crate) drive: Option<String>,
+    pub(crate) date: Option<String>,
+    pub(crate) tracks: Vec<Track>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Track {
+    pub(crate) number: u32,
+
+    /// The CRC-32 of the track's audio as extracted.
+    pub(crate) copy_crc: Option<u32>,
+
+    /// Whether AccurateRip confirmed the rip, if it was consulted.
+    pub(crate) accurate: Option<bool>,
+}
+
+impl Track {
+    pub(crate) fn accuraterip(&self) -> &'static str {
+        match self.accurate {
+            Some(true) => "verified",
+            Some(false) => "not verified",
+            None => "unknown",
+        }
+    }
+}
+
+impl RipLog {
+    /// Reads a log, which EAC writes as UTF-16 and whipper as UTF-8.
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let utf16 = |bytes: &[u8], unit: fn([u8; 2]) -> u16| {
+            let units: Vec<_> = bytes
+                .chunks_exact(2)
+                .map(|pair| unit([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        };
+        let text = match bytes.get(..2) {
+            Some([0xff, 0xfe]) => utf16(&bytes[2..], u16::from_le_bytes),
+            Some([0xfe, 0xff]) => utf16(&bytes[2..], u16::from_be_bytes),
+            _ => String::from_utf8_lossy(&bytes)
+                .trim_start_matches('\u{feff}')
+                .to_string(),
+        };
+
+        let log = Self::parse(&text);
+        if log.ripper.is_none() || log.tracks.is_empty() {
+            return Err(Error::RipLog(format!(
+                "{} is not an EAC or whipper log",
+                path.display()
+            )));
+        }
+        Ok(log)
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut log = RipLog::default();
+        let mut in_tracks = false;
+
+        for line in text.lines() {
+            let trimmed = line.trim();
+
+            // EAC
+            if let Some(version) = trimmed.strip_prefix("Exact Audio Copy ") {
+                let version = version.split(" from ").next().unwrap_or_default();
+                log.ripper = Some(format!("EAC {version}"));
+            } else if let Some(date) = trimmed.strip_prefix("EAC extraction logfile from ") {
+                log.date = Some(date.into());
+            } else if let Some(drive) = trimmed.strip_prefix("Used drive") {
+                let drive = drive.trim_start_matches([' ', ':']);
+                let drive = drive.split("Adapter:").next().unwrap_or_default();
+                log.drive = Some(drive.split_whitespace().collect::<Vec<_>>().join(" "));
+            } else if let Some(number) = trimmed
+                .strip_prefix("Track")
+                .and_then(|number| number.trim().parse().ok())
+            {
+                log.tracks.push(Track {
+                    number,
+                    copy_crc: None,
+                    accurate: None,
+                });
+            } else if trimmed.starts_with("Accurately ripped") {
+                set_accurate(&mut log, true);
+            } else if trimmed.starts_with("Cannot be verified as accurate") {
+                set_accurate(&mut log, false);
+            }
+            // whipper
+            else if let Some(ripper) = trimmed.strip_prefix("Log created by:") {
+                let ripper = ripper.split(" (").next().unwrap_or_default();
+                log.ripper = Some(ripper.trim().into());
+            } else if let Some(date) = trimmed.strip_prefix("Log creation date:") {
+                log.date = Some(date.trim().into());
+            } else if let (Some(drive), None) = (trimmed.strip_prefix("Drive:"), &log.drive) {
+                log.drive = Some(drive.trim().into());
+            } else if trimmed == "Tracks:" {
+                in_tracks = true;
+            } else if let Some(number) = trimmed
+                .strip_suffix(':')
+                .filter(|_| in_tracks)
+                .and_then(|number| number.parse().ok())
+            {
+                log.tracks.push(Track {
+                    number,
+                    copy_crc: None,
+                    accurate: None,
+                });
+            } else if let Some(result) = trimmed.strip_prefix("Result:") {
+                set_accurate(&mut log, result.trim() == "Found, exact match");
+            }
+            // Both
+            else if let Some(crc) = trimmed.strip_prefix("Copy CRC") {
+                let crc = crc.trim_start_matches([' ', ':']).trim();
+                if let (Some(track), Ok(crc)) =
+                    (log.tracks.last_mut(), u32::from_str_radix(crc, 16))
+                {
+                    track.copy_crc = Some(crc);
+                }
+            } else if trimmed.contains("not present in AccurateRip database") {
+                set_accurate(&mut log, false);
+            }
+        }
+
+        log
+    }
+
+    /// Whether AccurateRip confirmed the whole album.
+    pub(crate) fn accuraterip(&self) -> &'static str {
+        let verified = self
+            .tracks
+            .iter()
+            .filter(|track| track.accurate == Some(true))
+            .count();
+        match verified {
+            _ if self.tracks.iter().all(|track| track.accurate.is_none()) => "unknown",
+            n if n == self.tracks.len() => "verified",
+            0 => "not verified",
+            _ => "partially verified",
+        }
+    }
+}
+
+/// A track is accurate if any AccurateRip version matched it.
+fn set_accurate(log: &mut RipLog, accurate: bool) {
+    if let Some(track) = log.tracks.last_mut() {
+        track.accurate = Some(track.accurate == Some(true) || accurate);
+    }
+}
+
+/// The CRC-32 of a file's audio as 16-bit PCM, which is what rippers log as
+/// the copy CRC.
+pub(crate) fn copy_crc(path: &Path) -> Result<u32> {
+    let mut child = Tool::Ffmpeg
+        .command()
+        .args(["-loglevel", "error", "-i"])
+        .arg(path)
+        .args(["-f", "s16le", "-acodec", "pcm_s16le", "-"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|_| Tool::Ffmpeg.missing())?;
+
+    let stdout = child.stdout.as_mut().expect("stdout is piped");
+    let mut crc = 0;
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        match stdout.read(&mut buf)? {
+            0 => break,
+            n => crc = digest::crc32(crc, &buf[..n]),
+        }
+    }
+
+    match child.wait()?.success() {
+        true => Ok(crc),
+        false => Err(Error::FfmpegFailed(path.display().to_string())),
+    }
+}
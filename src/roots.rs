@@ -0,0 +1,126 @@
+use std::path::{self, Path, PathBuf};
+
+use crate::{config::Entry, throttle::Rate, Error, Result};
+
+/// A named library location, defined in config:
+///
+/// ```text
+/// [root nas]
+/// path = /mnt/nas/music
+/// throttle = 5MB/s
+/// ```
+///
+/// Files under a root can be named `nas:Prince/Purple Rain/01.flac` wherever
+/// a path is expected, and are written that way in attribute sheets.
+#[derive(Clone, Debug)]
+pub(crate) struct Root {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+
+    /// `throttle`: how fast scans may read files under this root
+    pub(crate) throttle: Option<Rate>,
+}
+
+impl Root {
+    pub(crate) fn from_entries(name: String, entries: &[Entry]) -> Result<Self> {
+        let mut path = None;
+        let mut throttle = None;
+
+        for entry in entries {
+            match entry.key.as_str() {
+                "path" => path = Some(PathBuf::from(&entry.value)),
+                "throttle" => throttle = Some(entry.parse()?),
+                key => return Err(entry.error(format!("unknown root key: {key}"))),
+            }
+        }
+
+        let line = entries.first().map_or(0, |entry| entry.line);
+        if name.contains([':', '/', '\\']) {
+            return Err(Error::Config {
+                line,
+                message: format!("invalid root name: {name}"),
+            });
+        }
+        let path = path.ok_or_else(|| Error::Config {
+            line,
+            message: format!("root {name} has no path"),
+        })?;
+
+        Ok(Root {
+            name,
+            path,
+            throttle,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Roots {
+    pub(crate) roots: Vec<Root>,
+}
+
+impl Roots {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Root> {
+        self.roots.iter().find(|root| root.name == name)
+    }
+
+    /// Expands a root-qualified path such as `nas:Prince/01.flac`. Returns
+    /// `None` for anything else, including paths whose prefix isn't the name
+    /// of a configured root.
+    pub(crate) fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let (name, rest) = path.split_once(':')?;
+        let root = self.get(name)?;
+        Some(
+            rest.split('/')
+                .fold(root.path.clone(), |path, part| path.join(part)),
+        )
+    }
+
+    /// The root-qualified form of a path under one of the roots, with `/`
+    /// separators. The deepest matching root wins.
+    pub(crate) fn qualify(&self, path: &Path) -> Result<Option<String>> {
+        let path = path::absolute(path)?;
+        let mut best: Option<(&Root, &Path)> = None;
+        for root in &self.roots {
+            let Ok(rest) = path.strip_prefix(path::absolute(&root.path)?) else {
+                continue;
+            };
+            if best.is_none_or(|(_, best)| rest.as_os_str().len() < best.as_os_str().len()) {
+                best = Some((root, rest));
+            }
+        }
+
+        Ok(best.map(|(root, rest)| {
+            let parts: Vec<_> = rest
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            format!("{}:{}", root.name, parts.join("/"))
+        }))
+    }
+}
+
+/// A command-line argument naming a file, which may be root-qualified.
+pub(crate) trait FileArgument {
+    fn resolve(&mut self, roots: &Roots);
+}
+
+impl FileArgument for String {
+    fn resolve(&mut self, roots: &Roots) {
+        if let Some(path) = roots.resolve(self) {
+            *self = path.to_string_lossy().into_owned();
+        }
+    }
+}
+
+impl FileArgument for PathBuf {
+    fn resolve(&mut self, roots: &Roots) {
+        if let Some(path) = self.to_str().and_then(|path| roots.resolve(path)) {
+            *self = path;
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{Error, Result};
+
+/// How many files an operation may change before it has to be confirmed,
+/// unless configured otherwise.
+pub(crate) const DEFAULT_LIMIT: usize = 1000;
+
+static LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_LIMIT);
+static YES: AtomicBool = AtomicBool::new(false);
+
+/// Sets the limit, from `[safety] max-files`, where 0 means none, and
+/// whether `--yes` was given, answering every question in advance.
+pub(crate) fn configure(limit: usize, yes: bool) {
+    LIMIT.store(limit, Ordering::Relaxed);
+    YES.store(yes, Ordering::Relaxed);
+}
+
+/// Asks a yes-or-no question on the terminal, unless `--yes` answered it.
+pub(crate) fn confirm(question: &str) -> Result<bool> {
+    if YES.load(Ordering::Relaxed) {
+        return Ok(true);
+    }
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Checks an operation about to change more files than the limit allows,
+/// such as one given a glob at the library root by mistake: shows how many
+/// files it would change, and where, then asks to go ahead. Fails when the
+/// answer is no, or when there's no terminal to ask at and `--yes` wasn't
+/// given.
+pub(crate) fn check<P: AsRef<Path>>(
+    operation: &str,
+    paths: impl IntoIterator<Item = P>,
+) -> Result<()> {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let paths: Vec<P> = paths.into_iter().collect();
+    if limit == 0 || paths.len() <= limit || YES.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let dirs: BTreeSet<&Path> = paths
+        .iter()
+        .map(|path| path.as_ref().parent().unwrap_or(Path::new("")))
+        .collect();
+    let mut common: Option<PathBuf> = None;
+    for dir in &dirs {
+        common = Some(match common {
+            None => dir.to_path_buf(),
+            Some(common) => common
+                .components()
+                .zip(dir.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    let common = common.unwrap_or_default();
+    let under = match common.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => &common,
+    };
+    let directories = match dirs.len() {
+        1 => "directory",
+        _ => "directories",
+    };
+    eprintln!(
+        "{operation} would change {} files in {} {directories} under {}, more than the limit of \
+         {limit}",
+        paths.len(),
+        dirs.len(),
+        under.display()
+    );
+
+    let declined = Error::NotConfirmed {
+        operation: operation.into(),
+        count: paths.len(),
+    };
+    if !io::stdin().is_terminal() {
+        return Err(declined);
+    }
+    match confirm("go ahead?")? {
+        true => Ok(()),
+        false => Err(declined),
+    }
+}
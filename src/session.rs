@@ -0,0 +1,202 @@
+use std::{fs, io, path::PathBuf};
+
+use crate::{config, Error, Result};
+
+/// Albums proposed by lookups, waiting to be reviewed and applied. A session
+/// is a directory in the data directory holding an attribute sheet per
+/// album, `<number>.csv`, and an index of them as tab-separated records:
+///
+/// ```text
+/// <number> <status> <release id> <description>
+/// ```
+///
+/// The sheets are ordinary attribute sheets, so they can be corrected by
+/// hand before they're approved.
+pub(crate) struct Session {
+    pub(crate) name: String,
+    pub(crate) albums: Vec<Album>,
+}
+
+pub(crate) struct Album {
+    pub(crate) number: usize,
+    pub(crate) status: Status,
+    pub(crate) release: String,
+    pub(crate) description: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Status {
+    Pending,
+    Approved,
+    Rejected,
+    Applied,
+}
+
+impl Status {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Approved => "approved",
+            Status::Rejected => "rejected",
+            Status::Applied => "applied",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            Status::Pending,
+            Status::Approved,
+            Status::Rejected,
+            Status::Applied,
+        ]
+        .into_iter()
+        .find(|status| status.name() == name)
+    }
+}
+
+impl Session {
+    /// Loads a session, or starts an empty one if there's none by that name.
+    pub(crate) fn open(name: &str) -> Result<Self> {
+        if !dir(name)?.join("albums.tsv").exists() {
+            return Ok(Session {
+                name: name.into(),
+                albums: Vec::new(),
+            });
+        }
+        Session::load(name)
+    }
+
+    pub(crate) fn load(name: &str) -> Result<Self> {
+        let index = dir(name)?.join("albums.tsv");
+        let mut reader = match csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_path(&index)
+        {
+            Ok(reader) => reader,
+            Err(e) if matches!(e.kind(), csv::ErrorKind::Io(e) if e.kind() == io::ErrorKind::NotFound) => {
+                return Err(Error::Session(format!("no session named {name}")))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let invalid = || Error::Session(format!("{} is not a session index", index.display()));
+        let mut albums = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let (Some(number), Some(status), Some(release), Some(description)) =
+                (record.get(0), record.get(1), record.get(2), record.get(3))
+            else {
+                return Err(invalid());
+            };
+            albums.push(Album {
+                number: number.parse().map_err(|_| invalid())?,
+                status: Status::from_name(status).ok_or_else(invalid)?,
+                release: release.into(),
+                description: description.into(),
+            });
+        }
+
+        Ok(Session {
+            name: name.into(),
+            albums,
+        })
+    }
+
+    /// The names of every saved session.
+    pub(crate) fn names() -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let dir = base()?;
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.path().join("albums.tsv").exists() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Adds an album's sheet to the session, pending review, and saves it.
+    /// Returns the album's number.
+    pub(crate) fn add(&mut self, release: &str, description: &str, sheet: &[u8]) -> Result<usize> {
+        let number = self
+            .albums
+            .iter()
+            .map(|album| album.number)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let dir = dir(&self.name)?;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(format!("{number}.csv")), sheet)?;
+        self.albums.push(Album {
+            number,
+            status: Status::Pending,
+            release: release.into(),
+            description: description.into(),
+        });
+        self.save()?;
+        Ok(number)
+    }
+
+    pub(crate) fn album(&self, number: usize) -> Result<&Album> {
+        self.albums
+            .iter()
+            .find(|album| album.number == number)
+            .ok_or_else(|| Error::Session(format!("no album {number} in session {}", self.name)))
+    }
+
+    /// Where an album's proposed sheet is kept.
+    pub(crate) fn sheet(&self, number: usize) -> Result<PathBuf> {
+        Ok(dir(&self.name)?.join(format!("{number}.csv")))
+    }
+
+    pub(crate) fn set_status(&mut self, number: usize, status: Status) -> Result<()> {
+        self.album(number)?;
+        for album in &mut self.albums {
+            if album.number == number {
+                album.status = status;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn save(&self) -> Result<()> {
+        let dir = dir(&self.name)?;
+        fs::create_dir_all(&dir)?;
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_path(dir.join("albums.tsv"))?;
+        for album in &self.albums {
+            writer.write_record([
+                album.number.to_string().as_str(),
+                album.status.name(),
+                &album.release,
+                &album.description,
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn base() -> Result<PathBuf> {
+    Ok(config::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unable to locate data directory"))?
+        .join("sessions"))
+}
+
+/// Where a named session is stored. Names are plain file names, so that a
+/// session can't be written outside the session directory.
+fn dir(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(Error::Session(format!("invalid session name: {name}")));
+    }
+    Ok(base()?.join(name))
+}
@@ -0,0 +1,370 @@
+use std::sync::OnceLock;
+
+use crate::{json, warning, Attribute, Attributes, Error, FileAttributes, Result};
+
+static SEPARATOR: OnceLock<String> = OnceLock::new();
+
+/// Records what separates the values of an attribute in a cell, from
+/// `--multi-sep` or config; a comma without either. Only the first call has
+/// any effect.
+pub(crate) fn configure(separator: Option<String>) {
+    let _ = SEPARATOR.set(separator.unwrap_or_else(|| ",".into()));
+}
+
+/// What separates the values of an attribute which takes more than one, such
+/// as artist, in a cell.
+pub(crate) fn separator() -> &'static str {
+    SEPARATOR.get().map_or(",", String::as_str)
+}
+
+/// A cell's values, split on [`separator`] and trimmed, leaving out any
+/// empty ones.
+pub(crate) fn split(text: &str) -> Vec<String> {
+    text.split(separator())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// The character separating the fields of a sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Delimiter {
+    Tab,
+    Comma,
+    /// as spreadsheets write csv where the comma is the decimal separator
+    Semicolon,
+}
+
+impl Delimiter {
+    pub(crate) fn byte(self) -> u8 {
+        match self {
+            Delimiter::Tab => b'\t',
+            Delimiter::Comma => b',',
+            Delimiter::Semicolon => b';',
+        }
+    }
+
+    /// The delimiter a sheet's header row uses: tabs if it has any, or
+    /// whichever of semicolons and commas it has more of.
+    fn detect(text: &str) -> Self {
+        let header = text.lines().next().unwrap_or_default();
+        let count = |c| header.matches(c).count();
+        match (header.contains('\t'), count(';') > count(',')) {
+            (true, _) => Delimiter::Tab,
+            (false, true) => Delimiter::Semicolon,
+            (false, false) => Delimiter::Comma,
+        }
+    }
+}
+
+/// A column of an attribute sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    Path,
+    /// The fingerprint `list` writes of each file's tags
+    Fingerprint,
+    Attribute(Attribute),
+    /// A raw field, named by the header, read with `--all-fields`
+    Field,
+}
+
+impl Column {
+    /// Maps a header onto the canonical schema, accepting common alternative
+    /// spellings. Matching ignores case, spaces, and punctuation, so
+    /// "Track Number", "track_number", and "TRACKNUMBER" are all `track`, and
+    /// "Album Artist" is `albumartist`.
+    fn from_header(header: &str) -> Option<Self> {
+        if header.trim() == "#" {
+            return Some(Column::Attribute(Attribute::Track));
+        }
+
+        let normalized: String = header
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect();
+
+        let column = match normalized.as_str() {
+            "path" | "file" | "filename" | "filepath" | "location" => Column::Path,
+            "fingerprint" => Column::Fingerprint,
+            "album" | "albumtitle" | "release" => Column::Attribute(Attribute::Album),
+            "artist" | "artists" | "performer" | "trackartist" => {
+                Column::Attribute(Attribute::Artist)
+            }
+            "title" | "tracktitle" | "name" | "song" | "songtitle" => {
+                Column::Attribute(Attribute::Title)
+            }
+            "track" | "tracknumber" | "trackno" | "tracknum" | "no" | "number" => {
+                Column::Attribute(Attribute::Track)
+            }
+            "year" | "date" | "releasedate" | "releaseyear" => Column::Attribute(Attribute::Year),
+            "language" | "languages" | "lang" => Column::Attribute(Attribute::Language),
+            "work" | "worktitle" => Column::Attribute(Attribute::Work),
+            "movementname" | "movementtitle" => Column::Attribute(Attribute::MovementName),
+            "movement" | "movementnumber" | "movementno" => Column::Attribute(Attribute::Movement),
+            "movementtotal" | "movementcount" | "totalmovements" => {
+                Column::Attribute(Attribute::MovementTotal)
+            }
+            "showmovement" => Column::Attribute(Attribute::ShowMovement),
+            "grouping" | "contentgroup" | "group" => Column::Attribute(Attribute::Grouping),
+            "media" | "mediatype" | "source" | "format" => Column::Attribute(Attribute::Media),
+            "releasecountry" | "country" => Column::Attribute(Attribute::ReleaseCountry),
+            "genre" | "genres" | "style" => Column::Attribute(Attribute::Genre),
+            "albumartist" | "albumartists" | "band" => Column::Attribute(Attribute::AlbumArtist),
+            "disc" | "discnumber" | "discno" | "disk" | "disknumber" => {
+                Column::Attribute(Attribute::Disc)
+            }
+            "composer" | "composers" => Column::Attribute(Attribute::Composer),
+            "comment" | "comments" | "description" | "notes" => {
+                Column::Attribute(Attribute::Comment)
+            }
+            _ => return None,
+        };
+
+        Some(column)
+    }
+}
+
+/// Whether a sheet reads a header as one of its own columns: the path, the
+/// fingerprint, or an attribute.
+pub(crate) fn is_column(header: &str) -> bool {
+    Column::from_header(header).is_some()
+}
+
+/// Whether a header can name a raw field: vorbis comment keys are printable
+/// ASCII, without `=`.
+fn is_field(header: &str) -> bool {
+    let header = header.trim();
+    !header.is_empty()
+        && header
+            .bytes()
+            .all(|b| (0x20..=0x7D).contains(&b) && b != b'=')
+}
+
+/// An attribute sheet's rows, and the attributes it has columns for.
+pub(crate) struct Sheet {
+    pub(crate) attributes: Vec<Attribute>,
+    pub(crate) rows: Vec<FileAttributes>,
+}
+
+/// Reads an attribute sheet. Headers may use any recognized synonym, in any
+/// order, and only the path column is required; multiple artists, languages,
+/// genres, or composers are separated by [`separator`] within a cell. Fields are
+/// separated by `delimiter`, or by whatever the header row uses. A sheet
+/// starting with `[` is read as JSON instead, an array of objects keyed by
+/// column, as `list --format json` writes.
+///
+/// Columns which name no attribute are refused, unless `all_fields` is set:
+/// then they're read as raw fields, named by their headers in upper case.
+///
+/// Malformed rows are reported with their line number, column, and value. With
+/// `skip_invalid`, they are reported as warnings and left out instead.
+pub(crate) fn read(
+    text: &str,
+    delimiter: Option<Delimiter>,
+    skip_invalid: bool,
+    all_fields: bool,
+) -> Result<Sheet> {
+    let (headers, records) = match text.trim_start().starts_with('[') {
+        true => json_records(text)?,
+        false => {
+            let delimiter = delimiter.unwrap_or_else(|| Delimiter::detect(text));
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter.byte())
+                .from_reader(text.as_bytes());
+            (reader.headers()?.clone(), reader.into_records().collect())
+        }
+    };
+    let columns: Vec<Option<Column>> = headers
+        .iter()
+        .map(|header| match Column::from_header(header) {
+            None if all_fields && is_field(header) => Some(Column::Field),
+            column => column,
+        })
+        .collect();
+
+    let unrecognized: Vec<String> = headers
+        .iter()
+        .zip(&columns)
+        .filter(|(_, column)| column.is_none())
+        .map(|(header, _)| header.to_string())
+        .collect();
+    if !unrecognized.is_empty() {
+        return Err(Error::UnrecognizedColumns(unrecognized));
+    }
+
+    let columns: Vec<Column> = columns.into_iter().flatten().collect();
+    if !columns.contains(&Column::Path) {
+        return Err(Error::MissingPathColumn);
+    }
+
+    let attributes: Vec<Attribute> = columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Attribute(attribute) => Some(*attribute),
+            _ => None,
+        })
+        .collect();
+    let artist_missing = !attributes.contains(&Attribute::Artist);
+    let mut rows = Vec::new();
+    for record in records {
+        match read_row(record, &headers, &columns) {
+            Ok(row) => rows.push(FileAttributes {
+                artist_missing,
+                ..row
+            }),
+            Err(e) if skip_invalid => {
+                warning::emit(warning::Code::SkippedRow, format_args!("skipping {e}"))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(Sheet { attributes, rows })
+}
+
+/// The header and records of a JSON sheet, as if it were csv: the columns
+/// are every key of every object, in the order they're first seen. Numbers
+/// and flags are taken as their text, arrays as their elements separated by
+/// [`separator`], and a key an object lacks as an empty field. Each object is
+/// counted as a line of its own.
+fn json_records(text: &str) -> Result<(csv::StringRecord, Vec<csv::Result<csv::StringRecord>>)> {
+    let invalid = |message: &str| Error::InvalidRow {
+        line: 0,
+        message: format!("invalid JSON sheet: {message}"),
+    };
+    let document = json::parse(text).map_err(|message| invalid(&message))?;
+
+    let field = |value: &json::Value| match value {
+        json::Value::Null => String::new(),
+        json::Value::Bool(flag) => u8::from(*flag).to_string(),
+        json::Value::Number(number) => number.to_string(),
+        json::Value::String(text) => text.clone(),
+        json::Value::Array(elements) => {
+            let texts: Vec<_> = elements.iter().filter_map(json::Value::as_str).collect();
+            texts.join(separator())
+        }
+        json::Value::Object(_) => String::new(),
+    };
+
+    let mut headers: Vec<&str> = Vec::new();
+    for row in document.as_array() {
+        let json::Value::Object(members) = row else {
+            return Err(invalid("expected an array of objects"));
+        };
+        for (key, _) in members {
+            if !headers.contains(&key.as_str()) {
+                headers.push(key);
+            }
+        }
+    }
+
+    let records = document
+        .as_array()
+        .iter()
+        .enumerate()
+        .map(|(idx, row)| {
+            let mut record: csv::StringRecord = headers
+                .iter()
+                .map(|header| row.get(header).map(field).unwrap_or_default())
+                .collect();
+            let mut position = csv::Position::new();
+            position.set_line(idx as u64 + 1);
+            record.set_position(Some(position));
+            Ok(record)
+        })
+        .collect();
+    Ok((headers.into_iter().collect(), records))
+}
+
+fn read_row(
+    record: csv::Result<csv::StringRecord>,
+    headers: &csv::StringRecord,
+    columns: &[Column],
+) -> Result<FileAttributes> {
+    let record = record.map_err(|e| match e.kind() {
+        csv::ErrorKind::UnequalLengths {
+            pos: Some(pos),
+            expected_len,
+            len,
+        } => Error::InvalidRow {
+            line: pos.line(),
+            message: format!("expected {expected_len} fields, found {len}"),
+        },
+        _ => e.into(),
+    })?;
+    let line = record.position().map_or(0, |pos| pos.line());
+
+    let mut path = String::new();
+    let mut fingerprint = None;
+    let mut attributes = Attributes::default();
+    let mut fields = Vec::new();
+
+    for ((column, header), value) in columns.iter().zip(headers).zip(record.iter()) {
+        let result = match column {
+            Column::Path => {
+                path = value.into();
+                Ok(())
+            }
+            Column::Fingerprint => {
+                fingerprint = Some(value.to_string()).filter(|value| !value.is_empty());
+                Ok(())
+            }
+            Column::Attribute(attribute) => attributes.set_text(*attribute, value),
+            Column::Field => {
+                fields.push((header.trim().to_ascii_uppercase(), value.to_string()));
+                Ok(())
+            }
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(Error::InvalidValue { attribute, .. }) => {
+                return Err(Error::InvalidCell {
+                    line,
+                    column: header.into(),
+                    value: value.into(),
+                    expected: attribute.expected(),
+                })
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if path.is_empty() {
+        return Err(Error::InvalidRow {
+            line,
+            message: "path is empty".into(),
+        });
+    }
+
+    let mut row = attributes.with_path(path);
+    row.fingerprint = fingerprint;
+    row.fields = fields;
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_map_onto_the_schema() {
+        let column = |attribute| Some(Column::Attribute(attribute));
+        assert_eq!(
+            Column::from_header("Track Number"),
+            column(Attribute::Track)
+        );
+        assert_eq!(Column::from_header("DATE"), column(Attribute::Year));
+        assert_eq!(
+            Column::from_header("Album Artist"),
+            column(Attribute::AlbumArtist)
+        );
+        assert_eq!(
+            Column::from_header("album_artist"),
+            column(Attribute::AlbumArtist)
+        );
+        assert_eq!(Column::from_header("File"), Some(Column::Path));
+        assert_eq!(Column::from_header("Mood"), None);
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use id3::Content;
+
+use crate::{art, config, ignore::Ignore, manifest, roots::Roots, Error, Result};
+
+/// Tag values keyed by field: vorbis comment keys for FLAC, frame ids for
+/// MP3. Pictures are recorded under `PICTURE` by type, size, and checksum.
+pub(crate) type Tags = BTreeMap<String, Vec<String>>;
+
+/// The full tag state of every FLAC and MP3 file under a directory, saved by
+/// name in the data directory as tab-separated records:
+///
+/// ```text
+/// snapshot <root>
+/// <path>   <key> <values...>
+/// ```
+///
+/// Paths are relative to the root. Every file gets a record with just its
+/// path, so that untagged files still show up.
+pub(crate) struct Snapshot {
+    pub(crate) root: String,
+    pub(crate) files: BTreeMap<String, Tags>,
+}
+
+/// One difference between two snapshots.
+pub(crate) enum Difference {
+    Added(String),
+    Removed(String),
+    Changed {
+        path: String,
+        key: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+}
+
+impl Snapshot {
+    /// Reads the tags of every FLAC and MP3 file under `root`.
+    pub(crate) fn capture(root: &Path, ignore: &Ignore) -> Result<Self> {
+        let paths = ignore.walk(root, &["flac", "mp3"])?;
+
+        let mut files = BTreeMap::new();
+        for path in paths {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let key = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.insert(key, read_tags(&path)?);
+        }
+
+        Ok(Snapshot {
+            root: root.to_string_lossy().into_owned(),
+            files,
+        })
+    }
+
+    /// Reads the tags of every file under every configured root, keyed by
+    /// root-qualified path. The snapshot's root is left empty, which stands
+    /// for all of them.
+    pub(crate) fn capture_roots(roots: &Roots, ignore: &Ignore) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for root in &roots.roots {
+            let snapshot = Snapshot::capture(&root.path, ignore)?;
+            for (path, tags) in snapshot.files {
+                files.insert(format!("{}:{path}", root.name), tags);
+            }
+        }
+
+        Ok(Snapshot {
+            root: String::new(),
+            files,
+        })
+    }
+
+    pub(crate) fn save(&self, name: &str) -> Result<PathBuf> {
+        let path = path(name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .from_path(&path)?;
+        writer.write_record(["snapshot", &self.root])?;
+        for (file, tags) in &self.files {
+            writer.write_record([file])?;
+            for (key, values) in tags {
+                let mut record = vec![file.as_str(), key];
+                record.extend(values.iter().map(String::as_str));
+                writer.write_record(&record)?;
+            }
+        }
+        writer.flush()?;
+
+        Ok(path)
+    }
+
+    pub(crate) fn load(name: &str) -> Result<Self> {
+        let path = path(name)?;
+        let mut reader = match csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .has_headers(false)
+            .from_path(&path)
+        {
+            Ok(reader) => reader,
+            Err(e) if matches!(e.kind(), csv::ErrorKind::Io(e) if e.kind() == io::ErrorKind::NotFound) => {
+                return Err(Error::Snapshot(format!("no snapshot named {name}")))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records = reader.records();
+        let root = match records.next().transpose()? {
+            Some(header) if &header[0] == "snapshot" => header.get(1).unwrap_or_default().into(),
+            _ => {
+                return Err(Error::Snapshot(format!(
+                    "{} is not a snapshot",
+                    path.display()
+                )))
+            }
+        };
+
+        let mut files: BTreeMap<String, Tags> = BTreeMap::new();
+        for record in records {
+            let record = record?;
+            let tags = files.entry(record[0].to_string()).or_default();
+            if let Some(key) = record.get(1) {
+                tags.insert(
+                    key.into(),
+                    record.iter().skip(2).map(String::from).collect(),
+                );
+            }
+        }
+
+        Ok(Snapshot { root, files })
+    }
+}
+
+/// Every field change between two snapshots, in path and key order.
+pub(crate) fn diff(before: &Snapshot, after: &Snapshot) -> Vec<Difference> {
+    let paths: BTreeSet<&String> = before.files.keys().chain(after.files.keys()).collect();
+    let empty = Vec::new();
+
+    let mut differences = Vec::new();
+    for path in paths {
+        let (old, new) = match (before.files.get(path), after.files.get(path)) {
+            (Some(old), Some(new)) => (old, new),
+            (Some(_), None) => {
+                differences.push(Difference::Removed(path.clone()));
+                continue;
+            }
+            (None, _) => {
+                differences.push(Difference::Added(path.clone()));
+                continue;
+            }
+        };
+
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for key in keys {
+            let old = old.get(key).unwrap_or(&empty);
+            let new = new.get(key).unwrap_or(&empty);
+            if old != new {
+                differences.push(Difference::Changed {
+                    path: path.clone(),
+                    key: key.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+    }
+
+    differences
+}
+
+/// Where a named snapshot is stored. Names are plain file names, so that a
+/// snapshot can't be written outside the snapshot directory.
+fn path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(Error::Snapshot(format!("invalid snapshot name: {name}")));
+    }
+
+    let dir = config::data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unable to locate data directory"))?
+        .join("snapshots");
+    Ok(dir.join(format!("{name}.tsv")))
+}
+
+pub(crate) fn read_tags(path: &Path) -> Result<Tags> {
+    let mut tags = Tags::new();
+
+    if path.extension() == Some(OsStr::new("flac")) {
+        let flac = metaflac::Tag::read_from_path(path)?;
+        if let Some(comment) = flac.vorbis_comments() {
+            for (key, values) in &comment.comments {
+                tags.insert(key.clone(), values.clone());
+            }
+        }
+    } else {
+        let tag = match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for frame in tag.frames() {
+            let (key, value) = match frame.content() {
+                Content::Picture(_) => continue,
+                Content::Text(text) => (frame.id().to_string(), text.clone()),
+                Content::ExtendedText(text) => (
+                    format!("{}:{}", frame.id(), text.description),
+                    text.value.clone(),
+                ),
+                Content::Comment(comment) => (
+                    format!("{}:{}", frame.id(), comment.description),
+                    comment.text.clone(),
+                ),
+                content => (frame.id().to_string(), content.to_string()),
+            };
+            tags.entry(key).or_default().push(value);
+        }
+    }
+
+    for picture in art::read(path)? {
+        let summary = format!(
+            "{:?} {}x{} {} bytes {:016x}",
+            picture.kind,
+            picture.width,
+            picture.height,
+            picture.data.len(),
+            checksum(&picture.data)
+        );
+        tags.entry("PICTURE".into()).or_default().push(summary);
+    }
+
+    Ok(tags)
+}
+
+/// FNV-1a, which is stable across Rust releases where the standard hasher
+/// isn't promised to be.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Writes a snapshot as one small text file per album directory (or per
+/// track) under `out`, in a form meant to be committed to version control:
+///
+/// ```text
+/// [01 - Let's Go Crazy.flac]
+/// ALBUM=Purple Rain
+/// ARTIST=Prince
+/// ```
+///
+/// Files, keys, and values are written in a fixed order so that re-exporting
+/// unchanged tags changes nothing. Exported files which no longer correspond
+/// to anything in the snapshot are removed. Returns the files written.
+pub(crate) fn export(snapshot: &Snapshot, out: &Path, per_track: bool) -> Result<Vec<PathBuf>> {
+    let mut documents: BTreeMap<PathBuf, String> = BTreeMap::new();
+    for (file, tags) in &snapshot.files {
+        let (dir, name) = file.rsplit_once('/').unwrap_or(("", file));
+        let (target, header) = match per_track {
+            true => (out.join(format!("{file}.tags")), None),
+            false => (out.join(dir).join("album.tags"), Some(name)),
+        };
+
+        let document = documents.entry(target).or_default();
+        if let Some(name) = header {
+            if !document.is_empty() {
+                document.push('\n');
+            }
+            document.push_str(&format!("[{}]\n", escape(name)));
+        }
+        for (key, values) in tags {
+            for value in values {
+                document.push_str(&format!("{key}={}\n", escape(value)));
+            }
+        }
+    }
+
+    let mut stale = Vec::new();
+    if out.exists() {
+        walk_exported(out, &mut stale)?;
+    }
+    for path in stale {
+        if !documents.contains_key(&path) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    for (path, document) in &documents {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::read_to_string(path).ok().as_deref() != Some(document) {
+            manifest::write(path, document)?;
+        }
+    }
+
+    Ok(documents.into_keys().collect())
+}
+
+/// Escapes backslashes and line breaks so every value fits on one line.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn walk_exported(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_exported(&path, paths)?;
+        } else if path.extension() == Some(OsStr::new("tags")) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{manifest, Result};
+
+/// A copy of a file made beside the place it's bound for, to be written and
+/// then moved over its target in one step, so that a run which is
+/// interrupted leaves either the old file or the new one, never part of
+/// each. The copy is removed if it's dropped before it's committed.
+pub(crate) struct Staged {
+    temporary: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl Staged {
+    /// Copies `source`, permissions and all, to a hidden file in the
+    /// directory of `target`; renaming within a directory can't leave the
+    /// file system, so the move is atomic.
+    pub(crate) fn copy(source: &Path, target: &Path) -> Result<Self> {
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let temporary = target.with_file_name(format!(".{name}.flacdat-{}.tmp", process::id()));
+        let staged = Staged {
+            temporary,
+            target: target.to_owned(),
+            committed: false,
+        };
+        fs::copy(source, &staged.temporary)?;
+        Ok(staged)
+    }
+
+    /// The copy, to be written to.
+    pub(crate) fn path(&self) -> &Path {
+        &self.temporary
+    }
+
+    /// Where the copy is bound for.
+    pub(crate) fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Flushes the copy to disk and renames it over the target, then flushes
+    /// the directory, so that the rename itself survives a crash.
+    pub(crate) fn commit(mut self) -> Result<()> {
+        File::open(&self.temporary)?.sync_all()?;
+        fs::rename(&self.temporary, &self.target)?;
+        self.committed = true;
+        manifest::moved(&self.temporary, &self.target);
+        sync_dir(&self.target);
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temporary);
+        }
+    }
+}
+
+/// Flushes a file's directory entry. Only some platforms can open a
+/// directory to do so, and a failure leaves the file written all the same.
+#[cfg(unix)]
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) {}
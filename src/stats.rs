@@ -0,0 +1,208 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{collate, json::Value, Attribute, Attributes};
+
+/// How `stats` writes its summary.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// sections of aligned counts, for reading in a terminal
+    #[default]
+    Table,
+    /// a single JSON object
+    Json,
+}
+
+/// Attributes a file is counted as missing when it has no value for them.
+const KEY_FIELDS: &[Attribute] = &[
+    Attribute::Album,
+    Attribute::Artist,
+    Attribute::Title,
+    Attribute::Track,
+    Attribute::Year,
+    Attribute::Genre,
+    Attribute::AlbumArtist,
+];
+
+/// A section of the summary: counts of files by name.
+struct Section<'a> {
+    name: &'static str,
+    entries: Vec<(&'a str, u64)>,
+    /// How many entries there are in all, shown or not
+    total: usize,
+}
+
+/// Counts of files gathered across a library.
+#[derive(Default)]
+pub(crate) struct Stats {
+    files: u64,
+    seconds: f64,
+    /// Files whose length couldn't be told
+    unknown_length: u64,
+    artists: HashMap<String, u64>,
+    albums: HashMap<String, u64>,
+    genres: HashMap<String, u64>,
+    years: HashMap<String, u64>,
+    /// Files without each of `KEY_FIELDS`
+    missing: [u64; KEY_FIELDS.len()],
+}
+
+impl Stats {
+    /// Counts a file, with its length in seconds if known. A file with
+    /// several artists or genres counts toward each; albums are told apart
+    /// by their album artist, or failing that their first artist.
+    pub(crate) fn add(&mut self, attributes: &Attributes, seconds: Option<f64>) {
+        self.files += 1;
+        match seconds {
+            Some(seconds) => self.seconds += seconds,
+            None => self.unknown_length += 1,
+        }
+        for artist in &attributes.artist {
+            *self.artists.entry(artist.clone()).or_default() += 1;
+        }
+        for genre in &attributes.genre {
+            *self.genres.entry(genre.clone()).or_default() += 1;
+        }
+        if let Some(album) = &attributes.album {
+            let artist = attributes
+                .album_artist
+                .as_ref()
+                .or(attributes.artist.first());
+            let album = match artist {
+                Some(artist) => format!("{album} ({artist})"),
+                None => album.clone(),
+            };
+            *self.albums.entry(album).or_default() += 1;
+        }
+        if let Some(year) = attributes.year {
+            *self.years.entry(year.to_string()).or_default() += 1;
+        }
+        for (missing, &attribute) in self.missing.iter_mut().zip(KEY_FIELDS) {
+            if attributes.values(attribute).is_empty() {
+                *missing += 1;
+            }
+        }
+    }
+
+    /// The sections of counts. Years are in order, every one of them; the
+    /// rest are ranked.
+    fn sections(&self, top: usize) -> Vec<Section<'_>> {
+        let mut years: Vec<(&str, u64)> = self
+            .years
+            .iter()
+            .map(|(year, &count)| (year.as_str(), count))
+            .collect();
+        years.sort();
+        let missing: Vec<(&str, u64)> = KEY_FIELDS
+            .iter()
+            .zip(self.missing)
+            .filter(|&(_, count)| count > 0)
+            .map(|(attribute, count)| (attribute.name(), count))
+            .collect();
+        let missing_total = missing.len();
+
+        let [artists, albums, genres] =
+            [&self.artists, &self.albums, &self.genres].map(|counts| ranked(counts, top));
+        let section = |name, (entries, total)| Section {
+            name,
+            entries,
+            total,
+        };
+        vec![
+            section("artists", artists),
+            section("albums", albums),
+            section("genres", genres),
+            section("years", (years, self.years.len())),
+            section("missing", (missing, missing_total)),
+        ]
+    }
+
+    /// The summary as text: the totals, then each section's counts under its
+    /// name, with how many entries were left out.
+    pub(crate) fn table(&self, top: usize) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "files     {}", self.files);
+        let _ = write!(text, "duration  {}", duration(self.seconds));
+        if self.unknown_length > 0 {
+            let _ = write!(text, " (unknown for {} files)", self.unknown_length);
+        }
+        text.push('\n');
+
+        for Section {
+            name,
+            entries,
+            total,
+        } in self.sections(top)
+        {
+            // Every attribute is listed as missing, even from no files.
+            let entries: Vec<_> = entries
+                .into_iter()
+                .filter(|&(_, count)| count > 0)
+                .collect();
+            if entries.is_empty() {
+                continue;
+            }
+            let _ = writeln!(text, "\n{name}");
+            let width = entries
+                .iter()
+                .map(|(_, count)| count.to_string().len())
+                .max()
+                .unwrap_or_default();
+            for (entry, count) in &entries {
+                let _ = writeln!(text, "  {count:>width$}  {entry}");
+            }
+            if total > entries.len() {
+                let _ = writeln!(text, "  ... and {} more", total - entries.len());
+            }
+        }
+        text
+    }
+
+    /// The summary as a JSON object: the totals, and each section as an
+    /// array of objects giving a name and a count of files.
+    pub(crate) fn json(&self, top: usize) -> Value {
+        let mut members = vec![
+            ("files".into(), self.files.into()),
+            ("seconds".into(), self.seconds.into()),
+            ("unknown_length".into(), self.unknown_length.into()),
+        ];
+        for Section { name, entries, .. } in self.sections(top) {
+            let entries = entries
+                .into_iter()
+                .map(|(entry, count)| {
+                    Value::Object(vec![
+                        ("name".into(), entry.into()),
+                        ("files".into(), count.into()),
+                    ])
+                })
+                .collect();
+            members.push((name.into(), Value::Array(entries)));
+        }
+        Value::Object(members)
+    }
+}
+
+/// Counts with the most files first, and no more than `top` of them unless
+/// `top` is 0, along with how many there are in all.
+fn ranked(counts: &HashMap<String, u64>, top: usize) -> (Vec<(&str, u64)>, usize) {
+    let mut entries: Vec<(&str, u64)> = counts
+        .iter()
+        .map(|(name, &count)| (name.as_str(), count))
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| collate::compare_text(a.0, b.0)));
+    let total = entries.len();
+    if top > 0 {
+        entries.truncate(top);
+    }
+    (entries, total)
+}
+
+/// A length of time as h:mm:ss.
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,178 @@
+use std::{fs, path::Path, str::FromStr};
+
+use id3::{Content, TagLike};
+use metaflac::block::VorbisComment;
+
+use crate::{
+    manifest::{self, Action},
+    verify, Attribute, Error, Result,
+};
+
+/// A field to remove: one of flacdat's attributes, which may be stored under
+/// several keys or frames, or a raw vorbis key, ID3 frame ID, or TXXX
+/// description.
+#[derive(Clone, Debug)]
+pub(crate) enum Field {
+    Attribute(Attribute),
+    Raw(String),
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "" => Err("expected a field name".into()),
+            s => Ok(s
+                .parse()
+                .map(Field::Attribute)
+                .unwrap_or_else(|_| Field::Raw(s.into()))),
+        }
+    }
+}
+
+/// Every vorbis key an attribute is read from.
+fn vorbis_keys(attribute: Attribute) -> Vec<&'static str> {
+    match attribute {
+        Attribute::Year => vec!["DATE", "YEAR", "ORIGINALDATE"],
+        Attribute::AlbumArtist => vec!["ALBUMARTIST", "ALBUM ARTIST"],
+        Attribute::Comment => vec!["COMMENT", "DESCRIPTION"],
+        attribute => vec![attribute.vorbis_key()],
+    }
+}
+
+/// The ID3 frames and TXXX descriptions an attribute is read from.
+pub(crate) fn id3_frames(
+    attribute: Attribute,
+) -> (&'static [&'static str], &'static [&'static str]) {
+    match attribute {
+        Attribute::Album => (&["TALB"], &[]),
+        Attribute::Artist => (&["TPE1"], &[]),
+        Attribute::Title => (&["TIT2"], &[]),
+        Attribute::Track => (&["TRCK"], &[]),
+        Attribute::Year => (&["TYER", "TDRC"], &[]),
+        Attribute::Language => (&["TLAN"], &[]),
+        Attribute::Work => (&[], &["WORK"]),
+        Attribute::MovementName => (&["MVNM"], &[]),
+        // MVIN holds both the number and the total.
+        Attribute::Movement | Attribute::MovementTotal => (&["MVIN"], &[]),
+        Attribute::ShowMovement => (&[], &["SHOWMOVEMENT"]),
+        Attribute::Grouping => (&["GRP1", "TIT1"], &[]),
+        Attribute::Media => (&["TMED"], &[]),
+        Attribute::ReleaseCountry => (
+            &[],
+            &["RELEASECOUNTRY", "MusicBrainz Album Release Country"],
+        ),
+        Attribute::Genre => (&["TCON"], &[]),
+        Attribute::AlbumArtist => (&["TPE2"], &[]),
+        Attribute::Disc => (&["TPOS"], &[]),
+        Attribute::Composer => (&["TCOM"], &[]),
+        // Handled separately: only the comment without a description.
+        Attribute::Comment => (&[], &[]),
+    }
+}
+
+/// Removes fields from a vorbis comment, or every field when `fields` is
+/// `None`. Keys match without regard to case.
+pub(crate) fn strip_vorbis(comment: &mut VorbisComment, fields: Option<&[Field]>) {
+    let Some(fields) = fields else {
+        comment.comments.clear();
+        return;
+    };
+
+    for field in fields {
+        let keys = match field {
+            Field::Attribute(attribute) => vorbis_keys(*attribute)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            Field::Raw(key) => vec![key.to_ascii_uppercase()],
+        };
+        comment
+            .comments
+            .retain(|key, _| !keys.contains(&key.to_ascii_uppercase()));
+    }
+}
+
+/// Removes fields from an ID3 tag, or every frame when `fields` is `None`.
+/// A raw field of four capitals or digits is a frame ID; anything else is the
+/// description of a TXXX frame.
+pub(crate) fn strip_id3(tag: &mut id3::Tag, fields: Option<&[Field]>) {
+    let Some(fields) = fields else {
+        let ids: Vec<_> = tag.frames().map(|frame| frame.id().to_string()).collect();
+        for id in ids {
+            tag.remove(id);
+        }
+        return;
+    };
+
+    for field in fields {
+        match field {
+            Field::Attribute(Attribute::Comment) => tag.remove_comment(Some(""), None),
+            Field::Attribute(attribute) => {
+                let (ids, descriptions) = id3_frames(*attribute);
+                for id in ids {
+                    tag.remove(*id);
+                }
+                for description in descriptions {
+                    remove_extended_text(tag, description);
+                }
+            }
+            Field::Raw(raw) if is_frame_id(raw) => {
+                tag.remove(raw.as_str());
+            }
+            Field::Raw(description) => remove_extended_text(tag, description),
+        }
+    }
+}
+
+fn is_frame_id(s: &str) -> bool {
+    s.len() == 4
+        && s.chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+fn remove_extended_text(tag: &mut id3::Tag, description: &str) {
+    let kept: Vec<_> = tag
+        .frames()
+        .filter(|frame| match frame.content() {
+            Content::ExtendedText(text) => !text.description.eq_ignore_ascii_case(description),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    if kept.len() == tag.frames().count() {
+        return;
+    }
+
+    tag.remove("TXXX");
+    for frame in kept.into_iter().filter(|frame| frame.id() == "TXXX") {
+        tag.add_frame(frame);
+    }
+}
+
+/// Rewrites a FLAC file without padding. metaflac keeps the metadata's
+/// original size when writing in place, filling the difference with padding,
+/// so dropping it means writing the file afresh.
+pub(crate) fn write_without_padding(flac: &mut metaflac::Tag, path: &Path) -> Result<()> {
+    flac.remove_blocks(metaflac::BlockType::Padding);
+
+    let data = fs::read(path)?;
+    let audio = verify::flac_audio(&data)
+        .ok_or_else(|| Error::UnsupportedFileTye(path.display().to_string()))?;
+    let mut rewritten = Vec::with_capacity(data.len());
+    flac.write_to(&mut rewritten)?;
+    rewritten.extend_from_slice(audio);
+
+    let temporary = path.with_extension("flac.tmp");
+    fs::write(&temporary, &rewritten)?;
+    let renamed = fs::metadata(path)
+        .and_then(|metadata| fs::set_permissions(&temporary, metadata.permissions()))
+        .and_then(|_| fs::rename(&temporary, path));
+    if let Err(e) = renamed {
+        let _ = fs::remove_file(&temporary);
+        return Err(e.into());
+    }
+    manifest::record(Action::Modified, path);
+    Ok(())
+}
@@ -0,0 +1,726 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use id3::TagLike;
+use metaflac::block::VorbisComment;
+
+use crate::{
+    capability, condition::Condition, dj, lock::FileLock, ogg, preflight, protect::Protection,
+    sheet, staging::Staged, verify, writeback, Attribute, Attributes, Error, Result,
+};
+
+/// How attributes are written to vorbis comments.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// The digits track numbers are zero-padded to
+    pub track_width: usize,
+    /// Whether a year is written to YEAR as well as DATE. YEAR is kept in step
+    /// whenever a file already has it, either way.
+    pub year_tag: bool,
+}
+
+/// Writes attributes to a FLAC or MP3 file in place, as `apply` does with a
+/// row of an attribute sheet. Attributes without a value are left alone,
+/// except the artist, which is cleared.
+pub fn apply(path: impl AsRef<Path>, attr: &Attributes, options: &Options) -> Result<()> {
+    let path = path.as_ref();
+    let _lock = FileLock::acquire(path)?;
+    match path.extension().and_then(OsStr::to_str) {
+        Some("flac") => {
+            let mut flac = metaflac::Tag::read_from_path(path)?;
+            write_attributes(flac.vorbis_comments_mut(), attr, options);
+            verify::write_flac(&mut flac, path)
+        }
+        Some("mp3") => {
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(e.into()),
+            };
+            write_id3(&mut tag, attr);
+            verify::write_id3(&tag, path)
+        }
+        _ => Err(Error::UnsupportedFileTye(path.display().to_string())),
+    }
+}
+
+/// How `apply` writes a row of an attribute sheet to its file.
+pub(crate) struct Apply<'a> {
+    pub(crate) options: Options,
+    /// The directory to write tagged copies to, or none to tag files in place
+    pub(crate) output: Option<&'a Path>,
+    /// Only work out the changes, without writing them
+    pub(crate) dry_run: bool,
+    /// Replace copies already in `output`
+    pub(crate) overwrite: bool,
+    /// The suffix of the name a file tagged in place is kept under first
+    pub(crate) backup: Option<&'a str>,
+    /// Skip files whose tags don't match
+    pub(crate) when: Option<&'a Condition>,
+    pub(crate) unrepresentable: capability::Policy,
+    /// Refuse to rewrite an MP3 whose DJ software data wouldn't survive
+    pub(crate) preserve_dj_data: bool,
+    pub(crate) protection: &'a Protection,
+}
+
+/// What applying one row did, for the caller to log and show.
+#[derive(Default)]
+pub(crate) struct Applied {
+    /// Files created, with the output copy or backup
+    pub(crate) created: Vec<PathBuf>,
+    /// The file's new tags, to be linted
+    pub(crate) lint: Option<(Attributes, Option<VorbisComment>)>,
+    /// The changes a dry run would make, as printed
+    pub(crate) changes: String,
+    /// The file written and its comments before and after
+    pub(crate) vorbis: Option<(PathBuf, VorbisComment, VorbisComment)>,
+    /// The MP3 written and its tag before and after
+    pub(crate) id3: Option<(PathBuf, id3::Tag, id3::Tag)>,
+}
+
+impl Apply<'_> {
+    /// Applies one row of the sheet, its attributes and raw fields, to its
+    /// file, filling in `applied` as it goes.
+    pub(crate) fn row(
+        &self,
+        path: &str,
+        (attr, fields): (&Attributes, &[(String, String)]),
+        applied: &mut Applied,
+    ) -> Result<()> {
+        // Held from reading the tags through writing them, so that another
+        // process can't change them in between.
+        let target = match self.output {
+            None => PathBuf::from(path),
+            Some(output) => output.join(Path::new(path).file_name().unwrap_or_default()),
+        };
+        let _lock = (!self.dry_run)
+            .then(|| FileLock::acquire(&target))
+            .transpose()?;
+
+        if Path::new(path).extension() == Some(OsStr::new("mp3")) {
+            return self.mp3_row(path, (attr, fields), &target, applied);
+        }
+
+        let mut flac = metaflac::Tag::read_from_path(path)?;
+        let comment = flac.vorbis_comments_mut();
+        let before = comment.clone();
+
+        if let Some(condition) = self.when {
+            if !condition.matches(&Attributes::from_vorbis(comment)) {
+                return Ok(());
+            }
+        }
+
+        write_attributes(comment, attr, &self.options);
+        write_vorbis_fields(comment, fields);
+        self.protection.enforce(Path::new(path), &before, comment);
+        let after = comment.clone();
+        applied.lint = Some((Attributes::from_vorbis(&after), Some(after.clone())));
+
+        if self.dry_run {
+            applied.changes = format_changes(
+                path,
+                &Attributes::from_vorbis(&before),
+                &Attributes::from_vorbis(&after),
+            );
+            for (key, _) in fields {
+                let (old, new) = (vorbis_field(&before, key), vorbis_field(&after, key));
+                applied.changes += &format_field_change(path, key, &old, &new);
+            }
+            return Ok(());
+        }
+
+        let staged = self.stage(Path::new(path), &target, &mut applied.created)?;
+        {
+            // A copy inherits the source's permissions, which may be read-only.
+            let _writable = preflight::Writable::new(staged.path())?;
+            verify::write_flac(&mut flac, staged.path())?;
+        }
+        let target = staged.target().to_owned();
+        staged.commit()?;
+        applied.vorbis = Some((target, before, after));
+        Ok(())
+    }
+
+    /// Writes the sheet's album, artist, title, track, year, genre, album
+    /// artist, disc, composer, and comment to an MP3's ID3 tag, and its raw
+    /// fields to TXXX frames. Frames the sheet doesn't touch, including DJ
+    /// software data, are carried over as they were.
+    fn mp3_row(
+        &self,
+        path: &str,
+        (attr, fields): (&Attributes, &[(String, String)]),
+        target: &Path,
+        applied: &mut Applied,
+    ) -> Result<()> {
+        let mut tag = match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let before = tag.clone();
+
+        if let Some(condition) = self.when {
+            if !condition.matches(&Attributes::from_id3(&tag)) {
+                return Ok(());
+            }
+        }
+
+        write_id3(&mut tag, attr);
+        if self.unrepresentable == capability::Policy::Txxx {
+            write_id3_extended(&mut tag, attr);
+        }
+        write_id3_fields(&mut tag, fields);
+        self.protection
+            .enforce_id3(Path::new(path), &before, &mut tag)?;
+        applied.lint = Some((Attributes::from_id3(&tag), None));
+        if self.preserve_dj_data {
+            dj::verify(Path::new(path), &before, &tag)?;
+        }
+        if self.dry_run {
+            applied.changes = format_changes(
+                path,
+                &Attributes::from_id3(&before),
+                &Attributes::from_id3(&tag),
+            );
+            for (key, _) in fields {
+                let (old, new) = (id3_field(&before, key), id3_field(&tag, key));
+                applied.changes += &format_field_change(path, key, &old, &new);
+            }
+            return Ok(());
+        }
+
+        let staged = self.stage(Path::new(path), target, &mut applied.created)?;
+        {
+            let _writable = preflight::Writable::new(staged.path())?;
+            verify::write_id3(&tag, staged.path())?;
+        }
+        let target = staged.target().to_owned();
+        staged.commit()?;
+        applied.id3 = Some((target, before, tag));
+        Ok(())
+    }
+
+    /// Readies the file a source's new tags are written to: a copy of the
+    /// source, staged to be moved to `target` in the output directory or,
+    /// tagging in place, over the source itself, after linking the original
+    /// aside when there's a backup suffix. The caller holds the lock on
+    /// `target`.
+    fn stage(&self, path: &Path, target: &Path, created: &mut Vec<PathBuf>) -> Result<Staged> {
+        if self.output.is_some() {
+            match (target.exists(), self.overwrite) {
+                (true, false) => {
+                    return Err(Error::IO(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        "writing metadata would overwrite existing file (--overwrite replaces it)",
+                    )))
+                }
+                (true, true) => {}
+                (false, _) => created.push(target.to_owned()),
+            }
+            return Staged::copy(path, target);
+        }
+
+        if let Some(suffix) = self.backup {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(suffix);
+            let backup = PathBuf::from(backup);
+            if backup.exists() {
+                return Err(Error::IO(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("backup would overwrite {}", backup.display()),
+                )));
+            }
+            // The source is only ever replaced, never written, so a link
+            // keeps the original as well as a copy would.
+            if fs::hard_link(path, &backup).is_err() {
+                fs::copy(path, &backup)?;
+            }
+            created.push(backup);
+        }
+        Staged::copy(path, path)
+    }
+}
+
+/// A line for each attribute whose values differ between two sets of tags.
+pub(crate) fn format_changes(path: &str, before: &Attributes, after: &Attributes) -> String {
+    let mut changes = String::new();
+    for &attribute in Attribute::ALL {
+        let (old, new) = (before.values(attribute), after.values(attribute));
+        if old != new {
+            changes += &format!(
+                "{path}\t{}\t{} -> {}\n",
+                attribute.name(),
+                old.join(";"),
+                new.join(";")
+            );
+        }
+    }
+    changes
+}
+
+/// The line format_changes gives for a raw field, if its values differ.
+pub(crate) fn format_field_change(path: &str, key: &str, old: &[String], new: &[String]) -> String {
+    match old == new {
+        true => String::new(),
+        false => format!("{path}\t{key}\t{} -> {}\n", old.join(";"), new.join(";")),
+    }
+}
+
+/// Sets a FLAC file's vorbis comments from attributes. Attributes without a
+/// value are left alone, except the artist, which is cleared.
+pub fn write_attributes(comment: &mut VorbisComment, attr: &Attributes, options: &Options) {
+    if let Some(album) = &attr.album {
+        comment.set_album(vec![album.to_string()]);
+    }
+    if let Some(title) = &attr.title {
+        comment.set_title(vec![title.clone()]);
+    }
+    if let Some(track) = attr.track {
+        comment.set(
+            "TRACKNUMBER",
+            vec![format_track(track, options.track_width)],
+        );
+    }
+    comment.set_artist(attr.artist.clone());
+    for attribute in [
+        Attribute::Year,
+        Attribute::Language,
+        Attribute::Work,
+        Attribute::MovementName,
+        Attribute::Movement,
+        Attribute::MovementTotal,
+        Attribute::ShowMovement,
+        Attribute::Grouping,
+        Attribute::Media,
+        Attribute::ReleaseCountry,
+        Attribute::Genre,
+        Attribute::AlbumArtist,
+        Attribute::Disc,
+        Attribute::Composer,
+        Attribute::Comment,
+    ] {
+        let values = attr.values(attribute);
+        if !values.is_empty() {
+            write_vorbis(comment, attribute, values, options.year_tag);
+        }
+    }
+}
+
+/// Sets the frames of an ID3 tag from attributes. Attributes without a
+/// value are left alone, except the artist, which is cleared.
+pub fn write_id3(tag: &mut id3::Tag, attr: &Attributes) {
+    if let Some(album) = &attr.album {
+        tag.set_album(album.clone());
+    }
+    if let Some(title) = &attr.title {
+        tag.set_title(title.clone());
+    }
+    if let Some(track) = attr.track {
+        tag.set_track(track);
+    }
+    if let Some(year) = attr.year {
+        tag.set_year(year);
+    }
+    match attr.artist.is_empty() {
+        true => tag.remove_artist(),
+        false => tag.set_text_values("TPE1", &attr.artist),
+    }
+    if !attr.genre.is_empty() {
+        tag.set_text_values("TCON", &attr.genre);
+    }
+    if let Some(album_artist) = &attr.album_artist {
+        tag.set_album_artist(album_artist.clone());
+    }
+    if let Some(disc) = attr.disc {
+        tag.set_disc(disc);
+    }
+    if !attr.composer.is_empty() {
+        tag.set_text_values("TCOM", &attr.composer);
+    }
+    if let Some(text) = &attr.comment {
+        tag.remove_comment(Some(""), None);
+        tag.add_frame(id3::frame::Comment {
+            lang: "eng".into(),
+            description: String::new(),
+            text: text.clone(),
+        });
+    }
+    if !attr.language.is_empty() {
+        tag.set_text_values("TLAN", &attr.language);
+    }
+    if let Some(name) = &attr.movement_name {
+        set_itunes_text(tag, "MVNM", name);
+    }
+    // MVIN holds the movement number and the total together, so either one
+    // keeps whatever the tag has for the other.
+    if attr.movement.is_some() || attr.movement_total.is_some() {
+        let current = Attributes::from_id3(tag);
+        let number = attr.movement.or(current.movement);
+        let total = attr.movement_total.or(current.movement_total);
+        match (number, total) {
+            (Some(number), Some(total)) => {
+                set_itunes_text(tag, "MVIN", &format!("{number}/{total}"))
+            }
+            (Some(number), None) => set_itunes_text(tag, "MVIN", &number.to_string()),
+            (None, _) => {}
+        }
+    }
+    if let Some(grouping) = &attr.grouping {
+        tag.set_text("GRP1", grouping.clone());
+    }
+    if let Some(media) = &attr.media {
+        tag.set_text("TMED", media.clone());
+    }
+}
+
+/// Sets one of the text frames iTunes added to ID3, such as MVNM, which the
+/// id3 crate doesn't know to be text, encoded as UTF-16 with a byte order
+/// mark, which both ID3v2.3 and v2.4 allow.
+fn set_itunes_text(tag: &mut id3::Tag, id: &str, text: &str) {
+    let mut data = vec![1, 0xFF, 0xFE];
+    data.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    let version = tag.version();
+    // Unknown frames are never taken to replace one another.
+    tag.remove(id);
+    tag.add_frame(id3::Frame::with_content(
+        id,
+        id3::Content::Unknown(id3::frame::Unknown { data, version }),
+    ));
+}
+
+/// Sets the attributes ID3 has no frame for, the work, whether to show the
+/// movement, and the release country, as user-defined (TXXX) frames named
+/// for their vorbis comments. Fewer players read these.
+pub fn write_id3_extended(tag: &mut id3::Tag, attr: &Attributes) {
+    for attribute in [
+        Attribute::Work,
+        Attribute::ShowMovement,
+        Attribute::ReleaseCountry,
+    ] {
+        let values = attr.values(attribute);
+        if !values.is_empty() {
+            tag.remove_extended_text(Some(attribute.vorbis_key()), None);
+            tag.add_frame(id3::frame::ExtendedText {
+                description: attribute.vorbis_key().into(),
+                value: values.join("\0"),
+            });
+        }
+    }
+}
+
+/// Vorbis comments holding pictures, which aren't listed as raw fields.
+const PICTURE_KEYS: &[&str] = &["METADATA_BLOCK_PICTURE", "COVERART"];
+
+/// The fields of a file's tags which no column of a sheet covers, by key in
+/// upper case: the vorbis comments of a FLAC or Ogg file, or the TXXX frames
+/// of an MP3, less those read as attributes, pictures, and write-back's.
+pub(crate) fn read_fields(path: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut fields = BTreeMap::new();
+    let mut add = |key: &str, values: Vec<String>| {
+        let key = key.to_ascii_uppercase();
+        if !sheet::is_column(&key)
+            && !writeback::is_computed(&key)
+            && !PICTURE_KEYS.contains(&key.as_str())
+        {
+            fields.entry(key).or_insert_with(Vec::new).extend(values);
+        }
+    };
+    match path.extension().and_then(OsStr::to_str) {
+        Some("flac") => {
+            if let Some(comment) = metaflac::Tag::read_from_path(path)?.vorbis_comments() {
+                for (key, values) in &comment.comments {
+                    add(key, values.clone());
+                }
+            }
+        }
+        Some("ogg" | "oga" | "opus") => {
+            for (key, values) in &ogg::read_comments(path)?.comments {
+                add(key, values.clone());
+            }
+        }
+        Some("mp3") => match id3::Tag::read_from_path(path) {
+            Ok(tag) => {
+                for text in tag.extended_texts() {
+                    add(&text.description, id3_values(&text.value));
+                }
+            }
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => {}
+            Err(e) => return Err(e.into()),
+        },
+        _ => {}
+    }
+    Ok(fields)
+}
+
+/// Writes raw fields, each as a sheet's cell holds it, to vorbis comments
+/// under their keys. A field whose cell is empty is removed.
+pub(crate) fn write_vorbis_fields(comment: &mut VorbisComment, fields: &[(String, String)]) {
+    for (key, text) in fields {
+        let values = sheet::split(text);
+        match values.is_empty() {
+            true => comment.remove(key),
+            false => comment.set(key.as_str(), values),
+        }
+    }
+}
+
+/// Writes raw fields, each as a sheet's cell holds it, to TXXX frames
+/// described by their keys, keeping the case of a frame already there. A
+/// field whose cell is empty is removed.
+pub(crate) fn write_id3_fields(tag: &mut id3::Tag, fields: &[(String, String)]) {
+    for (key, text) in fields {
+        let described: Vec<String> = tag
+            .extended_texts()
+            .filter(|frame| frame.description.eq_ignore_ascii_case(key))
+            .map(|frame| frame.description.clone())
+            .collect();
+        for description in &described {
+            tag.remove_extended_text(Some(description), None);
+        }
+
+        let values = sheet::split(text);
+        if !values.is_empty() {
+            tag.add_frame(id3::frame::ExtendedText {
+                description: described.into_iter().next().unwrap_or_else(|| key.clone()),
+                value: values.join("\0"),
+            });
+        }
+    }
+}
+
+/// The values of a raw field kept in vorbis comments.
+pub(crate) fn vorbis_field(comment: &VorbisComment, key: &str) -> Vec<String> {
+    comment.get(key).cloned().unwrap_or_default()
+}
+
+/// The values of a raw field kept in a TXXX frame.
+pub(crate) fn id3_field(tag: &id3::Tag, key: &str) -> Vec<String> {
+    id3_extended_text(tag, key)
+        .map(|value| id3_values(&value))
+        .unwrap_or_default()
+}
+
+/// The values of an ID3v2.4 text, which separates them with nulls.
+fn id3_values(text: &str) -> Vec<String> {
+    text.split('\0').map(String::from).collect()
+}
+
+/// Replaces an attribute's vorbis comment with the values given, or removes
+/// it if there are none. See [`Options::year_tag`] for how a year is written.
+pub fn write_vorbis(
+    comment: &mut VorbisComment,
+    attribute: Attribute,
+    values: Vec<String>,
+    year_tag: bool,
+) {
+    if attribute == Attribute::Year {
+        return write_year(comment, values, year_tag);
+    }
+
+    if values.is_empty() {
+        comment.remove(attribute.vorbis_key());
+    } else {
+        comment.set(attribute.vorbis_key(), values);
+    }
+}
+
+/// Writes a year to DATE, keeping a full date already there if its year
+/// matches. YEAR is kept in step when present, or written as well with
+/// `[format] year-tag = true`, since it's read in preference to DATE.
+fn write_year(comment: &mut VorbisComment, values: Vec<String>, year_tag: bool) {
+    let Some(year) = values.into_iter().next() else {
+        comment.remove("DATE");
+        comment.remove("YEAR");
+        return;
+    };
+
+    let date_matches = first_vorbis(comment, "DATE").is_some_and(|date| year_of_date(date) == year);
+    if !date_matches {
+        comment.set("DATE", vec![year.clone()]);
+    }
+    if year_tag || comment.get("YEAR").is_some() {
+        comment.set("YEAR", vec![year]);
+    }
+}
+
+/// Formats a track number, zero-padded to `width` digits.
+pub fn format_track(track: u32, width: usize) -> String {
+    format!("{track:0width$}")
+}
+
+/// Reads a vorbis flag, written as `1` or `0` by convention.
+pub(crate) fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+pub(crate) fn first_vorbis(comment: &VorbisComment, key: &str) -> Option<String> {
+    comment.get(key).and_then(|values| values.first()).cloned()
+}
+
+/// The values of an ID3 text frame. ID3v2.4 separates multiple values with
+/// nulls.
+pub(crate) fn id3_text(tag: &id3::Tag, id: &str) -> Vec<String> {
+    tag.get(id)
+        .and_then(|frame| match frame.content() {
+            id3::Content::Unknown(unknown) => decode_text(&unknown.data),
+            content => content.text().map(String::from),
+        })
+        .map(|text| {
+            text.split('\0')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Decodes a text frame the id3 crate reads as unknown, such as MVNM, by
+/// the encoding given in its first byte.
+fn decode_text(data: &[u8]) -> Option<String> {
+    let (&encoding, text) = data.split_first()?;
+    let utf16 = |text: &[u8], decode: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = text.chunks_exact(2).map(|c| decode([c[0], c[1]])).collect();
+        String::from_utf16(&units).ok()
+    };
+    let text = match (encoding, text) {
+        (0, text) => Some(text.iter().map(|&b| char::from(b)).collect()),
+        (1, [0xFF, 0xFE, text @ ..]) => utf16(text, u16::from_le_bytes),
+        (1, [0xFE, 0xFF, text @ ..]) | (2, text) => utf16(text, u16::from_be_bytes),
+        (3, text) => String::from_utf8(text.to_vec()).ok(),
+        _ => None,
+    }?;
+    Some(text.trim_end_matches('\0').into())
+}
+
+/// The value of a user-defined (TXXX) text frame.
+pub(crate) fn id3_extended_text(tag: &id3::Tag, description: &str) -> Option<String> {
+    tag.extended_texts()
+        .find(|text| text.description.eq_ignore_ascii_case(description))
+        .map(|text| text.value.clone())
+}
+
+/// Reduces a date such as `1984-06-25` to its year, leaving anything else as is.
+pub(crate) fn year_of_date(value: String) -> String {
+    let trimmed = value.trim();
+    match trimmed.as_bytes() {
+        [a, b, c, d, e, ..]
+            if [a, b, c, d].iter().all(|b| b.is_ascii_digit()) && !e.is_ascii_digit() =>
+        {
+            trimmed[..4].into()
+        }
+        _ => value,
+    }
+}
+
+/// The frames of an ID3 tag by the keys the audit log records them under: a
+/// text frame by its ID, with its values; a TXXX frame by `TXXX:` and its
+/// description, and a comment or lyrics frame by its ID, language, and
+/// description, as in `COMM:eng:`, with their text; and any other frame by
+/// its ID, with each frame of that ID encoded whole, in hex.
+pub(crate) fn id3_frame_fields(tag: &id3::Tag) -> BTreeMap<String, Vec<String>> {
+    let mut fields: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for frame in tag.frames() {
+        let (key, values) = id3_frame_field(frame);
+        fields.entry(key).or_default().extend(values);
+    }
+    fields
+}
+
+/// A frame's key and values, as [`id3_frame_fields`] gives them.
+fn id3_frame_field(frame: &id3::Frame) -> (String, Vec<String>) {
+    match frame.content() {
+        id3::Content::Text(text) if is_text_frame(frame.id()) => {
+            (frame.id().into(), id3_values(text))
+        }
+        id3::Content::ExtendedText(text) => (
+            format!("TXXX:{}", text.description),
+            vec![text.value.clone()],
+        ),
+        id3::Content::Comment(comment) => (
+            format!("COMM:{}:{}", comment.lang, comment.description),
+            vec![comment.text.clone()],
+        ),
+        id3::Content::Lyrics(lyrics) => (
+            format!("USLT:{}:{}", lyrics.lang, lyrics.description),
+            vec![lyrics.text.clone()],
+        ),
+        _ => {
+            let mut single = id3::Tag::new();
+            single.add_frame(frame.clone());
+            let mut data = Vec::new();
+            // Writing to memory can't fail.
+            let _ = single.write_to(&mut data, id3::Version::Id3v24);
+            (frame.id().into(), vec![hex::encode(data)])
+        }
+    }
+}
+
+/// Whether a frame ID is that of a text frame other than TXXX.
+fn is_text_frame(id: &str) -> bool {
+    id.starts_with('T') && id != "TXXX"
+}
+
+/// Replaces the frames [`id3_frame_fields`] gives under `key` with ones
+/// holding `values`, or removes them if there are none.
+pub(crate) fn set_id3_frame_field(tag: &mut id3::Tag, key: &str, values: &[String]) -> Result<()> {
+    let (id, described) = key.split_once(':').unwrap_or((key, ""));
+    for frame in tag.remove(id) {
+        if id3_frame_field(&frame).0 != key {
+            tag.add_frame(frame);
+        }
+    }
+
+    let (lang, description) = described.split_once(':').unwrap_or(("", described));
+    match id {
+        _ if values.is_empty() => {}
+        "TXXX" => {
+            for value in values {
+                tag.add_frame(id3::frame::ExtendedText {
+                    description: description.into(),
+                    value: value.clone(),
+                });
+            }
+        }
+        "COMM" => {
+            for text in values {
+                tag.add_frame(id3::frame::Comment {
+                    lang: lang.into(),
+                    description: description.into(),
+                    text: text.clone(),
+                });
+            }
+        }
+        "USLT" => {
+            for text in values {
+                tag.add_frame(id3::frame::Lyrics {
+                    lang: lang.into(),
+                    description: description.into(),
+                    text: text.clone(),
+                });
+            }
+        }
+        id if is_text_frame(id) => {
+            tag.add_frame(id3::Frame::text(id, values.join("\0")));
+        }
+        _ => {
+            for value in values {
+                let data = hex::decode(value).map_err(|_| {
+                    id3::Error::new(id3::ErrorKind::Parsing, "malformed frame in audit log")
+                })?;
+                for frame in id3::Tag::read_from(data.as_slice())?.frames() {
+                    tag.add_frame(frame.clone());
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use std::{path::Path, str::FromStr, sync::OnceLock};
+
+use crate::{Attribute, Attributes, Error, Result};
+
+/// A file name pattern such as `{track:02} - {artist} - {title}.flac`.
+///
+/// Fields name an attribute or a computed column from config, optionally
+/// followed by a width to which numbers are zero-padded. Alternatives
+/// separated by `|` are tried in order, so `{albumartist|artist}` falls back
+/// to the artist; an alternative starting with an operator (`+ - * / %`)
+/// does integer arithmetic on the value instead, so `{year|/10*10}` is the
+/// decade. Values with more than one entry are joined with ", ", and
+/// characters which aren't safe in file names are replaced. `{{` and `}}`
+/// stand for literal braces.
+#[derive(Clone, Debug)]
+pub(crate) struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Field {
+        sources: Vec<Source>,
+        operations: Vec<(char, i64)>,
+        width: Option<usize>,
+    },
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    Attribute(Attribute),
+    Column(String, Template),
+}
+
+impl Source {
+    fn name(&self) -> &str {
+        match self {
+            Source::Attribute(attribute) => attribute.name(),
+            Source::Column(name, _) => name,
+        }
+    }
+
+    fn value(&self, attributes: &Attributes) -> Option<String> {
+        match self {
+            Source::Attribute(attribute) => {
+                let values = attributes.values(*attribute);
+                (!values.is_empty()).then(|| values.join(", "))
+            }
+            Source::Column(_, template) => template.text(attributes),
+        }
+    }
+}
+
+static COLUMNS: OnceLock<Vec<(String, Template)>> = OnceLock::new();
+
+/// Records the computed columns defined in config, which patterns may then
+/// name like attributes. Only the first call has any effect.
+pub(crate) fn configure(columns: Vec<(String, Template)>) {
+    let _ = COLUMNS.set(columns);
+}
+
+/// The computed columns defined in config, in the order they were defined.
+pub(crate) fn columns() -> &'static [(String, Template)] {
+    COLUMNS.get().map_or(&[], Vec::as_slice)
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Template::with_columns(s, columns())
+    }
+}
+
+impl Template {
+    /// Parses a pattern whose fields may name any of `columns` as well as
+    /// attributes.
+    pub(crate) fn with_columns(s: &str, columns: &[(String, Template)]) -> Result<Self> {
+        let invalid = |reason: &str| Error::Template(format!("{s}: {reason}"));
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(invalid("unmatched }")),
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| invalid("unmatched {"))?;
+                    let (name, width) = match rest[..end].split_once(':') {
+                        Some((name, width)) => {
+                            let width = width
+                                .parse()
+                                .map_err(|_| invalid(&format!("bad width {width}")))?;
+                            (name, Some(width))
+                        }
+                        None => (&rest[..end], None),
+                    };
+
+                    let mut sources = Vec::new();
+                    let mut operations = Vec::new();
+                    for name in name.split('|').map(str::trim) {
+                        if name.starts_with(OPERATORS) {
+                            let parsed = parse_operations(name)
+                                .ok_or_else(|| invalid(&format!("bad arithmetic {name}")))?;
+                            operations.extend(parsed);
+                            continue;
+                        }
+                        let column = columns
+                            .iter()
+                            .find(|(column, _)| column.eq_ignore_ascii_case(name));
+                        sources.push(match column {
+                            Some((column, template)) => {
+                                Source::Column(column.clone(), template.clone())
+                            }
+                            None => Source::Attribute(name.parse()?),
+                        });
+                    }
+                    if sources.is_empty() {
+                        return Err(invalid("a field must name an attribute"));
+                    }
+                    chars = rest[end + 1..].chars();
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field {
+                        sources,
+                        operations,
+                        width,
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template { segments })
+    }
+
+    /// Fills in the pattern from a file's attributes, or names the first
+    /// field the file is missing (the last alternative, if there were
+    /// several).
+    pub(crate) fn render(&self, attributes: &Attributes) -> Result<String, String> {
+        self.fill(attributes, component)
+    }
+
+    /// Fills in the pattern as plain text, without making it safe for a file
+    /// name, as for a computed column. Nothing if a field is missing.
+    pub(crate) fn text(&self, attributes: &Attributes) -> Option<String> {
+        self.fill(attributes, str::to_string)
+            .ok()
+            .filter(|text| !text.is_empty())
+    }
+
+    fn fill(&self, attributes: &Attributes, clean: fn(&str) -> String) -> Result<String, String> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Field {
+                    sources,
+                    operations,
+                    width,
+                } => {
+                    let value = sources
+                        .iter()
+                        .find_map(|source| source.value(attributes))
+                        .ok_or_else(|| {
+                            let last = sources.last().expect("fields name an attribute");
+                            last.name().to_string()
+                        })?;
+                    let value = calculate(value, operations);
+                    let value = match (width, value.parse::<u32>()) {
+                        (&Some(width), Ok(n)) => format!("{n:0width$}"),
+                        _ => value,
+                    };
+                    rendered.push_str(&clean(&value));
+                }
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// Whether a field of the pattern, or of a computed column it names,
+    /// reads the attribute.
+    pub(crate) fn uses(&self, attribute: Attribute) -> bool {
+        self.segments.iter().any(|segment| match segment {
+            Segment::Literal(_) => false,
+            Segment::Field { sources, .. } => sources.iter().any(|source| match source {
+                Source::Attribute(used) => *used == attribute,
+                Source::Column(_, template) => template.uses(attribute),
+            }),
+        })
+    }
+
+    /// Whether the pattern ends with an extension of its own, as opposed to
+    /// one which should be carried over from the file.
+    pub(crate) fn has_extension(&self) -> bool {
+        match self.segments.last() {
+            Some(Segment::Literal(literal)) => {
+                literal.rsplit_once('.').is_some_and(|(_, extension)| {
+                    !extension.is_empty() && extension.chars().all(char::is_alphanumeric)
+                })
+            }
+            _ => false,
+        }
+    }
+
+    /// Reads attribute values back out of a path the pattern could have
+    /// produced: the reverse of [`Template::render`]. Fields take as little
+    /// text as they can, and numeric fields only digits. The pattern is
+    /// matched against as many trailing components of the path as it has,
+    /// without the extension unless the pattern has one. A field with
+    /// alternatives fills in the first; computed columns and arithmetic
+    /// can't be read back, so their text is matched but not kept.
+    pub(crate) fn parse(&self, path: &Path) -> Option<Vec<(Attribute, String)>> {
+        let depth = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.matches('/').count(),
+                Segment::Field { .. } => 0,
+            })
+            .sum::<usize>()
+            + 1;
+
+        // A relative path may not name the directories the pattern covers.
+        let path = std::path::absolute(path).ok()?;
+        let path = match self.has_extension() {
+            true => path,
+            false => path.with_extension(""),
+        };
+        let components: Vec<_> = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        let text = components[components.len().checked_sub(depth)?..].join("/");
+
+        let mut values = Vec::new();
+        match_segments(&self.segments, &text, &mut values).then(|| {
+            values.reverse();
+            values
+        })
+    }
+}
+
+fn match_segments(segments: &[Segment], text: &str, values: &mut Vec<(Attribute, String)>) -> bool {
+    let Some((segment, rest)) = segments.split_first() else {
+        return text.is_empty();
+    };
+
+    match segment {
+        Segment::Literal(literal) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| match_segments(rest, text, values)),
+        Segment::Field {
+            sources,
+            operations,
+            ..
+        } => {
+            let attribute = match (&sources[0], operations.is_empty()) {
+                (Source::Attribute(attribute), true) => Some(*attribute),
+                _ => None,
+            };
+            let numeric = matches!(
+                attribute,
+                Some(
+                    Attribute::Track
+                        | Attribute::Disc
+                        | Attribute::Year
+                        | Attribute::Movement
+                        | Attribute::MovementTotal
+                )
+            );
+            for (end, _) in text.char_indices().skip(1).chain([(text.len(), ' ')]) {
+                let value = text[..end].trim();
+                if value.is_empty() || value.contains('/') {
+                    continue;
+                }
+                if numeric && !value.chars().all(|c| c.is_ascii_digit()) {
+                    return false;
+                }
+                if match_segments(rest, &text[end..], values) {
+                    if let Some(attribute) = attribute {
+                        values.push((attribute, value.to_string()));
+                    }
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+const OPERATORS: [char; 5] = ['+', '-', '*', '/', '%'];
+
+/// Parses arithmetic such as `/10*10` into operators and operands.
+fn parse_operations(s: &str) -> Option<Vec<(char, i64)>> {
+    let mut operations = Vec::new();
+    let mut rest = s.trim();
+    while let Some(operator) = rest.chars().next() {
+        if !OPERATORS.contains(&operator) {
+            return None;
+        }
+        rest = rest[1..].trim_start();
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let operand: i64 = rest[..end].parse().ok()?;
+        if operand == 0 && matches!(operator, '/' | '%') {
+            return None;
+        }
+        operations.push((operator, operand));
+        rest = rest[end..].trim_start();
+    }
+    Some(operations)
+}
+
+/// Applies arithmetic to a numeric value, left to right. Values which aren't
+/// integers, or which would overflow, are left as they are.
+fn calculate(value: String, operations: &[(char, i64)]) -> String {
+    if operations.is_empty() {
+        return value;
+    }
+    let Ok(n) = value.trim().parse::<i64>() else {
+        return value;
+    };
+    operations
+        .iter()
+        .try_fold(n, |n, &(operator, operand)| match operator {
+            '+' => n.checked_add(operand),
+            '-' => n.checked_sub(operand),
+            '*' => n.checked_mul(operand),
+            '/' => n.checked_div(operand),
+            _ => n.checked_rem(operand),
+        })
+        .map_or(value, |n| n.to_string())
+}
+
+/// Makes a tag value safe to use as a file or directory name on any
+/// platform.
+pub(crate) fn component(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    match cleaned.trim().trim_end_matches('.') {
+        "" => "_".into(),
+        cleaned => cleaned.into(),
+    }
+}
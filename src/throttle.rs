@@ -0,0 +1,124 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{roots::Roots, Result};
+
+/// A limit on how fast a scan works through files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Rate {
+    BytesPerSecond(f64),
+    FilesPerSecond(f64),
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    /// Parses rates such as `20MB/s`, `500KB`, or `2files/s`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let expected = || format!("expected a rate such as 20MB/s or 2files/s; found {s}");
+        let lower = s.trim().to_ascii_lowercase();
+        let lower = lower.strip_suffix("/s").unwrap_or(&lower);
+
+        let split = lower
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(split);
+        let number: f64 = number.parse().map_err(|_| expected())?;
+        if number <= 0.0 {
+            return Err(expected());
+        }
+
+        let bytes = |scale: f64| Ok(Rate::BytesPerSecond(number * scale));
+        match unit.trim() {
+            "b" => bytes(1.0),
+            "k" | "kb" => bytes(1e3),
+            "m" | "mb" => bytes(1e6),
+            "g" | "gb" => bytes(1e9),
+            "file" | "files" => Ok(Rate::FilesPerSecond(number)),
+            _ => Err(expected()),
+        }
+    }
+}
+
+/// Paces a loop over files to stay under a rate, sleeping as needed. Files
+/// under a root with its own rate are paced separately, against that rate.
+/// Without a rate, it never waits.
+pub(crate) struct Throttle {
+    default: Budget,
+    roots: Vec<(PathBuf, Budget)>,
+}
+
+struct Budget {
+    rate: Option<Rate>,
+    start: Instant,
+    consumed: f64,
+}
+
+impl Budget {
+    fn new(rate: Option<Rate>) -> Self {
+        Budget {
+            rate,
+            start: Instant::now(),
+            consumed: 0.0,
+        }
+    }
+}
+
+impl Throttle {
+    pub(crate) fn new(rate: Option<Rate>, roots: &Roots) -> Self {
+        Throttle {
+            default: Budget::new(rate),
+            roots: roots
+                .roots
+                .iter()
+                .filter(|root| root.throttle.is_some())
+                .map(|root| (root.path.clone(), Budget::new(root.throttle)))
+                .collect(),
+        }
+    }
+
+    /// Waits until the budget allows another file, then charges the file to it.
+    pub(crate) fn wait(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let budget = self
+            .roots
+            .iter_mut()
+            .filter(|(root, _)| path.starts_with(root))
+            .max_by_key(|(root, _)| root.components().count())
+            .map_or(&mut self.default, |(_, budget)| budget);
+        let Some(rate) = budget.rate else {
+            return Ok(());
+        };
+
+        let (cost, per_second) = match rate {
+            Rate::BytesPerSecond(limit) => (fs::metadata(path)?.len() as f64, limit),
+            Rate::FilesPerSecond(limit) => (1.0, limit),
+        };
+
+        let due = Duration::from_secs_f64(budget.consumed / per_second);
+        if let Some(remaining) = due.checked_sub(budget.start.elapsed()) {
+            thread::sleep(remaining);
+        }
+        budget.consumed += cost;
+        Ok(())
+    }
+}
+
+pub(crate) fn lower_priority() {
+    #[cfg(unix)]
+    // SAFETY: nice has no memory safety preconditions.
+    unsafe {
+        libc::nice(10);
+    }
+
+    #[cfg(not(unix))]
+    crate::warning::emit(
+        crate::warning::Code::Unsupported,
+        "--nice is only supported on unix",
+    );
+}
@@ -0,0 +1,115 @@
+use std::{
+    env,
+    ffi::OsString,
+    path::PathBuf,
+    process::{self, Stdio},
+    sync::OnceLock,
+};
+
+use crate::{Error, Result};
+
+/// An external program flacdat shells out to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Tool {
+    Ffmpeg,
+    Ffprobe,
+    Fpcalc,
+    Curl,
+}
+
+/// Paths set with `[tools]` in config, filled in once at startup.
+static OVERRIDES: OnceLock<Vec<(Tool, PathBuf)>> = OnceLock::new();
+
+/// Records the tool paths given in config. Only the first call has any effect.
+pub(crate) fn configure(overrides: Vec<(Tool, PathBuf)>) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+impl Tool {
+    pub(crate) const ALL: &'static [Tool] =
+        &[Tool::Ffmpeg, Tool::Ffprobe, Tool::Fpcalc, Tool::Curl];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Tool::Ffmpeg => "ffmpeg",
+            Tool::Ffprobe => "ffprobe",
+            Tool::Fpcalc => "fpcalc",
+            Tool::Curl => "curl",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Tool::ALL.iter().copied().find(|tool| tool.name() == name)
+    }
+
+    /// The program to run: `$FLACDAT_<TOOL>`, then the `[tools]` entry in
+    /// config, then the bare name, looked up on `PATH`.
+    pub(crate) fn program(self) -> OsString {
+        let var = format!("FLACDAT_{}", self.name().to_ascii_uppercase());
+        if let Some(path) = env::var_os(var) {
+            return path;
+        }
+
+        OVERRIDES
+            .get()
+            .and_then(|overrides| overrides.iter().find(|(tool, _)| *tool == self))
+            .map(|(_, path)| path.into())
+            .unwrap_or_else(|| self.name().into())
+    }
+
+    pub(crate) fn command(self) -> process::Command {
+        process::Command::new(self.program())
+    }
+
+    /// The installed version, or `None` when the tool can't be run.
+    pub(crate) fn version(self) -> Option<String> {
+        let output = self
+            .command()
+            .arg(match self {
+                Tool::Curl => "--version",
+                _ => "-version",
+            })
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+
+        // "ffmpeg version 6.1.1 Copyright ...", "fpcalc version 1.5.1",
+        // "curl 8.5.0 (x86_64-pc-linux-gnu) ..."
+        let text = String::from_utf8_lossy(&output.stdout);
+        let mut words = text.lines().next()?.split_whitespace();
+        match self {
+            Tool::Curl => words.next()?,
+            _ => words.find(|&word| word == "version")?,
+        };
+        Some(words.next().unwrap_or_default().to_string())
+    }
+
+    /// Fails with installation instructions when the tool can't be run.
+    pub(crate) fn ensure(self) -> Result<()> {
+        match self.version() {
+            Some(_) => Ok(()),
+            None => Err(self.missing()),
+        }
+    }
+
+    pub(crate) fn missing(self) -> Error {
+        Error::ToolNotInstalled {
+            name: self.name(),
+            hint: self.install_hint(),
+        }
+    }
+
+    pub(crate) fn install_hint(self) -> &'static str {
+        match (self, env::consts::OS) {
+            (Tool::Ffmpeg | Tool::Ffprobe, "macos") => "brew install ffmpeg",
+            (Tool::Ffmpeg | Tool::Ffprobe, "windows") => "winget install ffmpeg",
+            (Tool::Ffmpeg | Tool::Ffprobe, _) => "install the ffmpeg package for your distribution",
+            (Tool::Fpcalc, "macos") => "brew install chromaprint",
+            (Tool::Fpcalc, "windows") => "download fpcalc from https://acoustid.org/chromaprint",
+            (Tool::Fpcalc, _) => "install the chromaprint tools package for your distribution",
+            (Tool::Curl, "macos") => "curl ships with macOS",
+            (Tool::Curl, "windows") => "winget install curl",
+            (Tool::Curl, _) => "install the curl package for your distribution",
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    env, fs,
+    path::Path,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use id3::TagLike;
+
+use crate::{
+    art, audio, encoding, lock::FileLock, rate, tags, tools::Tool, verify, writeback, Attributes,
+    Error, Result,
+};
+
+/// Vorbis comment keys `write_id3` already carries into an MP3 through
+/// [`crate::Attributes`]. Everything else goes into TXXX frames.
+const MAPPED: &[&str] = &[
+    "ALBUM",
+    "ALBUMARTIST",
+    "ALBUM ARTIST",
+    "ARTIST",
+    "COMMENT",
+    "COMPOSER",
+    "DATE",
+    "DESCRIPTION",
+    "DISCNUMBER",
+    "GENRE",
+    "GROUPING",
+    "LANGUAGE",
+    "MEDIA",
+    "MOVEMENT",
+    "MOVEMENTNAME",
+    "MOVEMENTTOTAL",
+    "TITLE",
+    "TRACKNUMBER",
+    "YEAR",
+];
+
+/// A lossy format `transcode` encodes to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Codec {
+    /// MP3, through LAME
+    Mp3,
+    /// Opus, in an Ogg file
+    Opus,
+    /// AAC, in an MP4 (.m4a) file
+    Aac,
+}
+
+impl Codec {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Codec::Mp3 => "mp3",
+            Codec::Opus => "opus",
+            Codec::Aac => "m4a",
+        }
+    }
+
+    /// The sample rates the encoder takes, in Hz.
+    pub(crate) fn sample_rates(self) -> &'static [u32] {
+        match self {
+            Codec::Mp3 => &[8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000],
+            Codec::Opus => &[8000, 12000, 16000, 24000, 48000],
+            Codec::Aac => &[
+                7350, 8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000, 64000, 88200,
+                96000,
+            ],
+        }
+    }
+
+    fn encoder(self) -> &'static str {
+        match self {
+            Codec::Mp3 => "libmp3lame",
+            Codec::Opus => "libopus",
+            Codec::Aac => "aac",
+        }
+    }
+}
+
+/// Transcodes a FLAC file to `target`: at `sample_rate`, in Hz, if given, or
+/// else at the rate of the source's own family [`rate::pick`] finds up to
+/// `max_sample_rate`. An MP3 is then tagged from the FLAC.
+pub(crate) fn transcode(
+    source: &Path,
+    target: &Path,
+    codec: Codec,
+    bitrate: &str,
+    sample_rate: Option<u32>,
+    max_sample_rate: u32,
+) -> Result<()> {
+    let source_rate = audio::read(source)?.sample_rate;
+    let rate = match sample_rate {
+        Some(rate) => {
+            rate::warn_avoidable(source, source_rate, rate);
+            rate
+        }
+        None => rate::pick(source_rate, Some(max_sample_rate), codec.sample_rates()),
+    };
+
+    let _lock = FileLock::acquire(target)?;
+    encode(source, target, codec, bitrate, rate)?;
+    if codec == Codec::Mp3 {
+        verify::write_id3(&mp3_tag(source)?, target)?;
+    }
+    Ok(())
+}
+
+/// An ID3 tag for an MP3 of a FLAC file: tagged as by `set`, with any other
+/// vorbis comments in TXXX frames, and every picture.
+fn mp3_tag(source: &Path) -> Result<id3::Tag> {
+    let flac = metaflac::Tag::read_from_path(source)?;
+    let mut tag = id3::Tag::new();
+    tags::write_id3(&mut tag, &Attributes::from_path(source)?);
+    if let Some(comment) = flac.vorbis_comments() {
+        for (key, values) in &comment.comments {
+            if MAPPED.contains(&key.to_ascii_uppercase().as_str()) || writeback::is_computed(key) {
+                continue;
+            }
+            tag.add_frame(id3::frame::ExtendedText {
+                description: key.clone(),
+                value: values.join("\0"),
+            });
+        }
+    }
+    for picture in art::read(source)? {
+        tag.add_frame(id3::frame::Picture {
+            mime_type: art::mime_type(&picture.data).into(),
+            picture_type: picture.kind,
+            description: String::new(),
+            data: picture.data,
+        });
+    }
+    Ok(tag)
+}
+
+/// Encodes a FLAC file's audio to `target`. Opus and AAC files take the
+/// FLAC's tags through ffmpeg, along with its cover: as a
+/// METADATA_BLOCK_PICTURE comment in Opus, and an attached picture in AAC.
+/// MP3s are left untagged, to be tagged with the id3 crate. The
+/// audio is resampled to `sample_rate`, in Hz, if it isn't already at it.
+fn encode(
+    source: &Path,
+    target: &Path,
+    codec: Codec,
+    bitrate: &str,
+    sample_rate: u32,
+) -> Result<()> {
+    let mut command = Tool::Ffmpeg.command();
+    command.args(["-y", "-loglevel", "error", "-i"]).arg(source);
+
+    let metadata = match codec {
+        Codec::Mp3 => None,
+        Codec::Opus | Codec::Aac => {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = env::temp_dir().join(format!(
+                "flacdat-{}-{}.ffmeta",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let flac = metaflac::Tag::read_from_path(source)?;
+            fs::write(&path, ffmetadata(&flac, codec == Codec::Opus))?;
+            command.args(["-f", "ffmetadata", "-i"]).arg(&path);
+            Some(path)
+        }
+    };
+
+    command.args(["-map", "0:a"]);
+    match codec {
+        Codec::Mp3 => command.args(["-map_metadata", "-1"]),
+        Codec::Opus => command.args(["-map_metadata", "1"]),
+        Codec::Aac => command.args([
+            "-map_metadata",
+            "1",
+            "-map",
+            "0:v:0?",
+            "-c:v",
+            "copy",
+            "-disposition:v:0",
+            "attached_pic",
+        ]),
+    };
+    let status = command
+        .args(["-c:a", codec.encoder(), "-b:a", bitrate])
+        .args(["-ar", &sample_rate.to_string()])
+        .arg(target)
+        .status();
+
+    if let Some(path) = metadata {
+        let _ = fs::remove_file(path);
+    }
+    match status?.success() {
+        true => Ok(()),
+        false => Err(Error::FfmpegFailed(source.display().to_string())),
+    }
+}
+
+/// Writes a FLAC's vorbis comments as an ffmpeg metadata file. The keys
+/// ffmpeg names differently are renamed so that it maps them to each
+/// container's own fields; several values of a key are joined with ";", as
+/// ffmpeg does itself.
+fn ffmetadata(flac: &metaflac::Tag, with_picture: bool) -> String {
+    let mut text = String::from(";FFMETADATA1\n");
+    let mut line = |key: &str, value: &str| {
+        text.push_str(&escape(key));
+        text.push('=');
+        text.push_str(&escape(value));
+        text.push('\n');
+    };
+
+    if let Some(comment) = flac.vorbis_comments() {
+        for (key, values) in &comment.comments {
+            if writeback::is_computed(key) {
+                continue;
+            }
+            let key = match key.to_ascii_uppercase().as_str() {
+                "ALBUMARTIST" | "ALBUM ARTIST" => "album_artist",
+                "TRACKNUMBER" => "track",
+                "DISCNUMBER" => "disc",
+                _ => key,
+            };
+            line(key, &values.join(";"));
+        }
+    }
+
+    // Only one picture survives, since a key holds only one value here.
+    if with_picture {
+        let pictures: Vec<_> = flac.pictures().collect();
+        let primary = pictures
+            .iter()
+            .find(|picture| {
+                art::from_flac_type(picture.picture_type) == id3::frame::PictureType::CoverFront
+            })
+            .or_else(|| pictures.first());
+        if let Some(picture) = primary {
+            line(
+                "METADATA_BLOCK_PICTURE",
+                &encoding::base64_encode(&picture.to_bytes()),
+            );
+        }
+    }
+
+    text
+}
+
+/// Escapes the characters special to ffmpeg metadata files.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
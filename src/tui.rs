@@ -0,0 +1,428 @@
+use std::io::{self, IsTerminal, Read, Write};
+
+use crate::{sheet, Attribute, Attributes, Error, Result};
+
+/// The widest a column of values is drawn; longer values are cut short.
+const MAX_WIDTH: usize = 24;
+
+/// A file being edited, with its tags as read and as edited so far.
+pub(crate) struct File {
+    pub(crate) path: String,
+    pub(crate) before: Attributes,
+    pub(crate) after: Attributes,
+}
+
+impl File {
+    pub(crate) fn changed(&self) -> bool {
+        Attribute::ALL
+            .iter()
+            .any(|&attribute| self.cell(attribute) != joined(&self.before, attribute))
+    }
+
+    fn cell(&self, attribute: Attribute) -> String {
+        joined(&self.after, attribute)
+    }
+}
+
+/// An attribute's values as shown and edited, separated as in a sheet, with
+/// a space after each separator.
+fn joined(attributes: &Attributes, attribute: Attribute) -> String {
+    attributes
+        .values(attribute)
+        .join(&format!("{} ", sheet::separator()))
+}
+
+/// A key pressed.
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Backspace,
+    Char(char),
+}
+
+/// Shows the files' tags as a table to move around and edit, until they're
+/// written or thrown away: returns whether the edits are to be written.
+///
+/// Enter edits a cell; s sets every file's value in the column to the one
+/// typed; u undoes the edits to a cell; w asks to write the files changed,
+/// and q to quit.
+pub(crate) fn run(files: &mut [File]) -> Result<bool> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(Error::NoTerminal("tui"));
+    }
+    let _screen = Screen::enter()?;
+    let mut editor = Editor {
+        files,
+        row: 0,
+        column: 0,
+        top: 0,
+        left: 0,
+        message: String::new(),
+        keys: Keys::default(),
+    };
+    editor.run()
+}
+
+struct Editor<'a> {
+    files: &'a mut [File],
+    /// The cell the cursor is on
+    row: usize,
+    column: usize,
+    /// The first row and column in view
+    top: usize,
+    left: usize,
+    /// Shown on the bottom line until the next key
+    message: String,
+    keys: Keys,
+}
+
+impl Editor<'_> {
+    fn run(&mut self) -> Result<bool> {
+        loop {
+            self.draw(None)?;
+            let key = self.keys.next()?;
+            self.message.clear();
+            let attribute = Attribute::ALL[self.column];
+            match key {
+                Key::Up | Key::Char('k') => self.row = self.row.saturating_sub(1),
+                Key::Down | Key::Char('j') => {
+                    self.row = (self.row + 1).min(self.files.len().saturating_sub(1))
+                }
+                Key::Left | Key::Char('h') => self.column = self.column.saturating_sub(1),
+                Key::Right | Key::Char('l') => {
+                    self.column = (self.column + 1).min(Attribute::ALL.len() - 1)
+                }
+                Key::Enter | Key::Char('e') if !self.files.is_empty() => {
+                    let current = self.files[self.row].cell(attribute);
+                    let prompt = format!("{}: ", attribute.name());
+                    if let Some(value) = self.prompt(&prompt, current)? {
+                        self.set(self.row..self.row + 1, attribute, &value);
+                    }
+                }
+                Key::Char('s') if !self.files.is_empty() => {
+                    let current = self.files[self.row].cell(attribute);
+                    let prompt =
+                        format!("{} for all {} files: ", attribute.name(), self.files.len());
+                    if let Some(value) = self.prompt(&prompt, current)? {
+                        self.set(0..self.files.len(), attribute, &value);
+                    }
+                }
+                Key::Char('u') if !self.files.is_empty() => {
+                    let file = &mut self.files[self.row];
+                    let values = file.before.values(attribute);
+                    file.after.set_values(attribute, values)?;
+                }
+                Key::Char('w') => match self.changed() {
+                    0 => self.message = "nothing to write".into(),
+                    changed => {
+                        if self.ask(&format!("write {changed} changed file(s)? (y/n) "))? {
+                            return Ok(true);
+                        }
+                    }
+                },
+                Key::Char('q') | Key::Escape => match self.changed() {
+                    0 => return Ok(false),
+                    changed => {
+                        if self.ask(&format!("discard changes to {changed} file(s)? (y/n) "))? {
+                            return Ok(false);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn changed(&self) -> usize {
+        self.files.iter().filter(|file| file.changed()).count()
+    }
+
+    /// Sets an attribute of a range of files from text, showing why if the
+    /// text isn't a valid value for it.
+    fn set(&mut self, rows: std::ops::Range<usize>, attribute: Attribute, value: &str) {
+        for file in &mut self.files[rows] {
+            let mut after = file.after.clone();
+            match after.set_text(attribute, value) {
+                Ok(()) => file.after = after,
+                Err(e) => {
+                    self.message = e.to_string();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Reads a line of text on the bottom line, starting from `text`;
+    /// `None` if escape is pressed.
+    fn prompt(&mut self, prompt: &str, mut text: String) -> Result<Option<String>> {
+        loop {
+            self.draw(Some(&format!("{prompt}{text}")))?;
+            match self.keys.next()? {
+                Key::Enter => return Ok(Some(text)),
+                Key::Escape => return Ok(None),
+                Key::Backspace => {
+                    text.pop();
+                }
+                Key::Char(c) if !c.is_control() => text.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// Asks a yes-or-no question on the bottom line.
+    fn ask(&mut self, question: &str) -> Result<bool> {
+        self.draw(Some(question))?;
+        Ok(matches!(self.keys.next()?, Key::Char('y' | 'Y')))
+    }
+
+    /// Draws the table, with `status` or the keys on the bottom line.
+    fn draw(&mut self, status: Option<&str>) -> Result<()> {
+        let (width, height) = size();
+        // The title and header above the rows, and the status line below
+        let rows = height.saturating_sub(3).max(1);
+        if self.row < self.top {
+            self.top = self.row;
+        } else if self.row >= self.top + rows {
+            self.top = self.row + 1 - rows;
+        }
+
+        let path_width = self
+            .files
+            .iter()
+            .map(|file| file.path.chars().count())
+            .max()
+            .unwrap_or_default()
+            .clamp(4, 40);
+        let widths: Vec<usize> = Attribute::ALL
+            .iter()
+            .map(|&attribute| {
+                self.files
+                    .iter()
+                    .map(|file| file.cell(attribute).chars().count())
+                    .max()
+                    .unwrap_or_default()
+                    .max(attribute.name().len())
+                    .min(MAX_WIDTH)
+            })
+            .collect();
+
+        // Scroll so that the cursor's column fits beside the paths.
+        let room = width.saturating_sub(path_width + 1);
+        self.left = self.left.min(self.column);
+        while self.left < self.column
+            && widths[self.left..=self.column]
+                .iter()
+                .map(|width| width + 1)
+                .sum::<usize>()
+                > room
+        {
+            self.left += 1;
+        }
+
+        let mut frame = String::from("\x1b[H");
+        let title = format!("{} file(s), {} changed", self.files.len(), self.changed());
+        line(&mut frame, &fit(&title, width));
+
+        let mut header = pad("path", path_width);
+        for (idx, &attribute) in Attribute::ALL.iter().enumerate().skip(self.left) {
+            header.push(' ');
+            header.push_str(&pad(attribute.name(), widths[idx]));
+        }
+        line(
+            &mut frame,
+            &format!("\x1b[1m{}\x1b[0m", fit(&header, width)),
+        );
+
+        for (row, file) in self.files.iter().enumerate().skip(self.top).take(rows) {
+            let mut text = pad(&file.path, path_width);
+            let mut used = path_width;
+            for (idx, &attribute) in Attribute::ALL.iter().enumerate().skip(self.left) {
+                if used + 1 + widths[idx] > width {
+                    break;
+                }
+                used += 1 + widths[idx];
+                let cell = pad(&file.cell(attribute), widths[idx]);
+                text.push(' ');
+                // The cursor is in reverse video, and edited cells in bold.
+                let cursor = row == self.row && idx == self.column;
+                let edited = file.cell(attribute) != joined(&file.before, attribute);
+                match (cursor, edited) {
+                    (true, _) => text.push_str(&format!("\x1b[7m{cell}\x1b[0m")),
+                    (false, true) => text.push_str(&format!("\x1b[1m{cell}\x1b[0m")),
+                    (false, false) => text.push_str(&cell),
+                }
+            }
+            line(&mut frame, &text);
+        }
+        frame.push_str("\x1b[J");
+
+        let keys = "arrows move  enter edit  s set column  u undo  w write  q quit";
+        let status = match (status, self.message.is_empty()) {
+            (Some(status), _) => status,
+            (None, false) => &self.message,
+            (None, true) => keys,
+        };
+        frame.push_str(&format!("\x1b[{height};1H{}", fit(status, width)));
+
+        let mut out = io::stdout().lock();
+        out.write_all(frame.as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Adds a line to a frame, clearing whatever was left of the line before.
+fn line(frame: &mut String, text: &str) {
+    frame.push_str(text);
+    frame.push_str("\x1b[K\r\n");
+}
+
+/// Cuts text down to a width.
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// Fits text to a width exactly, cut short or padded with spaces.
+fn pad(text: &str, width: usize) -> String {
+    format!("{:width$}", fit(text, width))
+}
+
+/// Keys read from the terminal. One read can hold several, when they're
+/// typed quickly or pasted, so what's left over is kept for the next.
+#[derive(Default)]
+struct Keys {
+    pending: Vec<u8>,
+}
+
+impl Keys {
+    fn next(&mut self) -> Result<Key> {
+        loop {
+            if let Some((key, len)) = parse(&self.pending) {
+                self.pending.drain(..len);
+                match key {
+                    Some(key) => return Ok(key),
+                    None => continue,
+                }
+            }
+            let mut buf = [0; 256];
+            let read = io::stdin().lock().read(&mut buf)?;
+            if read == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.pending.extend(&buf[..read]);
+        }
+    }
+}
+
+/// The first key in bytes read from the terminal, or `None` for a sequence
+/// not understood, with how many bytes it takes up; `None` until a whole key
+/// has been read. An escape not starting a sequence is the escape key.
+fn parse(bytes: &[u8]) -> Option<(Option<Key>, usize)> {
+    let arrow = |code| match code {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        _ => None,
+    };
+    let (key, len) = match bytes {
+        [] => return None,
+        // Control sequences end at the first byte from @ to ~.
+        [0x1b, b'[', rest @ ..] => {
+            let end = rest.iter().position(|b| (0x40..=0x7e).contains(b))?;
+            let key = match end {
+                0 => arrow(rest[0]),
+                _ => None,
+            };
+            (key, end + 3)
+        }
+        [0x1b, b'O', code, ..] => (arrow(*code), 3),
+        [0x1b, ..] => (Some(Key::Escape), 1),
+        [b'\r' | b'\n', ..] => (Some(Key::Enter), 1),
+        [0x7f | 0x08, ..] => (Some(Key::Backspace), 1),
+        [first, ..] => {
+            let len = match first {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Some((None, 1)),
+            };
+            let text = std::str::from_utf8(bytes.get(..len)?).ok();
+            (text.and_then(|s| s.chars().next()).map(Key::Char), len)
+        }
+    };
+    Some((key, len))
+}
+
+/// The terminal's width and height.
+#[cfg(unix)]
+fn size() -> (usize, usize) {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ writes a winsize, which size is, and nothing else.
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    match ok && size.ws_col > 0 && size.ws_row > 0 {
+        true => (usize::from(size.ws_col), usize::from(size.ws_row)),
+        false => (80, 24),
+    }
+}
+
+/// The terminal in raw mode, showing the alternate screen, until dropped.
+#[cfg(unix)]
+struct Screen {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl Screen {
+    fn enter() -> Result<Self> {
+        // SAFETY: termios is plain data, which tcgetattr fills in.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut raw = saved;
+        // SAFETY: raw is a termios read from the terminal.
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Screen { saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        // SAFETY: saved is the termios the terminal had before.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+#[cfg(not(unix))]
+fn size() -> (usize, usize) {
+    (80, 24)
+}
+
+#[cfg(not(unix))]
+struct Screen;
+
+#[cfg(not(unix))]
+impl Screen {
+    fn enter() -> Result<Self> {
+        Err(Error::NoTerminal("tui"))
+    }
+}
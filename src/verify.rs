@@ -0,0 +1,166 @@
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    digest,
+    manifest::{self, Action},
+    Error, Result,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns on `--verify-writes`: every tag write is read back and checked.
+pub(crate) fn configure(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Writes a FLAC file's metadata. With `--verify-writes`, fails unless the
+/// audio frames are byte-identical afterward and the comments, pictures, and
+/// application blocks read back as written.
+pub(crate) fn write_flac(flac: &mut metaflac::Tag, path: &Path) -> Result<()> {
+    if !enabled() {
+        flac.write_to_path(path)?;
+        manifest::record(Action::Modified, path);
+        return Ok(());
+    }
+
+    let before = audio_digest(path, flac_audio)?;
+    flac.write_to_path(path)?;
+    manifest::record(Action::Modified, path);
+    check_audio(path, before, flac_audio)?;
+
+    let written = metaflac::Tag::read_from_path(path)?;
+    // A key set to no values is simply not written.
+    let comments = |tag: &metaflac::Tag| {
+        tag.vorbis_comments()
+            .map(|comment| {
+                let mut comments: Vec<_> = comment
+                    .comments
+                    .iter()
+                    .filter(|(_, values)| !values.is_empty())
+                    .map(|(key, values)| (key.clone(), values.clone()))
+                    .collect();
+                comments.sort();
+                (comment.vendor_string.clone(), comments)
+            })
+            .unwrap_or_default()
+    };
+    if comments(&written) != comments(flac) {
+        return Err(mismatch(path, "vorbis comments read back differently"));
+    }
+    if !written.pictures().eq(flac.pictures()) {
+        return Err(mismatch(path, "pictures read back differently"));
+    }
+    let applications = |tag: &metaflac::Tag| {
+        tag.blocks()
+            .filter_map(|block| match block {
+                metaflac::Block::Application(application) => Some(application.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    if applications(&written) != applications(flac) {
+        return Err(mismatch(path, "application blocks read back differently"));
+    }
+    Ok(())
+}
+
+/// Writes an MP3 file's ID3v2 tag. With `--verify-writes`, fails unless the
+/// audio frames are byte-identical afterward and every frame reads back as
+/// written.
+pub(crate) fn write_id3(tag: &id3::Tag, path: &Path) -> Result<()> {
+    if !enabled() {
+        tag.write_to_path(path, tag.version())?;
+        manifest::record(Action::Modified, path);
+        return Ok(());
+    }
+
+    let before = audio_digest(path, mp3_audio)?;
+    tag.write_to_path(path, tag.version())?;
+    manifest::record(Action::Modified, path);
+    check_audio(path, before, mp3_audio)?;
+
+    let written = match id3::Tag::read_from_path(path) {
+        Ok(written) => written,
+        Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let same = written.frames().count() == tag.frames().count()
+        && tag.frames().all(|frame| {
+            written
+                .frames()
+                .any(|other| other.id() == frame.id() && other.content() == frame.content())
+        });
+    match same {
+        true => Ok(()),
+        false => Err(mismatch(path, "ID3 frames read back differently")),
+    }
+}
+
+fn mismatch(path: &Path, what: &str) -> Error {
+    Error::WriteVerification(format!("{}: {what}", path.display()))
+}
+
+fn audio_digest(path: &Path, audio: fn(&[u8]) -> Option<&[u8]>) -> Result<[u8; 32]> {
+    let data = fs::read(path)?;
+    match audio(&data) {
+        Some(audio) => Ok(digest::sha256(audio)),
+        None => Err(mismatch(path, "unreadable file structure")),
+    }
+}
+
+fn check_audio(path: &Path, before: [u8; 32], audio: fn(&[u8]) -> Option<&[u8]>) -> Result<()> {
+    match audio_digest(path, audio)? == before {
+        true => Ok(()),
+        false => Err(mismatch(path, "audio frames changed")),
+    }
+}
+
+/// The length of an ID3v2 tag at the start of `data`, or 0 if there is none.
+fn id3v2_len(data: &[u8]) -> usize {
+    match data.get(..10) {
+        Some(header) if header.starts_with(b"ID3") => {
+            let size = header[6..10]
+                .iter()
+                .fold(0, |size, &b| (size << 7) | usize::from(b & 0x7f));
+            let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    }
+}
+
+/// The frames of a FLAC file: everything after its metadata blocks.
+pub(crate) fn flac_audio(data: &[u8]) -> Option<&[u8]> {
+    let data = data.get(id3v2_len(data)..)?;
+    let mut at = 4;
+    if !data.starts_with(b"fLaC") {
+        return None;
+    }
+    loop {
+        let header = data.get(at..at + 4)?;
+        let length =
+            usize::from(header[1]) << 16 | usize::from(header[2]) << 8 | usize::from(header[3]);
+        at += 4 + length;
+        if header[0] & 0x80 != 0 {
+            return data.get(at..);
+        }
+    }
+}
+
+/// The frames of an MP3 file: everything but its ID3v2 tag and any ID3v1 tag
+/// at the end.
+pub(crate) fn mp3_audio(data: &[u8]) -> Option<&[u8]> {
+    let data = data.get(id3v2_len(data)..)?;
+    match data.len().checked_sub(128) {
+        Some(v1) if data[v1..].starts_with(b"TAG") => Some(&data[..v1]),
+        _ => Some(data),
+    }
+}
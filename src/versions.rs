@@ -0,0 +1,163 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Title suffixes marking an alternate version of a song, and the kind of
+/// version each one marks. Earlier entries win, so "Live Radio Edit" and
+/// "Song (Live) [Radio Edit]" are both live.
+const KINDS: &[(&str, &str)] = &[
+    ("karaoke", "karaoke"),
+    ("instrumental", "instrumental"),
+    ("a cappella", "a cappella"),
+    ("acapella", "a cappella"),
+    ("live", "live"),
+    ("demo", "demo"),
+    ("acoustic", "acoustic"),
+    ("remix", "remix"),
+    ("mix", "remix"),
+    ("radio edit", "edit"),
+    ("single edit", "edit"),
+    ("edit", "edit"),
+    ("extended", "extended"),
+    ("remaster", "remaster"),
+    ("mono", "mono"),
+    ("version", "version"),
+];
+
+/// Durations within this many seconds of each other are taken to be the same
+/// recording.
+const SAME_LENGTH: f64 = 2.0;
+
+pub(crate) struct Track {
+    pub(crate) path: PathBuf,
+    pub(crate) artist: String,
+    pub(crate) title: String,
+    pub(crate) seconds: Option<f64>,
+}
+
+/// One of several tracks sharing an artist and base title.
+pub(crate) struct Version<'a> {
+    pub(crate) track: &'a Track,
+
+    /// The kind of alternate version its title marks it as, if any.
+    pub(crate) kind: Option<&'static str>,
+
+    /// Whether this is the version to use in playlists: the unmarked track
+    /// whose length the most other unmarked tracks share.
+    pub(crate) canonical: bool,
+
+    pub(crate) note: Option<String>,
+}
+
+/// Groups tracks which look like versions of the same song, keyed by artist
+/// and base title, and leaves out songs with only one version.
+pub(crate) fn find(tracks: &[Track]) -> BTreeMap<(String, String), Vec<Version<'_>>> {
+    let mut groups: BTreeMap<_, Vec<Version>> = BTreeMap::new();
+    for track in tracks {
+        let (base, kind) = split_title(&track.title);
+        groups
+            .entry((track.artist.to_lowercase(), base))
+            .or_default()
+            .push(Version {
+                track,
+                kind,
+                canonical: false,
+                note: None,
+            });
+    }
+    groups.retain(|_, versions| versions.len() > 1);
+
+    for versions in groups.values_mut() {
+        annotate(versions);
+    }
+    groups
+}
+
+fn same_length(a: Option<f64>, b: Option<f64>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if (a - b).abs() <= SAME_LENGTH)
+}
+
+fn annotate(versions: &mut [Version]) {
+    let unmarked: Vec<usize> = (0..versions.len())
+        .filter(|&idx| versions[idx].kind.is_none())
+        .collect();
+    let canonical = unmarked.iter().copied().max_by_key(|&idx| {
+        let shared = unmarked
+            .iter()
+            .filter(|&&other| {
+                same_length(versions[idx].track.seconds, versions[other].track.seconds)
+            })
+            .count();
+        // Prefer the earliest path among equals.
+        (shared, usize::MAX - idx)
+    });
+
+    for idx in 0..versions.len() {
+        versions[idx].canonical = Some(idx) == canonical;
+        let seconds = versions[idx].track.seconds;
+
+        let duplicate = (0..idx).find(|&other| {
+            versions[other].kind == versions[idx].kind
+                && same_length(versions[other].track.seconds, seconds)
+        });
+        versions[idx].note = match (duplicate, canonical) {
+            (Some(other), _) => Some(format!(
+                "same length as {}; likely a duplicate",
+                versions[other].track.path.display()
+            )),
+            (None, Some(canonical))
+                if versions[idx].kind.is_none()
+                    && idx != canonical
+                    && !same_length(versions[canonical].track.seconds, seconds)
+                    && seconds.is_some() =>
+            {
+                Some("unmarked, but a different length; likely an alternate version".into())
+            }
+            _ => None,
+        };
+    }
+}
+
+/// A title without any version markers, lowercased for comparison, and the
+/// kind of version the markers name. Markers are recognized in parentheses,
+/// brackets, or after a dash: "Song (Radio Edit)", "Song [Live]",
+/// "Song - Instrumental".
+pub(crate) fn split_title(title: &str) -> (String, Option<&'static str>) {
+    let mut base = title.trim().to_lowercase();
+    let mut kind = None;
+
+    loop {
+        let (rest, marker) = if let Some(stripped) = base.strip_suffix(')') {
+            match stripped.rfind('(') {
+                Some(open) => (&base[..open], &stripped[open + 1..]),
+                None => break,
+            }
+        } else if let Some(stripped) = base.strip_suffix(']') {
+            match stripped.rfind('[') {
+                Some(open) => (&base[..open], &stripped[open + 1..]),
+                None => break,
+            }
+        } else if let Some(dash) = base.rfind(" - ") {
+            (&base[..dash], &base[dash + 3..])
+        } else {
+            break;
+        };
+
+        let Some(marked) = KINDS
+            .iter()
+            .position(|(word, _)| contains_word(marker, word))
+        else {
+            break;
+        };
+        kind = Some(kind.map_or(marked, |kind: usize| kind.min(marked)));
+        base = rest.trim_end().to_string();
+    }
+
+    (base, kind.map(|idx| KINDS[idx].1))
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(at, _)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
@@ -0,0 +1,219 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+use metaflac::block::VorbisComment;
+
+use crate::{Attributes, Error, Result};
+
+/// Something worth pointing out which doesn't stop a command. Each kind has
+/// a code, by which it can be silenced or made an error for a collection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Code {
+    /// A YEAR tag with no DATE, or a DATE which isn't an ISO 8601 date
+    NonstandardYear,
+    MissingAlbumArtist,
+    MissingTrack,
+    /// An attribute sheet fetched from a URL without --sha256
+    UnpinnedSheet,
+    /// An attribute sheet which wasn't UTF-8
+    SheetEncoding,
+    /// A change to a protected field, which was left as it was
+    ProtectedField,
+    /// An invalid sheet row, left out with --skip-invalid
+    SkippedRow,
+    /// A cached response used because the network was unreachable
+    StaleCache,
+    /// A file with more channels than FLAC holds
+    Downmix,
+    /// An album too long for one disc, split by track
+    SplitAlbum,
+    /// A different number of files than a release has tracks
+    TrackCount,
+    /// --nice on a platform which doesn't support it
+    Unsupported,
+    /// An album whose tracks credit different artists, filed by album artist
+    MixedArtists,
+    /// A sheet row for a file whose tags have changed since it was listed
+    StaleRow,
+    /// A sheet row which matches no file
+    UnmatchedRow,
+    /// A sheet row which matches more than one file
+    AmbiguousRow,
+    /// An attribute a format has no field of its own for
+    UnrepresentableField,
+    /// Resampling between the 44.1kHz and 48kHz families which a rate of
+    /// the source's own family would avoid
+    Resample,
+}
+
+impl Code {
+    const ALL: &'static [Code] = &[
+        Code::NonstandardYear,
+        Code::MissingAlbumArtist,
+        Code::MissingTrack,
+        Code::UnpinnedSheet,
+        Code::SheetEncoding,
+        Code::ProtectedField,
+        Code::SkippedRow,
+        Code::StaleCache,
+        Code::Downmix,
+        Code::SplitAlbum,
+        Code::TrackCount,
+        Code::Unsupported,
+        Code::MixedArtists,
+        Code::StaleRow,
+        Code::UnmatchedRow,
+        Code::AmbiguousRow,
+        Code::UnrepresentableField,
+        Code::Resample,
+    ];
+
+    fn number(self) -> usize {
+        Code::ALL
+            .iter()
+            .position(|&code| code == self)
+            .expect("every code is listed")
+            + 1
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Code::NonstandardYear => "nonstandard-year",
+            Code::MissingAlbumArtist => "missing-album-artist",
+            Code::MissingTrack => "missing-track",
+            Code::UnpinnedSheet => "unpinned-sheet",
+            Code::SheetEncoding => "sheet-encoding",
+            Code::ProtectedField => "protected-field",
+            Code::SkippedRow => "skipped-row",
+            Code::StaleCache => "stale-cache",
+            Code::Downmix => "downmix",
+            Code::SplitAlbum => "split-album",
+            Code::TrackCount => "track-count",
+            Code::Unsupported => "unsupported",
+            Code::MixedArtists => "mixed-artists",
+            Code::StaleRow => "stale-row",
+            Code::UnmatchedRow => "unmatched-row",
+            Code::AmbiguousRow => "ambiguous-row",
+            Code::UnrepresentableField => "unrepresentable-field",
+            Code::Resample => "resample",
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "W{:03}", self.number())
+    }
+}
+
+/// Codes are given as W001, or by name, as nonstandard-year.
+impl FromStr for Code {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|code| code.to_string().eq_ignore_ascii_case(s) || code.name() == s)
+            .ok_or_else(|| format!("unknown warning code: {s}"))
+    }
+}
+
+/// Which warnings are silenced and which are errors.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Policy {
+    pub(crate) allow: Vec<Code>,
+    pub(crate) deny: Vec<Code>,
+    /// Whether every warning not allowed is an error
+    pub(crate) all_errors: bool,
+}
+
+impl Policy {
+    /// Layers another policy, such as one from the command line, over this
+    /// one: what it allows or denies overrides what this one does.
+    pub(crate) fn extend(&mut self, other: &Policy) {
+        self.allow.retain(|code| !other.deny.contains(code));
+        self.deny.retain(|code| !other.allow.contains(code));
+        self.allow.extend(&other.allow);
+        self.deny.extend(&other.deny);
+        self.all_errors |= other.all_errors;
+    }
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+static DENIED: AtomicUsize = AtomicUsize::new(0);
+
+/// Records which warnings are silenced or errors. Only the first call has
+/// any effect.
+pub(crate) fn configure(policy: Policy) {
+    let _ = POLICY.set(policy);
+}
+
+/// Reports a warning, unless it's allowed. A denied warning is reported as
+/// an error, and fails the command once it has finished.
+pub(crate) fn emit(code: Code, message: impl Display) {
+    let policy = POLICY.get_or_init(Policy::default);
+    if policy.allow.contains(&code) {
+        return;
+    }
+    match policy.all_errors || policy.deny.contains(&code) {
+        true => {
+            eprintln!("error[{code}]: {message}");
+            DENIED.fetch_add(1, Ordering::Relaxed);
+        }
+        false => eprintln!("warning[{code}]: {message}"),
+    }
+}
+
+/// Fails if any denied warning was reported.
+pub(crate) fn finish() -> Result<()> {
+    match DENIED.load(Ordering::Relaxed) {
+        0 => Ok(()),
+        count => Err(Error::WarningsDenied(count)),
+    }
+}
+
+/// Warns about a file's tags: a nonstandard year, and a missing album artist
+/// or track number. `comment` holds a FLAC file's raw tags.
+pub(crate) fn lint(path: &str, attributes: &Attributes, comment: Option<&VorbisComment>) {
+    if let Some(comment) = comment {
+        let date = comment.get("DATE").and_then(|dates| dates.first());
+        match date {
+            None if comment.get("YEAR").is_some() => emit(
+                Code::NonstandardYear,
+                format_args!("{path}: YEAR is set, but DATE isn't"),
+            ),
+            Some(date) if !is_iso_date(date) => emit(
+                Code::NonstandardYear,
+                format_args!("{path}: DATE {date:?} isn't YYYY, YYYY-MM, or YYYY-MM-DD"),
+            ),
+            _ => {}
+        }
+    }
+    if attributes.album_artist.is_none() {
+        emit(
+            Code::MissingAlbumArtist,
+            format_args!("{path}: no album artist"),
+        );
+    }
+    if attributes.track.is_none() {
+        emit(Code::MissingTrack, format_args!("{path}: no track number"));
+    }
+}
+
+fn is_iso_date(date: &str) -> bool {
+    let parts: Vec<&str> = date.split('-').collect();
+    let digits = |part: &str, len| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    match parts[..] {
+        [year] => digits(year, 4),
+        [year, month] => digits(year, 4) && digits(month, 2),
+        [year, month, day] => digits(year, 4) && digits(month, 2) && digits(day, 2),
+        _ => false,
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{ignore::Ignore, metrics, Result};
+
+/// Finds files in a directory, or any below it, once they've finished
+/// arriving: when they've kept the same size and modification time for the
+/// settling interval. On Linux, inotify wakes it when a file is created,
+/// written, or moved in; elsewhere it looks again every interval.
+pub(crate) struct Watcher {
+    dir: PathBuf,
+    settle: Duration,
+    /// Files still arriving, with how they looked and since when
+    arriving: HashMap<PathBuf, (Look, Instant)>,
+    /// Files already handed out or claimed, which aren't taken again while
+    /// they're still there
+    taken: HashSet<PathBuf>,
+    events: Events,
+}
+
+type Look = (u64, Option<SystemTime>);
+
+impl Watcher {
+    pub(crate) fn new(dir: &Path, settle: Duration) -> Self {
+        Watcher {
+            dir: dir.to_owned(),
+            settle,
+            arriving: HashMap::new(),
+            taken: HashSet::new(),
+            events: Events::new(),
+        }
+    }
+
+    /// Files with one of the given extensions which have settled since the
+    /// last call, in path order. Files there when watching starts count as
+    /// new. Waits for at least one, unless `wait` is false: then, once
+    /// nothing is left arriving, it returns what it has, which may be none.
+    pub(crate) fn next(
+        &mut self,
+        ignore: &Ignore,
+        extensions: &[&str],
+        wait: bool,
+    ) -> Result<Vec<PathBuf>> {
+        loop {
+            self.events.watch(dirs(&self.dir));
+            let settled = self.look(ignore, extensions)?;
+            if !settled.is_empty() || (!wait && self.arriving.is_empty()) {
+                return Ok(settled);
+            }
+            // With nothing arriving, only an event can bring anything new.
+            let timeout = match self.arriving.is_empty() && self.events.live() {
+                true => None,
+                false => Some(self.settle.max(Duration::from_millis(100))),
+            };
+            self.events.wait(timeout);
+        }
+    }
+
+    /// Keeps a file the caller wrote, such as a conversion, from being
+    /// taken as a new arrival.
+    pub(crate) fn claim(&mut self, path: &Path) {
+        self.taken.insert(path.to_owned());
+    }
+
+    fn look(&mut self, ignore: &Ignore, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+        let files = ignore.walk(&self.dir, extensions)?;
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        self.taken.retain(|path| present.contains(path));
+        self.arriving.retain(|path, _| present.contains(path));
+
+        let mut settled = Vec::new();
+        for path in &files {
+            if self.taken.contains(path) {
+                continue;
+            }
+            // A file can go between the walk and now.
+            let Ok(metadata) = fs::metadata(path) else {
+                continue;
+            };
+            let look = (metadata.len(), metadata.modified().ok());
+            match self.arriving.get(path) {
+                Some((seen, since)) if *seen == look && since.elapsed() >= self.settle => {
+                    self.arriving.remove(path);
+                    self.taken.insert(path.clone());
+                    settled.push(path.clone());
+                }
+                Some((seen, _)) if *seen == look => {}
+                _ => {
+                    self.arriving.insert(path.clone(), (look, Instant::now()));
+                }
+            }
+        }
+        metrics::queued(self.arriving.len() + settled.len());
+        Ok(settled)
+    }
+}
+
+/// `dir` and every directory below it.
+fn dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![dir.to_owned()];
+    let mut idx = 0;
+    while let Some(dir) = dirs.get(idx) {
+        idx += 1;
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let below: Vec<_> = entries
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .map(|entry| entry.path())
+            .collect();
+        dirs.extend(below);
+    }
+    dirs
+}
+
+/// Wakes a watcher when something changes in a directory it watches.
+#[cfg(target_os = "linux")]
+struct Events {
+    /// The inotify instance, unless one couldn't be had
+    fd: Option<std::os::fd::OwnedFd>,
+    watched: HashSet<PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+impl Events {
+    fn new() -> Self {
+        use std::os::fd::FromRawFd;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        Events {
+            fd: (fd >= 0).then(|| unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) }),
+            watched: HashSet::new(),
+        }
+    }
+
+    fn live(&self) -> bool {
+        self.fd.is_some()
+    }
+
+    /// Watches each directory for files created, written, or moved into it.
+    /// Directories no longer among them have gone, taking their watches
+    /// with them, and are watched afresh if they're made again.
+    fn watch(&mut self, dirs: Vec<PathBuf>) {
+        use std::os::{fd::AsRawFd, unix::ffi::OsStrExt};
+
+        let Some(fd) = &self.fd else {
+            return;
+        };
+        self.watched.retain(|dir| dirs.contains(dir));
+        for dir in dirs {
+            if self.watched.contains(&dir) {
+                continue;
+            }
+            let Ok(name) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let mask = libc::IN_CREATE | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), name.as_ptr(), mask) } >= 0 {
+                self.watched.insert(dir);
+            }
+        }
+    }
+
+    /// Waits for an event, or until the timeout, if any. What the events
+    /// were doesn't matter: the watcher looks over the whole directory
+    /// afresh.
+    fn wait(&mut self, timeout: Option<Duration>) {
+        use std::os::fd::AsRawFd;
+
+        let Some(fd) = &self.fd else {
+            thread::sleep(timeout.unwrap_or(Duration::from_secs(1)));
+            return;
+        };
+        let mut poll = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+        });
+        unsafe { libc::poll(&mut poll, 1, millis) };
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Events;
+
+#[cfg(not(target_os = "linux"))]
+impl Events {
+    fn new() -> Self {
+        Events
+    }
+
+    fn live(&self) -> bool {
+        false
+    }
+
+    fn watch(&mut self, _dirs: Vec<PathBuf>) {}
+
+    fn wait(&mut self, timeout: Option<Duration>) {
+        thread::sleep(timeout.unwrap_or(Duration::from_secs(1)));
+    }
+}
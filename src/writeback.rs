@@ -0,0 +1,110 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use metaflac::block::StreamInfo;
+
+use crate::{audit::AuditLog, lock::FileLock, verify, Result};
+
+/// The duration, in seconds
+pub(crate) const DURATION: &str = "FLACDAT_DURATION";
+/// The MD5 of the decoded samples, for files whose encoder stored none in
+/// STREAMINFO
+pub(crate) const AUDIO_MD5: &str = "FLACDAT_AUDIO_MD5";
+/// The dynamic range score
+pub(crate) const DR: &str = "FLACDAT_DR";
+/// The peak and RMS the score was measured from, in dB
+pub(crate) const DR_PEAK: &str = "FLACDAT_DR_PEAK";
+pub(crate) const DR_RMS: &str = "FLACDAT_DR_RMS";
+/// The integrated loudness, in LUFS
+pub(crate) const LOUDNESS: &str = "FLACDAT_LOUDNESS";
+/// The highest sample peak, as a linear amplitude
+pub(crate) const PEAK: &str = "FLACDAT_PEAK";
+
+const STREAM: &str = "FLACDAT_STREAM";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns write-back on, with `--writeback` or `[scan] writeback = true`.
+/// While it's off, as it is by default, values are neither stored nor read
+/// back.
+pub(crate) fn configure(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The values stored under `keys`, in order, if the file holds every one
+/// of them for its current audio.
+pub(crate) fn read(path: &Path, keys: &[&str]) -> Result<Option<Vec<String>>> {
+    if !enabled() || !is_flac(path) {
+        return Ok(None);
+    }
+    let flac = metaflac::Tag::read_from_path(path)?;
+    let (Some(info), Some(comment)) = (flac.get_streaminfo(), flac.vorbis_comments()) else {
+        return Ok(None);
+    };
+    let first = |key: &str| comment.get(key).and_then(|values| values.first()).cloned();
+    if first(STREAM) != Some(stamp(info)) {
+        return Ok(None);
+    }
+    Ok(keys.iter().map(|&key| first(key)).collect())
+}
+
+/// Stores values computed from a FLAC's audio in its vorbis comments, so
+/// that other tools and later runs can use them without decoding the file
+/// again. The file's duration goes in too, and FLACDAT_STREAM, recording the
+/// STREAMINFO the values were computed against; values under a stamp which
+/// no longer matches, because the audio has been replaced, aren't read back.
+/// Files which aren't FLAC, or which are read-only, are left as they are:
+/// write-back never makes a file writable.
+pub(crate) fn store(path: &Path, values: &[(&str, String)], log: &mut AuditLog) -> Result<()> {
+    if !enabled() || !is_flac(path) || fs::metadata(path)?.permissions().readonly() {
+        return Ok(());
+    }
+
+    let _lock = FileLock::acquire(path)?;
+    let mut flac = metaflac::Tag::read_from_path(path)?;
+    let Some(info) = flac.get_streaminfo().cloned() else {
+        return Ok(());
+    };
+    let comment = flac.vorbis_comments_mut();
+    let before = comment.clone();
+
+    comment.set(STREAM, vec![stamp(&info)]);
+    if info.sample_rate > 0 && info.total_samples > 0 {
+        let seconds = info.total_samples as f64 / f64::from(info.sample_rate);
+        comment.set(DURATION, vec![format!("{seconds:.3}")]);
+    }
+    for (key, value) in values {
+        comment.set(*key, vec![value.clone()]);
+    }
+
+    if *comment != before {
+        let after = comment.clone();
+        verify::write_flac(&mut flac, path)?;
+        log.vorbis(path, &before, &after)?;
+    }
+    Ok(())
+}
+
+/// Whether a vorbis comment key is one write-back keeps. A lossy copy of a
+/// file shouldn't inherit them, since they describe the original's audio.
+pub(crate) fn is_computed(key: &str) -> bool {
+    key.to_ascii_uppercase().starts_with("FLACDAT_")
+}
+
+/// The STREAMINFO MD5 and sample count, which change whenever the audio
+/// does, short of a re-encode that leaves the samples as they were.
+fn stamp(info: &StreamInfo) -> String {
+    format!("{}/{}", hex::encode(&info.md5), info.total_samples)
+}
+
+fn is_flac(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("flac"))
+}
@@ -0,0 +1,425 @@
+//! Fixtures for the integration tests: tiny FLAC, MP3, and WAV files
+//! synthesized at test time, and a scratch directory to run flacdat in.
+
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use std::{
+    env, f64, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use id3::TagLike;
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const CHANNELS: u16 = 2;
+/// Frames of a FLAC fixture hold this many samples per channel.
+const BLOCK_SIZE: usize = 1152;
+
+/// A directory of its own for a test, emptied when created and removed when
+/// dropped, in which flacdat runs with its data, cache, and config
+/// directories kept inside it.
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir()
+            .join("flacdat-tests")
+            .join(format!("{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch { dir }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Runs flacdat in the directory, failing the test unless it succeeds.
+    pub fn flacdat(&self, args: &[&str]) -> Output {
+        let output = self.try_flacdat(args);
+        assert!(
+            output.status.success(),
+            "flacdat {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    pub fn try_flacdat(&self, args: &[&str]) -> Output {
+        let home = self.dir.join(".home");
+        Command::new(env!("CARGO_BIN_EXE_flacdat"))
+            .args(args)
+            .current_dir(&self.dir)
+            .env("HOME", &home)
+            .env("XDG_DATA_HOME", home.join("data"))
+            .env("XDG_CACHE_HOME", home.join("cache"))
+            .env("XDG_CONFIG_HOME", home.join("config"))
+            .env_remove("FLACDAT_CONFIG")
+            .output()
+            .unwrap()
+    }
+
+    /// The rows `flacdat list` prints for files, keyed by column.
+    pub fn list(&self, files: &[&str]) -> Vec<Row> {
+        let mut args = vec!["list"];
+        args.extend(files);
+        let output = self.flacdat(&args);
+        let mut reader = csv::Reader::from_reader(output.stdout.as_slice());
+        let headers = reader.headers().unwrap().clone();
+        reader
+            .records()
+            .map(|record| Row {
+                headers: headers.iter().map(String::from).collect(),
+                values: record.unwrap().iter().map(String::from).collect(),
+            })
+            .collect()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A row of an attribute sheet.
+#[derive(Clone, Debug)]
+pub struct Row {
+    headers: Vec<String>,
+    values: Vec<String>,
+}
+
+impl Row {
+    pub fn get(&self, column: &str) -> &str {
+        let idx = self.index(column);
+        &self.values[idx]
+    }
+
+    pub fn set(&mut self, column: &str, value: &str) {
+        let idx = self.index(column);
+        self.values[idx] = value.into();
+    }
+
+    fn index(&self, column: &str) -> usize {
+        self.headers
+            .iter()
+            .position(|header| header == column)
+            .unwrap_or_else(|| panic!("no {column} column"))
+    }
+}
+
+/// Writes rows as an attribute sheet.
+pub fn write_sheet(path: &Path, rows: &[Row]) {
+    let mut writer = csv::Writer::from_path(path).unwrap();
+    writer.write_record(&rows[0].headers).unwrap();
+    for row in rows {
+        writer.write_record(&row.values).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// A stereo 16-bit sine wave, interleaved, with the channels a fifth apart.
+pub fn sine(frequency: f64, samples: usize) -> Vec<i16> {
+    let at = |frequency: f64, n: usize| {
+        let t = n as f64 / f64::from(SAMPLE_RATE);
+        ((t * frequency * 2.0 * f64::consts::PI).sin() * 8000.0) as i16
+    };
+    (0..samples)
+        .flat_map(|n| [at(frequency, n), at(frequency * 1.5, n)])
+        .collect()
+}
+
+/// Writes a PCM WAV file, with tags in a RIFF INFO list, such as INAM for
+/// the title and IART for the artist.
+pub fn write_wav(path: &Path, samples: &[i16], info: &[(&str, &str)]) {
+    let mut list = b"INFO".to_vec();
+    for (id, value) in info {
+        let mut value = value.as_bytes().to_vec();
+        value.push(0);
+        list.extend(id.as_bytes());
+        list.extend((value.len() as u32).to_le_bytes());
+        if value.len() % 2 == 1 {
+            value.push(0);
+        }
+        list.extend(value);
+    }
+
+    let block_align = CHANNELS * 2;
+    let mut fmt = Vec::new();
+    fmt.extend(1u16.to_le_bytes());
+    fmt.extend(CHANNELS.to_le_bytes());
+    fmt.extend(SAMPLE_RATE.to_le_bytes());
+    fmt.extend((SAMPLE_RATE * u32::from(block_align)).to_le_bytes());
+    fmt.extend(block_align.to_le_bytes());
+    fmt.extend(16u16.to_le_bytes());
+
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+    let mut chunks = Vec::new();
+    for (id, body) in [(b"fmt ", &fmt), (b"LIST", &list), (b"data", &data)] {
+        if id == b"LIST" && info.is_empty() {
+            continue;
+        }
+        chunks.extend(id);
+        chunks.extend((body.len() as u32).to_le_bytes());
+        chunks.extend(body);
+    }
+
+    let mut wav = b"RIFF".to_vec();
+    wav.extend((chunks.len() as u32 + 4).to_le_bytes());
+    wav.extend(b"WAVE");
+    wav.extend(chunks);
+    fs::write(path, wav).unwrap();
+}
+
+/// Writes a FLAC file of the samples, stored verbatim, with the vorbis
+/// comments given.
+pub fn write_flac(path: &Path, samples: &[i16], comments: &[(&str, &str)]) {
+    let per_channel = samples.len() / usize::from(CHANNELS);
+
+    let mut streaminfo = Vec::new();
+    streaminfo.extend((BLOCK_SIZE as u16).to_be_bytes());
+    streaminfo.extend((BLOCK_SIZE as u16).to_be_bytes());
+    // Frame sizes unknown
+    streaminfo.extend([0; 6]);
+    // 20 bits of sample rate, 3 of channels - 1, 5 of bits per sample - 1,
+    // and 36 of total samples
+    let packed = u64::from(SAMPLE_RATE) << 44
+        | u64::from(CHANNELS - 1) << 41
+        | 15 << 36
+        | per_channel as u64;
+    streaminfo.extend(packed.to_be_bytes());
+    // MD5 unknown
+    streaminfo.extend([0; 16]);
+
+    let mut flac = b"fLaC".to_vec();
+    // The last metadata block, STREAMINFO
+    flac.push(0x80);
+    flac.extend(&(streaminfo.len() as u32).to_be_bytes()[1..]);
+    flac.extend(streaminfo);
+
+    let frames = samples.chunks(BLOCK_SIZE * usize::from(CHANNELS));
+    for (number, block) in frames.enumerate() {
+        let block_size = block.len() / usize::from(CHANNELS);
+        assert!(number < 128, "frame numbers past 127 take more than a byte");
+        // Sync code, fixed block size; block size as 16 bits at the end of
+        // the header, 44.1kHz; two independent channels, 16 bits per sample;
+        // the frame number; the block size - 1
+        let mut frame = vec![0xFF, 0xF8, 0x79, 0x18, number as u8];
+        frame.extend((block_size as u16 - 1).to_be_bytes());
+        frame.push(crc8(&frame));
+        for channel in 0..usize::from(CHANNELS) {
+            // A verbatim subframe
+            frame.push(0x02);
+            for sample in block.iter().skip(channel).step_by(usize::from(CHANNELS)) {
+                frame.extend(sample.to_be_bytes());
+            }
+        }
+        frame.extend(crc16(&frame).to_be_bytes());
+        flac.extend(frame);
+    }
+    fs::write(path, flac).unwrap();
+
+    if !comments.is_empty() {
+        let mut tag = metaflac::Tag::read_from_path(path).unwrap();
+        for (key, value) in comments {
+            tag.vorbis_comments_mut().set(*key, vec![*value]);
+        }
+        tag.save().unwrap();
+    }
+}
+
+/// Writes an MP3 file of silent frames, with an ID3v2.4 tag holding the
+/// text frames given, such as TIT2 for the title.
+pub fn write_mp3(path: &Path, frames: usize, text: &[(&str, &str)]) {
+    // MPEG-1 layer III, 128kbps, 44.1kHz, stereo, which takes 417 bytes a
+    // frame; side information of all zeros decodes to silence.
+    let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+    frame.resize(417, 0);
+    fs::write(path, frame.repeat(frames)).unwrap();
+
+    let mut tag = id3::Tag::new();
+    for (id, value) in text {
+        tag.set_text(*id, *value);
+    }
+    tag.write_to_path(path, id3::Version::Id3v24).unwrap();
+}
+
+/// The audio of a FLAC or MP3 file, less its tags, for checking that tag
+/// writes leave it alone.
+pub fn audio(path: &Path) -> Vec<u8> {
+    let data = fs::read(path).unwrap();
+    let start = match &data[..4] {
+        b"fLaC" => {
+            let mut pos = 4;
+            loop {
+                let header = &data[pos..pos + 4];
+                pos += 4
+                    + (usize::from(header[1]) << 16
+                        | usize::from(header[2]) << 8
+                        | usize::from(header[3]));
+                if header[0] & 0x80 != 0 {
+                    break pos;
+                }
+            }
+        }
+        [b'I', b'D', b'3', _] => {
+            // The size is syncsafe, seven bits a byte.
+            let size = data[6..10]
+                .iter()
+                .fold(0, |size, &b| size << 7 | usize::from(b));
+            10 + size
+        }
+        _ => 0,
+    };
+    data[start..].to_vec()
+}
+
+/// Decodes the 16-bit stereo audio of a FLAC file, interleaved, checking
+/// each frame's CRCs. It reads only the subframes flacdat's encoder and
+/// `write_flac` write: constant, verbatim, and fixed prediction.
+pub fn decode_flac(path: &Path) -> Vec<i16> {
+    let data = audio(path);
+    let mut samples = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut bits = Bits::new(&data[pos..]);
+        assert_eq!(bits.read(15), 0x7FFC, "frame sync at byte {pos}");
+        bits.read(1);
+        let block_code = bits.read(4);
+        let rate_code = bits.read(4);
+        let assignment = bits.read(4);
+        bits.read(4);
+        // The frame number, coded like UTF-8
+        let lead = bits.read(8);
+        bits.read(8 * (lead as u8).leading_ones().saturating_sub(1));
+        let count = match block_code {
+            1 => 192,
+            2..=5 => 576 << (block_code - 2),
+            6 => bits.read(8) as usize + 1,
+            7 => bits.read(16) as usize + 1,
+            _ => 256 << (block_code - 8),
+        };
+        bits.read(match rate_code {
+            12 => 8,
+            13 | 14 => 16,
+            _ => 0,
+        });
+        assert_eq!(bits.read(8) as u8, crc8(&bits.data[..bits.pos / 8 - 1]));
+
+        // Side channels take a bit more than the others.
+        let widths = match assignment {
+            8 => [16, 17],
+            9 => [17, 16],
+            10 => [16, 17],
+            _ => [16, 16],
+        };
+        let [a, b] = widths.map(|width| subframe(&mut bits, count, width));
+        for (a, b) in a.into_iter().zip(b) {
+            let (left, right) = match assignment {
+                8 => (a, a - b),
+                9 => (a + b, b),
+                10 => {
+                    let mid = a << 1 | b & 1;
+                    ((mid + b) >> 1, (mid - b) >> 1)
+                }
+                _ => (a, b),
+            };
+            samples.extend([left as i16, right as i16]);
+        }
+
+        bits.pos = bits.pos.div_ceil(8) * 8;
+        let end = bits.pos / 8;
+        assert_eq!(bits.read(16) as u16, crc16(&bits.data[..end]));
+        pos += end + 2;
+    }
+    samples
+}
+
+fn subframe(bits: &mut Bits, count: usize, width: u32) -> Vec<i64> {
+    bits.read(1);
+    let kind = bits.read(6);
+    assert_eq!(bits.read(1), 0, "wasted bits");
+    match kind {
+        0 => vec![bits.signed(width); count],
+        1 => (0..count).map(|_| bits.signed(width)).collect(),
+        8..=12 => {
+            let order = kind as usize - 8;
+            let mut samples: Vec<i64> = (0..order).map(|_| bits.signed(width)).collect();
+            let wide = bits.read(2) == 1;
+            let partitions = 1 << bits.read(4);
+            for partition in 0..partitions {
+                let k = bits.read(if wide { 5 } else { 4 }) as u32;
+                assert_ne!(k, if wide { 31 } else { 15 }, "escaped partition");
+                let len = count / partitions - if partition == 0 { order } else { 0 };
+                for _ in 0..len {
+                    let mut zeros = 0;
+                    while bits.read(1) == 0 {
+                        zeros += 1;
+                    }
+                    let value = zeros << k | bits.read(k);
+                    let residual = (value >> 1) as i64 ^ -((value & 1) as i64);
+                    let s = &samples[samples.len() - order..];
+                    let predicted = match order {
+                        0 => 0,
+                        1 => s[0],
+                        2 => 2 * s[1] - s[0],
+                        3 => 3 * s[2] - 3 * s[1] + s[0],
+                        _ => 4 * s[3] - 6 * s[2] + 4 * s[1] - s[0],
+                    };
+                    samples.push(predicted + residual);
+                }
+            }
+            samples
+        }
+        _ => panic!("subframe type {kind}"),
+    }
+}
+
+/// Reads values most significant bit first.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits { data, pos: 0 }
+    }
+
+    fn read(&mut self, bits: u32) -> u64 {
+        (0..bits).fold(0, |value, _| {
+            let bit = self.data[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            self.pos += 1;
+            value << 1 | u64::from(bit)
+        })
+    }
+
+    fn signed(&mut self, bits: u32) -> i64 {
+        let value = self.read(bits);
+        (value << (64 - bits)) as i64 >> (64 - bits)
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+            0 => crc << 1,
+            _ => crc << 1 ^ 0x07,
+        })
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => crc << 1 ^ 0x8005,
+        })
+    })
+}
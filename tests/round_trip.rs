@@ -0,0 +1,265 @@
+//! list, apply, revert, convert, and watch, run end to end on fixtures made at test time.
+
+mod common;
+
+use common::{audio, sine, write_flac, write_mp3, write_sheet, Scratch};
+
+/// A FLAC and an MP3 carrying the same tags.
+fn tagged_pair(scratch: &Scratch) {
+    write_flac(
+        &scratch.path("a.flac"),
+        &sine(440.0, 4410),
+        &[
+            ("ARTIST", "Prince"),
+            ("ALBUM", "Purple Rain"),
+            ("TITLE", "When Doves Cry"),
+            ("TRACKNUMBER", "1"),
+            ("DATE", "1984"),
+        ],
+    );
+    write_mp3(
+        &scratch.path("b.mp3"),
+        8,
+        &[
+            ("TPE1", "Prince"),
+            ("TALB", "Purple Rain"),
+            ("TIT2", "Take Me With U"),
+            ("TRCK", "2"),
+            ("TYER", "1984"),
+        ],
+    );
+}
+
+#[test]
+fn list_reads_tags_of_each_format() {
+    let scratch = Scratch::new("list");
+    tagged_pair(&scratch);
+
+    let rows = scratch.list(&["a.flac", "b.mp3"]);
+    assert_eq!(rows.len(), 2);
+    for (row, path, title, track) in [
+        (&rows[0], "a.flac", "When Doves Cry", "1"),
+        (&rows[1], "b.mp3", "Take Me With U", "2"),
+    ] {
+        assert_eq!(row.get("path"), path);
+        assert_eq!(row.get("artist"), "Prince");
+        assert_eq!(row.get("album"), "Purple Rain");
+        assert_eq!(row.get("title"), title);
+        assert_eq!(row.get("track"), track);
+        assert_eq!(row.get("year"), "1984");
+    }
+}
+
+#[test]
+fn applying_a_sheet_as_listed_changes_nothing() {
+    let scratch = Scratch::new("noop");
+    tagged_pair(&scratch);
+
+    write_sheet(
+        &scratch.path("sheet.csv"),
+        &scratch.list(&["a.flac", "b.mp3"]),
+    );
+    let output = scratch.flacdat(&["apply", "--attributes", "sheet.csv", "--dry-run", "-q"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
+
+#[test]
+fn apply_in_place_writes_listed_changes() {
+    let scratch = Scratch::new("in-place");
+    tagged_pair(&scratch);
+    let before = [
+        audio(&scratch.path("a.flac")),
+        audio(&scratch.path("b.mp3")),
+    ];
+
+    let mut rows = scratch.list(&["a.flac", "b.mp3"]);
+    for row in &mut rows {
+        row.set("album", "Purple Rain (Deluxe)");
+        row.set("genre", "Funk");
+    }
+    rows[1].set("track", "3");
+    write_sheet(&scratch.path("sheet.csv"), &rows);
+    scratch.flacdat(&["apply", "--attributes", "sheet.csv", "--in-place", "-q"]);
+
+    let after = scratch.list(&["a.flac", "b.mp3"]);
+    for (row, title, track) in [
+        (&after[0], "When Doves Cry", "1"),
+        (&after[1], "Take Me With U", "3"),
+    ] {
+        assert_eq!(row.get("album"), "Purple Rain (Deluxe)");
+        assert_eq!(row.get("genre"), "Funk");
+        assert_eq!(row.get("title"), title);
+        assert_eq!(row.get("track"), track);
+    }
+    assert_eq!(audio(&scratch.path("a.flac")), before[0]);
+    assert_eq!(audio(&scratch.path("b.mp3")), before[1]);
+}
+
+#[test]
+fn revert_undoes_an_apply_in_place() {
+    let scratch = Scratch::new("revert");
+    tagged_pair(&scratch);
+
+    let mut rows = scratch.list(&["a.flac", "b.mp3"]);
+    for row in &mut rows {
+        row.set("album", "Purple Rain (Deluxe)");
+        row.set("composer", "Prince");
+    }
+    write_sheet(&scratch.path("sheet.csv"), &rows);
+    scratch.flacdat(&["apply", "--attributes", "sheet.csv", "--in-place", "-q"]);
+
+    let log = scratch.flacdat(&["log"]);
+    let log = String::from_utf8_lossy(&log.stdout);
+    let id = log.lines().last().unwrap().split('\t').next().unwrap();
+    scratch.flacdat(&["revert", id]);
+
+    for row in scratch.list(&["a.flac", "b.mp3"]) {
+        assert_eq!(row.get("album"), "Purple Rain");
+        assert_eq!(row.get("composer"), "");
+    }
+}
+
+#[test]
+fn apply_writes_tagged_copies_and_leaves_sources() {
+    let scratch = Scratch::new("copies");
+    tagged_pair(&scratch);
+
+    let mut rows = scratch.list(&["a.flac", "b.mp3"]);
+    for row in &mut rows {
+        row.set("artist", "Prince and the Revolution");
+    }
+    write_sheet(&scratch.path("sheet.csv"), &rows);
+    scratch.flacdat(&[
+        "apply",
+        "--attributes",
+        "sheet.csv",
+        "--output",
+        "out",
+        "-q",
+    ]);
+
+    for row in scratch.list(&["a.flac", "b.mp3"]) {
+        assert_eq!(row.get("artist"), "Prince");
+    }
+    for row in scratch.list(&["out/a.flac", "out/b.mp3"]) {
+        assert_eq!(row.get("artist"), "Prince and the Revolution");
+    }
+    assert_eq!(
+        audio(&scratch.path("out/a.flac")),
+        audio(&scratch.path("a.flac"))
+    );
+
+    // A second run would overwrite the copies.
+    let again = scratch.try_flacdat(&["apply", "--attributes", "sheet.csv", "--output", "out"]);
+    assert!(!again.status.success());
+    scratch.flacdat(&[
+        "apply",
+        "--attributes",
+        "sheet.csv",
+        "--output",
+        "out",
+        "--overwrite",
+        "-q",
+    ]);
+}
+
+#[test]
+fn all_fields_carries_custom_comments_through_a_sheet() {
+    let scratch = Scratch::new("all-fields");
+    write_flac(
+        &scratch.path("a.flac"),
+        &sine(440.0, 4410),
+        &[("TITLE", "When Doves Cry"), ("LABEL", "Warner Bros.")],
+    );
+
+    let output = scratch.flacdat(&["list", "--all-fields", "a.flac"]);
+    let sheet = String::from_utf8_lossy(&output.stdout).replace("Warner Bros.", "Paisley Park");
+    std::fs::write(scratch.path("sheet.csv"), sheet).unwrap();
+    scratch.flacdat(&[
+        "apply",
+        "--attributes",
+        "sheet.csv",
+        "--all-fields",
+        "--output",
+        "out",
+        "-q",
+    ]);
+
+    let flac = metaflac::Tag::read_from_path(scratch.path("out/a.flac")).unwrap();
+    let comments = flac.vorbis_comments().unwrap();
+    assert_eq!(comments.get("LABEL").unwrap(), &["Paisley Park"]);
+    assert_eq!(comments.get("TITLE").unwrap(), &["When Doves Cry"]);
+
+    // Without --all-fields, the column is refused.
+    let refused = scratch.try_flacdat(&["apply", "--attributes", "sheet.csv", "--dry-run"]);
+    assert!(!refused.status.success());
+}
+
+#[test]
+#[cfg(feature = "native-encoder")]
+fn convert_encodes_a_wav_and_carries_its_tags() {
+    let scratch = Scratch::new("convert");
+    let samples = sine(1000.0, 10000);
+    common::write_wav(
+        &scratch.path("take.wav"),
+        &samples,
+        &[("INAM", "Take One"), ("IART", "The Band")],
+    );
+
+    scratch.flacdat(&["convert", "--no-ffmpeg", "--output", "out", "take.wav"]);
+
+    let flac = metaflac::Tag::read_from_path(scratch.path("out/take.flac")).unwrap();
+    let info = flac.get_streaminfo().unwrap();
+    assert_eq!(info.sample_rate, common::SAMPLE_RATE);
+    assert_eq!(u16::from(info.num_channels), common::CHANNELS);
+    assert_eq!(info.bits_per_sample, 16);
+    assert_eq!(info.total_samples, 10000);
+    // The last, shorter block doesn't count towards the smallest.
+    assert_eq!(info.min_block_size, 4096);
+    assert_eq!(info.max_block_size, 4096);
+    assert!(flac
+        .vorbis_comments()
+        .unwrap()
+        .vendor_string
+        .starts_with("flacdat "));
+    assert_eq!(common::decode_flac(&scratch.path("out/take.flac")), samples);
+
+    let rows = scratch.list(&["out/take.flac"]);
+    assert_eq!(rows[0].get("title"), "Take One");
+    assert_eq!(rows[0].get("artist"), "The Band");
+}
+
+#[test]
+#[cfg(feature = "native-encoder")]
+fn watch_converts_tags_and_files_what_arrives() {
+    let scratch = Scratch::new("watch");
+    std::fs::create_dir(scratch.path("inbox")).unwrap();
+    common::write_wav(&scratch.path("inbox/take.wav"), &sine(1000.0, 10000), &[]);
+    std::fs::write(
+        scratch.path("sheet.csv"),
+        "path,artist,album,title,track\ntake.wav,The Band,Demos,Take One,1\n",
+    )
+    .unwrap();
+
+    scratch.flacdat(&[
+        "watch",
+        "inbox",
+        "--once",
+        "--settle",
+        "0",
+        "--convert",
+        "--no-ffmpeg",
+        "--delete-source",
+        "--apply",
+        "sheet.csv",
+        "--organize",
+        "{artist}/{album}/{track:02} {title}",
+        "--into",
+        "library",
+    ]);
+
+    let rows = scratch.list(&["library/The Band/Demos/01 Take One.flac"]);
+    assert_eq!(rows[0].get("title"), "Take One");
+    assert!(!scratch.path("inbox/take.wav").exists());
+    assert!(!scratch.path("inbox/take.flac").exists());
+}